@@ -1,35 +1,108 @@
+use crate::core::index::IndexLock;
 use crate::core::object::Object;
 use crate::core::repository::Repository;
-use crate::utils::{file_utils, path_utils};
+use crate::utils::{eol, file_utils, path_utils};
 use anyhow::Result;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeSet;
 use walkdir::WalkDir;
 
-pub async fn add_files(repo: &mut Repository, paths: &[std::path::PathBuf]) -> Result<()> {
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Only stage tracked files, including their deletions
+    pub update: bool,
+    /// Also stage deletions of tracked files
+    pub all: bool,
+}
+
+pub async fn add_files(
+    repo: &mut Repository,
+    paths: &[std::path::PathBuf],
+    options: &AddOptions,
+) -> Result<()> {
+    let _lock = IndexLock::acquire(&repo.git_dir)?;
     let mut files_to_add = Vec::new();
+    let ignores = path_utils::IgnoreRules::new(&repo.path);
 
     // Collect all files to add
     for path in paths {
         if path.is_file() {
-            if !path_utils::is_ignored(path, &repo.path) {
+            if !ignores.is_ignored(path) {
                 files_to_add.push(path.clone());
             }
         } else if path.is_dir() {
+            if path_utils::is_nested_repo(path, &repo.path) {
+                println!(
+                    "{}",
+                    format!("Skipping nested repository {}", path.display()).yellow()
+                );
+                continue;
+            }
             for entry in WalkDir::new(path)
                 .into_iter()
+                .filter_entry(|e| {
+                    !(e.file_type().is_dir() && path_utils::is_nested_repo(e.path(), &repo.path))
+                })
                 .filter_map(|e| e.ok())
                 .filter(|e| e.file_type().is_file())
             {
                 let entry_path = entry.path();
-                if !path_utils::is_ignored(entry_path, &repo.path) {
+                if !ignores.is_ignored(entry_path) {
                     files_to_add.push(entry_path.to_path_buf());
                 }
             }
         }
     }
 
+    // Tracked paths: those in HEAD plus anything already staged
+    let head_files = repo.head_files();
+    let mut tracked: BTreeSet<String> = head_files.keys().cloned().collect();
+    tracked.extend(repo.index.get_file_paths());
+    if options.update {
+        files_to_add.retain(|file_path| tracked.contains(&relative_to_repo(repo, file_path)));
+    }
+
+    // Tracked files under the given paths that are gone from the working tree
+    let mut deleted = Vec::new();
+    if options.update || options.all {
+        // Pathspecs are relative to the repository, whatever form they came in
+        let cwd = std::env::current_dir()?;
+        let specs: Vec<String> = paths
+            .iter()
+            .map(|path| {
+                let absolute = cwd.join(path);
+                absolute
+                    .strip_prefix(&repo.path)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .trim_start_matches("./")
+                    .to_string()
+            })
+            .collect();
+        deleted = tracked
+            .iter()
+            .filter(|path| specs.iter().any(|spec| path_utils::pathspec_matches(spec, path)))
+            .filter(|path| !repo.path.join(path).exists())
+            .cloned()
+            .collect();
+    }
+    for path in &deleted {
+        if head_files.contains_key(path) {
+            repo.index.stage_deletion(path);
+        } else {
+            // Staged but never committed: just drop it
+            repo.index.remove_file(path);
+        }
+    }
+
     if files_to_add.is_empty() {
+        if !deleted.is_empty() {
+            repo.save()?;
+            println!("\n{}", "Files staged successfully!".green().bold());
+            println!("Deleted: {} files", deleted.len().to_string().red());
+            return Ok(());
+        }
         println!("{}", "No files to add".yellow());
         return Ok(());
     }
@@ -48,14 +121,12 @@ pub async fn add_files(repo: &mut Repository, paths: &[std::path::PathBuf]) -> R
     let mut skipped_count = 0;
 
     for file_path in files_to_add {
-        let relative_path =
-            path_utils::normalize_path(&file_path.strip_prefix(&repo.path).unwrap_or(&file_path))
-                .to_string_lossy()
-                .to_string();
+        let relative_path = relative_to_repo(repo, &file_path);
 
         pb.set_message(format!("Adding {}", relative_path));
 
         if let Ok(content) = file_utils::read_file_content(&file_path) {
+            let content = eol::to_repo(repo, &relative_path, content);
             let mode = file_utils::get_file_mode(&file_path)?;
             // Check if file is executable and set appropriate mode
             let mode = if file_utils::is_executable(&file_path)? {
@@ -65,10 +136,7 @@ pub async fn add_files(repo: &mut Repository, paths: &[std::path::PathBuf]) -> R
             };
 
             // --- Blob storage logic ---
-            let blob_object = Object::new(
-                "blob".to_string(),
-                String::from_utf8_lossy(&content).to_string(),
-            );
+            let blob_object = Object::new("blob".to_string(), content.clone());
             blob_object.save(&repo.get_objects_dir())?;
             let blob_hash = blob_object.id.clone();
             // --- End blob storage logic ---
@@ -83,6 +151,7 @@ pub async fn add_files(repo: &mut Repository, paths: &[std::path::PathBuf]) -> R
                 stage: 0,
             };
             repo.index.add_file(&relative_path, entry);
+            repo.index.conflicts.remove(&relative_path);
             added_count += 1;
         } else {
             skipped_count += 1;
@@ -97,6 +166,9 @@ pub async fn add_files(repo: &mut Repository, paths: &[std::path::PathBuf]) -> R
 
     println!("\n{}", "Files staged successfully!".green().bold());
     println!("Added: {} files", added_count.to_string().cyan());
+    if !deleted.is_empty() {
+        println!("Deleted: {} files", deleted.len().to_string().red());
+    }
     if skipped_count > 0 {
         println!("Skipped: {} files", skipped_count.to_string().yellow());
     }
@@ -107,3 +179,58 @@ pub async fn add_files(repo: &mut Repository, paths: &[std::path::PathBuf]) -> R
 
     Ok(())
 }
+
+fn relative_to_repo(repo: &Repository, file_path: &std::path::Path) -> String {
+    path_utils::normalize_path(file_path.strip_prefix(&repo.path).unwrap_or(file_path))
+        .to_string_lossy()
+        .trim_start_matches("./")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn update_stages_tracked_changes_but_not_new_files() {
+        let mut t = TestRepo::new();
+        t.write("kept.txt", "1\n");
+        t.write("gone.txt", "gone\n");
+        t.commit("base").await;
+        t.write("kept.txt", "2\n");
+        t.remove("gone.txt");
+        t.write("new.txt", "new\n");
+
+        let update = AddOptions { update: true, ..Default::default() };
+        let root = t.repo.path.clone();
+        add_files(&mut t.repo, &[root], &update).await.unwrap();
+
+        assert!(t.repo.index.get_file("kept.txt").is_some());
+        assert!(t.repo.index.get_file("new.txt").is_none());
+        assert!(t.repo.index.deletions.contains("gone.txt"));
+    }
+
+    #[tokio::test]
+    async fn all_stages_a_deletion() {
+        let mut t = TestRepo::new();
+        t.write("dir/gone.txt", "gone\n");
+        t.write("other.txt", "other\n");
+        t.commit("base").await;
+        t.remove("dir/gone.txt");
+        t.write("new.txt", "new\n");
+
+        let all = AddOptions { all: true, ..Default::default() };
+        let dir = t.repo.path.join("dir");
+        add_files(&mut t.repo, &[dir], &all).await.unwrap();
+        assert!(t.repo.index.deletions.contains("dir/gone.txt"));
+        assert!(t.repo.index.get_file("new.txt").is_none());
+
+        let root = t.repo.path.clone();
+        add_files(&mut t.repo, &[root], &all).await.unwrap();
+        assert!(t.repo.index.get_file("new.txt").is_some());
+        let head = t.commit("drop").await;
+        let files = t.repo.get_commit_object(&head).unwrap().tree_files();
+        assert_eq!(files.keys().collect::<Vec<_>>(), ["new.txt", "other.txt"]);
+    }
+}
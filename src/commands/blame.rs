@@ -0,0 +1,333 @@
+use crate::core::commit::Commit;
+use crate::core::object::Object;
+use crate::core::repository::Repository;
+use crate::utils::{diff_utils, hash_utils, path_utils};
+use anyhow::{Context, Result};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default)]
+pub struct BlameOptions {
+    /// Commit to blame from (default: HEAD)
+    pub rev: Option<String>,
+    /// Stream machine-readable attributions as lines are resolved
+    pub incremental: bool,
+}
+
+/// Where one line of the blamed file came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlameEntry {
+    pub commit_id: String,
+    /// 1-based line number in that commit's version of the file
+    pub original_line: usize,
+    /// 1-based line number in the blamed version
+    pub final_line: usize,
+    /// Path of the file in that commit (differs across renames)
+    pub path: String,
+}
+
+/// Attribute every line of `path` at `start` to the commit that introduced
+/// it, following first parents and renames. `on_line` is called as each
+/// line is resolved; the returned entries are ordered by final line.
+pub fn blame_file<F: FnMut(&BlameEntry)>(
+    repo: &Repository,
+    start: &str,
+    path: &str,
+    mut on_line: F,
+) -> Result<Vec<BlameEntry>> {
+    let objects_dir = repo.get_objects_dir();
+    let load = |hash: &str| -> String {
+        Object::load(&objects_dir, hash)
+            .map(|o| o.text())
+            .unwrap_or_default()
+    };
+    let shallow = repo.shallow_commits();
+
+    // Object ids, which is what parents and branches refer to
+    let mut commit_id = start.to_string();
+    let mut commit = repo.get_commit_object(start)?;
+    let mut path = path.to_string();
+    let mut blob = commit
+        .tree_files()
+        .get(&path)
+        .cloned()
+        .ok_or_else(|| {
+            anyhow::anyhow!("no such path '{}' in {}", path, hash_utils::get_short_hash(start))
+        })?;
+    let mut content = load(&blob);
+
+    // (final line, index of that line in the version being looked at)
+    let mut pending: Vec<(usize, usize)> =
+        (0..content.lines().count()).map(|i| (i + 1, i)).collect();
+    let mut entries = Vec::with_capacity(pending.len());
+    let mut resolve = |commit_id: &str, path: &str, final_line: usize, index: usize| {
+        let entry = BlameEntry {
+            commit_id: commit_id.to_string(),
+            original_line: index + 1,
+            final_line,
+            path: path.to_string(),
+        };
+        on_line(&entry);
+        entries.push(entry);
+    };
+
+    while !pending.is_empty() {
+        let parent = commit
+            .parent_ids
+            .first()
+            .filter(|_| !shallow.contains(&commit_id))
+            .and_then(|id| Some((id.clone(), repo.get_commit_object(id).ok()?)));
+        let Some((parent_id, parent)) = parent else { break };
+
+        let parent_tree = parent.tree_files();
+        let parent_path = if parent_tree.contains_key(&path) {
+            Some(path.clone())
+        } else {
+            diff_utils::detect_renames(
+                &parent_tree,
+                &commit.tree_files(),
+                diff_utils::DEFAULT_RENAME_THRESHOLD,
+                false,
+                |hash| Some(load(hash)),
+            )
+            .into_iter()
+            .find(|pair| pair.new_path == path)
+            .map(|pair| pair.old_path)
+        };
+        let Some(parent_path) = parent_path else { break };
+        let parent_blob = parent_tree[&parent_path].clone();
+
+        if parent_blob != blob {
+            let parent_content = load(&parent_blob);
+            let diff = TextDiff::from_lines(&parent_content, &content);
+            let mut to_parent: HashMap<usize, usize> = HashMap::new();
+            for change in diff.iter_all_changes() {
+                if let (ChangeTag::Equal, Some(old), Some(new)) =
+                    (change.tag(), change.old_index(), change.new_index())
+                {
+                    to_parent.insert(new, old);
+                }
+            }
+            // Lines this commit added are settled here; the rest move to
+            // their position in the parent's version
+            let mut remaining = Vec::with_capacity(pending.len());
+            for (final_line, index) in pending {
+                match to_parent.get(&index) {
+                    Some(&old) => remaining.push((final_line, old)),
+                    None => resolve(&commit_id, &path, final_line, index),
+                }
+            }
+            pending = remaining;
+            content = parent_content;
+        }
+        commit_id = parent_id;
+        commit = parent;
+        path = parent_path;
+        blob = parent_blob;
+    }
+    // Whatever is left was there when the file first appeared
+    for (final_line, index) in pending {
+        resolve(&commit_id, &path, final_line, index);
+    }
+
+    entries.sort_by_key(|entry| entry.final_line);
+    Ok(entries)
+}
+
+/// Blame results for a commit never change, so they are kept in
+/// `.helix/blame-cache`, keyed by commit and path.
+fn cache_path(repo: &Repository, commit_id: &str, path: &str) -> PathBuf {
+    let key = hash_utils::calculate_hash(format!("{}\0{}", commit_id, path).as_bytes());
+    repo.git_dir.join("blame-cache").join(format!("{}.json", key))
+}
+
+fn load_cached(repo: &Repository, commit_id: &str, path: &str) -> Option<Vec<BlameEntry>> {
+    let data = fs::read_to_string(cache_path(repo, commit_id, path)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+fn save_cached(repo: &Repository, commit_id: &str, path: &str, entries: &[BlameEntry]) -> Result<()> {
+    let cache_file = cache_path(repo, commit_id, path);
+    if let Some(dir) = cache_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&cache_file, serde_json::to_string(entries)?)
+        .with_context(|| format!("Failed to write {}", cache_file.display()))
+}
+
+pub async fn blame(repo: &Repository, file: &str, options: &BlameOptions) -> Result<()> {
+    let start = repo.resolve_rev(options.rev.as_deref().unwrap_or("HEAD"))?;
+    let path = path_utils::repo_relative_path(&repo.path, file);
+
+    let mut commits: HashMap<String, Commit> = HashMap::new();
+    let mut described = HashSet::new();
+    let mut emit = |entry: &BlameEntry| {
+        if !commits.contains_key(&entry.commit_id) {
+            if let Ok(commit) = repo.get_commit_object(&entry.commit_id) {
+                commits.insert(entry.commit_id.clone(), commit);
+            }
+        }
+        if options.incremental {
+            print!("{}", render_incremental(entry, commits.get(&entry.commit_id), &mut described));
+        }
+    };
+
+    let entries = match load_cached(repo, &start, &path) {
+        Some(entries) => {
+            entries.iter().for_each(&mut emit);
+            entries
+        }
+        None => {
+            let entries = blame_file(repo, &start, &path, &mut emit)?;
+            // A cache we can't write only costs speed
+            let _ = save_cached(repo, &start, &path, &entries);
+            entries
+        }
+    };
+    if options.incremental {
+        return Ok(());
+    }
+
+    let blob = repo
+        .get_commit_object(&start)?
+        .tree_files()
+        .get(&path)
+        .cloned()
+        .unwrap_or_default();
+    let content = Object::load(&repo.get_objects_dir(), &blob)?.text();
+    let author_of = |entry: &BlameEntry| {
+        commits
+            .get(&entry.commit_id)
+            .map(|c| c.author.clone())
+            .unwrap_or_default()
+    };
+    let author_width = entries.iter().map(|e| author_of(e).len()).max().unwrap_or(0);
+    for (entry, line) in entries.iter().zip(content.lines()) {
+        println!(
+            "{} {:<width$} {}",
+            hash_utils::get_short_hash(&entry.commit_id).cyan(),
+            author_of(entry),
+            line,
+            width = author_width
+        );
+    }
+    Ok(())
+}
+
+/// One line of the incremental protocol: `<commit> <orig> <final> 1`,
+/// then the commit's author and summary the first time it appears, then
+/// `filename <path>`.
+fn render_incremental(
+    entry: &BlameEntry,
+    commit: Option<&Commit>,
+    described: &mut HashSet<String>,
+) -> String {
+    let mut out = format!("{} {} {} 1\n", entry.commit_id, entry.original_line, entry.final_line);
+    if let Some(commit) = commit {
+        if described.insert(entry.commit_id.clone()) {
+            out.push_str(&format!("author {}\n", commit.author));
+            out.push_str(&format!("author-mail <{}>\n", commit.email));
+            out.push_str(&format!("author-time {}\n", commit.timestamp.timestamp()));
+            out.push_str(&format!("summary {}\n", commit.message.lines().next().unwrap_or("")));
+        }
+    }
+    out.push_str(&format!("filename {}\n", entry.path));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn incremental_output_attributes_every_line_once() {
+        let mut t = TestRepo::new();
+        t.write("f.txt", "one\ntwo\nthree\n");
+        let first = t.commit("first").await;
+        t.write("f.txt", "one\nTWO\nthree\nfour\n");
+        let second = t.commit("second").await;
+
+        let mut described = HashSet::new();
+        let mut out = String::new();
+        blame_file(&t.repo, &second, "f.txt", |entry| {
+            let commit = t.repo.get_commit_object(&entry.commit_id).ok();
+            out.push_str(&render_incremental(entry, commit.as_ref(), &mut described));
+        })
+        .unwrap();
+
+        let mut owners = HashMap::new();
+        let mut summaries = Vec::new();
+        for line in out.lines() {
+            if let Some(summary) = line.strip_prefix("summary ") {
+                summaries.push(summary.to_string());
+                continue;
+            }
+            let fields: Vec<&str> = line.split(' ').collect();
+            if fields.len() == 4 && fields[3] == "1" && fields[0].len() == first.len() {
+                let final_line: usize = fields[2].parse().unwrap();
+                assert!(owners.insert(final_line, fields[0].to_string()).is_none());
+            }
+        }
+        assert_eq!(owners.len(), 4);
+        assert_eq!(owners[&1], first);
+        assert_eq!(owners[&2], second);
+        assert_eq!(owners[&3], first);
+        assert_eq!(owners[&4], second);
+        // Each commit is described once, however many lines it owns
+        summaries.sort();
+        assert_eq!(summaries, vec!["first", "second"]);
+    }
+
+    /// (final line, owning commit, line number in that commit, path there)
+    fn attribution(t: &TestRepo, path: &str) -> Vec<(usize, String, usize, String)> {
+        blame_file(&t.repo, &t.head(), path, |_| {})
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.final_line, e.commit_id, e.original_line, e.path))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn each_line_belongs_to_the_commit_that_last_changed_it() {
+        let mut t = TestRepo::new();
+        t.write("f.txt", "a\nb\nc\n");
+        let first = t.commit("first").await;
+        t.write("f.txt", "new\na\nB\nc\n");
+        let second = t.commit("second").await;
+        t.write("other.txt", "unrelated\n");
+        t.commit("third").await;
+
+        let f = "f.txt".to_string();
+        assert_eq!(
+            attribution(&t, "f.txt"),
+            vec![
+                (1, second.clone(), 1, f.clone()),
+                (2, first.clone(), 1, f.clone()),
+                (3, second, 3, f.clone()),
+                (4, first, 3, f),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn blame_follows_a_rename() {
+        let mut t = TestRepo::new();
+        t.write("old.rs", "one\ntwo\nthree\nfour\n");
+        let first = t.commit("first").await;
+        t.remove("old.rs");
+        t.write("new.rs", "one\ntwo\nthree\nfour\nfive\n");
+        let renamed = t.commit("rename and extend").await;
+
+        let entries = attribution(&t, "new.rs");
+        assert_eq!(entries.len(), 5);
+        for (final_line, commit_id, original_line, path) in &entries[..4] {
+            assert_eq!((commit_id, original_line, path.as_str()), (&first, final_line, "old.rs"));
+        }
+        assert_eq!(entries[4], (5, renamed, 5, "new.rs".to_string()));
+    }
+}
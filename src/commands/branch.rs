@@ -1,4 +1,6 @@
+use crate::core::commit_graph::CommitParents;
 use crate::core::repository::Repository;
+use crate::utils::hash_utils;
 use anyhow::Result;
 use chrono::Duration;
 use colored::*;
@@ -7,6 +9,12 @@ pub async fn list_branches(repo: &Repository) -> Result<()> {
     println!("{}", "Branches".bold().blue());
     println!("{}", "=".repeat(40).blue());
 
+    if let Some(commit_id) = &repo.detached_head {
+        println!(
+            "* {}",
+            format!("(HEAD detached at {})", hash_utils::get_short_hash(commit_id)).red()
+        );
+    }
     for (name, branch) in &repo.branches {
         let indicator = if name == &repo.current_branch {
             "* "
@@ -26,7 +34,11 @@ pub async fn list_branches(repo: &Repository) -> Result<()> {
         }
 
         if let Some(upstream) = branch.get_upstream() {
-            println!("    Upstream: {}", upstream.magenta());
+            println!(
+                "    Upstream: {}{}",
+                upstream.magenta(),
+                tracking_summary(repo, branch.get_head_commit(), upstream)
+            );
         }
 
         // Show branch age
@@ -50,6 +62,86 @@ pub async fn list_branches(repo: &Repository) -> Result<()> {
     Ok(())
 }
 
+/// Remote-tracking branches, as last recorded by `fetch` or `pull`.
+pub async fn list_remote_branches(repo: &Repository) -> Result<()> {
+    println!("{}", "Remote-tracking branches".bold().blue());
+    println!("{}", "=".repeat(40).blue());
+
+    let refs = repo.remote_tracking_refs();
+    if refs.is_empty() {
+        println!("{}", "None (run 'hx fetch' first?)".yellow());
+    }
+    for (name, head) in refs {
+        println!("  {} {}", name.red(), hash_utils::get_short_hash(&head).cyan());
+    }
+    Ok(())
+}
+
+/// ` [ahead N, behind M]` relative to the upstream's remote-tracking ref,
+/// or ` [gone]` if it no longer exists.
+pub fn tracking_summary(repo: &Repository, head: Option<&String>, upstream: &str) -> String {
+    let upstream_head = match repo.get_tracking_ref(upstream) {
+        Some(upstream_head) => upstream_head,
+        None => return format!(" [{}]", "gone".red()),
+    };
+    let head = match head {
+        Some(head) => head,
+        None => return String::new(),
+    };
+    let (ahead, behind) = CommitParents::new(repo).ahead_behind(head, &upstream_head);
+    let mut parts = Vec::new();
+    if ahead > 0 {
+        parts.push(format!("ahead {}", ahead).green().to_string());
+    }
+    if behind > 0 {
+        parts.push(format!("behind {}", behind).red().to_string());
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", parts.join(", "))
+    }
+}
+
+/// Point `branch_name` (default: the current branch) at `upstream`, a
+/// `<remote>/<branch>` that must exist as a remote-tracking ref.
+pub async fn set_upstream(repo: &mut Repository, branch_name: Option<&str>, upstream: &str) -> Result<()> {
+    let branch_name = branch_name.unwrap_or(&repo.current_branch).to_string();
+    if repo.get_tracking_ref(upstream).is_none() {
+        anyhow::bail!(
+            "The requested upstream branch '{}' does not exist (run 'hx fetch' first?)",
+            upstream
+        );
+    }
+    let branch = repo
+        .branches
+        .get_mut(&branch_name)
+        .ok_or_else(|| anyhow::anyhow!("Branch '{}' does not exist", branch_name))?;
+    branch.set_upstream(upstream.to_string());
+    repo.save()?;
+    println!(
+        "Branch '{}' set up to track '{}'",
+        branch_name.yellow(),
+        upstream.magenta()
+    );
+    Ok(())
+}
+
+pub async fn unset_upstream(repo: &mut Repository, branch_name: Option<&str>) -> Result<()> {
+    let branch_name = branch_name.unwrap_or(&repo.current_branch).to_string();
+    let branch = repo
+        .branches
+        .get_mut(&branch_name)
+        .ok_or_else(|| anyhow::anyhow!("Branch '{}' does not exist", branch_name))?;
+    if branch.get_upstream().is_none() {
+        anyhow::bail!("Branch '{}' has no upstream information", branch_name);
+    }
+    branch.unset_upstream();
+    repo.save()?;
+    println!("Removed upstream of branch '{}'", branch_name.yellow());
+    Ok(())
+}
+
 fn format_duration(duration: Duration) -> String {
     if duration.num_days() > 0 {
         format!("{} days", duration.num_days())
@@ -75,3 +167,147 @@ pub async fn create_branch(repo: &mut Repository, name: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Delete `name`. Unless `force` is set, the branch's head has to be
+/// reachable from the current branch so no commits are lost.
+pub async fn delete_branch(repo: &mut Repository, name: &str, force: bool) -> Result<()> {
+    if name == repo.current_branch {
+        anyhow::bail!("Cannot delete branch '{}': it is the current branch", name);
+    }
+    let branch = repo
+        .branches
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("Branch '{}' not found", name))?;
+    let head = branch.get_head_commit().cloned();
+
+    if let (Some(head), false) = (&head, force) {
+        let merged = repo
+            .head_commit()
+            .is_some_and(|current| CommitParents::new(repo).ancestors(current).any(|id| id == *head));
+        if !merged {
+            anyhow::bail!(
+                "The branch '{}' is not fully merged.\nIf you are sure you want to delete it, run 'hx branch -D {}'",
+                name,
+                name
+            );
+        }
+    }
+
+    repo.branches.remove(name);
+    repo.save()?;
+    println!(
+        "{}",
+        format!(
+            "Deleted branch {} (was {})",
+            name,
+            head.as_deref().map(hash_utils::get_short_hash).unwrap_or_else(|| "empty".to_string())
+        )
+        .green()
+    );
+    Ok(())
+}
+
+/// Rename `old` to `new`, keeping its head, upstream and timestamps. HEAD
+/// follows the rename when `old` is the current branch.
+pub async fn rename_branch(repo: &mut Repository, old: &str, new: &str) -> Result<()> {
+    if repo.branches.contains_key(new) {
+        anyhow::bail!("A branch named '{}' already exists", new);
+    }
+    let mut branch = repo
+        .branches
+        .remove(old)
+        .ok_or_else(|| anyhow::anyhow!("Branch '{}' not found", old))?;
+    branch.name = new.to_string();
+    repo.branches.insert(new.to_string(), branch);
+    if repo.current_branch == old {
+        repo.current_branch = new.to_string();
+    }
+    repo.save()?;
+    println!("{}", format!("Renamed branch '{}' to '{}'", old, new).green());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn upstream_is_set_and_unset() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let head = t.commit("first").await;
+        t.repo.add_remote("origin", "http://example.invalid/repo").unwrap();
+
+        let missing = set_upstream(&mut t.repo, None, "origin/main").await.unwrap_err();
+        assert!(missing.to_string().contains("does not exist"), "{}", missing);
+        assert!(t.repo.branches["main"].get_upstream().is_none());
+
+        t.repo.set_remote_ref("origin", "main", &head).unwrap();
+        set_upstream(&mut t.repo, None, "origin/main").await.unwrap();
+        let reopened = Repository::open(&t.repo.path.to_string_lossy()).unwrap();
+        assert_eq!(reopened.branches["main"].get_upstream().map(String::as_str), Some("origin/main"));
+        assert_eq!(tracking_summary(&t.repo, Some(&head), "origin/main"), "");
+
+        unset_upstream(&mut t.repo, Some("main")).await.unwrap();
+        let reopened = Repository::open(&t.repo.path.to_string_lossy()).unwrap();
+        assert!(reopened.branches["main"].get_upstream().is_none());
+        assert!(unset_upstream(&mut t.repo, Some("main")).await.is_err());
+        assert!(set_upstream(&mut t.repo, Some("nope"), "origin/main").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_refuses_unmerged_and_current_branches() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("first").await;
+        t.checkout_new("merged").await;
+        t.checkout("main").await;
+        t.checkout_new("unmerged").await;
+        t.write("b", "only here\n");
+        t.commit("unmerged work").await;
+        t.checkout("main").await;
+
+        delete_branch(&mut t.repo, "merged", false).await.unwrap();
+        assert!(!t.repo.branches.contains_key("merged"));
+
+        let err = delete_branch(&mut t.repo, "unmerged", false).await.unwrap_err();
+        assert!(err.to_string().contains("not fully merged"), "{}", err);
+        assert!(t.repo.branches.contains_key("unmerged"));
+        delete_branch(&mut t.repo, "unmerged", true).await.unwrap();
+
+        assert!(delete_branch(&mut t.repo, "main", true).await.is_err());
+        assert!(delete_branch(&mut t.repo, "missing", true).await.is_err());
+        let reopened = Repository::open(&t.repo.path.to_string_lossy()).unwrap();
+        let mut names: Vec<_> = reopened.branches.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["main"]);
+    }
+
+    #[tokio::test]
+    async fn rename_moves_the_branch_and_head_with_it() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let head = t.commit("first").await;
+        t.repo.add_remote("origin", "http://example.invalid/repo").unwrap();
+        t.repo.set_remote_ref("origin", "main", &head).unwrap();
+        set_upstream(&mut t.repo, None, "origin/main").await.unwrap();
+        t.checkout_new("other").await;
+        t.checkout("main").await;
+        let before = t.repo.branches["main"].clone();
+
+        assert!(rename_branch(&mut t.repo, "main", "other").await.is_err());
+        rename_branch(&mut t.repo, "main", "trunk").await.unwrap();
+
+        let reopened = Repository::open(&t.repo.path.to_string_lossy()).unwrap();
+        assert!(!reopened.branches.contains_key("main"));
+        assert_eq!(reopened.current_branch, "trunk");
+        let trunk = &reopened.branches["trunk"];
+        assert_eq!(trunk.name, "trunk");
+        assert_eq!(trunk.get_head_commit(), Some(&head));
+        assert_eq!(trunk.get_upstream().map(String::as_str), Some("origin/main"));
+        assert_eq!(trunk.created_at, before.created_at);
+        assert_eq!(trunk.last_updated, before.last_updated);
+        assert_eq!(std::fs::read_to_string(t.repo.git_dir.join("HEAD")).unwrap().trim(), "trunk");
+    }
+}
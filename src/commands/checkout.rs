@@ -1,9 +1,20 @@
+use crate::core::branch::Branch;
+use crate::core::index::IndexEntry;
+use crate::core::object::Object;
 use crate::core::repository::Repository;
+use crate::core::undo::UndoEntry;
+use crate::utils::{eol, file_utils, hash_utils};
 use anyhow::Result;
 use colored::*;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
-pub async fn checkout_branch(repo: &mut Repository, branch_name: &str) -> Result<()> {
+pub async fn checkout_branch(repo: &mut Repository, branch_name: &str, force: bool) -> Result<()> {
     if !repo.branches.contains_key(branch_name) {
+        // Anything else naming a commit is checked out without a branch
+        if let Ok(commit_id) = repo.resolve_rev(branch_name) {
+            return checkout_detached(repo, branch_name, &commit_id, force).await;
+        }
         println!(
             "{}",
             format!("Branch '{}' does not exist", branch_name).red()
@@ -19,6 +30,14 @@ pub async fn checkout_branch(repo: &mut Repository, branch_name: &str) -> Result
         return Ok(());
     }
 
+    let target_head = repo.branches[branch_name].get_head_commit().cloned();
+    let (written, removed) = switch_worktree(
+        repo,
+        target_head.as_deref(),
+        &format!("checkout {}", branch_name),
+        force,
+    )?;
+
     repo.checkout_branch(branch_name)?;
 
     println!(
@@ -28,6 +47,9 @@ pub async fn checkout_branch(repo: &mut Repository, branch_name: &str) -> Result
             .bold()
     );
     println!("Current branch: {}", repo.current_branch.yellow().bold());
+    if written + removed > 0 {
+        println!("Updated {} files, removed {}", written, removed);
+    }
 
     if let Some(current_branch) = repo.get_current_branch() {
         if let Some(head_commit) = current_branch.get_head_commit() {
@@ -37,3 +59,256 @@ pub async fn checkout_branch(repo: &mut Repository, branch_name: &str) -> Result
 
     Ok(())
 }
+
+/// Check out `commit_id` (given as `rev`) with HEAD detached from any
+/// branch.
+async fn checkout_detached(repo: &mut Repository, rev: &str, commit_id: &str, force: bool) -> Result<()> {
+    let (written, removed) = switch_worktree(repo, Some(commit_id), &format!("checkout {}", rev), force)?;
+    repo.detach_head(commit_id)?;
+
+    let commit = repo.get_commit_object(commit_id)?;
+    println!("{}", format!("Note: switching to '{}'.", rev).yellow());
+    println!(
+        "{}",
+        "You are in 'detached HEAD' state. Commits made now won't belong to any branch;\n\
+         create one with 'hx checkout -b <name>' to keep them."
+            .yellow()
+    );
+    if written + removed > 0 {
+        println!("Updated {} files, removed {}", written, removed);
+    }
+    println!(
+        "HEAD is now at {} {}",
+        hash_utils::get_short_hash(commit_id).cyan(),
+        commit.message.lines().next().unwrap_or("")
+    );
+    Ok(())
+}
+
+/// Replace the working tree files of HEAD with those of `target` (no
+/// commit: an empty tree), saving anything overwritten for `hx undo`.
+/// Returns the number of files written and removed.
+pub fn switch_worktree(
+    repo: &Repository,
+    target: Option<&str>,
+    operation: &str,
+    force: bool,
+) -> Result<(usize, usize)> {
+    let target_tree = target
+        .and_then(|id| repo.get_commit_object(id).ok())
+        .map(|commit| commit.tree_files())
+        .unwrap_or_default();
+    repo.check_overwrite(&target_tree, force)?;
+
+    let current_tree = repo.head_files();
+    let mut targets: BTreeMap<String, Option<String>> = target_tree
+        .iter()
+        .map(|(path, blob)| (path.clone(), Some(blob.clone())))
+        .collect();
+    for path in current_tree.keys().filter(|path| !target_tree.contains_key(*path)) {
+        targets.insert(path.clone(), None);
+    }
+    UndoEntry::snapshot(repo, operation, &targets)?;
+    repo.update_worktree(&current_tree, &target_tree)
+}
+
+/// `checkout -b`: create `branch_name` at `start_point` (default: HEAD) and
+/// switch to it.
+pub async fn create_and_checkout(
+    repo: &mut Repository,
+    branch_name: &str,
+    start_point: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    if repo.branches.contains_key(branch_name) {
+        anyhow::bail!("A branch named '{}' already exists", branch_name);
+    }
+    let head = match start_point {
+        Some(rev) => Some(repo.resolve_rev(rev)?),
+        None => repo.head_commit().cloned(),
+    };
+    if let Some(head) = &head {
+        // Fail before creating anything if switching would clobber changes
+        repo.check_overwrite(&repo.get_commit_object(head)?.tree_files(), force)?;
+    }
+
+    let branch = match head {
+        Some(head) => Branch::with_head(branch_name, head),
+        None => Branch::new(branch_name),
+    };
+    repo.branches.insert(branch_name.to_string(), branch);
+    repo.save()?;
+    println!("{}", format!("Created branch '{}'", branch_name).green());
+    checkout_branch(repo, branch_name, force).await
+}
+
+/// Resolve merge conflicts by taking our (or their) version of each path.
+pub async fn checkout_conflict_side(
+    repo: &mut Repository,
+    paths: &[PathBuf],
+    ours: bool,
+) -> Result<()> {
+    let side = if ours { "ours" } else { "theirs" };
+    let relative = |path: &PathBuf| {
+        path.strip_prefix(&repo.path)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    };
+    let targets = paths
+        .iter()
+        .map(relative)
+        .filter_map(|path| {
+            let conflict = repo.index.conflicts.get(&path)?;
+            let chosen = if ours { &conflict.ours } else { &conflict.theirs };
+            Some((path, chosen.clone()))
+        })
+        .collect();
+    UndoEntry::snapshot(repo, &format!("checkout --{}", side), &targets)?;
+
+    for path in paths {
+        let relative_path = relative(path);
+
+        let conflict = match repo.index.conflicts.remove(&relative_path) {
+            Some(conflict) => conflict,
+            None => {
+                println!(
+                    "{}",
+                    format!("'{}' has no unresolved conflict", relative_path).yellow()
+                );
+                continue;
+            }
+        };
+
+        let chosen = if ours { conflict.ours } else { conflict.theirs };
+        match chosen {
+            Some(blob_hash) => {
+                let blob = Object::load(&repo.get_objects_dir(), &blob_hash)?;
+                let content = eol::to_worktree(repo, &relative_path, &blob.data);
+                file_utils::write_file_content(path, &content)?;
+                let entry = IndexEntry {
+                    path: relative_path.clone(),
+                    content_hash: blob_hash,
+                    size: blob.size as u64,
+                    mode: file_utils::get_file_mode(path)?,
+                    timestamp: chrono::Utc::now(),
+                    stage: 0,
+                };
+                repo.index.add_file(&relative_path, entry);
+            }
+            None => {
+                // The chosen side deleted the file
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+                if repo.head_files().contains_key(&relative_path) {
+                    repo.index.stage_deletion(&relative_path);
+                } else {
+                    repo.index.remove_file(&relative_path);
+                }
+            }
+        }
+
+        println!(
+            "{}",
+            format!("Resolved '{}' using {}", relative_path, side).green()
+        );
+    }
+
+    repo.save()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn detach_at_a_commit_then_reattach() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.write("a", "2\n");
+        t.write("b", "b\n");
+        let second = t.commit("second").await;
+
+        checkout_branch(&mut t.repo, &first[..8], false).await.unwrap();
+        assert_eq!(t.repo.detached_head.as_deref(), Some(first.as_str()));
+        assert_eq!(t.read("a"), "1\n");
+        assert!(!t.exists("b"));
+        // The branch itself is left where it was
+        assert_eq!(t.repo.branches["main"].get_head_commit(), Some(&second));
+
+        checkout_branch(&mut t.repo, "main", false).await.unwrap();
+        assert_eq!(t.repo.detached_head, None);
+        assert_eq!(t.head(), second);
+        assert_eq!(t.read("a"), "2\n");
+        assert!(t.exists("b"));
+    }
+
+    #[tokio::test]
+    async fn dirty_file_blocks_switching_until_forced() {
+        let mut t = TestRepo::new();
+        t.write("a", "main\n");
+        t.commit("first").await;
+        t.checkout_new("topic").await;
+        t.write("a", "topic\n");
+        t.commit("topic").await;
+        t.write("a", "uncommitted\n");
+
+        assert!(checkout_branch(&mut t.repo, "main", false).await.is_err());
+        assert_eq!(t.repo.current_branch, "topic");
+        assert_eq!(t.read("a"), "uncommitted\n");
+
+        checkout_branch(&mut t.repo, "main", true).await.unwrap();
+        assert_eq!(t.repo.current_branch, "main");
+        assert_eq!(t.read("a"), "main\n");
+    }
+
+    #[tokio::test]
+    async fn the_working_tree_follows_each_checkout() {
+        let mut t = TestRepo::new();
+        t.write("shared", "base\n");
+        t.write("main-only", "main\n");
+        t.commit("main").await;
+        t.checkout_new("topic").await;
+        t.remove("main-only");
+        t.write("shared", "topic\n");
+        t.write("dir/topic-only", "topic\n");
+        t.commit("topic").await;
+
+        t.checkout("main").await;
+        assert_eq!(t.read("shared"), "base\n");
+        assert_eq!(t.read("main-only"), "main\n");
+        assert!(!t.exists("dir/topic-only"));
+
+        t.checkout("topic").await;
+        assert_eq!(t.read("shared"), "topic\n");
+        assert_eq!(t.read("dir/topic-only"), "topic\n");
+        assert!(!t.exists("main-only"));
+    }
+
+    #[tokio::test]
+    async fn checkout_b_creates_the_branch_and_switches_to_it() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.write("a", "2\n");
+        let second = t.commit("second").await;
+
+        create_and_checkout(&mut t.repo, "feature", None, false).await.unwrap();
+        assert_eq!(t.repo.current_branch, "feature");
+        assert_eq!(t.repo.branches["feature"].get_head_commit(), Some(&second));
+
+        create_and_checkout(&mut t.repo, "old", Some("HEAD~1"), false).await.unwrap();
+        assert_eq!(t.repo.current_branch, "old");
+        assert_eq!(t.head(), first);
+        assert_eq!(t.read("a"), "1\n");
+        let reopened = Repository::open(t.repo.path.to_str().unwrap()).unwrap();
+        assert_eq!(reopened.current_branch, "old");
+
+        assert!(create_and_checkout(&mut t.repo, "feature", None, false).await.is_err());
+        assert_eq!(t.repo.current_branch, "old");
+    }
+}
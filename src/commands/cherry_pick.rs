@@ -0,0 +1,127 @@
+use crate::commands::commit::{self, CommitOptions};
+use crate::commands::merge;
+use crate::core::repository::Repository;
+use crate::utils::hash_utils;
+use anyhow::Result;
+use colored::*;
+use ed25519_dalek::SigningKey;
+
+/// Apply the change `rev` made to its parent on top of HEAD, as a new
+/// commit keeping the original message, author and author date. The
+/// working tree has to be clean.
+pub async fn cherry_pick(repo: &mut Repository, rev: &str, keypair: &SigningKey) -> Result<()> {
+    repo.require_clean("cherry-pick")?;
+    let commit_id = repo.resolve_rev(rev)?;
+    let commit = repo.get_commit_object(&commit_id)?;
+
+    println!(
+        "{}",
+        format!(
+            "Picking {} {}",
+            hash_utils::get_short_hash(&commit_id),
+            commit.message.lines().next().unwrap_or("")
+        )
+        .blue()
+    );
+    let conflicts = replay(repo, &commit_id, keypair).await?;
+    if !conflicts.is_empty() {
+        println!(
+            "{}",
+            format!("Could not apply {}: conflicts in", hash_utils::get_short_hash(&commit_id))
+                .red()
+                .bold()
+        );
+        for path in &conflicts {
+            println!("  {}", path.red().bold());
+        }
+        println!("Resolve them, 'hx add' the files and run 'hx commit'");
+    }
+    Ok(())
+}
+
+/// Apply `commit_id`'s change on top of HEAD and commit it with the
+/// original message, author and author date. When it doesn't apply
+/// cleanly, nothing is committed: the conflicted paths are left with
+/// markers in the working tree and returned. A change HEAD already has is
+/// skipped.
+pub async fn replay(repo: &mut Repository, commit_id: &str, keypair: &SigningKey) -> Result<Vec<String>> {
+    let commit = repo.get_commit_object(commit_id)?;
+    let (before, after) = merge::commit_change(repo, &commit)?;
+
+    let conflicts = merge::apply_tree_change(repo, &before, &after)?;
+    if !conflicts.is_empty() {
+        repo.save()?;
+        return Ok(conflicts);
+    }
+    if repo.index.is_empty() {
+        println!(
+            "{}",
+            format!("{} is already applied, skipping", hash_utils::get_short_hash(commit_id)).yellow()
+        );
+        return Ok(Vec::new());
+    }
+
+    let options = CommitOptions {
+        author: Some(format!("{} <{}>", commit.author, commit.email)),
+        date: Some(commit.timestamp.to_rfc3339()),
+        ..Default::default()
+    };
+    commit::commit_changes(repo, &commit.message, &options, keypair).await?;
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{keypair, TestRepo};
+
+    #[tokio::test]
+    async fn picks_a_change_onto_a_divergent_branch() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "one\ntwo\nthree\n");
+        t.write("b.txt", "b\n");
+        t.commit("base").await;
+        t.checkout_new("topic").await;
+        t.write("a.txt", "one\nTWO\nthree\n");
+        t.repo.config.author = "Picked".to_string();
+        t.repo.config.email = "picked@example.com".to_string();
+        let picked = t.commit("shout two").await;
+        t.repo.config.author = "Test".to_string();
+        t.repo.config.email = "test@example.com".to_string();
+        t.checkout("main").await;
+        t.write("b.txt", "b changed on main\n");
+        let main = t.commit("main work").await;
+
+        cherry_pick(&mut t.repo, &picked[..8], &keypair()).await.unwrap();
+
+        let head = t.head();
+        assert_ne!(head, picked);
+        let commit = t.repo.get_commit_object(&head).unwrap();
+        let original = t.repo.get_commit_object(&picked).unwrap();
+        assert_eq!(commit.parent_ids, vec![main]);
+        assert_eq!(commit.message, "shout two");
+        assert_eq!((commit.author.as_str(), commit.email.as_str()), ("Picked", "picked@example.com"));
+        assert_eq!(commit.timestamp, original.timestamp);
+        assert_eq!(t.read("a.txt"), "one\nTWO\nthree\n");
+        assert_eq!(t.read("b.txt"), "b changed on main\n");
+    }
+
+    #[tokio::test]
+    async fn a_conflict_leaves_markers_and_commits_nothing() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "base\n");
+        t.commit("base").await;
+        t.checkout_new("topic").await;
+        t.write("a.txt", "topic\n");
+        let picked = t.commit("topic edit").await;
+        t.checkout("main").await;
+        t.write("a.txt", "main\n");
+        let main = t.commit("main edit").await;
+
+        let conflicts = replay(&mut t.repo, &picked, &keypair()).await.unwrap();
+        assert_eq!(conflicts, vec!["a.txt"]);
+        assert_eq!(t.head(), main);
+        let content = t.read("a.txt");
+        assert!(content.contains("<<<<<<<") && content.contains(">>>>>>>"), "{}", content);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::commands::status;
+use crate::core::repository::Repository;
+use anyhow::Result;
+use colored::*;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct CleanOptions {
+    /// Actually delete; without it (or `dry_run`) nothing is removed
+    pub force: bool,
+    /// Only list what would be removed
+    pub dry_run: bool,
+    /// Also remove directories left holding nothing but untracked files
+    pub directories: bool,
+}
+
+/// Remove untracked files from the working tree. Tracked, staged and
+/// ignored files are never touched.
+pub async fn clean(repo: &Repository, options: &CleanOptions) -> Result<()> {
+    if !options.force && !options.dry_run {
+        anyhow::bail!("Refusing to clean without -f; use -n to see what would be removed");
+    }
+
+    let (untracked, directories) = clean_targets(repo, options)?;
+
+    let verb = if options.dry_run { "Would remove" } else { "Removing" };
+    for file in &untracked {
+        println!("{} {}", verb, file);
+        if !options.dry_run {
+            fs::remove_file(repo.path.join(file))?;
+        }
+    }
+    for dir in &directories {
+        println!("{} {}/", verb, dir);
+        if !options.dry_run {
+            fs::remove_dir(repo.path.join(dir))?;
+        }
+    }
+
+    if untracked.is_empty() && directories.is_empty() {
+        println!("{}", "Nothing to clean".green());
+    }
+    Ok(())
+}
+
+/// The untracked files `clean` removes and, with `directories`, the
+/// directories left empty by that, deepest first.
+fn clean_targets(repo: &Repository, options: &CleanOptions) -> Result<(Vec<String>, Vec<String>)> {
+    let untracked = status::untracked_files(repo, None)?;
+    let untracked_set: HashSet<PathBuf> = untracked.iter().map(|p| repo.path.join(p)).collect();
+
+    // Deepest first, so a directory is only removed after its contents
+    let mut directories = BTreeSet::new();
+    if options.directories {
+        for file in &untracked {
+            let mut dir = Path::new(file).parent();
+            while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+                if only_untracked(&repo.path.join(d), &untracked_set) {
+                    directories.insert(d.to_string_lossy().to_string());
+                }
+                dir = d.parent();
+            }
+        }
+    }
+    Ok((untracked, directories.into_iter().rev().collect()))
+}
+
+/// Whether everything under `dir` is one of the `untracked` files, so the
+/// directory is empty once they are gone.
+fn only_untracked(dir: &Path, untracked: &HashSet<PathBuf>) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else { return false };
+    entries.flatten().all(|entry| {
+        let path = entry.path();
+        if path.is_dir() {
+            only_untracked(&path, untracked)
+        } else {
+            untracked.contains(&path)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    async fn repo_with_clutter() -> TestRepo {
+        let mut t = TestRepo::new();
+        t.write(".helixignore", "*.cache\n");
+        t.write("tracked.txt", "tracked\n");
+        t.write("src/lib.rs", "lib\n");
+        t.commit("first").await;
+        t.write("staged.txt", "staged\n");
+        let path = t.repo.path.join("staged.txt");
+        crate::commands::add::add_files(&mut t.repo, &[path], &Default::default()).await.unwrap();
+        t.write("junk.txt", "junk\n");
+        t.write("src/scratch.rs", "scratch\n");
+        t.write("tmp/deep/out.txt", "out\n");
+        t.write("build.cache", "ignored\n");
+        t
+    }
+
+    #[tokio::test]
+    async fn dry_run_lists_without_removing() {
+        let t = repo_with_clutter().await;
+        let dry_run = CleanOptions { dry_run: true, directories: true, ..Default::default() };
+        let (files, dirs) = clean_targets(&t.repo, &dry_run).unwrap();
+        assert_eq!(files, ["junk.txt", "src/scratch.rs", "tmp/deep/out.txt"]);
+        assert_eq!(dirs, ["tmp/deep", "tmp"]);
+
+        clean(&t.repo, &dry_run).await.unwrap();
+        assert!(t.exists("junk.txt") && t.exists("tmp/deep/out.txt"));
+        assert!(clean(&t.repo, &CleanOptions::default()).await.is_err());
+        assert!(t.exists("junk.txt"));
+    }
+
+    #[tokio::test]
+    async fn force_removes_only_untracked_files() {
+        let t = repo_with_clutter().await;
+        clean(&t.repo, &CleanOptions { force: true, ..Default::default() }).await.unwrap();
+        assert!(!t.exists("junk.txt"));
+        assert!(!t.exists("src/scratch.rs"));
+        assert!(!t.exists("tmp/deep/out.txt"));
+        // Without -d the emptied directories stay
+        assert!(t.exists("tmp/deep"));
+        for kept in ["tracked.txt", "src/lib.rs", "staged.txt", "build.cache", ".helixignore"] {
+            assert!(t.exists(kept), "{} was removed", kept);
+        }
+
+    }
+
+    #[tokio::test]
+    async fn directories_emptied_by_the_clean_are_removed() {
+        let t = repo_with_clutter().await;
+        let with_dirs = CleanOptions { force: true, directories: true, ..Default::default() };
+        clean(&t.repo, &with_dirs).await.unwrap();
+        assert!(!t.exists("tmp"));
+        assert!(t.exists("src/lib.rs") && !t.exists("src/scratch.rs"));
+    }
+}
@@ -3,89 +3,72 @@ use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::fs;
 use std::path::Path;
+use crate::commands::pull::write_object;
+use crate::core::branch::Branch;
+use crate::core::fetch_progress::FetchProgress;
 use crate::core::repository::Repository;
-use crate::utils::remote_client::RemoteClient;
+use crate::utils::remote_client::OBJECT_BATCH_SIZE;
+use crate::utils::transport::{RemoteTransport, Transport};
 use crate::core::object::Object;
 use git2::Repository as GitRepository;
 use std::process::Command;
 
-pub async fn clone_repository(url: &str, path: &Path) -> Result<()> {
-    // Heuristic: detect VCS type
-    let is_git = url.ends_with(".git") || url.contains("github.com") || url.contains("gitlab.com");
-    let is_hg = url.contains("bitbucket.org") || url.ends_with(".hg") || url.contains("mercurial");
-    let is_svn = url.contains("svn") || url.contains("subversion") || url.ends_with("/trunk") || url.ends_with("/branches");
-    let is_bzr = url.contains("launchpad.net") || url.ends_with(".bzr") || url.contains("bazaar");
-
-    if is_git {
-        println!("{}", format!("Cloning Git repository from {}...", url).blue().bold());
-        match GitRepository::clone(url, path) {
-            Ok(_) => {
-                println!("{}", "Git repository cloned successfully!".green().bold());
-                println!("Location: {}", path.display().to_string().cyan());
-                println!("Source: {}", url.magenta());
-                return Ok(());
-            }
-            Err(e) => {
-                println!("{}", format!("Failed to clone Git repository: {}", e).red());
-            }
-        }
-    } else if is_hg {
-        println!("{}", format!("Cloning Mercurial (hg) repository from {}...", url).blue().bold());
-        let status = Command::new("hg")
-            .arg("clone")
-            .arg(url)
-            .arg(path)
-            .status();
-        match status {
-            Ok(s) if s.success() => {
-                println!("{}", "Mercurial repository cloned successfully!".green().bold());
-                println!("Location: {}", path.display().to_string().cyan());
-                println!("Source: {}", url.magenta());
-                return Ok(());
-            }
-            Ok(_) | Err(_) => {
-                println!("{}", "Failed to clone Mercurial repository. Is 'hg' installed?".red());
-            }
-        }
-    } else if is_svn {
-        println!("{}", format!("Cloning Subversion (svn) repository from {}...", url).blue().bold());
-        let status = Command::new("svn")
-            .arg("checkout")
-            .arg(url)
-            .arg(path)
-            .status();
-        match status {
-            Ok(s) if s.success() => {
-                println!("{}", "Subversion repository cloned successfully!".green().bold());
-                println!("Location: {}", path.display().to_string().cyan());
-                println!("Source: {}", url.magenta());
-                return Ok(());
-            }
-            Ok(_) | Err(_) => {
-                println!("{}", "Failed to clone Subversion repository. Is 'svn' installed?".red());
-            }
-        }
-    } else if is_bzr {
-        println!("{}", format!("Cloning Bazaar (bzr) repository from {}...", url).blue().bold());
-        let status = Command::new("bzr")
-            .arg("branch")
-            .arg(url)
-            .arg(path)
-            .status();
-        match status {
-            Ok(s) if s.success() => {
-                println!("{}", "Bazaar repository cloned successfully!".green().bold());
-                println!("Location: {}", path.display().to_string().cyan());
-                println!("Source: {}", url.magenta());
-                return Ok(());
-            }
-            Ok(_) | Err(_) => {
-                println!("{}", "Failed to clone Bazaar repository. Is 'bzr' installed?".red());
-            }
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    /// Clone with an external VCS (`git`, `hg`, `svn` or `bzr`) instead of
+    /// from a Helix remote
+    pub vcs: Option<String>,
+}
+
+/// Clone with the external tool for `vcs`.
+fn clone_external(vcs: &str, url: &str, path: &Path) -> Result<()> {
+    let (name, tool, subcommand) = match vcs {
+        "git" => ("Git", "git", "clone"),
+        "hg" => ("Mercurial (hg)", "hg", "clone"),
+        "svn" => ("Subversion (svn)", "svn", "checkout"),
+        "bzr" => ("Bazaar (bzr)", "bzr", "branch"),
+        other => anyhow::bail!("Unknown VCS '{}' (expected git, hg, svn or bzr)", other),
+    };
+    println!("{}", format!("Cloning {} repository from {}...", name, url).blue().bold());
+    if vcs == "git" {
+        GitRepository::clone(url, path)
+            .map_err(|e| anyhow::anyhow!("Failed to clone Git repository: {}", e))?;
+    } else {
+        let status = Command::new(tool).arg(subcommand).arg(url).arg(path).status();
+        if !matches!(status, Ok(s) if s.success()) {
+            anyhow::bail!("Failed to clone {} repository. Is '{}' installed?", name, tool);
         }
     }
+    println!("{}", format!("{} repository cloned successfully!", name).green().bold());
+    println!("Location: {}", path.display().to_string().cyan());
+    println!("Source: {}", url.magenta());
+    Ok(())
+}
+
+/// Clone a Helix repository over whichever transport `url` names (HTTP,
+/// SSH or a local path), or with an external VCS when asked to.
+pub async fn clone_repository(url: &str, path: &Path, options: &CloneOptions) -> Result<()> {
+    if let Some(vcs) = &options.vcs {
+        return clone_external(vcs, url, path);
+    }
+    let mut transport = Transport::from_url(url)?;
+    clone_from(&mut transport, url, path).await
+}
+
+/// Check the remote is reachable and learn what it supports, so downloads
+/// can be batched where the server allows it.
+async fn connect<T: RemoteTransport>(transport: &mut T) -> Result<()> {
+    if !transport.check_connectivity().await? {
+        anyhow::bail!("cannot connect");
+    }
+    // Older servers without /info/refs just get per-object requests
+    let _ = transport.discover_capabilities().await;
+    Ok(())
+}
 
-    // Default: try Helix
+/// Clone the `main` branch of the remote behind `transport` into `path`;
+/// `url` is only reported.
+pub async fn clone_from<T: RemoteTransport>(transport: &mut T, url: &str, path: &Path) -> Result<()> {
     let pb = ProgressBar::new(5);
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -99,56 +82,85 @@ pub async fn clone_repository(url: &str, path: &Path) -> Result<()> {
     pb.inc(1);
 
     pb.set_message("Connecting to remote...");
-    let client = RemoteClient::new(url);
-    let head = match client.get_ref("main").await {
+    let head = match connect(transport).await {
+        Ok(()) => transport.get_ref("main").await,
+        Err(e) => Err(e),
+    };
+    let head = match head {
         Ok(h) => h,
-        Err(_) => {
-            pb.finish_with_message("Failed: Only Helix remote repositories are supported. This is not a Helix remote.");
-            return Err(anyhow::anyhow!("Remote is not a valid Helix repository or is unreachable. Only Helix remotes are supported (not Git, hg, svn, bzr)."));
+        Err(e) => {
+            pb.finish_with_message("Failed: not a Helix remote.");
+            return Err(anyhow::anyhow!(
+                "Remote is not a valid Helix repository or is unreachable ({}). Use --vcs to clone from git, hg, svn or bzr.",
+                e
+            ));
         }
     };
     pb.inc(1);
 
     pb.set_message("Fetching objects...");
     let objects_dir = path.join(".helix/objects");
+    fs::create_dir_all(&objects_dir)?;
+    let mut progress = FetchProgress::load(&repo.git_dir)?;
+    if progress.recorded() > 0 {
+        println!(
+            "{}",
+            format!(
+                "Resuming interrupted clone ({} objects already fetched)",
+                progress.recorded()
+            )
+            .yellow()
+        );
+    }
     let mut to_download = vec![head.clone()];
     let mut seen = std::collections::HashSet::new();
-    while let Some(hash) = to_download.pop() {
-        if seen.contains(&hash) {
-            continue;
+    while !to_download.is_empty() {
+        // Fetch the whole frontier in batches, then queue what it references
+        let frontier: Vec<String> = to_download
+            .drain(..)
+            .filter(|hash| seen.insert(hash.clone()))
+            .collect();
+        // Objects left by an interrupted clone are read back, not refetched
+        let missing: Vec<String> = frontier
+            .iter()
+            .filter(|hash| !progress.has(&repo, hash))
+            .cloned()
+            .collect();
+        for batch in missing.chunks(OBJECT_BATCH_SIZE) {
+            for (hash, data) in transport.download_objects(batch).await? {
+                write_object(&objects_dir, &hash, &data)?;
+                progress.record(&hash)?;
+            }
         }
-        seen.insert(hash.clone());
-        let data = client.download_object(&hash).await?;
-        let (dir, file) = hash.split_at(2);
-        let dir_path = objects_dir.join(dir);
-        fs::create_dir_all(&dir_path)?;
-        let file_path = dir_path.join(file);
-        fs::write(&file_path, &data)?;
-        // If commit or tree, queue referenced objects
-        let obj: Object = serde_json::from_slice(&data).unwrap_or_else(|_| Object::new("blob".to_string(), String::new()));
-        if obj.is_commit() {
-            let commit: crate::core::commit::Commit = serde_json::from_str(&obj.data)?;
-            to_download.extend(commit.parent_ids.clone());
-            to_download.push(commit.tree_id.clone());
-        } else if obj.is_tree() {
-            let tree: crate::core::object::Tree = serde_json::from_str(&obj.data)?;
-            for entry in tree.entries {
-                to_download.push(entry.object_id);
+
+        for hash in frontier {
+            let (dir, file) = hash.split_at(2);
+            let data = match fs::read(objects_dir.join(dir).join(file)) {
+                Ok(data) => data,
+                Err(_) => anyhow::bail!("Remote did not send object {}", hash),
+            };
+            // If commit or tree, queue referenced objects
+            let obj = Object::from_compressed_checked(&data, &hash)?;
+            if obj.is_commit() {
+                let commit: crate::core::commit::Commit = serde_json::from_slice(&obj.data)?;
+                to_download.extend(commit.parent_ids.clone());
+                to_download.push(commit.tree_id.clone());
+            } else if obj.is_tree() {
+                let tree: crate::core::object::Tree = serde_json::from_slice(&obj.data)?;
+                for entry in tree.entries {
+                    to_download.push(entry.object_id);
+                }
             }
         }
     }
     pb.inc(1);
 
     pb.set_message("Setting up repository...");
-    let hx_dir = path.join(".helix");
-    fs::create_dir_all(&hx_dir)?;
-    fs::write(hx_dir.join("HEAD"), "main")?;
-    fs::write(
-        hx_dir.join("branches.json"),
-        serde_json::to_string_pretty(
-            &serde_json::json!({"main": {"name": "main", "head_commit": head, "upstream": null, "created_at": chrono::Utc::now(), "last_updated": chrono::Utc::now()}}),
-        )?,
-    )?;
+    let mut main = Branch::new("main");
+    main.update_head(head);
+    repo.branches.insert("main".to_string(), main);
+    repo.save()?;
+    progress.finish()?;
     pb.inc(1);
 
     pb.set_message("Checking out files...");
@@ -162,7 +174,11 @@ pub async fn clone_repository(url: &str, path: &Path) -> Result<()> {
             for entry in tree.entries {
                 if entry.object_type == "blob" {
                     let blob = Object::load(&repo.get_objects_dir(), &entry.object_id)?;
-                    fs::write(path.join(&entry.name), &blob.data)?;
+                    let file_path = path.join(&entry.name);
+                    if let Some(parent) = file_path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(file_path, &blob.data)?;
                 }
             }
         }
@@ -174,3 +190,57 @@ pub async fn clone_repository(url: &str, path: &Path) -> Result<()> {
     println!("Current branch: {}", "main".yellow().bold());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+    use crate::utils::transport::MockRemote;
+
+    #[tokio::test]
+    async fn clones_over_the_file_backend() {
+        let mut source = TestRepo::new();
+        source.write("a.txt", "a\n");
+        source.write("dir/b.txt", "b\n");
+        source.commit("first").await;
+        source.write("a.txt", "a2\n");
+        let head = source.commit("second").await;
+
+        let dest = tempfile::tempdir().unwrap();
+        let path = dest.path().join("copy");
+        let url = format!("file://{}", source.repo.path.display());
+        clone_repository(&url, &path, &CloneOptions::default()).await.unwrap();
+
+        let clone = Repository::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(clone.resolve_rev("main").unwrap(), head);
+        let first = clone.get_commit_object(&head).unwrap().parent_ids[0].clone();
+        assert_eq!(clone.get_commit_object(&first).unwrap().message, "first");
+        assert_eq!(fs::read_to_string(path.join("a.txt")).unwrap(), "a2\n");
+        assert_eq!(fs::read_to_string(path.join("dir/b.txt")).unwrap(), "b\n");
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_clone_resumes_without_refetching() {
+        let mut source = TestRepo::new();
+        for i in 0..3 {
+            source.write("a.txt", &format!("{}\n", i));
+            source.commit(&format!("commit {}", i)).await;
+        }
+        let dest = tempfile::tempdir().unwrap();
+        let path = dest.path().join("copy");
+
+        let mut dropped = MockRemote { fail_after: Some(3), ..MockRemote::from_repo(&source.repo) };
+        assert!(clone_from(&mut dropped, "mock://source", &path).await.is_err());
+        let fetched = dropped.downloads.lock().unwrap().clone();
+        assert_eq!(fetched.len(), 3);
+        assert_eq!(FetchProgress::load(&path.join(".helix")).unwrap().recorded(), 3);
+
+        let mut remote = MockRemote::from_repo(&source.repo);
+        clone_from(&mut remote, "mock://source", &path).await.unwrap();
+        let refetched = remote.downloads.lock().unwrap().clone();
+        assert!(refetched.iter().all(|id| !fetched.contains(id)), "{:?} fetched twice", refetched);
+        assert_eq!(fetched.len() + refetched.len(), remote.objects.lock().unwrap().len());
+        assert_eq!(fs::read_to_string(path.join("a.txt")).unwrap(), "2\n");
+        assert!(!FetchProgress::path(&path.join(".helix")).exists());
+    }
+}
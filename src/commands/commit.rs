@@ -1,21 +1,124 @@
-use crate::core::{commit::Commit, object::Tree, repository::Repository};
+use crate::core::submodule::GITLINK_MODE;
+use crate::core::index::{Index, IndexEntry, IndexLock};
+use crate::core::object::Object;
+use crate::core::{commit::{ChangeType, Commit, FileChange}, object::Tree, rebase, repository::Repository};
+use std::collections::HashMap;
+use std::io::BufRead;
 use anyhow::Result;
 use colored::*;
 use ed25519_dalek::SigningKey;
 use indicatif::{ProgressBar, ProgressStyle};
-use crate::utils::config::GlobalConfig;
+use crate::utils::date_utils;
+use crate::utils::hunk_select::HunkSelector;
+use crate::utils::{eol, file_utils};
+
+/// Message for a `--fixup`/`--squash` commit: `<prefix>! <target subject>`,
+/// followed by `body` when given.
+pub fn autosquash_message(
+    repo: &Repository,
+    prefix: &str,
+    rev: &str,
+    body: Option<&str>,
+) -> Result<String> {
+    let target = repo.get_commit_object(&repo.resolve_rev(rev)?)?;
+    let subject = rebase::original_subject(target.message.lines().next().unwrap_or(""));
+    let mut message = format!("{}! {}", prefix, subject);
+    if let Some(body) = body {
+        message.push_str("\n\n");
+        message.push_str(body);
+    }
+    Ok(message)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions {
+    /// Author date override (RFC3339)
+    pub date: Option<String>,
+    /// Replace the HEAD commit instead of adding a new one
+    pub amend: bool,
+    /// When amending, take authorship from the current identity
+    pub reset_author: bool,
+    /// Author as "Name <email>" instead of the current identity, which is
+    /// then recorded as the committer
+    pub author: Option<String>,
+    /// Record the commit even if its tree is the same as its parent's
+    pub allow_empty: bool,
+}
+
+/// Split "Name <email>" into its parts.
+pub fn parse_identity(identity: &str) -> Result<(String, String)> {
+    let (name, rest) = identity
+        .split_once('<')
+        .ok_or_else(|| anyhow::anyhow!("Expected 'Name <email>', got '{}'", identity))?;
+    let email = rest
+        .strip_suffix('>')
+        .ok_or_else(|| anyhow::anyhow!("Expected 'Name <email>', got '{}'", identity))?;
+    Ok((name.trim().to_string(), email.trim().to_string()))
+}
+
+/// Message of the HEAD commit, for `--amend --no-edit`.
+pub fn head_message(repo: &Repository) -> Result<String> {
+    let head = repo
+        .head_commit()
+        .ok_or_else(|| anyhow::anyhow!("No commit to amend"))?;
+    Ok(repo.get_commit_object(head)?.message)
+}
+
+/// Save the tree recording `files` (deleted entries left out) and return
+/// its id.
+pub fn write_tree(repo: &Repository, files: &HashMap<String, FileChange>) -> Result<String> {
+    let mut tree = Tree::new();
+    let mut paths: Vec<&String> = files.keys().collect();
+    paths.sort();
+    for path in paths {
+        let change = &files[path];
+        if matches!(change.change_type, ChangeType::Deleted) {
+            continue;
+        }
+        let object_type = if change.mode == GITLINK_MODE { "commit" } else { "blob" };
+        tree.add_entry(
+            change.path.clone(),
+            change.content_hash.clone(),
+            object_type.to_string(),
+            change.mode,
+        );
+    }
+    let tree_object = tree.to_object();
+    tree_object.save(&repo.get_objects_dir())?;
+    Ok(tree_object.id)
+}
 
 pub async fn commit_changes(
     repo: &mut Repository,
     message: &str,
+    options: &CommitOptions,
     keypair: &SigningKey,
 ) -> Result<()> {
-    if repo.index.is_empty() {
+    let amended = if options.amend {
+        let head = repo
+            .head_commit()
+            .ok_or_else(|| anyhow::anyhow!("No commit to amend"))?;
+        Some(repo.get_commit_object(head)?)
+    } else {
+        None
+    };
+
+    if repo.index.has_conflicts() {
+        println!("{}", "Cannot commit with unresolved conflicts:".red());
+        for path in repo.index.conflicts.keys() {
+            println!("  {}", path.red().bold());
+        }
+        println!("Use 'hx checkout --ours/--theirs <path>' or 'hx add <path>' to resolve them");
+        return Ok(());
+    }
+
+    if repo.index.is_empty() && amended.is_none() && !options.allow_empty {
         println!("{}", "No changes to commit".yellow());
         println!("Use 'hx add' to stage files first");
         return Ok(());
     }
 
+    let _lock = IndexLock::acquire(&repo.git_dir)?;
     let pb = ProgressBar::new(3);
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -25,55 +128,124 @@ pub async fn commit_changes(
 
     pb.set_message("Creating commit object...");
 
-    // Get parent commit ID
-    let parent_ids = if let Some(current_branch) = repo.get_current_branch() {
-        if let Some(head_commit) = current_branch.get_head_commit() {
-            vec![head_commit.clone()]
-        } else {
-            vec![]
-        }
+    // Get parent commit ID; an amended commit keeps its parents
+    let mut parent_ids = if let Some(amended) = &amended {
+        amended.parent_ids.clone()
+    } else if let Some(head_commit) = repo.head_commit() {
+        vec![head_commit.clone()]
     } else {
         vec![]
     };
-
-    // Create tree object from staged files (use blob hashes from index)
-    let mut tree = Tree::new();
-    for entry in repo.index.get_all_files() {
-        tree.add_entry(
-            entry.path.clone(),
-            entry.content_hash.clone(), // This is now the blob hash
-            "blob".to_string(),
-            entry.mode,
-        );
+    // Concluding a conflicted merge records the merged-in commit as a parent
+    let merge_head = repo.get_merge_head();
+    if let Some(merge_head) = &merge_head {
+        parent_ids.push(merge_head.clone());
     }
-    let tree_object = tree.to_object();
-    tree_object.save(&repo.get_objects_dir())?;
-    let tree_id = tree_object.id.clone();
 
-    // Load global config for fallback
-    let global_config = GlobalConfig::load().ok();
-    let author = if repo.config.author == "Unknown" || repo.config.author == "" {
-        global_config.as_ref().and_then(|c| c.get_user_name()).unwrap_or("Unknown").to_string()
-    } else {
-        repo.config.author.clone()
+    // Commits are full snapshots: the parent's files (or the amended
+    // commit's) with the staged changes on top. Deletions the parent
+    // recorded are not carried forward.
+    let mut files: HashMap<String, FileChange> = match &amended {
+        Some(amended) => amended.files.clone(),
+        None => parent_ids
+            .first()
+            .and_then(|id| repo.get_commit_object(id).ok())
+            .map(|parent| {
+                parent
+                    .files
+                    .into_iter()
+                    .filter(|(_, fc)| !fc.is_deleted())
+                    .collect()
+            })
+            .unwrap_or_default(),
     };
-    let email = if repo.config.email == "unknown@example.com" || repo.config.email == "" {
-        global_config.as_ref().and_then(|c| c.get_user_email()).unwrap_or("unknown@example.com").to_string()
-    } else {
-        repo.config.email.clone()
+    // What the staged changes are relative to: the first parent's tree
+    let first_parent = match &amended {
+        Some(amended) => amended.parent_ids.first(),
+        None => parent_ids.first(),
+    };
+    let previous = first_parent.and_then(|id| repo.get_commit_object(id).ok());
+    let previous_tree = previous
+        .as_ref()
+        .map(|parent| parent.tree_files())
+        .unwrap_or_default();
+    for (path, mut change) in repo.index.to_file_changes() {
+        match previous_tree.get(&path) {
+            Some(old_hash) if change.is_deleted() => change.content_hash = old_hash.clone(),
+            Some(_) => change.change_type = ChangeType::Modified,
+            // Nothing to delete relative to the parent
+            None if change.is_deleted() => {
+                files.remove(&path);
+                continue;
+            }
+            None => {}
+        }
+        files.insert(path, change);
+    }
+
+    // Create tree object from the commit's files (blob hashes from the index)
+    let tree_id = write_tree(repo, &files)?;
+
+    // Staged content that matches the parent would record nothing; a
+    // merge still records the join, and rewording an already empty
+    // commit keeps it as it was
+    let unchanged = previous.as_ref().is_some_and(|parent| parent.tree_id == tree_id)
+        && amended.as_ref().is_none_or(|amended| amended.tree_id != tree_id);
+    if unchanged && merge_head.is_none() && !options.allow_empty {
+        pb.finish_and_clear();
+        anyhow::bail!("nothing to commit, the tree is unchanged (use --allow-empty to commit anyway)");
+    }
+
+    let (author, email) = repo.identity();
+
+    // Amending keeps the original authorship and author date unless asked
+    // otherwise; the current identity is recorded as the committer instead
+    let (author, email, timestamp, committer) = match &amended {
+        Some(amended) if !options.reset_author && options.author.is_none() => (
+            amended.author.clone(),
+            amended.email.clone(),
+            match &options.date {
+                Some(date) => date_utils::resolve_commit_date(Some(date))?,
+                None => amended.timestamp,
+            },
+            Some(format!("{} <{}>", author, email)),
+        ),
+        _ => match &options.author {
+            Some(identity) => {
+                let (name, address) = parse_identity(identity)?;
+                (
+                    name,
+                    address,
+                    date_utils::resolve_commit_date(options.date.as_deref())?,
+                    Some(format!("{} <{}>", author, email)),
+                )
+            }
+            None => (
+                author,
+                email,
+                date_utils::resolve_commit_date(options.date.as_deref())?,
+                None,
+            ),
+        },
     };
 
     // Create commit and sign it
-    let commit = Commit::new(
+    let mut commit = Commit::new_at(
         parent_ids,
         tree_id,
         author.clone(),
         email.clone(),
         message.to_string(),
-        repo.index.to_file_changes(),
+        files,
+        timestamp,
         Some(keypair),
     );
-    // commit.sign(keypair); // Already signed in new()
+    if committer.is_some() {
+        commit.committer = committer;
+        commit.committed_at = Some(date_utils::resolve_commit_date(None)?);
+        // The signature has to cover the committer too
+        commit.sign(keypair);
+    }
 
     pb.inc(1);
     pb.set_message("Saving commit object...");
@@ -94,13 +266,21 @@ pub async fn commit_changes(
     pb.set_message("Updating branch...");
 
     // Update current branch
-    if let Some(current_branch) = repo.get_current_branch_mut() {
-        current_branch.update_head(commit_object.id.clone());
-    }
+    let old_head = repo.head_commit().cloned();
+    repo.set_head(&commit_object.id)?;
+    let reflog_action = if amended.is_some() { "commit (amend)" } else { "commit" };
+    repo.log_ref_update(
+        old_head.as_deref(),
+        &commit_object.id,
+        &format!("{}: {}", reflog_action, message),
+    )?;
 
     // Clear index after successful commit
     repo.index.clear();
     repo.save()?;
+    if merge_head.is_some() {
+        repo.set_merge_head(None)?;
+    }
 
     pb.finish_with_message("Commit created successfully!");
 
@@ -116,11 +296,280 @@ pub async fn commit_changes(
             .to_string()
             .yellow()
     );
+    let new_tree = commit.tree_files();
+    let changed = previous_tree
+        .keys()
+        .chain(new_tree.keys())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .filter(|path| previous_tree.get(*path) != new_tree.get(*path))
+        .count();
     println!(
         "Files: {} files changed",
-        commit.files.len().to_string().magenta()
+        changed.to_string().magenta()
     );
     println!("Branch: {}", repo.current_branch.yellow().bold());
 
     Ok(())
 }
+
+/// `commit --patch`: pick hunks of the working tree's changes to tracked
+/// files and commit exactly those, bypassing (and keeping) the index. The
+/// unselected changes stay in the working tree.
+pub async fn commit_patch(
+    repo: &mut Repository,
+    message: &str,
+    options: &CommitOptions,
+    keypair: &SigningKey,
+) -> Result<()> {
+    let stdin = std::io::stdin();
+    commit_patch_from(repo, message, options, keypair, stdin.lock()).await
+}
+
+/// [`commit_patch`], reading the answers to its prompts from `input`.
+async fn commit_patch_from<R: BufRead>(
+    repo: &mut Repository,
+    message: &str,
+    options: &CommitOptions,
+    keypair: &SigningKey,
+    input: R,
+) -> Result<()> {
+    if repo.index.has_conflicts() {
+        anyhow::bail!("Cannot commit with unresolved conflicts; resolve them first");
+    }
+    let head = repo
+        .head_commit()
+        .ok_or_else(|| anyhow::anyhow!("No commits yet; use 'hx add' and 'hx commit' first"))?;
+    let head = repo.get_commit_object(head)?;
+    let objects_dir = repo.get_objects_dir();
+
+    let mut selector = HunkSelector::new(input, "Commit");
+    let mut selection = Index::new();
+    for (path, blob) in head.tree_files() {
+        if selector.quit() {
+            break;
+        }
+        let file_path = repo.path.join(&path);
+        if !file_path.exists() {
+            if selector.confirm(&format!("Commit deletion of {}", path))? {
+                selection.stage_deletion(&path);
+            }
+            continue;
+        }
+        let Ok(content) = file_utils::read_file_content(&file_path) else {
+            continue;
+        };
+        let content = eol::to_repo(repo, &path, content);
+        let old = Object::load(&objects_dir, &blob)?;
+        if content.contains(&0) {
+            if old.data != content {
+                println!("{}", format!("Skipping binary file {}", path).yellow());
+            }
+            continue;
+        }
+        let (old, new) = (old.text(), String::from_utf8_lossy(&content));
+        if old == new {
+            continue;
+        }
+        let Some(selected) = selector.select(&path, &old, &new)? else {
+            continue;
+        };
+        let blob_object = Object::new("blob".to_string(), selected);
+        blob_object.save(&objects_dir)?;
+        selection.add_file(
+            &path,
+            IndexEntry {
+                path: path.clone(),
+                content_hash: blob_object.id.clone(),
+                size: blob_object.data.len() as u64,
+                mode: head.files.get(&path).map(|fc| fc.mode).unwrap_or(0o100644),
+                timestamp: chrono::Utc::now(),
+                stage: 0,
+            },
+        );
+    }
+
+    if selection.is_empty() {
+        println!("{}", "No changes selected".yellow());
+        return Ok(());
+    }
+    // Commit from the selection alone, then put back what was staged
+    let staged = std::mem::replace(&mut repo.index, selection);
+    let result = commit_changes(repo, message, options, keypair).await;
+    repo.index = staged;
+    repo.save()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    fn dated(date: &str) -> CommitOptions {
+        CommitOptions { date: Some(date.to_string()), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn same_date_and_content_give_the_same_id() {
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let mut test = TestRepo::new();
+            test.write("a.txt", "a\n");
+            let root = test.commit_with("root", &dated("2024-01-31T12:00:00Z")).await.unwrap();
+            test.write("a.txt", "b\n");
+            let second = test.commit_with("second", &dated("2024-02-01T12:00:00Z")).await.unwrap();
+            ids.push((root, second));
+        }
+        assert_eq!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn a_different_date_changes_the_id() {
+        let mut heads = Vec::new();
+        for date in ["2024-01-31T12:00:00Z", "2024-01-31T12:00:01Z"] {
+            let mut test = TestRepo::new();
+            test.write("a.txt", "a\n");
+            heads.push(test.commit_with("root", &dated(date)).await.unwrap());
+        }
+        assert_ne!(heads[0], heads[1]);
+    }
+
+    #[tokio::test]
+    async fn amend_no_edit_keeps_message_and_author() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let original = test.commit_with("keep me", &dated("2024-01-31T12:00:00Z")).await.unwrap();
+        let before = test.repo.get_commit_object(&original).unwrap();
+
+        test.repo.config.author = "Other".to_string();
+        test.repo.config.email = "other@example.com".to_string();
+        test.write("b.txt", "forgotten\n");
+        let message = head_message(&test.repo).unwrap();
+        let amend = CommitOptions { amend: true, ..Default::default() };
+        let amended = test.commit_with(&message, &amend).await.unwrap();
+        let after = test.repo.get_commit_object(&amended).unwrap();
+
+        assert_ne!(amended, original);
+        assert_eq!(after.message, "keep me");
+        assert_eq!((after.author.as_str(), after.email.as_str()), ("Test", "test@example.com"));
+        assert_eq!(after.timestamp, before.timestamp);
+        assert_eq!(after.committer.as_deref(), Some("Other <other@example.com>"));
+        assert_eq!(after.parent_ids, before.parent_ids);
+        assert_ne!(after.tree_id, before.tree_id);
+        assert!(test.repo.tree_files_of(&after.tree_id).unwrap().contains_key("b.txt"));
+
+        let reset = CommitOptions { amend: true, reset_author: true, ..Default::default() };
+        let reset_id = test.commit_with(&message, &reset).await.unwrap();
+        let reset = test.repo.get_commit_object(&reset_id).unwrap();
+        assert_eq!(reset.author, "Other");
+        assert_eq!(reset.committer, None);
+    }
+
+    #[tokio::test]
+    async fn patch_commits_only_the_selected_hunk() {
+        let mut test = TestRepo::new();
+        let lines: Vec<String> = (1..=20).map(|n| format!("line {}\n", n)).collect();
+        test.write("a.txt", &lines.concat());
+        test.commit("root").await;
+
+        let mut edited = lines.clone();
+        edited[1] = "line 2 changed\n".to_string();
+        edited[18] = "line 19 changed\n".to_string();
+        test.write("a.txt", &edited.concat());
+        let keypair = crate::utils::test_utils::keypair();
+        let options = CommitOptions::default();
+        commit_patch_from(&mut test.repo, "first hunk", &options, &keypair, "y\nn\n".as_bytes())
+            .await
+            .unwrap();
+
+        let head = test.repo.get_commit_object(&test.head()).unwrap();
+        assert_eq!(head.message, "first hunk");
+        let blob = head.tree_files()["a.txt"].clone();
+        let committed = Object::load(&test.repo.get_objects_dir(), &blob).unwrap().text();
+        let mut expected = lines.clone();
+        expected[1] = "line 2 changed\n".to_string();
+        assert_eq!(committed, expected.concat());
+        // The other hunk is left in the working tree
+        assert_eq!(test.read("a.txt"), edited.concat());
+    }
+
+    #[tokio::test]
+    async fn amending_the_message_keeps_the_parent_chain() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let root = test.commit("root").await;
+        test.write("a.txt", "b\n");
+        let original = test.commit("tpyo").await;
+        let before = test.repo.get_commit_object(&original).unwrap();
+
+        let amend = CommitOptions { amend: true, ..Default::default() };
+        let amended = test.commit_with("typo", &amend).await.unwrap();
+        let after = test.repo.get_commit_object(&amended).unwrap();
+
+        assert_ne!(amended, original);
+        assert_eq!(test.head(), amended);
+        assert_eq!(after.message, "typo");
+        assert_eq!(after.parent_ids, vec![root]);
+        assert_eq!(after.tree_id, before.tree_id);
+        assert!(after.verify());
+    }
+
+    #[tokio::test]
+    async fn amend_needs_a_commit() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let amend = CommitOptions { amend: true, ..Default::default() };
+        assert!(test.commit_with("first", &amend).await.is_err());
+        assert!(test.repo.head_commit().is_none());
+    }
+
+    #[tokio::test]
+    async fn a_no_op_commit_is_refused_unless_allowed() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let first = test.commit("first").await;
+
+        // Edited and then restored: the tree is HEAD's again
+        test.write("a.txt", "changed\n");
+        test.add_all().await;
+        test.write("a.txt", "a\n");
+        let refused = test.commit_with("nothing", &CommitOptions::default()).await;
+        assert!(refused.unwrap_err().to_string().contains("nothing to commit"));
+        assert_eq!(test.head(), first);
+
+        let allow = CommitOptions { allow_empty: true, ..Default::default() };
+        let marker = test.commit_with("marker", &allow).await.unwrap();
+        let commit = test.repo.get_commit_object(&marker).unwrap();
+        assert_ne!(marker, first);
+        assert_eq!(commit.parent_ids, vec![first.clone()]);
+        assert_eq!(commit.tree_id, test.repo.get_commit_object(&first).unwrap().tree_id);
+    }
+
+    #[tokio::test]
+    async fn author_and_date_overrides_are_stored() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let options = CommitOptions {
+            author: Some("Ada Lovelace <ada@example.com>".to_string()),
+            ..dated("2001-02-03T04:05:06Z")
+        };
+        let id = test.commit_with("imported", &options).await.unwrap();
+        let commit = test.repo.get_commit_object(&id).unwrap();
+
+        assert_eq!((commit.author.as_str(), commit.email.as_str()), ("Ada Lovelace", "ada@example.com"));
+        assert_eq!(commit.timestamp.to_rfc3339(), "2001-02-03T04:05:06+00:00");
+        assert_eq!(commit.committer.as_deref(), Some("Test <test@example.com>"));
+        assert!(commit.verify());
+    }
+
+    #[tokio::test]
+    async fn malformed_overrides_are_rejected() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        assert!(test.commit_with("bad date", &dated("yesterday-ish")).await.is_err());
+        let no_email = CommitOptions { author: Some("Ada".to_string()), ..Default::default() };
+        assert!(test.commit_with("bad author", &no_email).await.is_err());
+        assert!(test.repo.head_commit().is_none());
+    }
+}
@@ -0,0 +1,15 @@
+use crate::core::commit_graph::CommitGraph;
+use crate::core::repository::Repository;
+use anyhow::Result;
+use colored::*;
+
+/// Rebuild `.helix/commit-graph` from every branch and tag.
+pub async fn write_commit_graph(repo: &Repository) -> Result<()> {
+    let count = CommitGraph::build(repo).save(&repo.git_dir)?;
+    println!(
+        "{} {} commits",
+        "Wrote commit graph:".green().bold(),
+        count.to_string().cyan()
+    );
+    Ok(())
+}
@@ -1,48 +1,70 @@
+use crate::core::commit_graph::find_merge_base;
+use crate::core::commit::Commit;
+use crate::core::object::{Object, Tree};
 use crate::core::repository::Repository;
+use crate::core::tree_diff::{DiffStatus, FileDiff, TreeDiffOptions};
+use crate::utils::diff_utils;
 use anyhow::Result;
 use colored::*;
 use similar::{ChangeTag, TextDiff};
+use std::collections::{BTreeSet, HashSet};
 use std::fs;
 use std::path::Path;
 
-pub async fn show_diff(repo: &Repository, path: Option<&Path>) -> Result<()> {
-    println!("{}", "Diff View".bold().blue());
-    println!("{}", "=".repeat(40).blue());
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    pub find_copies: bool,
+    /// Similarity percent for pairing a deleted and an added file as a
+    /// rename (default: `DEFAULT_RENAME_THRESHOLD`)
+    pub rename_threshold: Option<u8>,
+    /// Print nothing; only report whether there are differences
+    pub quiet: bool,
+    /// Color moved blocks of lines differently from additions/removals
+    pub color_moved: bool,
+    /// Lines of context around each change (default: `DEFAULT_CONTEXT`)
+    pub context: Option<usize>,
+}
+
+/// Context lines shown around each change unless `-U<n>` says otherwise.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// Diff the working tree against HEAD. Returns whether any difference was found.
+pub async fn show_diff(repo: &Repository, path: Option<&Path>, options: &DiffOptions) -> Result<bool> {
+    if !options.quiet {
+        println!("{}", "Diff View".bold().blue());
+        println!("{}", "=".repeat(40).blue());
+    }
 
     // Helper to get last committed content for a file
     fn get_last_commit_content(repo: &Repository, file_path: &Path) -> Option<String> {
-        let branch = repo.get_current_branch()?;
-        let head_commit = branch.get_head_commit()?;
+        let head_commit = repo.head_commit()?;
         let commit = repo.get_commit_object(head_commit).ok()?;
         let file_change = commit.get_file_change(file_path.to_str()?)?;
         let blob_hash = &file_change.content_hash;
         let blob_obj =
             crate::core::object::Object::load(&repo.get_objects_dir(), blob_hash).ok()?;
-        Some(blob_obj.data)
+        Some(blob_obj.text())
     }
 
     let files_to_diff: Vec<std::path::PathBuf> = if let Some(file_path) = path {
         vec![file_path.to_path_buf()]
     } else {
-        let branch = match repo.get_current_branch() {
-            Some(b) => b,
-            None => {
-                println!("{}", "No current branch found".red());
-                return Ok(());
-            }
-        };
-        let head_commit = match branch.get_head_commit() {
+        let head_commit = match repo.head_commit() {
             Some(h) => h,
             None => {
-                println!("{}", "No HEAD commit found".red());
-                return Ok(());
+                if !options.quiet {
+                    println!("{}", "No HEAD commit found".red());
+                }
+                return Ok(false);
             }
         };
         let commit = match repo.get_commit_object(head_commit) {
             Ok(obj) => obj,
             Err(_) => {
-                println!("{}", "Failed to load HEAD commit object".red());
-                return Ok(());
+                if !options.quiet {
+                    println!("{}", "Failed to load HEAD commit object".red());
+                }
+                return Ok(false);
             }
         };
         commit
@@ -54,32 +76,623 @@ pub async fn show_diff(repo: &Repository, path: Option<&Path>) -> Result<()> {
 
     let mut any_diff = false;
     for file_path in files_to_diff {
-        let wd_content = fs::read_to_string(&file_path).unwrap_or_else(|_| String::new());
+        let wd_content =
+            fs::read_to_string(repo.path.join(&file_path)).unwrap_or_else(|_| String::new());
         let last_commit_content =
             get_last_commit_content(repo, &file_path).unwrap_or_else(|| String::new());
         if wd_content == last_commit_content {
             continue;
         }
         any_diff = true;
+        if options.quiet {
+            return Ok(true);
+        }
         println!("\nFile: {}", file_path.display().to_string().cyan());
-        let diff = TextDiff::from_lines(&last_commit_content, &wd_content);
-        for change in diff.iter_all_changes() {
+        print_text_diff_with(&last_commit_content, &wd_content, options);
+    }
+    if !any_diff && !options.quiet {
+        println!("\n{}", "No differences found".green());
+        println!("Working directory is clean");
+    }
+    Ok(any_diff)
+}
+
+/// Diff two commits, following renames (and copies with `find_copies`)
+/// so that a moved file shows only its content delta. Returns whether any
+/// difference was found.
+pub async fn show_commit_diff(
+    repo: &Repository,
+    from: &str,
+    to: &str,
+    options: &DiffOptions,
+) -> Result<bool> {
+    if !options.quiet {
+        println!("{}", "Diff View".bold().blue());
+        println!("{}", "=".repeat(40).blue());
+    }
+
+    let diffs = repo.diff_trees_with(
+        from,
+        to,
+        &TreeDiffOptions {
+            find_renames: true,
+            find_copies: options.find_copies,
+            rename_threshold: options.rename_threshold,
+        },
+    )?;
+    let any_diff = !diffs.is_empty();
+    if options.quiet {
+        return Ok(any_diff);
+    }
+    print!("{}", render_tree_diff(repo, &diffs, options));
+
+    if !any_diff {
+        println!("\n{}", "No differences found".green());
+    }
+    Ok(any_diff)
+}
+
+/// The per-file output of [`show_commit_diff`] for `diffs`.
+fn render_tree_diff(repo: &Repository, diffs: &[FileDiff], options: &DiffOptions) -> String {
+    let mut out = String::new();
+    for file_diff in diffs {
+        let header = match &file_diff.status {
+            DiffStatus::Renamed { old_path, similarity } => Some(format!(
+                "rename {} -> {} ({}%)",
+                old_path, file_diff.path, similarity
+            )),
+            DiffStatus::Copied { old_path, similarity } => Some(format!(
+                "copy {} -> {} ({}%)",
+                old_path, file_diff.path, similarity
+            )),
+            DiffStatus::Added => Some("new file".to_string()),
+            DiffStatus::Deleted => Some("deleted file".to_string()),
+            DiffStatus::Modified => None,
+        };
+        let old_content = load_blob(repo, file_diff.old_hash.as_ref());
+        let new_content = load_blob(repo, file_diff.new_hash.as_ref());
+        out.push_str(&format!("\nFile: {}\n", file_diff.path.cyan()));
+        if let Some(header) = header {
+            out.push_str(&format!("{}\n", header.magenta()));
+        }
+        if old_content != new_content {
+            out.push_str(&render_text_diff(&old_content, &new_content, options));
+        }
+    }
+    out
+}
+
+/// Diff a commit against the working tree: every path the commit, HEAD or
+/// the index knows about whose working copy no longer matches the commit.
+/// Untracked files are left out. Returns whether any difference was found.
+pub async fn show_worktree_diff(repo: &Repository, rev: &str, options: &DiffOptions) -> Result<bool> {
+    if !options.quiet {
+        println!("{}", "Diff View".bold().blue());
+        println!("{}", "=".repeat(40).blue());
+    }
+
+    let commit_id = repo.resolve_rev(rev)?;
+    let commit_files = repo.get_commit_object(&commit_id)?.tree_files();
+    let mut paths: BTreeSet<String> = commit_files.keys().cloned().collect();
+    paths.extend(repo.head_files().into_keys());
+    paths.extend(repo.index.get_file_paths());
+
+    let mut any_diff = false;
+    for path in &paths {
+        let old_hash = commit_files.get(path);
+        let new_hash = repo.worktree_blob_id(path);
+        if old_hash == new_hash.as_ref() {
+            continue;
+        }
+        any_diff = true;
+        if options.quiet {
+            return Ok(true);
+        }
+        let old_content = old_hash
+            .and_then(|h| Object::load(&repo.get_objects_dir(), h).ok())
+            .map(|obj| obj.text())
+            .unwrap_or_default();
+        let new_content = fs::read(repo.path.join(path))
+            .map(|data| String::from_utf8_lossy(&data).into_owned())
+            .unwrap_or_default();
+        println!("\nFile: {}", path.cyan());
+        if old_hash.is_none() {
+            println!("{}", "new file".magenta());
+        } else if new_hash.is_none() {
+            println!("{}", "deleted file".magenta());
+        }
+        print_text_diff_with(&old_content, &new_content, options);
+    }
+
+    if !any_diff && !options.quiet {
+        println!("\n{}", "No differences found".green());
+    }
+    Ok(any_diff)
+}
+
+/// Diff a revision range: `A..B` compares the two tips, `A...B` compares
+/// the merge base of A and B with B. An empty side means HEAD.
+pub async fn show_range_diff(repo: &Repository, range: &str, options: &DiffOptions) -> Result<bool> {
+    let (from, to) = range_endpoints(repo, range)?;
+    show_commit_diff(repo, &from, &to, options).await
+}
+
+/// The commit ids a range diff compares, left side first.
+fn range_endpoints(repo: &Repository, range: &str) -> Result<(String, String)> {
+    let (from, to, three_dot) = match range.split_once("...") {
+        Some((from, to)) => (from, to, true),
+        None => match range.split_once("..") {
+            Some((from, to)) => (from, to, false),
+            None => anyhow::bail!("'{}' is not a revision range", range),
+        },
+    };
+    let from = if from.is_empty() { "HEAD" } else { from };
+    let to = if to.is_empty() { "HEAD" } else { to };
+
+    let from_id = repo.resolve_rev(from)?;
+    let to_id = repo.resolve_rev(to)?;
+    if !three_dot {
+        return Ok((from_id, to_id));
+    }
+    let base = find_merge_base(repo, &from_id, &to_id)
+        .ok_or_else(|| anyhow::anyhow!("No merge base between '{}' and '{}'", from, to))?;
+    Ok((base, to_id))
+}
+
+/// Diff the staged content against HEAD (`--staged` / `--cached`).
+pub async fn show_staged_diff(repo: &Repository, path: Option<&str>, options: &DiffOptions) -> Result<bool> {
+    let changes = staged_changes(repo, path);
+    let any_diff = !changes.is_empty();
+    if options.quiet {
+        return Ok(any_diff);
+    }
+    println!("{}", "Diff View (staged)".bold().blue());
+    println!("{}", "=".repeat(40).blue());
+    print!("{}", render_staged_diff(repo, &changes, options));
+    if !any_diff {
+        println!("\n{}", "No staged changes".green());
+    }
+    Ok(any_diff)
+}
+
+/// Staged paths whose content differs from HEAD, sorted, as (path, HEAD
+/// blob, staged blob); a missing staged blob is a staged deletion.
+fn staged_changes(repo: &Repository, path: Option<&str>) -> Vec<(String, Option<String>, Option<String>)> {
+    let head_files = repo.head_files();
+    let mut staged: Vec<(&str, Option<&String>)> = repo
+        .index
+        .get_staged_files()
+        .into_iter()
+        .map(|entry| (entry.path.as_str(), Some(&entry.content_hash)))
+        .collect();
+    staged.extend(repo.index.deletions.iter().map(|path| (path.as_str(), None)));
+    staged.sort_by(|a, b| a.0.cmp(b.0));
+
+    staged
+        .into_iter()
+        .filter(|(staged_path, _)| path.is_none_or(|p| p.trim_start_matches("./") == *staged_path))
+        .filter(|(staged_path, new_hash)| head_files.get(*staged_path) != *new_hash)
+        .map(|(staged_path, new_hash)| {
+            (staged_path.to_string(), head_files.get(staged_path).cloned(), new_hash.cloned())
+        })
+        .collect()
+}
+
+fn render_staged_diff(
+    repo: &Repository,
+    changes: &[(String, Option<String>, Option<String>)],
+    options: &DiffOptions,
+) -> String {
+    let mut out = String::new();
+    for (path, old_hash, new_hash) in changes {
+        out.push_str(&format!("\nFile: {}\n", path.cyan()));
+        if old_hash.is_none() {
+            out.push_str(&format!("{}\n", "new file".magenta()));
+        } else if new_hash.is_none() {
+            out.push_str(&format!("{}\n", "deleted file".magenta()));
+        }
+        out.push_str(&render_text_diff(
+            &load_blob(repo, old_hash.as_ref()),
+            &load_blob(repo, new_hash.as_ref()),
+            options,
+        ));
+    }
+    out
+}
+
+/// Paths a commit changes against its first parent, sorted by path; `path`
+/// limits them to one file. Renames are paired; a root commit shows every
+/// file as added. Only paths the commit records are considered, so commits
+/// that stored partial snapshots don't show untouched files as deleted.
+fn commit_changes(repo: &Repository, commit: &Commit, path: Option<&str>) -> Result<Vec<FileDiff>> {
+    let parent_tree = match commit.parent_ids.first() {
+        Some(parent) => repo.get_commit_object(parent)?.tree_id,
+        None => Tree::empty_id(),
+    };
+    Ok(repo
+        .diff_trees(&parent_tree, &commit.tree_id)?
+        .into_iter()
+        .filter(|file_diff| commit.files.contains_key(&file_diff.path))
+        .filter(|file_diff| path.is_none_or(|filter| filter == file_diff.path))
+        .collect())
+}
+
+/// Number of files a commit changes against its first parent.
+pub fn changed_file_count(repo: &Repository, commit: &Commit) -> Result<usize> {
+    Ok(commit_changes(repo, commit, None)?.len())
+}
+
+fn load_blob(repo: &Repository, hash: Option<&String>) -> String {
+    hash.and_then(|h| Object::load(&repo.get_objects_dir(), h).ok())
+        .map(|o| o.text())
+        .unwrap_or_default()
+}
+
+/// Print the changes a commit records against its first parent, as
+/// `log -p` shows them; `path` limits the output to one file.
+pub fn print_commit_patch(repo: &Repository, commit: &Commit, path: Option<&str>) -> Result<()> {
+    print!("{}", render_commit_patch(repo, commit, path)?);
+    Ok(())
+}
+
+/// The text `print_commit_patch` prints.
+pub fn render_commit_patch(repo: &Repository, commit: &Commit, path: Option<&str>) -> Result<String> {
+    let mut out = String::new();
+    for file_diff in commit_changes(repo, commit, path)? {
+        let file_path = &file_diff.path;
+        out.push_str(&format!("\nFile: {}\n", file_path.cyan()));
+        match &file_diff.status {
+            DiffStatus::Added => out.push_str(&format!("{}\n", "new file".magenta())),
+            DiffStatus::Deleted => out.push_str(&format!("{}\n", "deleted file".magenta())),
+            DiffStatus::Renamed { old_path, similarity } => out.push_str(&format!(
+                "{}\n",
+                format!("rename {} -> {} ({}%)", old_path, file_path, similarity).magenta()
+            )),
+            _ => {}
+        }
+        let (old, new) = (
+            load_blob(repo, file_diff.old_hash.as_ref()),
+            load_blob(repo, file_diff.new_hash.as_ref()),
+        );
+        if is_binary(repo, file_path, &old) || is_binary(repo, file_path, &new) {
+            out.push_str(&format!("Binary files a/{} and b/{} differ\n", file_path, file_path));
+        } else {
+            out.push_str(&render_text_diff(&old, &new, &DiffOptions::default()));
+        }
+    }
+    Ok(out)
+}
+
+/// Print a diffstat of a commit against its first parent: one line per file
+/// with its changed line count and a `+`/`-` bar, then the totals.
+pub fn print_commit_stat(repo: &Repository, commit: &Commit) -> Result<()> {
+    print!("{}", render_commit_stat(repo, commit)?);
+    Ok(())
+}
+
+/// The text `print_commit_stat` prints.
+pub fn render_commit_stat(repo: &Repository, commit: &Commit) -> Result<String> {
+    const BAR_WIDTH: usize = 40;
+    let mut out = String::new();
+    let mut rows = Vec::new();
+    for file_diff in commit_changes(repo, commit, None)? {
+        let file_path = match &file_diff.status {
+            DiffStatus::Renamed { old_path, .. } => format!("{} => {}", old_path, file_diff.path),
+            _ => file_diff.path.clone(),
+        };
+        let (old, new) = (
+            load_blob(repo, file_diff.old_hash.as_ref()),
+            load_blob(repo, file_diff.new_hash.as_ref()),
+        );
+        if is_binary(repo, &file_diff.path, &old) || is_binary(repo, &file_diff.path, &new) {
+            rows.push((file_path, None));
+            continue;
+        }
+        let (mut insertions, mut deletions) = (0, 0);
+        for change in TextDiff::from_lines(&old, &new).iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => insertions += 1,
+                ChangeTag::Delete => deletions += 1,
+                ChangeTag::Equal => {}
+            }
+        }
+        rows.push((file_path, Some((insertions, deletions))));
+    }
+    if rows.is_empty() {
+        return Ok(out);
+    }
+
+    let name_width = rows.iter().map(|(p, _)| p.len()).max().unwrap_or(0);
+    let largest = rows
+        .iter()
+        .filter_map(|(_, counts)| counts.map(|(i, d)| i + d))
+        .max()
+        .unwrap_or(0);
+    let (mut total_insertions, mut total_deletions) = (0, 0);
+    for (file_path, counts) in &rows {
+        match counts {
+            Some((insertions, deletions)) => {
+                total_insertions += insertions;
+                total_deletions += deletions;
+                // Scale the bar down only when the biggest change won't fit
+                let scale = |n: usize| {
+                    if largest <= BAR_WIDTH { n } else { (n * BAR_WIDTH).div_ceil(largest) }
+                };
+                let bar = format!(
+                    "{}{}",
+                    "+".repeat(scale(*insertions)).green(),
+                    "-".repeat(scale(*deletions)).red()
+                );
+                let line = format!(
+                    " {:<width$} | {:>5} {}",
+                    file_path,
+                    insertions + deletions,
+                    bar,
+                    width = name_width
+                );
+                out.push_str(&format!("{}\n", line.trim_end()));
+            }
+            None => out.push_str(&format!(" {:<width$} | {:>5}\n", file_path, "Bin", width = name_width)),
+        }
+    }
+    out.push_str(&format!(
+        " {} file{} changed, {} insertion{}(+), {} deletion{}(-)\n",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" },
+        total_insertions,
+        if total_insertions == 1 { "" } else { "s" },
+        total_deletions,
+        if total_deletions == 1 { "" } else { "s" }
+    ));
+    Ok(out)
+}
+
+/// Print the paths a commit changes against its first parent, each
+/// prefixed with `A`, `M` or `D` when `with_status` is set.
+pub fn print_commit_names(repo: &Repository, commit: &Commit, with_status: bool) -> Result<()> {
+    for file_diff in commit_changes(repo, commit, None)? {
+        if !with_status {
+            println!("{}", file_diff.path);
+            continue;
+        }
+        let letter = file_diff.letter().to_string();
+        let status = match file_diff.status {
+            DiffStatus::Added => letter.green(),
+            DiffStatus::Deleted => letter.red(),
+            _ => letter.yellow(),
+        };
+        println!("{}\t{}", status, file_diff.path);
+    }
+    Ok(())
+}
+
+/// Binary by `.helixattributes` or because the content has a NUL byte.
+fn is_binary(repo: &Repository, path: &str, content: &str) -> bool {
+    content.contains('\0') || repo.attributes().has(path, "binary")
+}
+
+pub fn print_text_diff(old: &str, new: &str) {
+    print_text_diff_with(old, new, &DiffOptions::default());
+}
+
+/// Unified line diff: hunks with `@@ -a,b +c,d @@` headers and
+/// `options.context` lines of context. With `color_moved`, blocks moved
+/// elsewhere in the file are colored magenta where removed and cyan where
+/// added instead of red/green.
+pub fn print_text_diff_with(old: &str, new: &str, options: &DiffOptions) {
+    print!("{}", render_text_diff(old, new, options));
+}
+
+/// The text `print_text_diff_with` prints.
+pub fn render_text_diff(old: &str, new: &str, options: &DiffOptions) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let moved: HashSet<(Option<usize>, Option<usize>)> = if options.color_moved {
+        let changes: Vec<_> = diff.iter_all_changes().collect();
+        let lines: Vec<(ChangeTag, String)> = changes
+            .iter()
+            .map(|change| (change.tag(), change.to_string()))
+            .collect();
+        let lines: Vec<(ChangeTag, &str)> =
+            lines.iter().map(|(tag, line)| (*tag, line.as_str())).collect();
+        diff_utils::detect_moved_lines(&lines, diff_utils::MIN_MOVED_LINES)
+            .into_iter()
+            .zip(&changes)
+            .filter(|(moved, _)| *moved)
+            .map(|(_, change)| (change.old_index(), change.new_index()))
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    let mut out = String::new();
+    let context = options.context.unwrap_or(DEFAULT_CONTEXT);
+    for hunk in diff.unified_diff().context_radius(context).iter_hunks() {
+        out.push_str(&format!("{}\n", hunk.header().to_string().cyan()));
+        for change in hunk.iter_changes() {
+            let moved = moved.contains(&(change.old_index(), change.new_index()));
+            let line = change.to_string();
             let (sign, color) = match change.tag() {
+                ChangeTag::Delete if moved => ("-", "magenta"),
+                ChangeTag::Insert if moved => ("+", "cyan"),
                 ChangeTag::Delete => ("-", "red"),
                 ChangeTag::Insert => ("+", "green"),
                 ChangeTag::Equal => (" ", "white"),
             };
-            let line = change.to_string();
-            match color {
-                "red" => print!("{}", format!("{}{}", sign, line).red()),
-                "green" => print!("{}", format!("{}{}", sign, line).green()),
-                _ => print!("{}{}", sign, line),
+            let text = format!("{}{}", sign, line);
+            let text = match color {
+                "red" => text.red().to_string(),
+                "green" => text.green().to_string(),
+                "magenta" => text.magenta().bold().to_string(),
+                "cyan" => text.cyan().bold().to_string(),
+                _ => text,
+            };
+            out.push_str(&text);
+            if change.missing_newline() {
+                out.push_str("\n\\ No newline at end of file\n");
             }
         }
     }
-    if !any_diff {
-        println!("\n{}", "No differences found".green());
-        println!("Working directory is clean");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{strip_ansi, TestRepo};
+
+    #[tokio::test]
+    async fn show_diff_reports_whether_the_worktree_differs() {
+        let mut test = TestRepo::new();
+        let quiet = DiffOptions { quiet: true, ..Default::default() };
+        assert!(!show_diff(&test.repo, None, &quiet).await.unwrap());
+
+        test.write("a.txt", "a\n");
+        test.commit("first").await;
+        assert!(!show_diff(&test.repo, None, &quiet).await.unwrap());
+        test.write("a.txt", "changed\n");
+        assert!(show_diff(&test.repo, None, &quiet).await.unwrap());
+        assert!(show_diff(&test.repo, Some(Path::new("a.txt")), &quiet).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn commit_changes_match_diff_trees_against_the_parent() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let root = test.commit("root").await;
+        test.write("a.txt", "changed\n");
+        test.write("b.txt", "b\n");
+        let second = test.commit("second").await;
+
+        let root_commit = test.repo.get_commit_object(&root).unwrap();
+        let added: Vec<char> = commit_changes(&test.repo, &root_commit, None)
+            .unwrap()
+            .iter()
+            .map(FileDiff::letter)
+            .collect();
+        assert_eq!(added, vec!['A']);
+
+        let commit = test.repo.get_commit_object(&second).unwrap();
+        let changes = commit_changes(&test.repo, &commit, None).unwrap();
+        assert_eq!(changes, test.repo.diff_trees(&root, &second).unwrap());
+        assert_eq!(changed_file_count(&test.repo, &commit).unwrap(), 2);
+        let only_b = commit_changes(&test.repo, &commit, Some("b.txt")).unwrap();
+        assert_eq!(only_b.len(), 1);
+        assert_eq!(only_b[0].status, DiffStatus::Added);
+    }
+
+    #[tokio::test]
+    async fn three_dot_ranges_start_from_the_fork_point() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let fork = test.commit("fork").await;
+        test.checkout_new("feature").await;
+        test.write("f.txt", "f\n");
+        let feature = test.commit("feature work").await;
+        test.checkout("main").await;
+        test.write("m.txt", "m\n");
+        let main = test.commit("main moved on").await;
+
+        assert_eq!(range_endpoints(&test.repo, "main..feature").unwrap(), (main.clone(), feature.clone()));
+        assert_eq!(range_endpoints(&test.repo, "main...feature").unwrap(), (fork, feature.clone()));
+        assert_eq!(range_endpoints(&test.repo, "feature..").unwrap(), (feature, main));
+        assert!(range_endpoints(&test.repo, "main").is_err());
+
+        let changed = |range: &str| -> Vec<String> {
+            let (from, to) = range_endpoints(&test.repo, range).unwrap();
+            let from = test.repo.get_commit_object(&from).unwrap().tree_id;
+            let to = test.repo.get_commit_object(&to).unwrap().tree_id;
+            let mut paths: Vec<String> = test
+                .repo
+                .diff_trees(&from, &to)
+                .unwrap()
+                .iter()
+                .map(|change| format!("{} {}", change.letter(), change.path))
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(changed("main..feature"), vec!["A f.txt", "D m.txt"]);
+        assert_eq!(changed("main...feature"), vec!["A f.txt"]);
+    }
+
+    #[test]
+    fn unified_context_limits_the_hunk() {
+        let old: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        let new = old.replace("line 50\n", "changed 50\n");
+
+        let two = DiffOptions { context: Some(2), ..Default::default() };
+        let hunk = strip_ansi(&render_text_diff(&old, &new, &two));
+        assert_eq!(
+            hunk.lines().collect::<Vec<_>>(),
+            ["@@ -48,5 +48,5 @@", " line 48", " line 49", "-line 50", "+changed 50", " line 51", " line 52"]
+        );
+
+        let default = strip_ansi(&render_text_diff(&old, &new, &DiffOptions::default()));
+        assert!(default.starts_with("@@ -47,7 +47,7 @@\n"));
+        assert_eq!(default.lines().count(), 9);
+    }
+
+    #[tokio::test]
+    async fn staged_diff_shows_the_staged_edit() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "one\ntwo\n");
+        test.write("gone.txt", "bye\n");
+        test.commit("base").await;
+        test.write("a.txt", "one\nTWO\n");
+        test.write("new.txt", "hello\n");
+        test.remove("gone.txt");
+        test.add_all().await;
+        // Unstaged edits after staging are not part of it
+        test.write("a.txt", "one\nunstaged\n");
+
+        let changes = staged_changes(&test.repo, None);
+        let paths: Vec<&str> = changes.iter().map(|(path, _, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "gone.txt", "new.txt"]);
+
+        let out = strip_ansi(&render_staged_diff(&test.repo, &changes, &DiffOptions::default()));
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines.contains(&"-two"));
+        assert!(lines.contains(&"+TWO"));
+        assert!(!lines.contains(&"+unstaged"));
+        assert!(lines.contains(&"deleted file"));
+        assert!(lines.contains(&"-bye"));
+        assert!(lines.contains(&"new file"));
+        assert!(lines.contains(&"+hello"));
+
+        assert_eq!(staged_changes(&test.repo, Some("./new.txt")).len(), 1);
+        let quiet = DiffOptions { quiet: true, ..Default::default() };
+        assert!(show_staged_diff(&test.repo, None, &quiet).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn commit_diff_covers_added_deleted_and_modified_files() {
+        let mut test = TestRepo::new();
+        test.write("kept.txt", "same\n");
+        test.write("edited.txt", "old line\n");
+        test.write("removed.txt", "removed\n");
+        let first = test.commit("first").await;
+        test.write("edited.txt", "new line\n");
+        test.remove("removed.txt");
+        test.write("added.txt", "brand new\n");
+        let second = test.commit("second").await;
+
+        let diffs = test
+            .repo
+            .diff_trees_with(&first[..8], &second[..8], &TreeDiffOptions::default())
+            .unwrap();
+        let out = strip_ansi(&render_tree_diff(&test.repo, &diffs, &DiffOptions::default()));
+        let files: Vec<&str> = out.lines().filter_map(|line| line.strip_prefix("File: ")).collect();
+        assert_eq!(files, vec!["added.txt", "edited.txt", "removed.txt"]);
+        let lines: Vec<&str> = out.lines().collect();
+        for expected in ["new file", "+brand new", "-old line", "+new line", "deleted file", "-removed"] {
+            assert!(lines.contains(&expected), "missing {:?} in\n{}", expected, out);
+        }
+
+        let quiet = DiffOptions { quiet: true, ..Default::default() };
+        assert!(show_commit_diff(&test.repo, &first, &second, &quiet).await.unwrap());
+        assert!(!show_commit_diff(&test.repo, &second, "HEAD", &quiet).await.unwrap());
+        // A single commit is compared with the working tree
+        assert!(!show_worktree_diff(&test.repo, &second, &quiet).await.unwrap());
+        assert!(show_worktree_diff(&test.repo, &first, &quiet).await.unwrap());
     }
-    Ok(())
 }
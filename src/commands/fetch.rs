@@ -0,0 +1,497 @@
+use crate::commands::pull::{download_missing_objects, save_objects_to_repository};
+use crate::core::commit::Commit;
+use crate::core::object::{Object, Tree};
+use crate::core::repository::Repository;
+use crate::utils::hash_utils;
+use crate::utils::pack::{extract_objects_from_pack, Pack};
+use crate::utils::remote_client::{NegotiationRequest, RemoteClient, OBJECT_PAGE_SIZE};
+use crate::utils::transport::{self, RemoteTransport};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::*;
+use futures_util::TryStreamExt;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::pin::pin;
+
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// Only fetch commits made at or after this time
+    pub shallow_since: Option<DateTime<Utc>>,
+}
+
+/// What a fetch from one remote changed.
+pub struct FetchSummary {
+    pub objects_downloaded: usize,
+    /// Branch -> (previous tracking ref, new head) for refs that moved
+    pub updated_refs: BTreeMap<String, (Option<String>, String)>,
+    /// Shallow boundary after the fetch, if the history is shallow
+    pub shallow: BTreeSet<String>,
+}
+
+/// Download the objects behind every branch of `remote_name` and record the
+/// heads as remote-tracking refs. Local branches and the working tree are
+/// left alone.
+pub async fn fetch_remote<T, F>(
+    repo: &mut Repository,
+    remote_name: &str,
+    options: &FetchOptions,
+    connect: F,
+) -> Result<FetchSummary>
+where
+    T: RemoteTransport,
+    F: Fn(&str) -> T,
+{
+    let remote = repo
+        .remotes
+        .get(remote_name)
+        .ok_or_else(|| anyhow::anyhow!("No '{}' remote configured", remote_name))?;
+    let client = connect(&remote.url);
+    fetch_from(repo, &client, remote_name, options).await
+}
+
+/// `fetch_remote` over any transport.
+pub async fn fetch_from<T: RemoteTransport>(
+    repo: &mut Repository,
+    client: &T,
+    remote_name: &str,
+    options: &FetchOptions,
+) -> Result<FetchSummary> {
+    if !client.check_connectivity().await? {
+        anyhow::bail!("Failed to connect to remote '{}'", remote_name);
+    }
+    let remote_refs = client.get_refs().await
+        .with_context(|| "Failed to fetch remote refs")?;
+    let heads: BTreeMap<String, String> = remote_refs
+        .iter()
+        .filter_map(|(name, id)| {
+            name.strip_prefix("refs/heads/")
+                .map(|branch| (branch.to_string(), id.clone()))
+        })
+        .collect();
+
+    let mut shallow = repo.shallow_commits();
+    let objects_downloaded = match options.shallow_since {
+        Some(since) => {
+            fetch_since(client, repo, heads.values(), since, &mut shallow).await?
+        }
+        None => fetch_all(client, repo, heads.values(), &mut shallow).await?,
+    };
+    repo.set_shallow_commits(&shallow)?;
+
+    let mut updated_refs = BTreeMap::new();
+    for (branch, head) in &heads {
+        let previous = repo.get_remote_ref(remote_name, branch);
+        if previous.as_deref() != Some(head.as_str()) {
+            repo.set_remote_ref(remote_name, branch, head)?;
+            updated_refs.insert(branch.clone(), (previous, head.clone()));
+        }
+    }
+
+    if let Some(remote) = repo.remotes.get_mut(remote_name) {
+        remote.update_last_fetch();
+    }
+    repo.save()?;
+
+    Ok(FetchSummary {
+        objects_downloaded,
+        updated_refs,
+        shallow,
+    })
+}
+
+/// Download every remote object we don't have. This completes a shallow
+/// history unless the server sends a pack with its own boundary.
+async fn fetch_all<'h, T: RemoteTransport>(
+    client: &T,
+    repo: &Repository,
+    heads: impl Iterator<Item = &'h String>,
+    shallow: &mut BTreeSet<String>,
+) -> Result<usize> {
+    let mut missing_objects: HashSet<String> = HashSet::new();
+    let mut common_objects: Vec<String> = Vec::new();
+    let mut remote_hashes = pin!(transport::object_hashes(client, OBJECT_PAGE_SIZE));
+    while let Some(hash) = remote_hashes.try_next().await
+        .with_context(|| "Failed to fetch remote object hashes")?
+    {
+        if repo.object_exists(&hash) {
+            common_objects.push(hash);
+        } else {
+            missing_objects.insert(hash);
+        }
+    }
+
+    if !missing_objects.is_empty() {
+        let wants: Vec<String> = heads
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let negotiation_request = NegotiationRequest {
+            wants,
+            haves: common_objects,
+            shallow: shallow.iter().cloned().collect(),
+            deepen_since: None,
+            deepen_not: None,
+            filter: None,
+        };
+        let negotiation_response = client.negotiate_fetch(&negotiation_request).await
+            .with_context(|| "Failed to negotiate with remote")?;
+
+        match negotiation_response.packfile {
+            Some(pack_id) => {
+                let pack_data = client.download_pack(&pack_id).await
+                    .with_context(|| "Failed to download pack")?;
+                let pack = Pack::from_bytes(&pack_data)
+                    .with_context(|| "Failed to parse pack")?;
+                save_objects_to_repository(repo, &extract_objects_from_pack(&pack))?;
+                shallow.extend(negotiation_response.shallow);
+                for id in &negotiation_response.unshallow {
+                    shallow.remove(id);
+                }
+                return Ok(missing_objects.len());
+            }
+            None => download_missing_objects(client, repo, &missing_objects).await?,
+        }
+    }
+    shallow.clear();
+
+    Ok(missing_objects.len())
+}
+
+/// Download the history behind `heads` back to `since`. The oldest commits
+/// kept whose parents are older than the cutoff become the new shallow
+/// boundary; those parents are not stored. Servers that answer the
+/// `deepen_since` negotiation with a pack do the cut themselves.
+async fn fetch_since<'h, T: RemoteTransport>(
+    client: &T,
+    repo: &Repository,
+    heads: impl Iterator<Item = &'h String>,
+    since: DateTime<Utc>,
+    shallow: &mut BTreeSet<String>,
+) -> Result<usize> {
+    let heads: Vec<String> = heads.cloned().collect::<HashSet<_>>().into_iter().collect();
+    let negotiation_request = NegotiationRequest {
+        wants: heads.clone(),
+        haves: Vec::new(),
+        shallow: shallow.iter().cloned().collect(),
+        deepen_since: Some(since.timestamp()),
+        deepen_not: None,
+        filter: None,
+    };
+    let negotiation_response = client.negotiate_fetch(&negotiation_request).await
+        .with_context(|| "Failed to negotiate with remote")?;
+    if let Some(pack_id) = negotiation_response.packfile {
+        let pack_data = client.download_pack(&pack_id).await
+            .with_context(|| "Failed to download pack")?;
+        let pack = Pack::from_bytes(&pack_data)
+            .with_context(|| "Failed to parse pack")?;
+        let objects = extract_objects_from_pack(&pack);
+        save_objects_to_repository(repo, &objects)?;
+        shallow.extend(negotiation_response.shallow);
+        for id in &negotiation_response.unshallow {
+            shallow.remove(id);
+        }
+        return Ok(objects.len());
+    }
+
+    // Walk it ourselves, one frontier at a time
+    let mut saved = 0;
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    let mut to_download = heads.clone();
+    let mut seen = HashSet::new();
+    while !to_download.is_empty() {
+        let frontier: Vec<String> = to_download
+            .drain(..)
+            .filter(|hash| seen.insert(hash.clone()))
+            .collect();
+        // Local history is walked in place, since an older boundary the new
+        // cutoff may reach past can sit behind it. Boundary commits are
+        // fetched again to learn their parents.
+        let mut missing = Vec::new();
+        for hash in frontier {
+            if !repo.object_exists(&hash) || shallow.contains(&hash) {
+                missing.push(hash);
+            } else if let Ok(commit) = repo.get_commit_object(&hash) {
+                to_download.extend(commit.parent_ids);
+            }
+        }
+        let downloaded = client.download_objects(&missing).await?;
+
+        for hash in missing {
+            let data = downloaded
+                .get(&hash)
+                .ok_or_else(|| anyhow::anyhow!("Remote did not send object {}", hash))?;
+            let object = match Object::from_compressed(data) {
+                Ok(object) => object,
+                Err(_) => serde_json::from_slice(data)?,
+            };
+            if object.is_commit() {
+                let commit = Commit::from_object(&object)?;
+                if commit.timestamp < since && !heads.contains(&hash) {
+                    // Too old: its children mark the boundary
+                    for child in children.get(&hash).into_iter().flatten() {
+                        shallow.insert(child.clone());
+                    }
+                    continue;
+                }
+                shallow.remove(&hash);
+                for parent in &commit.parent_ids {
+                    children.entry(parent.clone()).or_default().push(hash.clone());
+                }
+                if commit.timestamp < since {
+                    // An old branch head is kept, but nothing behind it
+                    shallow.insert(hash.clone());
+                } else {
+                    to_download.extend(commit.parent_ids.iter().cloned());
+                }
+                to_download.push(commit.tree_id.clone());
+                to_download.extend(commit.files.values().map(|fc| fc.content_hash.clone()));
+            } else if object.is_tree() {
+                let tree = Tree::from_object(&object)?;
+                to_download.extend(tree.entries.into_iter().map(|e| e.object_id));
+            }
+            if !repo.object_exists(&hash) {
+                save_objects_to_repository(repo, &HashMap::from([(hash, data.clone())]))?;
+                saved += 1;
+            }
+        }
+    }
+    Ok(saved)
+}
+
+/// Fetch from `remote` (default `origin`), or from every configured remote
+/// with `all`. A failing remote is reported and the rest are still fetched;
+/// returns whether every fetch succeeded.
+pub async fn fetch(
+    repo: &mut Repository,
+    remote: Option<&str>,
+    all: bool,
+    options: &FetchOptions,
+) -> Result<bool> {
+    fetch_with(repo, remote, all, options, RemoteClient::configured).await
+}
+
+/// `fetch`, with `connect` opening the transport for a remote's URL.
+async fn fetch_with<T, F>(
+    repo: &mut Repository,
+    remote: Option<&str>,
+    all: bool,
+    options: &FetchOptions,
+    connect: F,
+) -> Result<bool>
+where
+    T: RemoteTransport,
+    F: Fn(&str) -> T,
+{
+    let mut remote_names: Vec<String> = if all {
+        repo.remotes.keys().cloned().collect()
+    } else {
+        vec![remote.unwrap_or("origin").to_string()]
+    };
+    remote_names.sort();
+
+    if remote_names.is_empty() {
+        println!("{}", "No remote repositories configured".yellow());
+        println!("Use 'hx remote add origin <url>' to add a remote");
+        return Ok(true);
+    }
+
+    let mut all_ok = true;
+    for remote_name in &remote_names {
+        println!("{} {}", "Fetching".bold(), remote_name.cyan());
+        match fetch_remote(repo, remote_name, options, &connect).await {
+            Ok(summary) => print_summary(remote_name, &summary),
+            Err(e) => {
+                all_ok = false;
+                println!(
+                    "{} could not fetch '{}': {:#}",
+                    "error:".red().bold(),
+                    remote_name,
+                    e
+                );
+            }
+        }
+    }
+    Ok(all_ok)
+}
+
+fn print_summary(remote_name: &str, summary: &FetchSummary) {
+    for (branch, (previous, head)) in &summary.updated_refs {
+        let range = match previous {
+            Some(previous) => format!(
+                "{}..{}",
+                hash_utils::get_short_hash(previous),
+                hash_utils::get_short_hash(head)
+            ),
+            None => "[new branch]".to_string(),
+        };
+        println!(
+            "   {:<20} {} -> {}",
+            range,
+            branch,
+            format!("{}/{}", remote_name, branch).green()
+        );
+    }
+    if summary.updated_refs.is_empty() {
+        println!("   {}", "Already up to date".green());
+    }
+    println!(
+        "   Objects downloaded: {}",
+        summary.objects_downloaded.to_string().cyan()
+    );
+    if !summary.shallow.is_empty() {
+        println!(
+            "   Shallow boundary: {} commits",
+            summary.shallow.len().to_string().yellow()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::commit::CommitOptions;
+    use crate::core::remote::Remote;
+    use crate::utils::test_utils::TestRepo;
+    use crate::utils::transport::MockRemote;
+
+    #[tokio::test]
+    async fn fetch_records_remote_heads_and_leaves_the_worktree_alone() {
+        let mut upstream = TestRepo::new();
+        upstream.write("shared.txt", "base\n");
+        upstream.commit("base").await;
+        upstream.write("shared.txt", "upstream change\n");
+        let upstream_head = upstream.commit("upstream").await;
+        let remote = MockRemote::from_repo(&upstream.repo);
+
+        let mut local = TestRepo::new();
+        local.repo.remotes.insert("origin".to_string(), Remote::new("origin", "mock://origin"));
+        local.write("shared.txt", "local work\n");
+        let local_head = local.commit("local").await;
+        local.write("untracked.txt", "scratch\n");
+
+        let summary = fetch_from(&mut local.repo, &remote, "origin", &FetchOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.updated_refs["main"], (None, upstream_head.clone()));
+        let ref_file = local.repo.get_refs_dir().join("remotes/origin/main");
+        assert_eq!(std::fs::read_to_string(ref_file).unwrap(), format!("{}\n", upstream_head));
+        assert!(local.repo.object_exists(&upstream_head));
+        // Nothing local moved
+        assert_eq!(local.head(), local_head);
+        assert_eq!(local.read("shared.txt"), "local work\n");
+        assert_eq!(local.read("untracked.txt"), "scratch\n");
+        assert!(local.repo.remotes["origin"].last_fetch.is_some());
+    }
+
+    #[tokio::test]
+    async fn objects_already_on_disk_are_not_downloaded_again() {
+        let mut upstream = TestRepo::new();
+        upstream.write("shared.txt", "same everywhere\n");
+        upstream.commit("base").await;
+        upstream.write("new.txt", "only upstream\n");
+        let upstream_head = upstream.commit("upstream").await;
+        let remote = MockRemote::from_repo(&upstream.repo);
+
+        let mut local = TestRepo::new();
+        local.repo.remotes.insert("origin".to_string(), Remote::new("origin", "mock://origin"));
+        local.write("shared.txt", "same everywhere\n");
+        local.commit("local").await;
+        let shared = Object::new("blob".to_string(), b"same everywhere\n".to_vec()).id;
+        assert!(local.repo.object_exists(&shared));
+        let missing: Vec<String> = remote
+            .objects
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|id| !local.repo.object_exists(id))
+            .cloned()
+            .collect();
+
+        let summary = fetch_from(&mut local.repo, &remote, "origin", &FetchOptions::default())
+            .await
+            .unwrap();
+
+        let mut downloads = remote.downloads.lock().unwrap().clone();
+        assert!(!downloads.contains(&shared));
+        assert!(downloads.contains(&upstream_head));
+        assert_eq!(summary.objects_downloaded, downloads.len());
+        downloads.sort();
+        assert_eq!(downloads, missing);
+    }
+
+    #[tokio::test]
+    async fn fetch_all_updates_every_remote() {
+        let mut origin = TestRepo::new();
+        origin.write("a.txt", "origin\n");
+        let origin_head = origin.commit("origin work").await;
+        let mut fork = TestRepo::new();
+        fork.write("b.txt", "fork\n");
+        let fork_head = fork.commit("fork work").await;
+
+        let mut local = TestRepo::new();
+        local.repo.remotes.insert("origin".to_string(), Remote::new("origin", "mock://origin"));
+        local.repo.remotes.insert("fork".to_string(), Remote::new("fork", "mock://fork"));
+        let connect = |url: &str| match url {
+            "mock://origin" => MockRemote::from_repo(&origin.repo),
+            _ => MockRemote::from_repo(&fork.repo),
+        };
+
+        let ok = fetch_with(&mut local.repo, None, true, &FetchOptions::default(), connect)
+            .await
+            .unwrap();
+
+        assert!(ok);
+        assert_eq!(local.repo.get_remote_ref("origin", "main"), Some(origin_head.clone()));
+        assert_eq!(local.repo.get_remote_ref("fork", "main"), Some(fork_head.clone()));
+        assert!(local.repo.object_exists(&origin_head) && local.repo.object_exists(&fork_head));
+
+        // Naming one remote leaves the others alone
+        origin.write("a.txt", "more\n");
+        let newer = origin.commit("more origin work").await;
+        fork.write("b.txt", "more\n");
+        fork.commit("more fork work").await;
+        let connect = |url: &str| match url {
+            "mock://origin" => MockRemote::from_repo(&origin.repo),
+            _ => MockRemote::from_repo(&fork.repo),
+        };
+        fetch_with(&mut local.repo, Some("origin"), false, &FetchOptions::default(), connect)
+            .await
+            .unwrap();
+        assert_eq!(local.repo.get_remote_ref("origin", "main"), Some(newer));
+        assert_eq!(local.repo.get_remote_ref("fork", "main"), Some(fork_head));
+    }
+
+    #[tokio::test]
+    async fn shallow_since_keeps_older_commits_out() {
+        let mut upstream = TestRepo::new();
+        let mut ids = Vec::new();
+        for (i, date) in ["2020-01-01T00:00:00Z", "2021-01-01T00:00:00Z", "2024-01-01T00:00:00Z"]
+            .into_iter()
+            .enumerate()
+        {
+            upstream.write("a.txt", &format!("{}\n", i));
+            let options = CommitOptions { date: Some(date.to_string()), ..Default::default() };
+            ids.push(upstream.commit_with(&format!("commit {}", i), &options).await.unwrap());
+        }
+        let remote = MockRemote::from_repo(&upstream.repo);
+
+        let mut local = TestRepo::new();
+        local.repo.remotes.insert("origin".to_string(), Remote::new("origin", "mock://origin"));
+        let since = "2023-01-01T00:00:00Z".parse().unwrap();
+        let options = FetchOptions { shallow_since: Some(since) };
+        let summary = fetch_from(&mut local.repo, &remote, "origin", &options).await.unwrap();
+
+        let requests = remote.fetch_requests.lock().unwrap();
+        assert_eq!(requests[0].deepen_since, Some(since.timestamp()));
+        assert!(local.repo.object_exists(&ids[2]));
+        assert!(!local.repo.object_exists(&ids[1]));
+        assert!(!local.repo.object_exists(&ids[0]));
+        // Nor anything only the old commits point at
+        let old_tree = upstream.repo.get_commit_object(&ids[0]).unwrap().tree_id;
+        assert!(!local.repo.object_exists(&old_tree));
+        assert_eq!(summary.shallow, BTreeSet::from([ids[2].clone()]));
+        assert_eq!(local.repo.shallow_commits(), summary.shallow);
+    }
+}
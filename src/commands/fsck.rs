@@ -0,0 +1,56 @@
+use crate::commands::gc;
+use crate::core::object::Object;
+use crate::core::repository::Repository;
+use anyhow::Result;
+use colored::*;
+use std::fs;
+
+/// Check that every loose object decodes and hashes to its file name,
+/// report files that don't fit the object layout, and with `unreachable`
+/// list the objects nothing refers to. Returns whether the object store is
+/// free of corruption and stray files.
+pub async fn run_fsck(repo: &Repository, unreachable: bool) -> Result<bool> {
+    let objects_dir = repo.get_objects_dir();
+    let ids = gc::list_loose_objects(repo)?;
+    let mut corrupt = 0;
+    for id in &ids {
+        let data = fs::read(objects_dir.join(&id[..2]).join(&id[2..]))?;
+        match Object::from_compressed(&data) {
+            Ok(object) if object.id == *id => {}
+            Ok(object) => {
+                println!("{} {} hashes to {}", "corrupt:".red().bold(), id, object.id);
+                corrupt += 1;
+            }
+            Err(e) => {
+                println!("{} {}: {}", "corrupt:".red().bold(), id, e);
+                corrupt += 1;
+            }
+        }
+    }
+
+    // Anything that doesn't fit the shard layout isn't a loose object
+    let stray = gc::stray_object_files(repo)?;
+    for path in &stray {
+        let relative = path.strip_prefix(&repo.path).unwrap_or(path);
+        println!("{} {}", "stray:".yellow().bold(), relative.display());
+    }
+
+    if unreachable {
+        gc::print_unreachable(&gc::unreachable_objects(repo)?);
+    }
+    println!(
+        "Checked {} objects, {}{}",
+        ids.len(),
+        if corrupt == 0 {
+            "no corruption".green().to_string()
+        } else {
+            format!("{} corrupt", corrupt).red().to_string()
+        },
+        if stray.is_empty() {
+            String::new()
+        } else {
+            format!(", {} stray files", stray.len()).yellow().to_string()
+        }
+    );
+    Ok(corrupt == 0 && stray.is_empty())
+}
@@ -0,0 +1,623 @@
+use crate::core::commit_graph::CommitGraph;
+use crate::core::index::IndexLock;
+use crate::core::object::{Object, Tree};
+use crate::core::reflog::Reflog;
+use crate::core::repository::Repository;
+use crate::core::stash::StashList;
+use crate::core::tag::{Tag, Tags};
+use crate::core::undo::UndoEntry;
+use crate::utils::config::GlobalConfig;
+use crate::utils::date_utils;
+use crate::utils::file_utils;
+use crate::utils::pack::{self, Pack};
+use anyhow::Result;
+use colored::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Default for `gc.pruneExpire`. Reflog entries older than this expire, and
+/// unreachable loose objects are only pruned once their mtime is older than
+/// this, so objects written by a concurrent command that hasn't referenced
+/// them yet survive.
+pub const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+/// Default for `gc.undoExpire`: `hx undo` snapshots older than this are
+/// dropped.
+pub const DEFAULT_UNDO_EXPIRE: &str = "1.week.ago";
+
+pub async fn run_gc(repo: &Repository, prune: Option<&str>) -> Result<()> {
+    println!("{}", "Garbage Collection".bold().blue());
+    println!("{}", "=".repeat(40).blue());
+
+    let config = GlobalConfig::load().ok();
+    let expire = prune
+        .or_else(|| config.as_ref().and_then(|c| c.get_gc_prune_expire()))
+        .unwrap_or(DEFAULT_PRUNE_EXPIRE);
+    let cutoff = date_utils::parse_date(expire)?;
+    let cutoff_time = std::time::SystemTime::from(cutoff);
+    // Keep `add`/`commit` from writing objects while unreferenced ones go
+    let _lock = IndexLock::acquire(&repo.git_dir)?;
+
+    // Expire old reflog entries first so they no longer keep commits alive
+    let mut reflog = Reflog::load(&repo.git_dir)?;
+    let expired = reflog.expire(cutoff);
+    reflog.save(&repo.git_dir)?;
+
+    let undo_expire = config
+        .as_ref()
+        .and_then(|c| c.get_gc_undo_expire())
+        .unwrap_or(DEFAULT_UNDO_EXPIRE);
+    let expired_undo = UndoEntry::expire(&repo.git_dir, date_utils::parse_date(undo_expire)?)?;
+
+    let reachable = collect_reachable(repo, &reflog);
+    let objects_dir = repo.get_objects_dir();
+
+    let mut pruned = 0;
+    let mut spared = 0;
+    let mut freed = 0u64;
+    for id in list_loose_objects(repo)? {
+        if reachable.contains(&id) {
+            continue;
+        }
+        let path = objects_dir.join(&id[..2]).join(&id[2..]);
+        let metadata = fs::metadata(&path)?;
+        // Within the grace window: possibly written by an in-flight command
+        if metadata.modified()? > cutoff_time {
+            spared += 1;
+            continue;
+        }
+        freed += metadata.len();
+        fs::remove_file(&path)?;
+        pruned += 1;
+    }
+
+    let (packed, expired_packed, pack_path) = pack_loose_objects(repo, &reachable, cutoff_time)?;
+    pruned += expired_packed;
+    let removed_shards = remove_empty_shards(repo)?;
+
+    println!("Reachable objects: {}", reachable.len().to_string().cyan());
+    println!("Pruned objects: {}", pruned.to_string().yellow());
+    if let Some(pack_path) = pack_path {
+        let relative = pack_path.strip_prefix(&repo.path).unwrap_or(&pack_path);
+        println!(
+            "Packed objects: {} into {}",
+            packed.to_string().cyan(),
+            relative.display()
+        );
+    }
+    if removed_shards > 0 {
+        println!(
+            "Removed empty shard directories: {}",
+            removed_shards.to_string().yellow()
+        );
+    }
+    if spared > 0 {
+        println!(
+            "Recent unreachable objects kept: {}",
+            spared.to_string().yellow()
+        );
+    }
+    println!("Freed: {} bytes", freed.to_string().yellow());
+    let graph_commits = CommitGraph::build(repo).save(&repo.git_dir)?;
+    println!("Commit graph: {} commits", graph_commits.to_string().cyan());
+    if expired > 0 {
+        println!("Expired reflog entries: {}", expired.to_string().yellow());
+    }
+    if expired_undo > 0 {
+        println!("Expired undo snapshots: {}", expired_undo.to_string().yellow());
+    }
+    println!("Prune cutoff: {}", cutoff.format("%Y-%m-%d %H:%M:%S").to_string().dimmed());
+    println!("\n{}", "Garbage collection complete!".green().bold());
+
+    Ok(())
+}
+
+/// Move the reachable loose objects, together with the reachable objects of
+/// every pack without a `.keep` marker, into one new pack, then delete the
+/// loose files and the old packs. Unreachable objects of a pack written
+/// after `cutoff` are put back loose with the pack's mtime, so their grace
+/// window keeps running; those of older packs are dropped. Nothing is
+/// deleted until the new pack and its index are on disk. Returns how many
+/// objects were packed and dropped, and the pack written, if any. Objects
+/// that don't decode to their own id are left loose for `fsck`.
+fn pack_loose_objects(
+    repo: &Repository,
+    reachable: &HashSet<String>,
+    cutoff: std::time::SystemTime,
+) -> Result<(usize, usize, Option<PathBuf>)> {
+    let objects_dir = repo.get_objects_dir();
+    let mut ids: Vec<String> = list_loose_objects(repo)?
+        .into_iter()
+        .filter(|id| reachable.contains(id))
+        .collect();
+    ids.sort();
+
+    let mut pack = Pack::new();
+    let mut packed = Vec::new();
+    for id in ids {
+        let path = objects_dir.join(&id[..2]).join(&id[2..]);
+        let data = fs::read(&path)?;
+        match Object::from_compressed(&data) {
+            Ok(object) if object.id == id => {
+                pack.add_object(&id, pack::pack_type(&object.object_type), data);
+                packed.push(path);
+            }
+            _ => continue,
+        }
+    }
+    // A single pack of reachable objects and nothing loose is already as
+    // packed as it gets
+    let old_packs = pack::repackable_packs(&objects_dir);
+    let all_reachable = old_packs
+        .iter()
+        .all(|(_, pack_ids)| pack_ids.iter().all(|id| reachable.contains(id)));
+    if packed.is_empty() && old_packs.len() < 2 && all_reachable {
+        return Ok((0, 0, None));
+    }
+    let mut dropped = 0;
+    for (old_pack, pack_ids) in &old_packs {
+        let written = fs::metadata(old_pack)?.modified()?;
+        for id in pack_ids {
+            if pack.index.contains_key(id) {
+                continue;
+            }
+            let Some(data) = pack::read_packed(&objects_dir, id)? else {
+                continue;
+            };
+            if reachable.contains(id) {
+                let object_type = Object::from_compressed(&data)?.object_type;
+                pack.add_object(id, pack::pack_type(&object_type), data);
+            } else if written > cutoff {
+                let path = objects_dir.join(&id[..2]).join(&id[2..]);
+                if !path.exists() {
+                    file_utils::write_file_durable(&path, &data)?;
+                    fs::File::options().write(true).open(&path)?.set_modified(written)?;
+                }
+            } else {
+                dropped += 1;
+            }
+        }
+    }
+
+    let pack_path = if pack.objects.is_empty() {
+        None
+    } else {
+        Some(pack::write_pack(&objects_dir, &pack)?)
+    };
+    for path in &packed {
+        fs::remove_file(path)?;
+    }
+    for (old_pack, _) in &old_packs {
+        if Some(old_pack) != pack_path.as_ref() {
+            pack::remove_pack(old_pack)?;
+        }
+    }
+    Ok((pack.objects.len(), dropped, pack_path))
+}
+
+/// Every object reachable from branch heads, tags, an in-progress merge, the
+/// index, stash entries and the (unexpired) reflog.
+pub fn collect_reachable(repo: &Repository, reflog: &Reflog) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let mut stack: Vec<String> = repo
+        .branches
+        .values()
+        .filter_map(|b| b.get_head_commit().cloned())
+        .collect();
+    // Fetched but not yet merged history is only held by tracking refs
+    stack.extend(repo.remote_tracking_refs().into_values());
+    // A detached HEAD's commits belong to no branch until one is created
+    stack.extend(repo.detached_head.clone());
+    stack.extend(repo.get_merge_head());
+    if let Ok(tags) = Tags::load(&repo.git_dir) {
+        let objects_dir = repo.get_objects_dir();
+        // Annotated tag objects themselves, then the commits they tag
+        for id in tags.refs.values() {
+            if Tag::load(&objects_dir, id).is_some() {
+                reachable.insert(id.clone());
+            }
+        }
+        stack.extend(tags.targets(&objects_dir));
+    }
+    for entry in &reflog.entries {
+        stack.push(entry.new_commit.clone());
+        stack.extend(entry.old_commit.clone());
+    }
+    if let Ok(stash) = StashList::load(&repo.git_dir) {
+        for entry in &stash.entries {
+            stack.extend(entry.base_commit.clone());
+            reachable.extend(entry.files.values().flatten().cloned());
+        }
+    }
+
+    // Staged blobs aren't referenced by any commit yet
+    for entry in repo.index.get_all_files() {
+        reachable.insert(entry.content_hash.clone());
+    }
+    for conflict in repo.index.conflicts.values() {
+        for blob in [&conflict.base, &conflict.ours, &conflict.theirs].into_iter().flatten() {
+            reachable.insert(blob.clone());
+        }
+    }
+
+    let objects_dir = repo.get_objects_dir();
+    while let Some(commit_id) = stack.pop() {
+        if !reachable.insert(commit_id.clone()) {
+            continue;
+        }
+        let commit = match repo.get_commit_object(&commit_id) {
+            Ok(commit) => commit,
+            Err(_) => continue,
+        };
+        reachable.insert(commit.tree_id.clone());
+        if let Ok(tree_obj) = Object::load(&objects_dir, &commit.tree_id) {
+            if let Ok(tree) = Tree::from_object(&tree_obj) {
+                reachable.extend(tree.entries.into_iter().map(|e| e.object_id));
+            }
+        }
+        reachable.extend(commit.files.values().map(|fc| fc.content_hash.clone()));
+        stack.extend(commit.parent_ids.iter().cloned());
+    }
+
+    reachable
+}
+
+/// An unreachable loose object: id, type (`None` if it can't be decoded)
+/// and size on disk.
+pub struct UnreachableObject {
+    pub id: String,
+    pub object_type: Option<String>,
+    pub size: u64,
+}
+
+/// Loose objects not reachable from any ref, the stash or the reflog.
+pub fn unreachable_objects(repo: &Repository) -> Result<Vec<UnreachableObject>> {
+    let reflog = Reflog::load(&repo.git_dir)?;
+    let reachable = collect_reachable(repo, &reflog);
+    let objects_dir = repo.get_objects_dir();
+
+    let mut unreachable = Vec::new();
+    for id in list_loose_objects(repo)? {
+        if reachable.contains(&id) {
+            continue;
+        }
+        let path = objects_dir.join(&id[..2]).join(&id[2..]);
+        let data = fs::read(&path)?;
+        unreachable.push(UnreachableObject {
+            object_type: Object::from_compressed(&data).ok().map(|o| o.object_type),
+            size: data.len() as u64,
+            id,
+        });
+    }
+    unreachable.sort_by(|a, b| (&a.object_type, &a.id).cmp(&(&b.object_type, &b.id)));
+    Ok(unreachable)
+}
+
+/// Print unreachable objects grouped by type, with a total.
+pub fn print_unreachable(objects: &[UnreachableObject]) {
+    let mut current_type = None;
+    for object in objects {
+        if current_type != Some(&object.object_type) {
+            current_type = Some(&object.object_type);
+            let heading = object.object_type.as_deref().unwrap_or("unknown");
+            println!("{}", format!("{}:", heading).bold());
+        }
+        println!("  {} {} bytes", object.id.yellow(), object.size);
+    }
+    let total: u64 = objects.iter().map(|o| o.size).sum();
+    println!(
+        "Unreachable: {} objects, {} bytes",
+        objects.len().to_string().cyan(),
+        total.to_string().cyan()
+    );
+}
+
+/// Ids of all loose objects under `.helix/objects/xx/`.
+pub fn list_loose_objects(repo: &Repository) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    let objects_dir = repo.get_objects_dir();
+    if !objects_dir.exists() {
+        return Ok(ids);
+    }
+
+    for entry in fs::read_dir(&objects_dir)? {
+        let entry = entry?;
+        let shard = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type()?.is_dir() || !is_hex(&shard, 2) {
+            continue;
+        }
+        for obj in fs::read_dir(entry.path())? {
+            let obj = obj?;
+            let name = obj.file_name().to_string_lossy().to_string();
+            if obj.file_type()?.is_file() && is_hex(&name, 62) {
+                ids.push(format!("{}{}", shard, name));
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Whether `name` is exactly `len` lowercase hex digits.
+fn is_hex(name: &str, len: usize) -> bool {
+    name.len() == len && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Entries under `.helix/objects` that don't fit the loose object layout:
+/// two-hex-digit shard directories holding files named by the remaining 62
+/// hex digits of the id. `pack` and `info` are left to pack storage.
+pub fn stray_object_files(repo: &Repository) -> Result<Vec<PathBuf>> {
+    let mut stray = Vec::new();
+    let objects_dir = repo.get_objects_dir();
+    if !objects_dir.exists() {
+        return Ok(stray);
+    }
+
+    for entry in fs::read_dir(&objects_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type()?.is_dir() {
+            stray.push(entry.path());
+            continue;
+        }
+        if name == "pack" || name == "info" {
+            continue;
+        }
+        if !is_hex(&name, 2) {
+            stray.push(entry.path());
+            continue;
+        }
+        for obj in fs::read_dir(entry.path())? {
+            let obj = obj?;
+            let obj_name = obj.file_name().to_string_lossy().to_string();
+            if !obj.file_type()?.is_file() || !is_hex(&obj_name, 62) {
+                stray.push(obj.path());
+            }
+        }
+    }
+
+    stray.sort();
+    Ok(stray)
+}
+
+/// Delete shard directories left empty after objects were removed.
+/// Returns how many were removed.
+pub fn remove_empty_shards(repo: &Repository) -> Result<usize> {
+    let objects_dir = repo.get_objects_dir();
+    if !objects_dir.exists() {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    for entry in fs::read_dir(&objects_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type()?.is_dir() || !is_hex(&name, 2) {
+            continue;
+        }
+        if fs::read_dir(entry.path())?.next().is_none() {
+            fs::remove_dir(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{checkout, prune, reset};
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn detached_head_commits_are_reachable() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.write("a", "2\n");
+        t.commit("second").await;
+        checkout::checkout_branch(&mut t.repo, &first[..8], false).await.unwrap();
+        t.write("a", "detached\n");
+        let detached = t.commit("detached").await;
+        // Without the reflog, HEAD is the only thing pointing at it
+        std::fs::remove_file(Reflog::path(&t.repo.git_dir)).unwrap();
+
+        let unreachable = unreachable_objects(&t.repo).unwrap();
+        assert!(unreachable.is_empty(), "{:?}", unreachable.iter().map(|o| &o.id).collect::<Vec<_>>());
+        prune::run_prune(&t.repo, false).await.unwrap();
+        assert!(t.repo.get_commit_object(&detached).is_ok());
+    }
+
+    #[tokio::test]
+    async fn remote_tracking_refs_are_roots() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("first").await;
+        t.checkout_new("fetched").await;
+        t.write("a", "2\n");
+        let fetched = t.commit("fetched").await;
+        t.checkout("main").await;
+        // Only origin/main remembers the commit, as after a fetch
+        t.repo.add_remote("origin", "http://example.invalid/repo").unwrap();
+        t.repo.set_remote_ref("origin", "main", &fetched).unwrap();
+        t.repo.branches.remove("fetched");
+        std::fs::remove_file(Reflog::path(&t.repo.git_dir)).unwrap();
+
+        assert!(unreachable_objects(&t.repo).unwrap().is_empty());
+        assert!(collect_reachable(&t.repo, &Reflog::default()).contains(&fetched));
+    }
+
+    fn packs(t: &TestRepo) -> Vec<PathBuf> {
+        let mut packs: Vec<PathBuf> = fs::read_dir(pack::pack_dir(&t.repo.get_objects_dir()))
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "pack"))
+            .collect();
+        packs.sort();
+        packs
+    }
+
+    #[tokio::test]
+    async fn repack_merges_packs_but_leaves_kept_ones() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("first").await;
+        run_gc(&t.repo, None).await.unwrap();
+        let kept = packs(&t).remove(0);
+        fs::write(pack::keep_marker(&kept), "").unwrap();
+
+        t.write("a", "2\n");
+        t.commit("second").await;
+        run_gc(&t.repo, None).await.unwrap();
+        t.write("a", "3\n");
+        let third = t.commit("third").await;
+        run_gc(&t.repo, None).await.unwrap();
+
+        // The kept pack is untouched; the other two were merged into one
+        let after = packs(&t);
+        assert_eq!(after.len(), 2);
+        assert!(after.contains(&kept));
+        assert!(pack::index_path(&kept).exists());
+        assert!(list_loose_objects(&t.repo).unwrap().is_empty());
+        let tree = t.repo.get_commit_object(&third).unwrap().tree_files();
+        assert!(Object::load(&t.repo.get_objects_dir(), &tree["a"]).is_ok());
+
+        fs::remove_file(pack::keep_marker(&kept)).unwrap();
+        run_gc(&t.repo, None).await.unwrap();
+        assert_eq!(packs(&t).len(), 1);
+        assert_eq!(t.repo.get_commit_object(&t.head()).unwrap().message, "third");
+    }
+
+    #[tokio::test]
+    async fn packed_objects_still_load() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        t.write("dir/b", "b\n");
+        let head = t.commit("first").await;
+        let objects_dir = t.repo.get_objects_dir();
+        let loose = list_loose_objects(&t.repo).unwrap();
+
+        run_gc(&t.repo, None).await.unwrap();
+
+        assert!(list_loose_objects(&t.repo).unwrap().is_empty());
+        for id in &loose {
+            let object = Object::load(&objects_dir, id).unwrap();
+            assert_eq!(Object::new(object.object_type.clone(), object.data).id, *id);
+        }
+        let files = t.repo.get_commit_object(&head).unwrap().tree_files();
+        assert_eq!(Object::load(&objects_dir, &files["dir/b"]).unwrap().data, b"b\n");
+    }
+
+    #[tokio::test]
+    async fn fresh_unreachable_objects_are_spared() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        t.commit("first").await;
+        let orphan = Object::new("blob".to_string(), b"not referenced yet".to_vec());
+        orphan.save(&t.repo.get_objects_dir()).unwrap();
+
+        run_gc(&t.repo, None).await.unwrap();
+        assert!(Object::load(&t.repo.get_objects_dir(), &orphan.id).is_ok());
+
+        run_gc(&t.repo, Some("now")).await.unwrap();
+        assert!(Object::load(&t.repo.get_objects_dir(), &orphan.id).is_err());
+    }
+
+    #[tokio::test]
+    async fn gc_and_commit_exclude_each_other() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        t.commit("first").await;
+        let orphan = Object::new("blob".to_string(), b"staged elsewhere".to_vec());
+        orphan.save(&t.repo.get_objects_dir()).unwrap();
+
+        // A commit in flight holds the lock, so gc leaves everything alone
+        let lock = IndexLock::acquire(&t.repo.git_dir).unwrap();
+        assert!(run_gc(&t.repo, Some("now")).await.is_err());
+        assert!(prune::run_prune(&t.repo, false).await.is_err());
+        assert!(Object::load(&t.repo.get_objects_dir(), &orphan.id).is_ok());
+        drop(lock);
+
+        run_gc(&t.repo, Some("now")).await.unwrap();
+        assert!(!t.repo.git_dir.join("index.lock").exists());
+    }
+
+    #[tokio::test]
+    async fn reset_orphans_survive_until_the_reflog_expires() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.write("a", "2\n");
+        let orphaned = t.commit("second").await;
+        reset::reset_repository(&mut t.repo, &first, "hard", true).await.unwrap();
+
+        // Only the reflog still remembers it
+        assert!(!collect_reachable(&t.repo, &Reflog::default()).contains(&orphaned));
+        run_gc(&t.repo, None).await.unwrap();
+        assert!(t.repo.get_commit_object(&orphaned).is_ok());
+
+        run_gc(&t.repo, Some("now")).await.unwrap();
+        assert!(t.repo.get_commit_object(&orphaned).is_err());
+        assert!(t.repo.get_commit_object(&first).is_ok());
+    }
+
+    #[tokio::test]
+    async fn recently_packed_orphans_are_put_back_loose() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.write("a", "2\n");
+        let orphaned = t.commit("second").await;
+        reset::reset_repository(&mut t.repo, &first, "hard", true).await.unwrap();
+        run_gc(&t.repo, None).await.unwrap();
+        assert!(!list_loose_objects(&t.repo).unwrap().contains(&orphaned));
+
+        // Unreachable now, but the pack holding it is younger than the cutoff
+        std::fs::remove_file(Reflog::path(&t.repo.git_dir)).unwrap();
+        run_gc(&t.repo, None).await.unwrap();
+        assert!(list_loose_objects(&t.repo).unwrap().contains(&orphaned));
+        assert!(t.repo.get_commit_object(&orphaned).is_ok());
+    }
+
+    #[tokio::test]
+    async fn pruning_the_last_object_of_a_shard_removes_the_shard() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("first").await;
+        let objects_dir = t.repo.get_objects_dir();
+        // An unreachable blob alone in its shard
+        let orphan = (0..)
+            .map(|i| Object::new("blob".to_string(), format!("orphan {}", i).into_bytes()))
+            .find(|object| !objects_dir.join(&object.id[..2]).exists())
+            .unwrap();
+        orphan.save(&objects_dir).unwrap();
+        let shard = objects_dir.join(&orphan.id[..2]);
+
+        prune::run_prune(&t.repo, false).await.unwrap();
+        assert!(!shard.exists());
+        assert!(stray_object_files(&t.repo).unwrap().is_empty());
+        assert_eq!(remove_empty_shards(&t.repo).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn files_outside_the_shard_layout_are_stray() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("first").await;
+        assert!(stray_object_files(&t.repo).unwrap().is_empty());
+
+        let objects_dir = t.repo.get_objects_dir();
+        fs::write(objects_dir.join("README"), "").unwrap();
+        fs::create_dir_all(objects_dir.join("zz")).unwrap();
+        fs::create_dir_all(objects_dir.join("ab")).unwrap();
+        fs::write(objects_dir.join("ab").join("short"), "").unwrap();
+        let stray: Vec<String> = stray_object_files(&t.repo)
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(&objects_dir).unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(stray, ["README", "ab/short", "zz"]);
+    }
+}
@@ -1,40 +1,104 @@
+use crate::commands::diff;
 use crate::core::commit::Commit;
 use crate::core::repository::Repository;
+use crate::core::object::Object;
+use crate::core::tag::Tags;
+use crate::utils::{diff_utils, path_utils};
+use crate::utils::graph::GraphRenderer;
+use crate::utils::hash_utils::get_short_hash;
 use anyhow::Result;
 use colored::*;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::io::IsTerminal;
+
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    pub limit: usize,
+    pub graph: bool,
+    /// Only show commits with more than one parent
+    pub merges: bool,
+    /// Hide commits with more than one parent
+    pub no_merges: bool,
+    /// Only show commits that change this path
+    pub path: Option<String>,
+    /// Keep tracing `path` through renames
+    pub follow: bool,
+    /// Print each commit's diff against its first parent
+    pub patch: bool,
+    /// Print each commit's diffstat against its first parent
+    pub stat: bool,
+    /// Start from this revision instead of HEAD, e.g. `origin/main`
+    pub rev: Option<String>,
+    /// Only show commits whose author name or email contains this
+    pub author: Option<String>,
+    /// Only show commits whose message contains this
+    pub grep: Option<String>,
+    /// Only show commits made at or after this time
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only show commits made at or before this time
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl LogOptions {
+    fn shows(&self, commit: &Commit) -> bool {
+        let is_merge = commit.parent_ids.len() > 1;
+        if self.merges && !is_merge || self.no_merges && is_merge {
+            return false;
+        }
+        if let Some(author) = &self.author {
+            if !commit.author.contains(author.as_str()) && !commit.email.contains(author.as_str()) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.grep {
+            if !commit.message.contains(pattern.as_str()) {
+                return false;
+            }
+        }
+        self.since.is_none_or(|since| commit.timestamp >= since)
+            && self.until.is_none_or(|until| commit.timestamp <= until)
+    }
+}
+
+pub async fn show_log(repo: &Repository, options: &LogOptions) -> Result<()> {
+    if options.graph && (std::env::var_os("NO_COLOR").is_some() || !std::io::stdout().is_terminal())
+    {
+        colored::control::set_override(false);
+    }
 
-pub async fn show_log(repo: &Repository, limit: usize) -> Result<()> {
     println!("{}", "📜 Commit History".bold().blue());
     println!("{}", "=".repeat(40).blue());
 
-    if let Some(current_branch) = repo.get_current_branch() {
-        if let Some(head_commit) = current_branch.get_head_commit() {
-            let mut queue = VecDeque::new();
-            let mut visited = HashSet::new();
-            queue.push_back((head_commit.clone(), 0));
-            let mut commit_count = 0;
-            while let Some((commit_id, depth)) = queue.pop_front() {
-                if visited.contains(&commit_id) || commit_count >= limit {
-                    continue;
-                }
-                if let Ok(commit_object) =
-                    crate::core::object::Object::load(&repo.get_objects_dir(), &commit_id)
-                {
-                    if let Ok(commit) = Commit::from_object(&commit_object) {
-                        let is_head = commit_count == 0;
-                        let valid = commit.verify();
-                        display_commit_dag(&commit, is_head, depth, valid);
-                        for parent in &commit.parent_ids {
-                            queue.push_back((parent.clone(), depth + 1));
-                        }
-                        visited.insert(commit_id);
-                        commit_count += 1;
-                    }
-                }
+    let start = match &options.rev {
+        Some(rev) => Some(repo.resolve_rev(rev)?),
+        None => repo.head_commit().cloned(),
+    };
+    let current_head = repo.resolve_rev("HEAD").ok();
+    if let Some(head_commit) = &start {
+        if options.graph {
+            return show_log_graph(repo, head_commit, options);
+        }
+        if let Some(path) = &options.path {
+            return show_path_log(repo, head_commit, path, options);
+        }
+        let labels = decorations(repo)?;
+        for entry in log_commits(repo, head_commit, options) {
+            let commit = &entry.commit;
+            display_commit_dag(
+                commit,
+                current_head.as_ref() == Some(&entry.id),
+                diff::changed_file_count(repo, commit)?,
+                commit.verify(),
+                labels.get(&entry.id).map_or(&[], Vec::as_slice),
+            );
+            if options.stat {
+                diff::print_commit_stat(repo, commit)?;
+                println!();
+            }
+            if options.patch {
+                diff::print_commit_patch(repo, commit, None)?;
+                println!();
             }
-        } else {
-            println!("{}", "No commits yet".yellow());
         }
     } else {
         println!("{}", "No commits yet".yellow());
@@ -42,17 +106,217 @@ pub async fn show_log(repo: &Repository, limit: usize) -> Result<()> {
     Ok(())
 }
 
+/// A commit `log` lists, under its object id.
+struct LogEntry {
+    id: String,
+    commit: Commit,
+    /// Name of the followed path in this commit, for a path log
+    path: Option<String>,
+}
+
+/// The commits `start`'s history shows under `options`, breadth first, up
+/// to `options.limit`.
+fn log_commits(repo: &Repository, start: &str, options: &LogOptions) -> Vec<LogEntry> {
+    let mut entries = Vec::new();
+    let mut queue = VecDeque::from([start.to_string()]);
+    let mut visited = HashSet::new();
+    while let Some(commit_id) = queue.pop_front() {
+        if entries.len() >= options.limit {
+            break;
+        }
+        if !visited.insert(commit_id.clone()) {
+            continue;
+        }
+        let Ok(commit) = repo.get_commit_object(&commit_id) else {
+            continue;
+        };
+        queue.extend(commit.parent_ids.iter().cloned());
+        if options.shows(&commit) {
+            entries.push(LogEntry { id: commit_id, commit, path: None });
+        }
+    }
+    entries
+}
+
+/// Print history one commit per line with lane graph prefixes.
+///
+/// Commits are emitted newest first, but never before all of their children,
+/// so every lane can be drawn continuously.
+fn show_log_graph(repo: &Repository, head_commit: &str, options: &LogOptions) -> Result<()> {
+    let mut commits: HashMap<String, Commit> = HashMap::new();
+    let mut stack = vec![head_commit.to_string()];
+    while let Some(commit_id) = stack.pop() {
+        if commits.contains_key(&commit_id) {
+            continue;
+        }
+        if let Ok(commit_object) = Object::load(&repo.get_objects_dir(), &commit_id) {
+            if let Ok(commit) = Commit::from_object(&commit_object) {
+                stack.extend(commit.parent_ids.iter().cloned());
+                commits.insert(commit_id, commit);
+            }
+        }
+    }
+
+    let mut pending_children: HashMap<&str, usize> = HashMap::new();
+    for commit in commits.values() {
+        for parent in &commit.parent_ids {
+            *pending_children.entry(parent.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    let mut ready = BinaryHeap::new();
+    if let Some(commit) = commits.get(head_commit) {
+        ready.push((commit.timestamp, head_commit.to_string()));
+    }
+
+    let labels = decorations(repo)?;
+    let use_color = colored::control::SHOULD_COLORIZE.should_colorize();
+    let mut renderer = GraphRenderer::new(use_color);
+    let mut shown = 0;
+    while let Some((_, commit_id)) = ready.pop() {
+        if shown >= options.limit {
+            break;
+        }
+        let commit = &commits[&commit_id];
+        let parents: Vec<String> = commit
+            .parent_ids
+            .iter()
+            .filter(|p| commits.contains_key(*p))
+            .cloned()
+            .collect();
+
+        // Filtered-out commits still advance the lanes, they just aren't printed
+        let rows = renderer.next(&commit_id, &parents);
+        if options.shows(commit) {
+            let decoration = match labels.get(&commit_id) {
+                Some(names) => format!(" ({})", names.join(", ")).yellow().to_string(),
+                None => String::new(),
+            };
+            println!(
+                "{}{}{} {}",
+                rows.commit_row,
+                get_short_hash(&commit_id).yellow(),
+                decoration,
+                commit.message.lines().next().unwrap_or("")
+            );
+            for connector in rows.connectors {
+                println!("{}", connector.trim_end());
+            }
+            shown += 1;
+        }
+
+        for parent in &parents {
+            if let Some(count) = pending_children.get_mut(parent.as_str()) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push((commits[parent].timestamp, parent.clone()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// History of a single path, newest first.
+///
+/// A commit is listed when it records a change to the tracked path. With
+/// `follow`, a commit that introduces the path is checked for a rename from
+/// its first parent, and the walk continues with the old name from there.
+fn show_path_log(
+    repo: &Repository,
+    head_commit: &str,
+    path: &str,
+    options: &LogOptions,
+) -> Result<()> {
+    let labels = decorations(repo)?;
+    let current_head = repo.resolve_rev("HEAD").ok();
+    let entries = path_log_commits(repo, head_commit, path, options);
+    for entry in &entries {
+        let commit = &entry.commit;
+        display_commit_dag(
+            commit,
+            current_head.as_ref() == Some(&entry.id),
+            diff::changed_file_count(repo, commit)?,
+            commit.verify(),
+            labels.get(&entry.id).map_or(&[], Vec::as_slice),
+        );
+        if options.stat {
+            diff::print_commit_stat(repo, commit)?;
+            println!();
+        }
+        if options.patch {
+            diff::print_commit_patch(repo, commit, entry.path.as_deref())?;
+            println!();
+        }
+    }
+    if entries.is_empty() {
+        println!("{}", "No commits touch this path".yellow());
+    }
+    Ok(())
+}
+
+fn path_log_commits(
+    repo: &Repository,
+    head_commit: &str,
+    path: &str,
+    options: &LogOptions,
+) -> Vec<LogEntry> {
+    let objects_dir = repo.get_objects_dir();
+    let path = path_utils::repo_relative_path(&repo.path, path);
+    let mut entries = Vec::new();
+    let mut queue = VecDeque::new();
+    let mut visited = HashSet::new();
+    queue.push_back((head_commit.to_string(), path));
+    while let Some((commit_id, path)) = queue.pop_front() {
+        if entries.len() >= options.limit {
+            break;
+        }
+        if !visited.insert(commit_id.clone()) {
+            continue;
+        }
+        let commit = match repo.get_commit_object(&commit_id) {
+            Ok(commit) => commit,
+            Err(_) => continue,
+        };
+        let tree = commit.tree_files();
+        let parent_tree = commit
+            .parent_ids
+            .first()
+            .and_then(|id| repo.get_commit_object(id).ok())
+            .map(|parent| parent.tree_files())
+            .unwrap_or_default();
+
+        let mut parent_path = path.clone();
+        if options.follow && tree.contains_key(&path) && !parent_tree.contains_key(&path) {
+            let renames = diff_utils::detect_renames(
+                &parent_tree,
+                &tree,
+                diff_utils::DEFAULT_RENAME_THRESHOLD,
+                false,
+                |hash| Object::load(&objects_dir, hash).ok().map(|o| o.text()),
+            );
+            if let Some(pair) = renames.into_iter().find(|pair| pair.new_path == path) {
+                parent_path = pair.old_path;
+            }
+        }
+        for parent in &commit.parent_ids {
+            queue.push_back((parent.clone(), parent_path.clone()));
+        }
+
+        let touched = commit.files.contains_key(&path) && tree.get(&path) != parent_tree.get(&path);
+        if touched && options.shows(&commit) {
+            entries.push(LogEntry { id: commit_id, commit, path: Some(path) });
+        }
+    }
+    entries
+}
+
 #[allow(dead_code)]
 pub async fn verify_history(repo: &Repository, commit_id: Option<&str>) -> Result<()> {
     let target_commit = if let Some(cid) = commit_id {
         cid.to_string()
-    } else if let Some(current_branch) = repo.get_current_branch() {
-        if let Some(head_commit) = current_branch.get_head_commit() {
-            head_commit.clone()
-        } else {
-            println!("No commits yet");
-            return Ok(());
-        }
+    } else if let Some(head_commit) = repo.head_commit() {
+        head_commit.clone()
     } else {
         println!("No commits yet");
         return Ok(());
@@ -89,45 +353,41 @@ pub async fn show_dag(repo: &Repository) -> Result<()> {
     use std::collections::{HashSet, VecDeque};
     println!("{}", "Commit DAG Visualization".bold().blue());
     println!("{}", "=".repeat(40).blue());
-    if let Some(current_branch) = repo.get_current_branch() {
-        if let Some(head_commit) = current_branch.get_head_commit() {
-            let mut queue = VecDeque::new();
-            let mut visited = HashSet::new();
-            queue.push_back((head_commit.clone(), 0));
-            while let Some((commit_id, depth)) = queue.pop_front() {
-                if visited.contains(&commit_id) {
-                    continue;
-                }
-                if let Ok(commit_object) =
-                    crate::core::object::Object::load(&repo.get_objects_dir(), &commit_id)
-                {
-                    if let Ok(commit) = crate::core::commit::Commit::from_object(&commit_object) {
-                        let indent = "  ".repeat(depth);
-                        let parents = if commit.parent_ids.is_empty() {
-                            "(root)".to_string()
-                        } else {
-                            commit
-                                .parent_ids
-                                .iter()
-                                .map(|p| crate::utils::hash_utils::get_short_hash(p))
-                                .collect::<Vec<_>>()
-                                .join(", ")
-                        };
-                        println!(
-                            "{}{} -> {}",
-                            indent,
-                            crate::utils::hash_utils::get_short_hash(&commit.id).cyan(),
-                            parents
-                        );
-                        for parent in &commit.parent_ids {
-                            queue.push_back((parent.clone(), depth + 1));
-                        }
-                        visited.insert(commit_id);
+    if let Some(head_commit) = repo.head_commit() {
+        let mut queue = VecDeque::new();
+        let mut visited = HashSet::new();
+        queue.push_back((head_commit.clone(), 0));
+        while let Some((commit_id, depth)) = queue.pop_front() {
+            if visited.contains(&commit_id) {
+                continue;
+            }
+            if let Ok(commit_object) =
+                crate::core::object::Object::load(&repo.get_objects_dir(), &commit_id)
+            {
+                if let Ok(commit) = crate::core::commit::Commit::from_object(&commit_object) {
+                    let indent = "  ".repeat(depth);
+                    let parents = if commit.parent_ids.is_empty() {
+                        "(root)".to_string()
+                    } else {
+                        commit
+                            .parent_ids
+                            .iter()
+                            .map(|p| crate::utils::hash_utils::get_short_hash(p))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    };
+                    println!(
+                        "{}{} -> {}",
+                        indent,
+                        crate::utils::hash_utils::get_short_hash(&commit.id).cyan(),
+                        parents
+                    );
+                    for parent in &commit.parent_ids {
+                        queue.push_back((parent.clone(), depth + 1));
                     }
+                    visited.insert(commit_id);
                 }
             }
-        } else {
-            println!("{}", "No commits yet".yellow());
         }
     } else {
         println!("{}", "No commits yet".yellow());
@@ -135,11 +395,28 @@ pub async fn show_dag(repo: &Repository) -> Result<()> {
     Ok(())
 }
 
-fn display_commit_dag(
+/// Labels to print next to each commit: `tag: <name>` for tags and
+/// `<remote>/<branch>` for remote-tracking refs.
+pub fn decorations(repo: &Repository) -> Result<HashMap<String, Vec<String>>> {
+    let mut labels: HashMap<String, Vec<String>> = HashMap::new();
+    for (commit_id, names) in Tags::load(&repo.git_dir)?.by_commit(&repo.get_objects_dir()) {
+        labels
+            .entry(commit_id)
+            .or_default()
+            .extend(names.iter().map(|name| format!("tag: {}", name)));
+    }
+    for (name, commit_id) in repo.remote_tracking_refs() {
+        labels.entry(commit_id).or_default().insert(0, name);
+    }
+    Ok(labels)
+}
+
+pub fn display_commit_dag(
     commit: &crate::core::commit::Commit,
     is_head: bool,
-    _depth: usize,
+    files_changed: usize,
     valid: bool,
+    decorations: &[String],
 ) {
     let branch_indicator = if is_head { "HEAD -> " } else { "     " };
     let commit_id = crate::utils::hash_utils::get_short_hash(&commit.id);
@@ -158,11 +435,17 @@ fn display_commit_dag(
     } else {
         "INVALID".red()
     };
+    let decoration = if decorations.is_empty() {
+        String::new()
+    } else {
+        format!("({}) ", decorations.join(", ")).yellow().to_string()
+    };
     println!(
-        "{}{} {} {}",
+        "{}{} {} {}{}",
         branch_indicator,
         commit_id.cyan(),
         validity,
+        decoration,
         commit.message.bold()
     );
     println!("{}", format!("    Parents: {}", parents).dimmed());
@@ -180,7 +463,208 @@ fn display_commit_dag(
     );
     println!(
         "{}",
-        format!("    Files:  {} files changed", commit.files.len()).dimmed()
+        format!("    Files:  {} files changed", files_changed).dimmed()
     );
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{strip_ansi, TestRepo};
+
+    fn messages(entries: Vec<LogEntry>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.commit.message).collect()
+    }
+
+    fn all() -> LogOptions {
+        LogOptions { limit: usize::MAX, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn merge_filters_pick_out_the_merge() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        t.commit("base").await;
+        t.checkout_new("topic").await;
+        t.write("b", "b\n");
+        t.commit("topic work").await;
+        t.checkout("main").await;
+        t.write("c", "c\n");
+        t.commit("main work").await;
+        let head = t.merge("topic").await;
+
+        let merges = LogOptions { merges: true, ..all() };
+        let only = log_commits(&t.repo, &head, &merges);
+        assert_eq!(only.len(), 1);
+        assert_eq!(only[0].id, head);
+
+        let no_merges = LogOptions { no_merges: true, ..all() };
+        let mut rest = messages(log_commits(&t.repo, &head, &no_merges));
+        rest.sort();
+        assert_eq!(rest, vec!["base", "main work", "topic work"]);
+        assert_eq!(log_commits(&t.repo, &head, &all()).len(), 4);
+    }
+
+    #[tokio::test]
+    async fn follow_traces_a_file_back_through_its_rename() {
+        let mut t = TestRepo::new();
+        let body = "one\ntwo\nthree\nfour\nfive\n";
+        t.write("a", body);
+        t.commit("create a").await;
+        t.write("a", &format!("{}six\n", body));
+        t.commit("extend a").await;
+        t.write("other", "unrelated\n");
+        t.commit("unrelated").await;
+        t.remove("a");
+        t.write("b", &format!("{}six\n", body));
+        let head = t.commit("rename a to b").await;
+
+        let plain = path_log_commits(&t.repo, &head, "b", &all());
+        assert_eq!(messages(plain), vec!["rename a to b"]);
+
+        let follow = LogOptions { follow: true, ..all() };
+        let entries = path_log_commits(&t.repo, &head, "b", &follow);
+        let paths: Vec<_> = entries.iter().map(|entry| entry.path.clone().unwrap()).collect();
+        assert_eq!(paths, vec!["b", "a", "a"]);
+        assert_eq!(messages(entries), vec!["rename a to b", "extend a", "create a"]);
+    }
+
+    #[tokio::test]
+    async fn patch_output_includes_the_changed_lines() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "one\ntwo\n");
+        t.commit("root").await;
+        t.write("a.txt", "one\nTWO\n");
+        t.write("b.txt", "b\n");
+        t.write_bytes("c.bin", b"\0\x01");
+        let head = t.commit("edit").await;
+
+        let patches: Vec<String> = log_commits(&t.repo, &head, &all())
+            .iter()
+            .map(|entry| strip_ansi(&diff::render_commit_patch(&t.repo, &entry.commit, None).unwrap()))
+            .collect();
+        let edit: Vec<&str> = patches[0].lines().collect();
+        assert!(edit.contains(&"File: a.txt"));
+        assert!(edit.contains(&"-two"));
+        assert!(edit.contains(&"+TWO"));
+        assert!(edit.contains(&" one"));
+        assert!(edit.contains(&"+b"));
+        assert!(edit.contains(&"Binary files a/c.bin and b/c.bin differ"));
+        // The root commit shows everything as added
+        assert!(patches[1].contains("new file"));
+        assert!(patches[1].lines().any(|line| line == "+one"));
+
+        let edit = t.repo.get_commit_object(&head).unwrap();
+        let only_a = diff::render_commit_patch(&t.repo, &edit, Some("a.txt")).unwrap();
+        assert!(!strip_ansi(&only_a).contains("b.txt"));
+    }
+
+    #[tokio::test]
+    async fn stat_lists_the_files_of_every_commit() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "one\n");
+        t.commit("root").await;
+        t.write("a.txt", "one\ntwo\n");
+        t.write("b.txt", "b\n");
+        let head = t.commit("edit").await;
+
+        let stats: Vec<String> = log_commits(&t.repo, &head, &all())
+            .iter()
+            .map(|entry| strip_ansi(&diff::render_commit_stat(&t.repo, &entry.commit).unwrap()))
+            .collect();
+        assert_eq!(stats.len(), 2);
+        assert!(stats[0].contains(" a.txt |"));
+        assert!(stats[0].contains(" b.txt |"));
+        assert!(stats[0].contains("2 files changed, 2 insertions(+)"));
+        // The root commit shows its file as added
+        assert!(stats[1].contains(" a.txt |"));
+        assert!(!stats[1].contains("b.txt"));
+        assert!(stats[1].contains("1 file changed, 1 insertion(+)"));
+
+        let limited = LogOptions { limit: 1, ..all() };
+        assert_eq!(log_commits(&t.repo, &head, &limited).len(), 1);
+    }
+
+    /// Commits by different authors on different days, newest first:
+    /// "fix parser" (bob, March), "docs" (alice, February), "add parser"
+    /// (alice, January).
+    async fn history() -> (TestRepo, String) {
+        let mut t = TestRepo::new();
+        let commits = [
+            ("add parser", "Alice <alice@example.com>", "2024-01-10T12:00:00Z"),
+            ("docs", "Alice <alice@example.com>", "2024-02-10T12:00:00Z"),
+            ("fix parser", "Bob <bob@example.org>", "2024-03-10T12:00:00Z"),
+        ];
+        for (i, (message, author, date)) in commits.into_iter().enumerate() {
+            t.write("file", &format!("{}\n", i));
+            let options = crate::commands::commit::CommitOptions {
+                author: Some(author.to_string()),
+                date: Some(date.to_string()),
+                ..Default::default()
+            };
+            t.commit_with(message, &options).await.unwrap();
+        }
+        let head = t.head();
+        (t, head)
+    }
+
+    fn date(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        Some(crate::utils::date_utils::parse_date(value).unwrap())
+    }
+
+    #[tokio::test]
+    async fn each_filter_narrows_the_history() {
+        let (t, head) = history().await;
+        let log = |options: LogOptions| messages(log_commits(&t.repo, &head, &options));
+
+        let by_alice = LogOptions { author: Some("Alice".into()), ..all() };
+        assert_eq!(log(by_alice), vec!["docs", "add parser"]);
+        let by_email = LogOptions { author: Some("example.org".into()), ..all() };
+        assert_eq!(log(by_email), vec!["fix parser"]);
+
+        let grep = LogOptions { grep: Some("parser".into()), ..all() };
+        assert_eq!(log(grep), vec!["fix parser", "add parser"]);
+
+        let since = LogOptions { since: date("2024-02-01"), ..all() };
+        assert_eq!(log(since), vec!["fix parser", "docs"]);
+        let until = LogOptions { until: date("2024-02-10T12:00:00Z"), ..all() };
+        assert_eq!(log(until), vec!["docs", "add parser"]);
+    }
+
+    #[tokio::test]
+    async fn filters_combine_and_the_limit_counts_matches() {
+        let (t, head) = history().await;
+        let log = |options: LogOptions| messages(log_commits(&t.repo, &head, &options));
+
+        let alice_parser = LogOptions { author: Some("Alice".into()), grep: Some("parser".into()), ..all() };
+        assert_eq!(log(alice_parser), vec!["add parser"]);
+        let window = LogOptions { since: date("2024-02-01"), until: date("2024-03-01"), ..all() };
+        assert_eq!(log(window), vec!["docs"]);
+        let nobody = LogOptions { author: Some("Carol".into()), ..all() };
+        assert!(log(nobody).is_empty());
+
+        // The limit applies after filtering, so it reaches past bob's commit
+        let first_alice = LogOptions { limit: 1, author: Some("Alice".into()), ..Default::default() };
+        assert_eq!(log(first_alice), vec!["docs"]);
+    }
+
+    #[tokio::test]
+    async fn path_log_lists_only_the_commits_touching_the_file() {
+        let mut t = TestRepo::new();
+        t.write("src/main.rs", "fn main() {}\n");
+        t.write("README", "readme\n");
+        t.commit("create main").await;
+        t.write("README", "readme, edited\n");
+        t.commit("edit readme").await;
+        t.write("src/main.rs", "fn main() { run() }\n");
+        let head = t.commit("call run").await;
+
+        let entries = path_log_commits(&t.repo, &head, "src/main.rs", &all());
+        assert_eq!(messages(entries), vec!["call run", "create main"]);
+
+        let limited = LogOptions { limit: 1, ..Default::default() };
+        assert_eq!(messages(path_log_commits(&t.repo, &head, "src/main.rs", &limited)), vec!["call run"]);
+        assert!(path_log_commits(&t.repo, &head, "missing.rs", &all()).is_empty());
+    }
+}
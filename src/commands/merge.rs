@@ -1,9 +1,14 @@
+use crate::commands::{checkout, commit};
+use crate::core::commit::{ChangeType, Commit, FileChange};
+use crate::core::commit_graph::find_merge_base;
+use crate::core::index::{ConflictEntry, IndexEntry};
 use crate::core::object::Object;
 use crate::core::repository::Repository;
+use crate::utils::{eol, file_utils, hash_utils};
 use anyhow::Result;
 use colored::*;
 use diffy::merge;
-use crate::core::commit::ChangeType;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
 use chrono::Utc;
 
@@ -24,408 +29,911 @@ impl fmt::Display for MergeStrategy {
     }
 }
 
-fn find_merge_base(repo: &Repository, commit1: &str, commit2: &str) -> Option<String> {
-    use std::collections::{HashSet, VecDeque};
-    // Collect all ancestors of commit1
-    let mut ancestors1 = HashSet::new();
-    let mut queue = VecDeque::new();
-    queue.push_back(commit1.to_string());
-    while let Some(current) = queue.pop_front() {
-        if !ancestors1.insert(current.clone()) {
-            continue;
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// Merge commit message; replaces the default one
+    pub message: Option<String>,
+    /// Use the default message without opening an editor
+    pub no_edit: bool,
+    /// Merge branches that share no commits instead of refusing
+    pub allow_unrelated_histories: bool,
+}
+
+/// How the two histories relate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeKind {
+    /// Their commit is already part of ours
+    UpToDate,
+    /// Our commit is an ancestor of theirs, so the branch just moves forward
+    FastForward,
+    /// Both sides have commits of their own; a merge commit joins them
+    ThreeWay,
+}
+
+/// What merging does to one path whose result differs from our version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathOutcome {
+    /// The path ends up with this content
+    Write { content: Vec<u8>, mode: u32 },
+    /// The path is removed
+    Delete,
+    /// Both sides changed the path differently; `content` (conflict markers,
+    /// or our side for binaries and modify/delete conflicts) goes in the
+    /// working tree until it is resolved
+    Conflict { content: Vec<u8>, binary: bool },
+}
+
+/// Everything a merge would do, worked out from the base, ours and theirs
+/// trees before any file is touched.
+#[derive(Debug)]
+pub struct MergePlan {
+    pub ours: String,
+    pub theirs: String,
+    /// `None` for unrelated histories, which merge against an empty tree
+    pub base: Option<String>,
+    pub kind: MergeKind,
+    /// Paths where the result differs from ours
+    pub paths: BTreeMap<String, PathOutcome>,
+    /// Paths theirs changed that merged without conflict
+    pub clean: usize,
+    /// Paths changed on both sides that could not be merged
+    pub conflicts: Vec<String>,
+    /// Paths changed on both sides that the ours/theirs strategy settled
+    pub resolved: Vec<String>,
+}
+
+/// Work out the merge of `theirs` into `ours` (commit ids). Paths are
+/// compared against the merge base, so a path one side deleted stays
+/// deleted unless the other side changed it.
+pub fn plan_merge(
+    repo: &Repository,
+    ours: &str,
+    theirs: &str,
+    strategy: &MergeStrategy,
+    allow_unrelated_histories: bool,
+) -> Result<MergePlan> {
+    let base = find_merge_base(repo, ours, theirs);
+    if base.is_none() && !allow_unrelated_histories {
+        anyhow::bail!(
+            "refusing to merge unrelated histories: '{}' and '{}' have no common ancestor (use --allow-unrelated-histories)",
+            hash_utils::get_short_hash(ours),
+            hash_utils::get_short_hash(theirs)
+        );
+    }
+    let kind = match base.as_deref() {
+        Some(base) if base == theirs => MergeKind::UpToDate,
+        Some(base) if base == ours => MergeKind::FastForward,
+        _ => MergeKind::ThreeWay,
+    };
+
+    let ours_commit = repo.get_commit_object(ours)?;
+    let theirs_commit = repo.get_commit_object(theirs)?;
+    let base_commit = base.as_deref().map(|id| repo.get_commit_object(id)).transpose()?;
+    let base_tree = base_commit.as_ref().map(|c| c.tree_files()).unwrap_or_default();
+    let ours_tree = ours_commit.tree_files();
+    let theirs_tree = theirs_commit.tree_files();
+    let mode = |commit: Option<&crate::core::commit::Commit>, path: &str| {
+        commit.and_then(|c| c.get_file_change(path)).map(|fc| fc.mode)
+    };
+
+    let objects_dir = repo.get_objects_dir();
+    let load = |hash: Option<&String>| -> Result<Vec<u8>> {
+        match hash {
+            Some(hash) => Ok(Object::load(&objects_dir, hash)?.data),
+            None => Ok(Vec::new()),
         }
-        if let Ok(obj) = Object::load(&repo.get_objects_dir(), &current) {
-            if let Ok(commit) = crate::core::commit::Commit::from_object(&obj) {
-                for parent in &commit.parent_ids {
-                    queue.push_back(parent.clone());
-                }
-            }
+    };
+
+    let mut plan = MergePlan {
+        ours: ours.to_string(),
+        theirs: theirs.to_string(),
+        base: base.clone(),
+        kind,
+        paths: BTreeMap::new(),
+        clean: 0,
+        conflicts: Vec::new(),
+        resolved: Vec::new(),
+    };
+    let paths: BTreeSet<&String> = base_tree
+        .keys()
+        .chain(ours_tree.keys())
+        .chain(theirs_tree.keys())
+        .collect();
+    for path in paths {
+        let (base_blob, ours_blob, theirs_blob) =
+            (base_tree.get(path), ours_tree.get(path), theirs_tree.get(path));
+        // Unchanged by theirs, or changed the same way on both sides
+        if theirs_blob == base_blob || ours_blob == theirs_blob {
+            continue;
         }
-    }
-    // Walk ancestors of commit2, return first found in ancestors1
-    let mut queue = VecDeque::new();
-    queue.push_back(commit2.to_string());
-    while let Some(current) = queue.pop_front() {
-        if ancestors1.contains(&current) {
-            return Some(current);
+        let theirs_mode = mode(Some(&theirs_commit), path).unwrap_or(0o100644);
+        let take_theirs = || -> Result<PathOutcome> {
+            Ok(match theirs_blob {
+                Some(_) => PathOutcome::Write { content: load(theirs_blob)?, mode: theirs_mode },
+                None => PathOutcome::Delete,
+            })
+        };
+        // Only theirs changed it
+        if ours_blob == base_blob {
+            plan.paths.insert(path.clone(), take_theirs()?);
+            plan.clean += 1;
+            continue;
         }
-        if let Ok(obj) = Object::load(&repo.get_objects_dir(), &current) {
-            if let Ok(commit) = crate::core::commit::Commit::from_object(&obj) {
-                for parent in &commit.parent_ids {
-                    queue.push_back(parent.clone());
+
+        let (base_content, ours_content, theirs_content) =
+            (load(base_blob)?, load(ours_blob)?, load(theirs_blob)?);
+        // Only text is merged line by line; anything else (including bytes
+        // that are not UTF-8) is kept byte for byte
+        let texts = match [&base_content, &ours_content, &theirs_content].map(|c| std::str::from_utf8(c)) {
+            [Ok(base), Ok(ours), Ok(theirs)] => Some((base, ours, theirs)),
+            _ => None,
+        };
+        let binary = repo.attributes().has(path, "binary")
+            || texts.is_none()
+            || [&base_content, &ours_content, &theirs_content]
+                .iter()
+                .any(|c| file_utils::is_binary(c));
+        let markers = match (ours_blob, theirs_blob, texts) {
+            (Some(_), Some(_), Some((base_text, ours_text, theirs_text))) if !binary => {
+                let merged = diff3_merge(base_text, ours_text, theirs_text, std::path::Path::new(path));
+                if !merged.contains("<<<<<<<") {
+                    // Keep our mode unless only theirs changed it
+                    let ours_mode = mode(Some(&ours_commit), path).unwrap_or(0o100644);
+                    let mode = if Some(ours_mode) == mode(base_commit.as_ref(), path) {
+                        theirs_mode
+                    } else {
+                        ours_mode
+                    };
+                    plan.paths.insert(path.clone(), PathOutcome::Write { content: merged.into_bytes(), mode });
+                    plan.clean += 1;
+                    continue;
                 }
+                Some(merged.into_bytes())
+            }
+            _ => None,
+        };
+
+        match strategy {
+            MergeStrategy::Ours => plan.resolved.push(path.clone()),
+            MergeStrategy::Theirs => {
+                plan.paths.insert(path.clone(), take_theirs()?);
+                plan.resolved.push(path.clone());
+            }
+            MergeStrategy::Manual => {
+                plan.conflicts.push(path.clone());
+                let content = match (markers, ours_blob) {
+                    (Some(markers), _) => markers,
+                    (None, Some(_)) => ours_content,
+                    // We deleted what they modified: leave their version
+                    (None, None) => theirs_content,
+                };
+                plan.paths.insert(path.clone(), PathOutcome::Conflict { content, binary });
             }
         }
     }
-    None
+    Ok(plan)
 }
 
 pub async fn merge_branch(
     repo: &mut Repository,
     branch_name: &str,
     strategy: Option<MergeStrategy>,
+    preview: bool,
+    options: &MergeOptions,
 ) -> Result<()> {
     let strategy = strategy.unwrap_or(MergeStrategy::Manual);
-    if !repo.branches.contains_key(branch_name) {
-        println!(
-            "{}",
-            format!("Branch '{}' does not exist", branch_name).red()
-        );
-        return Ok(());
-    }
-    if branch_name == repo.current_branch {
+    // Anything that names a commit can be merged: a branch, a tag, a
+    // remote-tracking ref or a (short) commit id
+    let theirs = repo.resolve_rev(branch_name)?;
+    if repo.head_commit() == Some(&theirs) {
         println!("{}", "Cannot merge branch into itself".red());
         return Ok(());
     }
+    let kind = if repo.branches.contains_key(branch_name) { "branch" } else { "commit" };
+    let action = if preview { "Previewing merge of" } else { "Merging" };
     println!(
         "{}",
         format!(
-            "Merging branch '{}' into '{}' with strategy: {}",
+            "{} {} '{}' into '{}' with strategy: {}",
+            action,
+            kind,
             branch_name, repo.current_branch, strategy
         )
         .blue()
         .bold()
     );
-    
-    // Find merge base (for now, use main as base if exists)
-    let base_commit_id = repo
-        .branches
-        .get("main")
-        .and_then(|b| b.get_head_commit())
-        .cloned();
-    let ours_commit_id = repo
-        .get_current_branch()
-        .and_then(|b| b.get_head_commit())
-        .cloned();
-    let theirs_commit_id = repo
-        .branches
-        .get(branch_name)
-        .and_then(|b| b.get_head_commit())
-        .cloned();
-    
-    if let (Some(_base), Some(ours), Some(theirs)) =
-        (base_commit_id, ours_commit_id, theirs_commit_id)
-    {
-        // Find the true merge base
-        let resolved_base_commit_id = match find_merge_base(repo, &ours, &theirs) {
-            Some(base) => base,
-            None => {
-                println!("{}", "Warning: No common ancestor found, using root commit as base".yellow());
-                // Fallback: use the root commit (first commit in ours history)
-                let mut root = ours.clone();
-                let mut last = ours.clone();
-                while let Ok(obj) = Object::load(&repo.get_objects_dir(), &root) {
-                    if let Ok(commit) = crate::core::commit::Commit::from_object(&obj) {
-                        if let Some(parent) = commit.parent_ids.first() {
-                            last = parent.clone();
-                            root = parent.clone();
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                last
-            }
+
+    let Some(ours) = repo.head_commit().cloned() else {
+        println!("{}", "Could not find merge base or commits".red());
+        println!("Make sure both branches have commits and try again.");
+        return Ok(());
+    };
+    let plan = plan_merge(repo, &ours, &theirs, &strategy, options.allow_unrelated_histories)?;
+    if preview {
+        let conflict_list = if plan.conflicts.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", plan.conflicts.join(", "))
         };
-        
-        // Load commits with better error handling
-        let base_commit = match Object::load(&repo.get_objects_dir(), &resolved_base_commit_id) {
-            Ok(obj) => match crate::core::commit::Commit::from_object(&obj) {
-                Ok(commit) => commit,
-                Err(_) => {
-                    println!("{}", format!("Failed to parse base commit: {}", resolved_base_commit_id).red());
-                    return Ok(());
-                }
-            },
-            Err(_) => {
-                println!("{}", format!("Failed to load base commit: {}", resolved_base_commit_id).red());
-                return Ok(());
-            }
+        let fast_forward = if plan.kind == MergeKind::FastForward {
+            "yes"
+        } else {
+            "no"
         };
-        
-        let ours_commit = match Object::load(&repo.get_objects_dir(), &ours) {
-            Ok(obj) => match crate::core::commit::Commit::from_object(&obj) {
-                Ok(commit) => commit,
-                Err(_) => {
-                    println!("{}", format!("Failed to parse our commit: {}", ours).red());
-                    return Ok(());
-                }
-            },
-            Err(_) => {
-                println!("{}", format!("Failed to load our commit: {}", ours).red());
-                return Ok(());
-            }
+        let resolved = if plan.resolved.is_empty() {
+            String::new()
+        } else {
+            format!(", {} resolved using '{}'", plan.resolved.len().to_string().yellow(), strategy)
         };
-        
-        let theirs_commit = match Object::load(&repo.get_objects_dir(), &theirs) {
-            Ok(obj) => match crate::core::commit::Commit::from_object(&obj) {
-                Ok(commit) => commit,
-                Err(_) => {
-                    println!("{}", format!("Failed to parse their commit: {}", theirs).red());
-                    return Ok(());
-                }
-            },
-            Err(_) => {
-                println!("{}", format!("Failed to load their commit: {}", theirs).red());
-                return Ok(());
+        println!(
+            "{} clean, {} conflict{}{}{}, fast-forward: {}",
+            plan.clean.to_string().green(),
+            plan.conflicts.len().to_string().red(),
+            if plan.conflicts.len() == 1 { "" } else { "s" },
+            conflict_list,
+            resolved,
+            fast_forward
+        );
+        if plan.kind == MergeKind::UpToDate {
+            println!("{}", "Already up to date".green());
+        }
+        println!("{}", "Preview only: no files were changed".dimmed());
+        return Ok(());
+    }
+    match plan.kind {
+        MergeKind::UpToDate => {
+            println!("{}", "Already up to date".green());
+            return Ok(());
+        }
+        MergeKind::FastForward => {
+            let operation = format!("merge {}", branch_name);
+            let (written, removed) = checkout::switch_worktree(repo, Some(&theirs), &operation, false)?;
+            repo.set_head(&theirs)?;
+            repo.log_ref_update(Some(&ours), &theirs, &format!("{}: Fast-forward", operation))?;
+            println!(
+                "{}",
+                format!(
+                    "Fast-forward {}..{}",
+                    hash_utils::get_short_hash(&ours),
+                    hash_utils::get_short_hash(&theirs)
+                )
+                .green()
+                .bold()
+            );
+            if written + removed > 0 {
+                println!("Updated {} files, removed {}", written, removed);
             }
-        };
+            return Ok(());
+        }
+        MergeKind::ThreeWay => {}
+    }
 
-        // Collect all file paths from base, ours, and theirs
-        let mut all_paths = std::collections::HashSet::new();
-        for commit in [&base_commit, &ours_commit, &theirs_commit] {
-            for (path, _) in commit.get_files() {
-                all_paths.insert(path.clone());
-            }
+    apply_merge_plan(repo, &plan)?;
+    let binary_conflicts: Vec<&String> = plan
+        .paths
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, PathOutcome::Conflict { binary: true, .. }))
+        .map(|(path, _)| path)
+        .collect();
+    if !plan.conflicts.is_empty() {
+        println!(
+            "{}",
+            format!("Merge completed with {} conflicts.", plan.conflicts.len())
+                .yellow()
+                .bold()
+        );
+        println!("Conflicted files:");
+        for f in &plan.conflicts {
+            println!("  {}", f.red().bold());
         }
-        // Also handle renames: add old_path for renamed files
-        for commit in [&base_commit, &ours_commit, &theirs_commit] {
-            for fc in commit.get_files().values() {
-                if let ChangeType::Renamed { old_path } = &fc.change_type {
-                    all_paths.insert(old_path.clone());
-                }
+        if !binary_conflicts.is_empty() {
+            println!("Binary files changed on both sides (kept our version):");
+            for f in &binary_conflicts {
+                println!("  {}", f.red().bold());
             }
+            println!("Use 'hx checkout --ours <path>' or 'hx checkout --theirs <path>' to pick a side.");
         }
+        println!("Please resolve conflicts and commit the result.");
+        repo.set_merge_head(Some(&theirs))?;
+        repo.save()?;
+        println!("Current branch: {}", repo.current_branch.yellow().bold());
+        return Ok(());
+    }
+    if plan.resolved.is_empty() {
+        println!("{}", "Merge completed successfully".green().bold());
+    } else {
+        println!(
+            "{}",
+            format!(
+                "Merge completed with {} conflicts, resolved automatically using '{}'.",
+                plan.resolved.len(),
+                strategy
+            )
+            .yellow()
+            .bold()
+        );
+    }
+    println!("Current branch: {}", repo.current_branch.yellow().bold());
 
-        let mut conflicts = 0;
-        let mut conflicted_files = Vec::new();
-        for path in all_paths {
-            let base_fc = base_commit.get_file_change(&path);
-            let ours_fc = ours_commit.get_file_change(&path);
-            let theirs_fc = theirs_commit.get_file_change(&path);
-
-            // Handle deletions
-            let ours_deleted = ours_fc.map_or(false, |fc| matches!(fc.change_type, ChangeType::Deleted));
-            let theirs_deleted = theirs_fc.map_or(false, |fc| matches!(fc.change_type, ChangeType::Deleted));
-            if ours_deleted || theirs_deleted {
-                // If deleted in either, remove file if exists
-                if std::path::Path::new(&path).exists() {
-                    let _ = std::fs::remove_file(&path);
-                }
-                continue;
+    let default_message = format!(
+        "Merge {} '{}' into '{}'",
+        kind, branch_name, repo.current_branch
+    );
+    let message = match (&options.message, crate::utils::editor::editor()) {
+        (Some(message), _) => message.clone(),
+        (None, Some(editor)) if !options.no_edit => {
+            let mut template = format!("{}\n\n", default_message);
+            template.push_str("# Explain why this merge is needed.\n");
+            template.push_str("# Lines starting with '#' are ignored; an empty message aborts the commit.\n");
+            template.push_str("#\n# Merged files:\n");
+            for path in plan.paths.keys() {
+                template.push_str(&format!("#\t{}\n", path));
             }
-
-            // Handle renames
-            let ours_renamed = ours_fc.and_then(|fc| match &fc.change_type {
-                ChangeType::Renamed { old_path } => Some(old_path.clone()),
-                _ => None,
-            });
-            let theirs_renamed = theirs_fc.and_then(|fc| match &fc.change_type {
-                ChangeType::Renamed { old_path } => Some(old_path.clone()),
-                _ => None,
-            });
-            let mut actual_path = path.clone();
-            if let Some(_renamed_from) = ours_renamed.or(theirs_renamed) {
-                // If both renamed from the same file, merge into new name
-                actual_path = path.clone();
-                // Optionally, handle more complex rename conflicts here
+            let message = crate::utils::editor::edit_message(
+                &editor,
+                &repo.git_dir.join("MERGE_MSG"),
+                &template,
+            )?;
+            if message.is_empty() {
+                // Leave the merge in progress so `hx commit` can finish it
+                repo.set_merge_head(Some(&theirs))?;
+                repo.save()?;
+                println!("{}", "Not committing merge: empty commit message.".yellow());
+                println!("Commit the result with 'hx commit -m <message>'.");
+                return Ok(());
             }
+            message
+        }
+        _ => default_message,
+    };
+    let commit_id = commit_merge(repo, &plan, message)?;
+    repo.log_ref_update(Some(&ours), &commit_id, &format!("merge {}", branch_name))?;
+    println!("{}", format!("Created merge commit: {}", commit_id).green().bold());
+    Ok(())
+}
 
-            // Get blob hashes
-            let base_blob = base_fc.map(|fc| fc.content_hash.clone());
-            let ours_blob = ours_fc.map(|fc| fc.content_hash.clone());
-            let theirs_blob = theirs_fc.map(|fc| fc.content_hash.clone());
-
-            // If file only exists in one side, take that version
-            match (ours_blob.as_ref(), theirs_blob.as_ref()) {
-                (Some(ours_hash), None) => {
-                    match Object::load(&repo.get_objects_dir(), ours_hash) {
-                        Ok(ours_obj) => {
-                            if let Err(e) = std::fs::write(&actual_path, &ours_obj.data) {
-                                println!("{}", format!("Failed to write file {}: {}", path, e).red());
-                            }
-                        }
-                        Err(_) => {
-                            println!("{}", format!("Failed to load our blob: {}", ours_hash).red());
-                        }
-                    }
-                    continue;
-                }
-                (None, Some(theirs_hash)) => {
-                    match Object::load(&repo.get_objects_dir(), theirs_hash) {
-                        Ok(theirs_obj) => {
-                            if let Err(e) = std::fs::write(&actual_path, &theirs_obj.data) {
-                                println!("{}", format!("Failed to write file {}: {}", path, e).red());
-                            }
-                        }
-                        Err(_) => {
-                            println!("{}", format!("Failed to load their blob: {}", theirs_hash).red());
-                        }
-                    }
-                    continue;
-                }
-                (None, None) => continue, // deleted or missing
-                _ => {}
-            }
+/// Bring the working tree and index to the merge result. Clean results are
+/// staged; conflicts get a conflict entry and their working tree content.
+/// Fails before touching anything if a path the merge changes has local
+/// modifications.
+fn apply_merge_plan(repo: &mut Repository, plan: &MergePlan) -> Result<()> {
+    let ours_tree = repo.get_commit_object(&plan.ours)?.tree_files();
+    let theirs_tree = repo.get_commit_object(&plan.theirs)?.tree_files();
+    let base_tree = match &plan.base {
+        Some(base) => repo.get_commit_object(base)?.tree_files(),
+        None => BTreeMap::new(),
+    };
+    let dirty: Vec<&String> = plan
+        .paths
+        .keys()
+        .filter(|path| repo.worktree_blob_id(path).as_ref() != ours_tree.get(*path))
+        .collect();
+    if !dirty.is_empty() {
+        anyhow::bail!(
+            "Your local changes to the following files would be overwritten by merge:\n  {}\nCommit or stash them first",
+            dirty.iter().map(|p| p.as_str()).collect::<Vec<_>>().join("\n  ")
+        );
+    }
 
-            // Both sides have the file, do a three-way merge
-            let base_content = if let Some(base_hash) = base_blob {
-                match Object::load(&repo.get_objects_dir(), &base_hash) {
-                    Ok(obj) => obj.data,
-                    Err(_) => String::new()
-                }
-            } else {
-                String::new()
-            };
-            
-            let ours_content = match Object::load(&repo.get_objects_dir(), ours_blob.as_ref().unwrap()) {
-                Ok(obj) => obj.data,
-                Err(_) => {
-                    println!("{}", format!("Failed to load our content for: {}", path).red());
-                    continue;
-                }
-            };
-            
-            let theirs_content = match Object::load(&repo.get_objects_dir(), theirs_blob.as_ref().unwrap()) {
-                Ok(obj) => obj.data,
-                Err(_) => {
-                    println!("{}", format!("Failed to load their content for: {}", path).red());
-                    continue;
-                }
-            };
-            
-                let merged = diff3_merge(
-                &base_content,
-                &ours_content,
-                &theirs_content,
-                std::path::Path::new(&actual_path),
+    let objects_dir = repo.get_objects_dir();
+    for (path, outcome) in &plan.paths {
+        let full_path = repo.path.join(path);
+        match outcome {
+            PathOutcome::Write { content, mode } => {
+                let blob = Object::new("blob".to_string(), content.clone());
+                blob.save(&objects_dir)?;
+                file_utils::write_file_content(&full_path, &eol::to_worktree(repo, path, content))?;
+                repo.index.add_file(
+                    path,
+                    IndexEntry {
+                        path: path.clone(),
+                        content_hash: blob.id.clone(),
+                        size: content.len() as u64,
+                        mode: *mode,
+                        timestamp: Utc::now(),
+                        stage: 0,
+                    },
                 );
-                if merged.contains("<<<<<<<") {
-                // Conflict detected
-                    conflicts += 1;
-                conflicted_files.push(actual_path.clone());
-                match strategy {
-                    MergeStrategy::Ours => {
-                        if let Err(e) = std::fs::write(&actual_path, &ours_content) {
-                            println!("{}", format!("Failed to write our version to {}: {}", path, e).red());
-                        }
-                        continue;
-                    }
-                    MergeStrategy::Theirs => {
-                        if let Err(e) = std::fs::write(&actual_path, &theirs_content) {
-                            println!("{}", format!("Failed to write their version to {}: {}", path, e).red());
-                        }
-                        continue;
-                    }
-                    MergeStrategy::Manual => {
-                        if let Err(e) = std::fs::write(&actual_path, merged) {
-                            println!("{}", format!("Failed to write conflict markers to {}: {}", path, e).red());
-                        }
-                    }
-                }
-            } else {
-                if let Err(e) = std::fs::write(&actual_path, merged) {
-                    println!("{}", format!("Failed to write merged content to {}: {}", path, e).red());
-                }
             }
-        }
-        if conflicts > 0 {
-            match strategy {
-                MergeStrategy::Manual => {
-            println!(
-                "{}",
-                format!("Merge completed with {} conflicts.", conflicts)
-                    .yellow()
-                    .bold()
-            );
-                    println!("{}", "Conflicted files:");
-                    for f in conflicted_files {
-                        println!("  {}", f.red().bold());
-                    }
-                    println!("Please resolve conflicts and commit the result.");
-                }
-                MergeStrategy::Ours | MergeStrategy::Theirs => {
-                    println!(
-                        "{}",
-                        format!("Merge completed with {} conflicts, resolved automatically using '{}'.", conflicts, strategy)
-                            .yellow()
-                            .bold()
-                    );
+            PathOutcome::Delete => {
+                if full_path.is_file() {
+                    std::fs::remove_file(&full_path)?;
                 }
+                repo.index.stage_deletion(path);
+            }
+            PathOutcome::Conflict { content, .. } => {
+                file_utils::write_file_content(&full_path, &eol::to_worktree(repo, path, content))?;
+                repo.index.conflicts.insert(
+                    path.clone(),
+                    ConflictEntry {
+                        path: path.clone(),
+                        base: base_tree.get(path).cloned(),
+                        ours: ours_tree.get(path).cloned(),
+                        theirs: theirs_tree.get(path).cloned(),
+                    },
+                );
             }
-        } else {
-            println!("{}", "Merge completed successfully".green().bold());
         }
-        println!("Current branch: {}", repo.current_branch.yellow().bold());
+    }
+    repo.save()
+}
 
-        // If we performed a true merge (not fast-forward), create a merge commit
-        if resolved_base_commit_id != ours && resolved_base_commit_id != theirs {
-            use crate::core::commit::Commit;
-            use crate::core::object::Object as CoreObject;
-            use crate::core::index::{Index, IndexEntry};
-            use crate::core::object::Tree;
-            // Stage all merged files
-            let mut index = Index::new();
-            for entry in std::fs::read_dir(".")? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_file() {
-                    let data = std::fs::read_to_string(&path)?;
-                    let blob = CoreObject::new("blob".to_string(), data.clone());
-                    blob.save(&repo.get_objects_dir())?;
-                    let index_entry = IndexEntry {
-                        path: path.file_name().unwrap().to_string_lossy().to_string(),
-                        content_hash: blob.id.clone(),
-                        mode: 0o100644,
-                        size: data.len() as u64,
-                        stage: 0,
-                        timestamp: Utc::now(),
-                    };
-                    index.add_file(&index_entry.path.clone(), index_entry);
-                }
-            }
-            // Create tree object
-            let mut tree = Tree::new();
-            for entry in index.get_all_files() {
-                tree.add_entry(
-                    entry.path.clone(),
-                    entry.content_hash.clone(),
-                    "blob".to_string(),
-                    entry.mode,
+/// Record the merge: our commit's files with the merged paths on top, with
+/// both commits as parents. Moves HEAD and clears the staged results.
+fn commit_merge(repo: &mut Repository, plan: &MergePlan, message: String) -> Result<String> {
+    let ours_commit = repo.get_commit_object(&plan.ours)?;
+    let mut files: HashMap<String, FileChange> = ours_commit
+        .files
+        .into_iter()
+        .filter(|(_, fc)| !fc.is_deleted())
+        .collect();
+    for (path, outcome) in &plan.paths {
+        match outcome {
+            PathOutcome::Write { content, mode } => {
+                let blob_id = Object::new("blob".to_string(), content.clone()).id;
+                let change_type = if files.contains_key(path) {
+                    ChangeType::Modified
+                } else {
+                    ChangeType::Added
+                };
+                files.insert(
+                    path.clone(),
+                    FileChange::new(path.clone(), change_type, blob_id, content.len() as u64, *mode),
                 );
             }
-            let tree_object = tree.to_object();
-            tree_object.save(&repo.get_objects_dir())?;
-            let tree_id = tree_object.id.clone();
-            // Create merge commit
-            let author = repo.config.author.clone();
-            let email = repo.config.email.clone();
-            let message = format!(
-                "Merge branch '{}' into '{}'",
-                branch_name, repo.current_branch
-            );
-            let parents = vec![ours.clone(), theirs.clone()];
-            let file_changes = index.to_file_changes();
-            let commit = Commit::new(
-                parents,
-                tree_id,
-                author,
-                email,
-                message,
-                file_changes,
-                None,
-            );
-            let commit_object = commit.to_object();
-            commit_object.save(&repo.get_objects_dir())?;
-            // Update branch head
-            if let Some(current_branch) = repo.get_current_branch_mut() {
-                current_branch.set_head_commit(commit_object.id.clone());
+            PathOutcome::Delete => {
+                files.remove(path);
             }
-            repo.save()?;
-            println!("{}", format!("Created merge commit: {}", commit_object.id).green().bold());
+            PathOutcome::Conflict { .. } => anyhow::bail!("{} still has a conflict", path),
         }
-    } else {
-        println!("{}", "Could not find merge base or commits".red());
-        println!("Make sure both branches have commits and try again.");
     }
-    Ok(())
+    let tree_id = commit::write_tree(repo, &files)?;
+
+    let (author, email) = repo.identity();
+    let commit = Commit::new(
+        vec![plan.ours.clone(), plan.theirs.clone()],
+        tree_id,
+        author,
+        email,
+        message,
+        files,
+        None,
+    );
+    let commit_object = commit.to_object();
+    commit_object.save(&repo.get_objects_dir())?;
+    repo.set_head(&commit_object.id)?;
+    repo.index.clear();
+    repo.save()?;
+    Ok(commit_object.id)
 }
 
-fn diff3_merge(base: &str, ours: &str, theirs: &str, _path: &std::path::Path) -> String {
+pub fn diff3_merge(base: &str, ours: &str, theirs: &str, _path: &std::path::Path) -> String {
     match merge(base, ours, theirs) {
         Ok(result) => result,
         Err(conflict) => conflict,
     }
 }
+
+/// Apply the change from `base` to `theirs` (path -> blob id) on top of
+/// HEAD, in the working tree and the index; this is how cherry-pick and
+/// revert replay a commit. Paths HEAD changed the same way are skipped.
+/// Paths changed differently on both sides get diff3 conflict markers and
+/// a conflict entry, and are returned.
+pub fn apply_tree_change(
+    repo: &mut Repository,
+    base: &BTreeMap<String, String>,
+    theirs: &BTreeMap<String, String>,
+) -> Result<Vec<String>> {
+    let objects_dir = repo.get_objects_dir();
+    let load = |hash: Option<&String>| -> Result<Vec<u8>> {
+        match hash {
+            Some(hash) => Ok(Object::load(&objects_dir, hash)?.data),
+            None => Ok(Vec::new()),
+        }
+    };
+    let ours = repo.head_files();
+    let paths: BTreeSet<&String> = base.keys().chain(theirs.keys()).collect();
+
+    let mut conflicted = Vec::new();
+    for path in paths {
+        let (base_blob, ours_blob, theirs_blob) = (base.get(path), ours.get(path), theirs.get(path));
+        if base_blob == theirs_blob || ours_blob == theirs_blob {
+            continue;
+        }
+        let full_path = repo.path.join(path);
+        let content = if ours_blob == base_blob {
+            match theirs_blob {
+                Some(_) => load(theirs_blob)?,
+                None => {
+                    if full_path.exists() {
+                        std::fs::remove_file(&full_path)?;
+                    }
+                    repo.index.stage_deletion(path);
+                    continue;
+                }
+            }
+        } else {
+            let (base_content, ours_content, theirs_content) =
+                (load(base_blob)?, load(ours_blob)?, load(theirs_blob)?);
+            let is_binary = [&base_content, &ours_content, &theirs_content]
+                .iter()
+                .any(|c| file_utils::is_binary(c));
+            let merged = (!is_binary && ours_blob.is_some() && theirs_blob.is_some()).then(|| {
+                diff3_merge(
+                    &String::from_utf8_lossy(&base_content),
+                    &String::from_utf8_lossy(&ours_content),
+                    &String::from_utf8_lossy(&theirs_content),
+                    std::path::Path::new(path),
+                )
+            });
+            match merged {
+                Some(merged) if !merged.contains("<<<<<<<") => merged.into_bytes(),
+                merged => {
+                    // Binary files and delete/modify conflicts keep our side
+                    let content = merged.map(|m| m.into_bytes()).unwrap_or(ours_content);
+                    file_utils::write_file_content(&full_path, &content)?;
+                    repo.index.conflicts.insert(
+                        path.clone(),
+                        ConflictEntry {
+                            path: path.clone(),
+                            base: base_blob.cloned(),
+                            ours: ours_blob.cloned(),
+                            theirs: theirs_blob.cloned(),
+                        },
+                    );
+                    conflicted.push(path.clone());
+                    continue;
+                }
+            }
+        };
+
+        let blob = Object::new("blob".to_string(), content);
+        blob.save(&objects_dir)?;
+        file_utils::write_file_content(&full_path, &eol::to_worktree(repo, path, &blob.data))?;
+        repo.index.add_file(
+            path,
+            IndexEntry {
+                path: path.clone(),
+                content_hash: blob.id.clone(),
+                size: blob.data.len() as u64,
+                mode: file_utils::get_file_mode(&full_path).unwrap_or(0o100644),
+                timestamp: Utc::now(),
+                stage: 0,
+            },
+        );
+    }
+    Ok(conflicted)
+}
+
+/// The change a commit made, as (before, after) trees limited to the paths
+/// it records, so replaying it never touches anything else. A root commit
+/// starts from nothing; merge commits are refused.
+pub fn commit_change(
+    repo: &Repository,
+    commit: &crate::core::commit::Commit,
+) -> Result<(BTreeMap<String, String>, BTreeMap<String, String>)> {
+    if commit.parent_ids.len() > 1 {
+        anyhow::bail!(
+            "{} is a merge commit; replaying merges is not supported",
+            commit.get_short_id()
+        );
+    }
+    let mut before = match commit.parent_ids.first() {
+        Some(parent) => repo.get_commit_object(parent)?.tree_files(),
+        None => BTreeMap::new(),
+    };
+    before.retain(|path, _| commit.get_files().contains_key(path));
+    Ok((before, commit.tree_files()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::branch::Branch;
+    use crate::core::reflog::Reflog;
+    use crate::utils::test_utils::TestRepo;
+
+    fn no_edit() -> MergeOptions {
+        MergeOptions { no_edit: true, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn merge_commit_is_a_full_snapshot() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        t.write("sub/s.txt", "s\n");
+        t.write("gone", "gone\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("b", "b\n");
+        t.remove("gone");
+        let feat = t.commit("feat").await;
+        t.checkout("main").await;
+        t.write("c", "c\n");
+        let main = t.commit("main").await;
+        t.write("untracked.txt", "junk\n");
+
+        merge_branch(&mut t.repo, "feat", None, false, &no_edit()).await.unwrap();
+
+        let merge = t.repo.get_commit_object(&t.head()).unwrap();
+        assert_eq!(merge.parent_ids, vec![main, feat]);
+        let files: Vec<String> = merge.tree_files().into_keys().collect();
+        assert_eq!(files, ["a", "b", "c", "sub/s.txt"]);
+        assert!(!t.exists("gone"));
+        assert!(t.exists("sub/s.txt"));
+
+        // The next commit builds on the merge's snapshot
+        t.remove("untracked.txt");
+        t.write("c", "c2\n");
+        let next = t.commit("after").await;
+        let files: Vec<String> = t.repo.get_commit_object(&next).unwrap().tree_files().into_keys().collect();
+        assert_eq!(files, ["a", "b", "c", "sub/s.txt"]);
+    }
+
+    #[tokio::test]
+    async fn deletion_on_their_side_is_not_undone() {
+        let mut t = TestRepo::new();
+        t.write("keep", "1\n");
+        t.write("old", "old\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.remove("old");
+        t.commit("drop old").await;
+        // A second commit, whose snapshot no longer records the deletion
+        t.write("keep", "2\n");
+        t.commit("feat again").await;
+        t.checkout("main").await;
+        t.write("other", "x\n");
+        t.commit("main").await;
+
+        merge_branch(&mut t.repo, "feat", None, false, &no_edit()).await.unwrap();
+
+        let tree = t.repo.get_commit_object(&t.head()).unwrap().tree_files();
+        assert!(!tree.contains_key("old"));
+        assert!(!t.exists("old"));
+        assert_eq!(t.read("keep"), "2\n");
+    }
+
+    #[tokio::test]
+    async fn three_way_merge_uses_the_common_ancestor() {
+        let mut t = TestRepo::new();
+        t.write("f", "1\n2\n3\n4\n5\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("f", "1\n2\n3\n4\nfive\n");
+        t.commit("feat").await;
+        // Move `main` on, so it is no longer the merge base
+        t.checkout("main").await;
+        t.write("f", "one\n2\n3\n4\n5\n");
+        let base = t.commit("main").await;
+        t.checkout_new("topic").await;
+        t.write("f", "one\ntwo\n3\n4\n5\n");
+        t.commit("topic").await;
+        t.checkout("feat").await;
+        t.write("g", "g\n");
+        t.commit("feat 2").await;
+
+        let plan = plan_merge(
+            &t.repo,
+            &t.head(),
+            &t.repo.resolve_rev("topic").unwrap(),
+            &MergeStrategy::Manual,
+            false,
+        )
+        .unwrap();
+        assert_eq!(plan.kind, MergeKind::ThreeWay);
+        assert_ne!(plan.base.as_deref(), Some(base.as_str()));
+
+        merge_branch(&mut t.repo, "topic", None, false, &no_edit()).await.unwrap();
+        assert_eq!(t.read("f"), "one\ntwo\n3\n4\nfive\n");
+        assert_eq!(t.repo.get_commit_object(&t.head()).unwrap().parent_ids.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fast_forward_moves_head_without_a_merge_commit() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        let old = t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("b", "b\n");
+        let feat = t.commit("feat").await;
+        t.checkout("main").await;
+
+        merge_branch(&mut t.repo, "feat", None, false, &no_edit()).await.unwrap();
+
+        assert_eq!(t.head(), feat);
+        assert_eq!(t.read("b"), "b\n");
+        assert!(t.repo.index.to_file_changes().is_empty());
+        let reflog = Reflog::load(&t.repo.git_dir).unwrap();
+        let last = reflog.for_ref("HEAD")[0];
+        assert_eq!(last.old_commit.as_deref(), Some(old.as_str()));
+        assert_eq!(last.new_commit, feat);
+        assert_eq!(last.message, "merge feat: Fast-forward");
+    }
+
+    #[tokio::test]
+    async fn unrelated_histories_need_the_flag() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        t.commit("main root").await;
+        t.repo.branches.insert("other".to_string(), Branch::new("other"));
+        t.checkout("other").await;
+        t.write("b", "b\n");
+        t.commit("other root").await;
+        t.checkout("main").await;
+        let main = t.head();
+
+        let err = merge_branch(&mut t.repo, "other", None, false, &no_edit()).await.unwrap_err();
+        assert!(err.to_string().contains("unrelated histories"), "{}", err);
+        assert_eq!(t.head(), main);
+        assert!(!t.exists("b"));
+
+        let options = MergeOptions { allow_unrelated_histories: true, ..no_edit() };
+        merge_branch(&mut t.repo, "other", None, false, &options).await.unwrap();
+        assert_eq!(t.repo.get_commit_object(&t.head()).unwrap().parent_ids.len(), 2);
+        assert_eq!(t.read("a"), "a\n");
+        assert_eq!(t.read("b"), "b\n");
+    }
+
+    #[tokio::test]
+    async fn preview_reports_the_plan_without_writing() {
+        let mut t = TestRepo::new();
+        t.write("clean", "1\n");
+        t.write("both", "1\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("clean", "2\n");
+        t.write("both", "theirs\n");
+        t.commit("feat").await;
+        t.checkout("main").await;
+        t.write("both", "ours\n");
+        let main = t.commit("main").await;
+
+        merge_branch(&mut t.repo, "feat", None, true, &no_edit()).await.unwrap();
+        assert_eq!(t.head(), main);
+        assert_eq!(t.read("clean"), "1\n");
+        assert_eq!(t.read("both"), "ours\n");
+        assert!(t.repo.index.to_file_changes().is_empty());
+        assert!(!t.repo.index.has_conflicts());
+
+        // The real merge does exactly what the preview's plan said
+        let feat = t.repo.resolve_rev("feat").unwrap();
+        let plan = plan_merge(&t.repo, &main, &feat, &MergeStrategy::Manual, false).unwrap();
+        assert_eq!(plan.kind, MergeKind::ThreeWay);
+        assert_eq!(plan.clean, 1);
+        assert_eq!(plan.conflicts, ["both"]);
+        merge_branch(&mut t.repo, "feat", None, false, &no_edit()).await.unwrap();
+        assert_eq!(t.read("clean"), "2\n");
+        let conflicts: Vec<&String> = t.repo.index.conflicts.keys().collect();
+        assert_eq!(conflicts, ["both"]);
+    }
+
+    /// Base, feat and main each with their own `logo.png` bytes; returns
+    /// feat's and main's versions.
+    async fn binary_conflict(t: &mut TestRepo) -> (Vec<u8>, Vec<u8>) {
+        let ours = vec![0x89, b'P', b'N', b'G', 0, 1, 2];
+        // Not UTF-8, but no NUL either
+        let theirs = vec![0xff, 0xfe, b'x', 0x80];
+        t.write_bytes("logo.png", &[0x89, b'P', b'N', b'G', 0]);
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write_bytes("logo.png", &theirs);
+        t.commit("feat").await;
+        t.checkout("main").await;
+        t.write_bytes("logo.png", &ours);
+        t.commit("main").await;
+        (theirs, ours)
+    }
+
+    #[tokio::test]
+    async fn binary_changed_on_both_sides_is_a_conflict() {
+        let mut t = TestRepo::new();
+        let (theirs, ours) = binary_conflict(&mut t).await;
+
+        merge_branch(&mut t.repo, "feat", None, false, &no_edit()).await.unwrap();
+
+        // Our bytes stay, untouched by conflict markers; both sides are recorded
+        assert_eq!(std::fs::read(t.repo.path.join("logo.png")).unwrap(), ours);
+        let conflict = &t.repo.index.conflicts["logo.png"];
+        assert_eq!(conflict.ours.as_deref(), Some(Object::new("blob".to_string(), ours.clone()).id.as_str()));
+        assert_eq!(conflict.theirs.as_deref(), Some(Object::new("blob".to_string(), theirs.clone()).id.as_str()));
+
+        let path = t.repo.path.join("logo.png");
+        checkout::checkout_conflict_side(&mut t.repo, &[path], false).await.unwrap();
+        assert_eq!(std::fs::read(t.repo.path.join("logo.png")).unwrap(), theirs);
+        assert!(!t.repo.index.has_conflicts());
+    }
+
+    #[tokio::test]
+    async fn theirs_strategy_settles_binaries_without_conflicts() {
+        let mut t = TestRepo::new();
+        let (theirs, _) = binary_conflict(&mut t).await;
+        let feat = t.repo.resolve_rev("feat").unwrap();
+
+        let plan = plan_merge(&t.repo, &t.head(), &feat, &MergeStrategy::Theirs, false).unwrap();
+        assert!(plan.conflicts.is_empty());
+        assert_eq!(plan.resolved, ["logo.png"]);
+
+        merge_branch(&mut t.repo, "feat", Some(MergeStrategy::Theirs), false, &no_edit()).await.unwrap();
+        assert_eq!(std::fs::read(t.repo.path.join("logo.png")).unwrap(), theirs);
+        assert_eq!(t.repo.get_commit_object(&t.head()).unwrap().parent_ids.len(), 2);
+    }
+
+    /// main and feat with a commit each since their base; returns feat's.
+    async fn diverged(t: &mut TestRepo) -> String {
+        t.write("a", "a\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("b", "b\n");
+        let feat = t.commit("feat").await;
+        t.checkout("main").await;
+        t.write("c", "c\n");
+        t.commit("main").await;
+        feat
+    }
+
+    #[tokio::test]
+    async fn merges_a_unique_commit_prefix() {
+        let mut t = TestRepo::new();
+        let feat = diverged(&mut t).await;
+
+        merge_branch(&mut t.repo, &feat[..8], None, false, &no_edit()).await.unwrap();
+        assert_eq!(t.repo.get_commit_object(&t.head()).unwrap().parent_ids[1], feat);
+        assert_eq!(t.read("b"), "b\n");
+    }
+
+    #[tokio::test]
+    async fn ambiguous_prefix_is_reported() {
+        let mut t = TestRepo::new();
+        let feat = diverged(&mut t).await;
+        let main = t.head();
+        // A second commit object whose id shares feat's first eight digits
+        let objects = t.repo.get_objects_dir().join(&feat[..2]);
+        let twin = format!("{}{}", &feat[2..8], "0".repeat(56));
+        std::fs::copy(objects.join(&feat[2..]), objects.join(twin)).unwrap();
+
+        let err = merge_branch(&mut t.repo, &feat[..8], None, false, &no_edit()).await.unwrap_err();
+        assert_eq!(err.to_string(), format!("Ambiguous revision '{}'", &feat[..8]));
+        assert_eq!(t.head(), main);
+    }
+
+    #[tokio::test]
+    async fn unknown_revision_is_reported() {
+        let mut t = TestRepo::new();
+        diverged(&mut t).await;
+
+        let err = merge_branch(&mut t.repo, "nope", None, false, &no_edit()).await.unwrap_err();
+        assert_eq!(err.to_string(), "Unknown revision 'nope'");
+    }
+
+    #[tokio::test]
+    async fn refuses_to_merge_head_by_id() {
+        let mut t = TestRepo::new();
+        diverged(&mut t).await;
+        let head = t.head();
+
+        merge_branch(&mut t.repo, &head[..8], None, false, &no_edit()).await.unwrap();
+        assert_eq!(t.head(), head);
+    }
+
+    #[tokio::test]
+    async fn message_option_sets_the_merge_message_exactly() {
+        let mut t = TestRepo::new();
+        t.write("a", "a\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("b", "b\n");
+        t.commit("feat").await;
+        t.checkout("main").await;
+        t.write("c", "c\n");
+        t.commit("main").await;
+
+        let message = "Bring in feat\n\nNeeded for the release.";
+        let options = MergeOptions { message: Some(message.to_string()), ..Default::default() };
+        merge_branch(&mut t.repo, "feat", None, false, &options).await.unwrap();
+
+        let merge = t.repo.get_commit_object(&t.head()).unwrap();
+        assert_eq!(merge.parent_ids.len(), 2);
+        assert_eq!(merge.message, message);
+    }
+}
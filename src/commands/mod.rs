@@ -1,14 +1,33 @@
 pub mod add;
+pub mod blame;
 pub mod branch;
+pub mod cherry_pick;
 pub mod checkout;
+pub mod clean;
 pub mod clone;
 pub mod commit;
+pub mod commit_graph;
 pub mod diff;
+pub mod fetch;
+pub mod fsck;
+pub mod gc;
 pub mod init;
 pub mod log;
 pub mod merge;
+pub mod prune;
 pub mod pull;
 pub mod push;
+pub mod rebase;
+pub mod reflog;
 pub mod reset;
 pub mod restore;
+pub mod revert;
+pub mod rev_parse;
+pub mod rm;
+pub mod show;
+pub mod stash;
 pub mod status;
+pub mod submodule;
+pub mod tag;
+pub mod undo;
+pub mod verify_pack;
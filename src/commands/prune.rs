@@ -0,0 +1,65 @@
+use crate::commands::gc;
+use crate::core::index::IndexLock;
+use crate::core::repository::Repository;
+use anyhow::Result;
+use colored::*;
+use std::fs;
+
+/// Remove every unreachable loose object; with `dry_run`, only list them.
+pub async fn run_prune(repo: &Repository, dry_run: bool) -> Result<()> {
+    let _lock = IndexLock::acquire(&repo.git_dir)?;
+    let unreachable = gc::unreachable_objects(repo)?;
+    if dry_run {
+        gc::print_unreachable(&unreachable);
+        return Ok(());
+    }
+
+    let objects_dir = repo.get_objects_dir();
+    let mut freed = 0u64;
+    for object in &unreachable {
+        fs::remove_file(objects_dir.join(&object.id[..2]).join(&object.id[2..]))?;
+        freed += object.size;
+    }
+    gc::remove_empty_shards(repo)?;
+    println!(
+        "{} {} objects, {} bytes",
+        "Pruned".green().bold(),
+        unreachable.len().to_string().cyan(),
+        freed.to_string().cyan()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::reset;
+    use crate::core::reflog::Reflog;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn an_orphaned_commit_is_listed_then_pruned() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("kept").await;
+        t.write("a", "2\n");
+        let orphan = t.commit("orphaned").await;
+        reset::reset_repository(&mut t.repo, "HEAD~1", "hard", false).await.unwrap();
+        // The reflog would otherwise keep it alive
+        fs::remove_file(Reflog::path(&t.repo.git_dir)).unwrap();
+
+        let unreachable = gc::unreachable_objects(&t.repo).unwrap();
+        let commits: Vec<&str> = unreachable
+            .iter()
+            .filter(|o| o.object_type.as_deref() == Some("commit"))
+            .map(|o| o.id.as_str())
+            .collect();
+        assert_eq!(commits, vec![orphan.as_str()]);
+
+        run_prune(&t.repo, true).await.unwrap();
+        assert!(t.repo.get_commit_object(&orphan).is_ok());
+        run_prune(&t.repo, false).await.unwrap();
+        assert!(t.repo.get_commit_object(&orphan).is_err());
+        assert!(gc::unreachable_objects(&t.repo).unwrap().is_empty());
+    }
+}
@@ -1,15 +1,31 @@
 use crate::core::commit::Commit;
+use crate::core::commit_graph::find_merge_base;
+use crate::core::fetch_progress::FetchProgress;
 use crate::core::object::Object;
 use crate::core::repository::Repository;
+use crate::utils::hash_utils;
 use crate::utils::pack::{extract_objects_from_pack, Pack};
-use crate::utils::remote_client::{NegotiationRequest, RemoteClient};
+use crate::utils::remote_client::{NegotiationRequest, RemoteClient, OBJECT_BATCH_SIZE, OBJECT_PAGE_SIZE};
+use crate::utils::transport::{self, RemoteTransport};
 use anyhow::{Context, Result};
 use colored::*;
+use ed25519_dalek::SigningKey;
+use futures_util::TryStreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::collections::{HashMap, HashSet};
 use std::fs;
-
-pub async fn pull_changes(repo: &Repository) -> Result<()> {
+use std::path::Path;
+use std::pin::pin;
+
+/// Fetch the current branch from `remote_name` over `client` and
+/// fast-forward it when possible. Returns the remote head, or `None` when
+/// there was nothing to fetch from.
+pub async fn pull_changes<T: RemoteTransport>(
+    repo: &mut Repository,
+    client: &mut T,
+    remote_name: &str,
+    verify_depth: Option<usize>,
+) -> Result<Option<String>> {
     let pb = ProgressBar::new(6);
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -19,79 +35,67 @@ pub async fn pull_changes(repo: &Repository) -> Result<()> {
 
     pb.set_message("Initializing pull...");
 
-    // Check for remote configuration
-    if repo.remotes.is_empty() {
-        println!("{}", "No remote repositories configured".yellow());
-        println!("Use 'hx remote add origin <url>' to add a remote");
-        return Ok(());
-    }
-
-    let remote = match repo.remotes.get("origin") {
-        Some(remote) => remote,
-        None => {
-            println!("{}", "No 'origin' remote configured".yellow());
-            println!("Use 'hx remote add origin <url>' to add a remote");
-            return Ok(());
-        }
-    };
-
-    let mut _client = RemoteClient::new(&remote.url);
+    let remote_url = repo
+        .remotes
+        .get(remote_name)
+        .map(|remote| remote.url.clone())
+        .unwrap_or_else(|| remote_name.to_string());
 
     // Check connectivity
     pb.set_message("Checking remote connectivity...");
-    if !_client.check_connectivity().await? {
+    if !client.check_connectivity().await? {
         println!("{}", "Failed to connect to remote repository".red());
-        return Ok(());
+        return Ok(None);
     }
 
     // Discover remote capabilities
     pb.set_message("Discovering remote capabilities...");
-    let _capabilities = _client.discover_capabilities().await
+    let _capabilities = client.discover_capabilities().await
         .with_context(|| "Failed to discover remote capabilities")?;
     
     pb.inc(1);
 
     // Get current branch and remote refs
     pb.set_message("Fetching remote state...");
-    let current_branch = &repo.current_branch;
-    let remote_refs = _client.get_refs().await
+    let current_branch = repo.current_branch.clone();
+    let remote_refs = client.get_refs().await
         .with_context(|| "Failed to fetch remote refs")?;
 
     let remote_head = match remote_refs.get(&format!("refs/heads/{}", current_branch)) {
         Some(head) => head.clone(),
         None => {
             println!("{}", format!("Remote branch '{}' not found", current_branch).yellow());
-            return Ok(());
+            return Ok(None);
         }
     };
 
-    // Collect local objects for negotiation
-    pb.set_message("Collecting local objects...");
-    let local_objects = collect_local_objects(repo)?;
-    let local_object_hashes: HashSet<String> = local_objects.keys().cloned().collect();
-
-    // Get remote objects
-    let remote_object_hashes = _client.get_all_object_hashes().await
-        .with_context(|| "Failed to fetch remote object hashes")?;
-    let remote_objects_set: HashSet<String> = remote_object_hashes.into_iter().collect();
+    // Determine what we need to fetch while streaming the remote listing
+    pb.set_message("Comparing with local objects...");
+    let mut missing_objects: HashSet<String> = HashSet::new();
+    let mut common_objects: Vec<String> = Vec::new();
+    let mut remote_hashes = pin!(transport::object_hashes(client, OBJECT_PAGE_SIZE));
+    while let Some(hash) = remote_hashes.try_next().await
+        .with_context(|| "Failed to fetch remote object hashes")?
+    {
+        if repo.object_exists(&hash) {
+            common_objects.push(hash);
+        } else {
+            missing_objects.insert(hash);
+        }
+    }
 
     pb.inc(1);
 
-    // Determine what we need to fetch
-    let missing_objects: HashSet<String> = remote_objects_set
-        .difference(&local_object_hashes)
-        .cloned()
-        .collect();
-
     if missing_objects.is_empty() {
-        println!("{}", "Already up to date".green());
-        return Ok(());
+        // The objects may have come with an earlier fetch
+        update_local_refs(repo, remote_name, &remote_head, &current_branch)?;
+        return Ok(Some(remote_head));
     }
 
     // Create negotiation request
     pb.set_message("Negotiating with remote...");
     let wants = vec![remote_head.clone()];
-    let haves: Vec<String> = local_object_hashes.into_iter().collect();
+    let haves = common_objects;
 
     let negotiation_request = NegotiationRequest {
         wants,
@@ -103,7 +107,7 @@ pub async fn pull_changes(repo: &Repository) -> Result<()> {
     };
 
     // Perform negotiation
-    let negotiation_response = _client.negotiate_fetch(&negotiation_request).await
+    let negotiation_response = client.negotiate_fetch(&negotiation_request).await
         .with_context(|| "Failed to negotiate with remote")?;
 
     pb.inc(1);
@@ -111,7 +115,7 @@ pub async fn pull_changes(repo: &Repository) -> Result<()> {
     // Download pack if available
     if let Some(pack_id) = negotiation_response.packfile {
         pb.set_message("Downloading pack...");
-        let pack_data = _client.download_pack(&pack_id).await
+        let pack_data = client.download_pack(&pack_id).await
             .with_context(|| "Failed to download pack")?;
 
         let pack = Pack::from_bytes(&pack_data)
@@ -124,191 +128,285 @@ pub async fn pull_changes(repo: &Repository) -> Result<()> {
         pb.inc(1);
     } else {
         // Fallback to individual object download
-        pb.set_message("Downloading individual objects...");
-        download_objects_individually(&_client, repo, &missing_objects).await?;
+        pb.set_message("Downloading objects...");
+        download_missing_objects(client, repo, &missing_objects).await?;
         pb.inc(1);
     }
 
-    // Update local refs
-    pb.set_message("Updating local refs...");
-    update_local_refs(repo, &remote_refs, current_branch)?;
-
     pb.finish_with_message("Pull completed successfully!");
 
     // Report results
     println!("\n{}", "Pull completed successfully!".green().bold());
     println!("Objects downloaded: {}", missing_objects.len().to_string().cyan());
-    println!("Remote: {}", remote.url.cyan());
+    println!("Remote: {}", remote_url.cyan());
     println!("Branch: {}", current_branch.yellow().bold());
 
     // Verify downloaded commits
     pb.set_message("Verifying downloaded commits...");
-    if let Some(_branch) = repo.get_current_branch() {
-        if let Some(head_commit) = _branch.get_head_commit() {
-            let all_valid = Commit::verify_ancestry(repo, head_commit, |commit, valid| {
-                if !valid {
-                    println!(
-                        "{} {} {}",
-                        commit.get_short_id().cyan(),
-                        "INVALID".red(),
-                        commit.message.bold()
-                    );
-                }
-            });
-            if !all_valid {
+    // Commits we already had were verified when they arrived
+    let local_head = repo.head_commit().map(|h| h.as_str());
+    let all_valid = Commit::verify_range(
+        repo,
+        local_head,
+        &remote_head,
+        verify_depth,
+        |commit, valid| {
+            if !valid {
                 println!(
-                    "{}",
-                    "Warning: unsigned or invalid commits detected in pulled history!"
-                        .red()
-                        .bold()
+                    "{} {} {}",
+                    commit.get_short_id().cyan(),
+                    "INVALID".red(),
+                    commit.message.bold()
                 );
-            } else {
-                println!("{}", "All pulled commits are valid!".green().bold());
             }
-        }
+        },
+    );
+    if !all_valid {
+        println!(
+            "{}",
+            "Warning: unsigned or invalid commits detected in pulled history!"
+                .red()
+                .bold()
+        );
+    } else {
+        println!("{}", "All pulled commits are valid!".green().bold());
     }
 
-    Ok(())
-}
-
-fn collect_local_objects(repo: &Repository) -> Result<HashMap<String, Vec<u8>>> {
-    let mut objects = HashMap::new();
-    let objects_dir = repo.get_objects_dir();
+    // Only now, so the range verified above starts at the old local head
+    update_local_refs(repo, remote_name, &remote_head, &current_branch)?;
 
-    for entry in std::fs::read_dir(&objects_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            for obj in std::fs::read_dir(entry.path())? {
-                let obj = obj?;
-                let hash = format!(
-                    "{}{}",
-                    entry.file_name().to_string_lossy(),
-                    obj.file_name().to_string_lossy()
-                );
-                let data = std::fs::read(obj.path())?;
-                objects.insert(hash, data);
-            }
-        }
-    }
-
-    Ok(objects)
+    Ok(Some(remote_head))
 }
 
-async fn download_objects_individually(
-    client: &RemoteClient,
+/// Download `object_hashes` in batches, writing each batch before asking
+/// for the next so an interrupted pull keeps what it already has.
+pub async fn download_missing_objects<T: RemoteTransport>(
+    client: &T,
     repo: &Repository,
     object_hashes: &HashSet<String>,
 ) -> Result<()> {
-    let objects_dir = repo.get_objects_dir();
-    let mut _downloaded = 0;
-    let mut seen = HashSet::new();
-
-    for hash in object_hashes {
-        if seen.contains(hash) {
-            continue;
-        }
-        seen.insert(hash.clone());
-
-        // Download object
-        let data = client.download_object(hash).await?;
-        
-        // Save to .helix/objects
-        let (dir, file) = hash.split_at(2);
-        let dir_path = objects_dir.join(dir);
-        fs::create_dir_all(&dir_path)?;
-        let file_path = dir_path.join(file);
-        fs::write(&file_path, &data)?;
-        _downloaded += 1;
-
-        // If commit or tree, queue referenced objects
-        let obj: Object = serde_json::from_slice(&data)
-            .unwrap_or_else(|_| Object::new("blob".to_string(), String::new()));
-        
-        if obj.is_commit() {
-            let commit: crate::core::commit::Commit = serde_json::from_str(&obj.data)?;
-            for parent_id in &commit.parent_ids {
-                if !seen.contains(parent_id) {
-                    seen.insert(parent_id.clone());
-                }
-            }
-            if !seen.contains(&commit.tree_id) {
-                seen.insert(commit.tree_id.clone());
-            }
-        } else if obj.is_tree() {
-            let tree: crate::core::object::Tree = serde_json::from_str(&obj.data)?;
-            for entry in tree.entries {
-                if !seen.contains(&entry.object_id) {
-                    seen.insert(entry.object_id.clone());
-                }
-            }
+    let mut progress = FetchProgress::load(&repo.git_dir)?;
+    let wanted: Vec<String> = object_hashes
+        .iter()
+        .filter(|hash| !progress.has(repo, hash))
+        .cloned()
+        .collect();
+    for batch in wanted.chunks(OBJECT_BATCH_SIZE) {
+        let objects = client.download_objects(batch).await?;
+        save_objects_to_repository(repo, &objects)?;
+        for hash in objects.keys() {
+            progress.record(hash)?;
         }
     }
-
-    Ok(())
+    progress.finish()
 }
 
-fn save_objects_to_repository(repo: &Repository, objects: &HashMap<String, Vec<u8>>) -> Result<()> {
+pub fn save_objects_to_repository(repo: &Repository, objects: &HashMap<String, Vec<u8>>) -> Result<()> {
     let objects_dir = repo.get_objects_dir();
-
     for (hash, data) in objects {
-        let (dir, file) = hash.split_at(2);
-        let dir_path = objects_dir.join(dir);
-        fs::create_dir_all(&dir_path)?;
-        let file_path = dir_path.join(file);
-        fs::write(&file_path, data)?;
+        write_object(&objects_dir, hash, data)?;
     }
+    Ok(())
+}
 
+/// Store downloaded object data under `hash`, refusing data that isn't
+/// that object. It goes to a temporary file first, so a crash never leaves
+/// a truncated object behind.
+pub fn write_object(objects_dir: &Path, hash: &str, data: &[u8]) -> Result<()> {
+    Object::from_compressed_checked(data, hash)?;
+    let (dir, file) = hash.split_at(2);
+    let dir_path = objects_dir.join(dir);
+    fs::create_dir_all(&dir_path)?;
+    let temp_path = dir_path.join(format!("{}.tmp", file));
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, dir_path.join(file))?;
     Ok(())
 }
 
+/// Record `remote_head` as `<remote>/<branch>` and fast-forward the local
+/// branch (and working tree) to it. A branch that is ahead of the remote or
+/// has diverged from it is left alone.
 fn update_local_refs(
-    repo: &Repository,
-    remote_refs: &HashMap<String, String>,
+    repo: &mut Repository,
+    remote_name: &str,
+    remote_head: &str,
     current_branch: &str,
 ) -> Result<()> {
-    let ref_key = format!("refs/heads/{}", current_branch);
-    
-    if let Some(remote_head) = remote_refs.get(&ref_key) {
-        // Update the local branch to point to the remote head
-        if let Some(_branch) = repo.get_current_branch() {
-            // TODO: Implement proper ref update logic
-            // For now, we'll just update the branch head
-            println!("Updated {} to {}", current_branch, remote_head);
+    repo.set_remote_ref(remote_name, current_branch, remote_head)?;
+
+    let local_head = repo
+        .branches
+        .get(current_branch)
+        .and_then(|b| b.get_head_commit())
+        .cloned();
+    let tracking = format!("{}/{}", remote_name, current_branch);
+    if let Some(local_head) = &local_head {
+        match find_merge_base(repo, local_head, remote_head) {
+            Some(base) if base == *local_head => {}
+            Some(base) if base == remote_head => {
+                println!("{}", format!("{} is ahead of {}", current_branch, tracking).green());
+                return Ok(());
+            }
+            _ => {
+                println!(
+                    "{}",
+                    format!("{} and {} have diverged; not fast-forwarding", current_branch, tracking)
+                        .yellow()
+                );
+                return Ok(());
+            }
+        }
+        if local_head == remote_head {
+            println!("{}", "Already up to date".green());
+            return Ok(());
         }
     }
 
+    let target_tree = repo.get_commit_object(remote_head)?.tree_files();
+    repo.check_overwrite(&target_tree, false)?;
+    repo.update_worktree(&repo.head_files(), &target_tree)?;
+    repo.set_head(remote_head)?;
+    repo.log_ref_update(
+        local_head.as_deref(),
+        remote_head,
+        &format!("pull: fast-forward to {}", tracking),
+    )?;
+    println!(
+        "{}",
+        format!(
+            "Fast-forwarded {} to {}",
+            current_branch,
+            hash_utils::get_short_hash(remote_head)
+        )
+        .green()
+    );
     Ok(())
 }
 
 pub async fn pull_with_options(
-    repo: &Repository,
+    repo: &mut Repository,
     remote_name: Option<&str>,
     branch_name: Option<&str>,
     rebase: bool,
+    verify_depth: Option<usize>,
 ) -> Result<()> {
     let remote_name = remote_name.unwrap_or("origin");
     let _branch_name = branch_name.unwrap_or(&repo.current_branch);
     
-    let remote = match repo.remotes.get(remote_name) {
-        Some(remote) => remote,
-        None => {
-            println!("{}", format!("No '{}' remote configured", remote_name).yellow());
-            return Ok(());
-        }
+    if !repo.remotes.contains_key(remote_name) {
+        println!("{}", format!("No '{}' remote configured", remote_name).yellow());
+        return Ok(());
+    }
+
+    // TODO: Implement branch-specific pull
+    // TODO: Implement merge strategy selection
+
+    // Load the key up front so a rebase that can't sign fails before pulling
+    let keypair = if rebase {
+        Some(crate::utils::key_utils::load_signing_key()?)
+    } else {
+        None
     };
+    let mut client = RemoteClient::configured(&repo.remotes[remote_name].url);
+    pull_from(repo, &mut client, remote_name, keypair.as_ref(), verify_depth).await
+}
 
-    let mut client = RemoteClient::new(&remote.url);
+/// Pull from `client`, then with a `rebase_key`, replay the local commits
+/// on top of the remote head, signing them with it.
+async fn pull_from<T: RemoteTransport>(
+    repo: &mut Repository,
+    client: &mut T,
+    remote_name: &str,
+    rebase_key: Option<&SigningKey>,
+    verify_depth: Option<usize>,
+) -> Result<()> {
+    let remote_head = pull_changes(repo, client, remote_name, verify_depth).await?;
+    if let (Some(keypair), Some(remote_head)) = (rebase_key, remote_head) {
+        crate::commands::rebase::rebase_onto(repo, &remote_head, &Default::default(), keypair).await?;
+    }
+    Ok(())
+}
 
-    // Enhanced pull with options
-    if rebase {
-        println!("{}", "Rebase mode requested".yellow());
-        // TODO: Implement rebase logic
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{keypair, TestRepo};
+    use crate::utils::transport::MockRemote;
+
+    #[tokio::test]
+    async fn pull_rebase_puts_local_commits_on_top() {
+        let mut remote = TestRepo::new();
+        remote.write("shared.txt", "base\n");
+        let base = remote.commit("base").await;
+        let mut local = TestRepo::new();
+        local.repo.add_remote("origin", "mock://remote").unwrap();
+        pull_changes(&mut local.repo, &mut MockRemote::from_repo(&remote.repo), "origin", None)
+            .await
+            .unwrap();
+
+        remote.write("remote.txt", "remote\n");
+        let remote_head = remote.commit("remote work").await;
+        local.write("local.txt", "one\n");
+        local.commit("local one").await;
+        local.write("local.txt", "two\n");
+        local.commit("local two").await;
+
+        let mut mock = MockRemote::from_repo(&remote.repo);
+        pull_from(&mut local.repo, &mut mock, "origin", Some(&keypair()), None).await.unwrap();
+
+        let mut history = Vec::new();
+        let mut id = local.head();
+        while id != base {
+            let commit = local.repo.get_commit_object(&id).unwrap();
+            assert_eq!(commit.parent_ids.len(), 1, "history is not linear");
+            history.push((id, commit.message));
+            id = commit.parent_ids[0].clone();
+        }
+        let messages: Vec<&str> = history.iter().map(|(_, message)| message.as_str()).collect();
+        assert_eq!(messages, ["local two", "local one", "remote work"]);
+        assert_eq!(history[2].0, remote_head);
+        assert_eq!(local.read("local.txt"), "two\n");
+        assert_eq!(local.read("remote.txt"), "remote\n");
     }
 
-    // TODO: Implement branch-specific pull
-    // TODO: Implement merge strategy selection
-    // TODO: Implement conflict resolution
+    #[tokio::test]
+    async fn update_local_refs_persists_the_pulled_head() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.write("a", "2\n");
+        let fetched = t.commit("fetched").await;
+        // As if `fetched` had just arrived from the remote
+        crate::commands::reset::reset_repository(&mut t.repo, &first, "hard", false).await.unwrap();
+
+        update_local_refs(&mut t.repo, "origin", &fetched, "main").unwrap();
+
+        let reopened = Repository::open(t.repo.path.to_str().unwrap()).unwrap();
+        assert_eq!(reopened.branches["main"].get_head_commit(), Some(&fetched));
+        assert_eq!(reopened.get_remote_ref("origin", "main"), Some(fetched));
+        assert_eq!(t.read("a"), "2\n");
+    }
 
-    // For now, delegate to the main pull function
-    pull_changes(repo).await
+    #[tokio::test]
+    async fn a_corrupted_object_fails_the_pull() {
+        let mut remote = TestRepo::new();
+        remote.write("a", "1\n");
+        let head = remote.commit("first").await;
+        let blob = remote.repo.get_commit_object(&head).unwrap().tree_files()["a"].clone();
+
+        // Garbage, and a valid object that isn't the one asked for
+        let other = MockRemote::from_repo(&remote.repo).objects.lock().unwrap()[&head].clone();
+        for corruption in [b"not zlib at all".to_vec(), other] {
+            let mut local = TestRepo::new();
+            local.repo.add_remote("origin", "mock://remote").unwrap();
+            let mut mock = MockRemote::from_repo(&remote.repo);
+            mock.objects.lock().unwrap().insert(blob.clone(), corruption);
+
+            let err = pull_changes(&mut local.repo, &mut mock, "origin", None).await.unwrap_err();
+            let message = format!("{:#}", err);
+            assert!(message.contains(&blob), "{}", message);
+            assert!(!local.repo.object_exists(&blob));
+            assert!(local.repo.head_commit().is_none());
+        }
+    }
 }
@@ -1,13 +1,74 @@
+use crate::commands::gc;
+use crate::core::branch::Branch;
 use crate::core::commit::Commit;
+use crate::core::commit_graph::find_merge_base;
 use crate::core::repository::Repository;
-use crate::utils::pack::create_thin_pack;
-use crate::utils::remote_client::{NegotiationRequest, PushRequest, RemoteClient};
+use crate::utils::pack::{self, create_thin_pack};
+use crate::utils::remote_client::{NegotiationRequest, PushRequest, PushResponse, RemoteClient, OBJECT_PAGE_SIZE};
+use crate::utils::transport::{self, RemoteTransport};
 use anyhow::{Context, Result};
 use colored::*;
+use futures_util::TryStreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::collections::{HashMap, HashSet};
+use std::pin::pin;
+
+/// Options for `hx push`.
+#[derive(Debug, Clone, Default)]
+pub struct PushOptions {
+    pub force: bool,
+    /// Apply all ref updates on the remote or none of them
+    pub atomic: bool,
+    /// Push every local branch rather than only the current one
+    pub all: bool,
+    pub verify_depth: Option<usize>,
+    /// Push exactly these refs rather than the current branch
+    pub refspecs: Vec<Refspec>,
+}
+
+/// `[+]<src>[:<dst>]`: push local branch `src` to `dst` on the remote,
+/// forcing the update with a leading `+`. A bare name pushes a branch to
+/// the remote branch of the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    pub src: String,
+    pub dst: String,
+    pub force: bool,
+}
+
+impl Refspec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (force, rest) = match spec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (src, dst) = rest.split_once(':').unwrap_or((rest, rest));
+        if src.is_empty() {
+            anyhow::bail!("Invalid refspec '{}': deleting remote refs is not supported", spec);
+        }
+        if dst.is_empty() {
+            anyhow::bail!("Invalid refspec '{}': missing destination", spec);
+        }
+        Ok(Self {
+            src: src.trim_start_matches("refs/heads/").to_string(),
+            dst: dst.trim_start_matches("refs/heads/").to_string(),
+            force,
+        })
+    }
+
+    /// The remote ref this refspec updates.
+    pub fn remote_ref(&self) -> String {
+        format!("refs/heads/{}", self.dst)
+    }
+}
 
-pub async fn push_changes(repo: &Repository) -> Result<()> {
+/// Push to `remote_name` over `client`.
+pub async fn push_changes<T: RemoteTransport>(
+    repo: &Repository,
+    client: &mut T,
+    remote_name: &str,
+    options: &PushOptions,
+) -> Result<()> {
     let pb = ProgressBar::new(5);
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -17,23 +78,11 @@ pub async fn push_changes(repo: &Repository) -> Result<()> {
 
     pb.set_message("Initializing push...");
 
-    // Check for remote configuration
-    if repo.remotes.is_empty() {
-        println!("{}", "No remote repositories configured".yellow());
-        println!("Use 'hx remote add origin <url>' to add a remote");
-        return Ok(());
-    }
-
-    let remote = match repo.remotes.get("origin") {
-        Some(remote) => remote,
-        None => {
-            println!("{}", "No 'origin' remote configured".yellow());
-            println!("Use 'hx remote add origin <url>' to add a remote");
-            return Ok(());
-        }
-    };
-
-    let mut client = RemoteClient::new(&remote.url);
+    let remote_url = repo
+        .remotes
+        .get(remote_name)
+        .map(|remote| remote.url.clone())
+        .unwrap_or_else(|| remote_name.to_string());
 
     // Check connectivity
     pb.set_message("Checking remote connectivity...");
@@ -44,16 +93,94 @@ pub async fn push_changes(repo: &Repository) -> Result<()> {
 
     // Discover remote capabilities
     pb.set_message("Discovering remote capabilities...");
-    let _capabilities = client.discover_capabilities().await
+    let capabilities = client.discover_capabilities().await
         .with_context(|| "Failed to discover remote capabilities")?;
-    
+    if options.atomic && !capabilities.atomic {
+        anyhow::bail!("The remote does not support atomic pushes");
+    }
+
     pb.inc(1);
 
+    // Collect local objects
+    pb.set_message("Collecting local objects...");
+    let local_objects = collect_local_objects(repo)?;
+    let local_object_hashes: HashSet<String> = local_objects.keys().cloned().collect();
+
+    // Get remote refs and objects
+    pb.set_message("Fetching remote state...");
+    let remote_refs = client.get_refs().await
+        .with_context(|| "Failed to fetch remote refs")?;
+
+    // Refs this push will update on the remote, and those it may force
+    let mut refs_to_update = HashMap::new();
+    let mut forced = HashSet::new();
+    if options.refspecs.is_empty() {
+        let branches: Vec<&Branch> = if options.all {
+            repo.branches.values().collect()
+        } else {
+            repo.get_current_branch().into_iter().collect()
+        };
+        for branch in branches {
+            if let Some(head_commit) = branch.get_head_commit() {
+                refs_to_update.insert(format!("refs/heads/{}", branch.name), head_commit.clone());
+            }
+        }
+    }
+    for spec in &options.refspecs {
+        let head_commit = repo
+            .branches
+            .get(&spec.src)
+            .ok_or_else(|| anyhow::anyhow!("Unknown local branch '{}'", spec.src))?
+            .get_head_commit()
+            .ok_or_else(|| anyhow::anyhow!("Branch '{}' has no commits", spec.src))?;
+        refs_to_update.insert(spec.remote_ref(), head_commit.clone());
+        if spec.force {
+            forced.insert(spec.remote_ref());
+        }
+    }
+
+    // Without --force (or a `+` refspec) a remote branch may only move forward
+    if !options.force {
+        let mut rejected: Vec<String> = refs_to_update
+            .iter()
+            .filter(|(ref_name, _)| !forced.contains(*ref_name))
+            .filter(|(ref_name, head_commit)| {
+                remote_refs.get(*ref_name).is_some_and(|remote_head| {
+                    remote_head != *head_commit
+                        && find_merge_base(repo, head_commit, remote_head).as_ref() != Some(remote_head)
+                })
+            })
+            .map(|(ref_name, _)| ref_name.clone())
+            .collect();
+        if !rejected.is_empty() {
+            rejected.sort();
+            pb.finish_and_clear();
+            for ref_name in &rejected {
+                println!(" {} {} (non-fast-forward)", "! [rejected]".red(), ref_name);
+                refs_to_update.remove(ref_name);
+            }
+            println!(
+                "{}",
+                "hint: the remote has commits you don't have; pull them first (e.g. 'hx pull --rebase') or use --force"
+                    .yellow()
+            );
+            if options.atomic || refs_to_update.is_empty() {
+                anyhow::bail!("Push to {} rejected", remote_url);
+            }
+        }
+    }
+
     // Verify local commits before push
     pb.set_message("Verifying local commits...");
-    if let Some(branch) = repo.get_current_branch() {
-        if let Some(head_commit) = branch.get_head_commit() {
-            let all_valid = Commit::verify_ancestry(repo, head_commit, |commit, valid| {
+    for (ref_name, head_commit) in &refs_to_update {
+        // Only the commits the remote doesn't have yet need verifying
+        let remote_head = remote_refs.get(ref_name);
+        let all_valid = Commit::verify_range(
+            repo,
+            remote_head.map(|h| h.as_str()),
+            head_commit,
+            options.verify_depth,
+            |commit, valid| {
                 if !valid {
                     println!(
                         "{} {} {}",
@@ -62,54 +189,50 @@ pub async fn push_changes(repo: &Repository) -> Result<()> {
                         commit.message.bold()
                     );
                 }
-            });
-            if !all_valid {
-                println!(
-                    "{}",
-                    "Push aborted: unsigned or invalid commits detected!"
-                        .red()
-                        .bold()
-                );
-                return Ok(());
-            }
+            },
+        );
+        if !all_valid {
+            println!(
+                "{}",
+                "Push aborted: unsigned or invalid commits detected!"
+                    .red()
+                    .bold()
+            );
+            return Ok(());
         }
     }
 
     pb.inc(1);
 
-    // Collect local objects
-    pb.set_message("Collecting local objects...");
-    let local_objects = collect_local_objects(repo)?;
-    let local_object_hashes: HashSet<String> = local_objects.keys().cloned().collect();
-
-    // Get remote refs and objects
-    pb.set_message("Fetching remote state...");
-    let _remote_refs = client.get_refs().await
-        .with_context(|| "Failed to fetch remote refs")?;
-    
-    let remote_object_hashes = client.get_all_object_hashes().await
-        .with_context(|| "Failed to fetch remote object hashes")?;
-    let remote_objects_set: HashSet<String> = remote_object_hashes.iter().cloned().collect();
-    let remote_objects_map: HashMap<String, Vec<u8>> = remote_object_hashes.clone().into_iter().map(|h| (h, Vec::new())).collect();
+    // Determine what needs to be pushed while streaming the remote listing,
+    // so only objects we also have locally are kept in memory
+    let mut missing_objects: HashSet<String> = local_object_hashes;
+    let mut common_objects: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut remote_hashes = pin!(transport::object_hashes(client, OBJECT_PAGE_SIZE));
+    while let Some(hash) = remote_hashes.try_next().await
+        .with_context(|| "Failed to fetch remote object hashes")?
+    {
+        if missing_objects.remove(&hash) {
+            common_objects.insert(hash, Vec::new());
+        }
+    }
 
     pb.inc(1);
 
-    // Determine what needs to be pushed
-    let missing_objects: HashSet<String> = local_object_hashes
-        .difference(&remote_objects_set)
-        .cloned()
-        .collect();
-
-    if missing_objects.is_empty() {
+    // A forced push may move a ref to objects the remote already has
+    let up_to_date = refs_to_update
+        .iter()
+        .all(|(ref_name, head_commit)| remote_refs.get(ref_name) == Some(head_commit));
+    if missing_objects.is_empty() && up_to_date {
         println!("{}", "No new objects to push".green());
-            return Ok(());
-        }
+        return Ok(());
+    }
 
     // Create negotiation request
     pb.set_message("Negotiating with remote...");
     let current_branch = &repo.current_branch;
     let wants = vec![current_branch.clone()];
-    let haves = remote_object_hashes;
+    let haves: Vec<String> = common_objects.keys().cloned().collect();
 
     let negotiation_request = NegotiationRequest {
         wants,
@@ -128,48 +251,66 @@ pub async fn push_changes(repo: &Repository) -> Result<()> {
 
     // Build and upload pack
     pb.set_message("Building and uploading pack...");
-    let pack = create_thin_pack(&local_objects, &remote_objects_map);
+    let pack = create_thin_pack(&local_objects, &common_objects);
     let pack_data = pack.to_bytes()
         .with_context(|| "Failed to serialize pack")?;
 
-    client.upload_pack(&pack_data).await
-        .with_context(|| "Failed to upload pack")?;
+    // A push that only moves refs has nothing to upload
+    if pack.objects.is_empty() {
+        pb.set_message("No objects to upload");
+    } else if capabilities.resumable_upload {
+        client
+            .upload_pack_resumable(&pack_data, |sent, total| {
+                pb.set_message(format!("Uploading pack... {}/{} bytes", sent, total));
+            })
+            .await
+            .with_context(|| "Failed to upload pack")?;
+    } else {
+        client.upload_pack(&pack_data).await
+            .with_context(|| "Failed to upload pack")?;
+    }
 
     // Update remote refs
     pb.set_message("Updating remote refs...");
-    let mut refs_to_update = HashMap::new();
-    
-    if let Some(branch) = repo.get_current_branch() {
-        if let Some(head_commit) = branch.get_head_commit() {
-            refs_to_update.insert(format!("refs/heads/{}", current_branch), head_commit.to_string());
-        }
-    }
-
+    // Refs that weren't allowed to be forced were checked above
     let push_request = PushRequest {
         refs: refs_to_update,
         objects: missing_objects.into_iter().collect(),
-        force: false,
+        force: options.force || !forced.is_empty(),
+        atomic: options.atomic,
     };
 
     let push_response = client.negotiate_push(&push_request).await
         .with_context(|| "Failed to push refs")?;
 
+    // An atomic push is all-or-nothing: any refusal means no ref moved
+    if options.atomic && (!push_response.success || !push_response.rejected_refs.is_empty()) {
+        pb.finish_and_clear();
+        println!(
+            "\n{}",
+            "Atomic push rejected; no remote refs were updated".red().bold()
+        );
+        print_rejected_refs(&push_response, capabilities.report_status);
+        if let Some(error) = &push_response.error {
+            println!("{}", error.red());
+        }
+        anyhow::bail!("Atomic push to {} failed", remote_url);
+    }
+
     pb.finish_with_message("Push completed successfully!");
 
     // Report results
     println!("\n{}", "Push completed successfully!".green().bold());
     println!("Objects uploaded: {}", pack.header.object_count.to_string().cyan());
     println!("Pack size: {} bytes", pack_data.len().to_string().cyan());
-    println!("Remote: {}", remote.url.cyan());
+    println!("Remote: {}", remote_url.cyan());
     println!("Branch: {}", current_branch.yellow().bold());
 
     if !push_response.updated_refs.is_empty() {
         println!("Updated refs: {}", push_response.updated_refs.join(", ").green());
     }
 
-    if !push_response.rejected_refs.is_empty() {
-        println!("Rejected refs: {}", push_response.rejected_refs.join(", ").red());
-    }
+    print_rejected_refs(&push_response, capabilities.report_status);
 
     if let Some(error) = push_response.error {
         println!("Warning: {}", error.yellow());
@@ -178,23 +319,29 @@ pub async fn push_changes(repo: &Repository) -> Result<()> {
     Ok(())
 }
 
+/// List refused refs, with the server's reason when it advertises
+/// `report-status`.
+fn print_rejected_refs(response: &PushResponse, report_status: bool) {
+    for ref_name in &response.rejected_refs {
+        let reason = response.ref_status.get(ref_name).filter(|_| report_status);
+        match reason {
+            Some(reason) => println!(" {} {} ({})", "! [rejected]".red(), ref_name, reason),
+            None => println!(" {} {}", "! [rejected]".red(), ref_name),
+        }
+    }
+}
+
 fn collect_local_objects(repo: &Repository) -> Result<HashMap<String, Vec<u8>>> {
     let mut objects = HashMap::new();
     let objects_dir = repo.get_objects_dir();
 
-    for entry in std::fs::read_dir(&objects_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            for obj in std::fs::read_dir(entry.path())? {
-                let obj = obj?;
-                let hash = format!(
-                    "{}{}",
-                    entry.file_name().to_string_lossy(),
-                    obj.file_name().to_string_lossy()
-                );
-                let data = std::fs::read(obj.path())?;
-                objects.insert(hash, data);
-            }
+    for hash in gc::list_loose_objects(repo)? {
+        let data = std::fs::read(objects_dir.join(&hash[..2]).join(&hash[2..]))?;
+        objects.insert(hash, data);
+    }
+    for hash in pack::packed_ids(&objects_dir) {
+        if let Some(data) = pack::read_packed(&objects_dir, &hash)? {
+            objects.insert(hash, data);
         }
     }
 
@@ -203,31 +350,136 @@ fn collect_local_objects(repo: &Repository) -> Result<HashMap<String, Vec<u8>>>
 
 pub async fn push_with_options(
     repo: &Repository,
-    force: bool,
     remote_name: Option<&str>,
-    _refspec: Option<&str>,
+    options: &PushOptions,
 ) -> Result<()> {
     let remote_name = remote_name.unwrap_or("origin");
     
-    let remote = match repo.remotes.get(remote_name) {
-        Some(remote) => remote,
-        None => {
-            println!("{}", format!("No '{}' remote configured", remote_name).yellow());
-            return Ok(());
-        }
-    };
-
-    let _client = RemoteClient::new(&remote.url);
+    if !repo.remotes.contains_key(remote_name) {
+        println!("{}", format!("No '{}' remote configured", remote_name).yellow());
+        return Ok(());
+    }
 
     // Enhanced push with options
-    if force {
+    if options.force {
         println!("{}", "Force push requested - this may overwrite remote changes!".yellow().bold());
     }
 
-    // TODO: Implement refspec parsing and filtering
-    // TODO: Implement force push logic
     // TODO: Implement dry-run mode
 
-    // For now, delegate to the main push function
-    push_changes(repo).await
+    let mut client = RemoteClient::configured(&repo.remotes[remote_name].url);
+    push_changes(repo, &mut client, remote_name, options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::remote_client::Capabilities;
+    use crate::utils::test_utils::TestRepo;
+    use crate::utils::transport::MockRemote;
+
+    #[tokio::test]
+    async fn atomic_push_rejected_for_one_ref_updates_none() {
+        let mut local = TestRepo::new();
+        local.write("a", "1\n");
+        let old = local.commit("first").await;
+        let mut mock = MockRemote::from_repo(&local.repo);
+        local.checkout_new("feat").await;
+        local.write("b", "b\n");
+        local.commit("feat").await;
+        local.checkout("main").await;
+        local.write("a", "2\n");
+        local.commit("second").await;
+
+        mock.capabilities = Capabilities { atomic: true, report_status: true, ..Default::default() };
+        mock.reject.insert("refs/heads/feat".to_string());
+        let options = PushOptions { atomic: true, all: true, ..Default::default() };
+        let err = push_changes(&local.repo, &mut mock, "origin", &options).await.unwrap_err();
+
+        assert!(err.to_string().contains("Atomic push"), "{}", err);
+        assert_eq!(mock.get("refs/heads/main"), Some(old));
+        assert_eq!(mock.get("refs/heads/feat"), None);
+        let pushes = mock.push_requests.lock().unwrap();
+        assert!(pushes[0].atomic);
+        assert_eq!(pushes[0].refs.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn only_force_moves_a_remote_branch_backwards_or_sideways() {
+        let mut local = TestRepo::new();
+        local.write("a", "1\n");
+        let base = local.commit("base").await;
+        local.checkout_new("other").await;
+        local.write("c", "c\n");
+        let other = local.commit("other").await;
+        local.checkout("main").await;
+        local.write("a", "2\n");
+        let head = local.commit("main").await;
+        let mut mock = MockRemote::from_repo(&local.repo);
+        let set_remote_main = |mock: &MockRemote, id: &str| {
+            mock.refs.lock().unwrap().insert("refs/heads/main".to_string(), id.to_string());
+        };
+
+        // Fast-forward
+        set_remote_main(&mock, &base);
+        push_changes(&local.repo, &mut mock, "origin", &PushOptions::default()).await.unwrap();
+        assert_eq!(mock.get("refs/heads/main"), Some(head.clone()));
+        assert!(!mock.push_requests.lock().unwrap()[0].force);
+
+        // Diverged: refused without force
+        set_remote_main(&mock, &other);
+        let err = push_changes(&local.repo, &mut mock, "origin", &PushOptions::default()).await.unwrap_err();
+        assert!(err.to_string().contains("rejected"), "{}", err);
+        assert_eq!(mock.get("refs/heads/main"), Some(other.clone()));
+        assert_eq!(mock.push_requests.lock().unwrap().len(), 1);
+
+        let force = PushOptions { force: true, ..Default::default() };
+        push_changes(&local.repo, &mut mock, "origin", &force).await.unwrap();
+        assert_eq!(mock.get("refs/heads/main"), Some(head));
+        assert!(mock.push_requests.lock().unwrap()[1].force);
+    }
+
+    #[test]
+    fn refspecs_parse_source_destination_and_force() {
+        let spec = |src: &str, dst: &str, force| Refspec { src: src.to_string(), dst: dst.to_string(), force };
+        assert_eq!(Refspec::parse("main").unwrap(), spec("main", "main", false));
+        assert_eq!(Refspec::parse("main:release").unwrap(), spec("main", "release", false));
+        assert_eq!(Refspec::parse("+main:release").unwrap(), spec("main", "release", true));
+        assert_eq!(
+            Refspec::parse("refs/heads/main:refs/heads/release").unwrap(),
+            spec("main", "release", false)
+        );
+        assert_eq!(Refspec::parse("+main:release").unwrap().remote_ref(), "refs/heads/release");
+        assert!(Refspec::parse(":release").is_err());
+        assert!(Refspec::parse("main:").is_err());
+    }
+
+    #[tokio::test]
+    async fn refspecs_push_only_the_named_refs() {
+        let mut local = TestRepo::new();
+        local.write("a", "1\n");
+        let main = local.commit("first").await;
+        local.checkout_new("topic").await;
+        local.write("b", "b\n");
+        let topic = local.commit("topic").await;
+        local.checkout("main").await;
+        let mut mock = MockRemote::default();
+
+        let refspecs = ["main:release", "+topic"].iter().map(|s| Refspec::parse(s).unwrap()).collect();
+        let options = PushOptions { refspecs, ..Default::default() };
+        push_changes(&local.repo, &mut mock, "origin", &options).await.unwrap();
+
+        let pushed = mock.push_requests.lock().unwrap()[0].clone();
+        let mut refs: Vec<(String, String)> = pushed.refs.into_iter().collect();
+        refs.sort();
+        assert_eq!(
+            refs,
+            vec![("refs/heads/release".to_string(), main), ("refs/heads/topic".to_string(), topic)]
+        );
+        assert!(pushed.force);
+
+        let unknown = PushOptions { refspecs: vec![Refspec::parse("nope").unwrap()], ..Default::default() };
+        let err = push_changes(&local.repo, &mut mock, "origin", &unknown).await.unwrap_err();
+        assert!(err.to_string().contains("Unknown local branch 'nope'"), "{}", err);
+    }
 }
@@ -0,0 +1,241 @@
+use crate::commands::commit::{self, CommitOptions};
+use crate::commands::{cherry_pick, merge};
+use crate::core::commit_graph::find_merge_base;
+use crate::core::rebase::{self, RebaseAction, RebaseStep};
+use crate::core::repository::Repository;
+use crate::utils::hash_utils;
+use anyhow::Result;
+use colored::*;
+use ed25519_dalek::SigningKey;
+
+/// Options for `hx rebase`.
+#[derive(Debug, Clone, Default)]
+pub struct RebaseOptions {
+    /// Fold `fixup!`/`squash!` commits into the commits they name
+    pub autosquash: bool,
+}
+
+/// Replay the current branch's commits since it diverged from `upstream`
+/// on top of `upstream`, one new commit each, leaving a linear history.
+/// Merge commits are dropped. Stops at the first commit that conflicts,
+/// with markers in the working tree, and lists what was left unapplied.
+pub async fn rebase_onto(
+    repo: &mut Repository,
+    upstream: &str,
+    options: &RebaseOptions,
+    keypair: &SigningKey,
+) -> Result<()> {
+    let head = repo
+        .head_commit()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("No commits on '{}' to rebase", repo.current_branch))?;
+    let base = find_merge_base(repo, &head, upstream);
+    // Already on top of upstream: only autosquash can still change anything
+    if base.as_deref() == Some(upstream) && !options.autosquash {
+        println!("{}", "Current branch is up to date".green());
+        return Ok(());
+    }
+
+    // Local commits since the merge base, oldest first
+    let mut steps = Vec::new();
+    let mut commit_id = head.clone();
+    while base.as_ref() != Some(&commit_id) {
+        let commit = repo.get_commit_object(&commit_id)?;
+        if commit.parent_ids.len() <= 1 {
+            let subject = commit.message.lines().next().unwrap_or("");
+            steps.push(RebaseStep::pick(&commit_id, subject));
+        }
+        match commit.parent_ids.first() {
+            Some(parent) => commit_id = parent.clone(),
+            None => break,
+        }
+    }
+    steps.reverse();
+    if options.autosquash {
+        steps = rebase::autosquash(steps);
+    }
+    let squashes = steps.iter().any(|step| step.action != RebaseAction::Pick);
+    if base.as_deref() == Some(upstream) && !squashes {
+        println!("{}", "Current branch is up to date".green());
+        return Ok(());
+    }
+    repo.require_clean("rebase")?;
+
+    // Start over from the upstream head
+    let upstream_tree = repo.get_commit_object(upstream)?.tree_files();
+    repo.update_worktree(&repo.head_files(), &upstream_tree)?;
+    repo.set_head(upstream)?;
+    repo.log_ref_update(
+        Some(&head),
+        upstream,
+        &format!("rebase: checkout {}", hash_utils::get_short_hash(upstream)),
+    )?;
+    if steps.is_empty() {
+        println!(
+            "{}",
+            format!("Fast-forwarded {} to {}", repo.current_branch, hash_utils::get_short_hash(upstream)).green()
+        );
+        return Ok(());
+    }
+
+    for (i, step) in steps.iter().enumerate() {
+        let verb = match step.action {
+            RebaseAction::Pick => "Applying",
+            RebaseAction::Fixup => "Fixing up",
+            RebaseAction::Squash => "Squashing",
+        };
+        println!(
+            "{}",
+            format!(
+                "{} ({}/{}) {} {}",
+                verb,
+                i + 1,
+                steps.len(),
+                hash_utils::get_short_hash(&step.commit_id),
+                step.subject
+            )
+            .blue()
+        );
+        let conflicts = match step.action {
+            RebaseAction::Pick => cherry_pick::replay(repo, &step.commit_id, keypair).await?,
+            RebaseAction::Fixup | RebaseAction::Squash => squash_into_head(repo, step, keypair).await?,
+        };
+        if conflicts.is_empty() {
+            continue;
+        }
+        println!(
+            "{}",
+            format!("Could not apply {}: conflicts in", hash_utils::get_short_hash(&step.commit_id))
+                .red()
+                .bold()
+        );
+        for path in &conflicts {
+            println!("  {}", path.red().bold());
+        }
+        if step.action == RebaseAction::Pick {
+            println!("Resolve them, 'hx add' the files and run 'hx commit'.");
+        } else {
+            println!("Resolve them, 'hx add' the files and run 'hx commit --amend'.");
+        }
+        if i + 1 < steps.len() {
+            println!("Then cherry-pick the commits that were not applied yet:");
+            for remaining in &steps[i + 1..] {
+                println!("  hx cherry-pick {}  # {}", hash_utils::get_short_hash(&remaining.commit_id), remaining.subject);
+            }
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Successfully rebased {} onto {}",
+            repo.current_branch,
+            hash_utils::get_short_hash(upstream)
+        )
+        .green()
+        .bold()
+    );
+    Ok(())
+}
+
+/// Apply a `fixup!`/`squash!` commit's change and fold it into HEAD. A
+/// fixup keeps HEAD's message; a squash adds its own below it. Conflicted
+/// paths are returned with nothing committed, as for `cherry_pick::replay`.
+async fn squash_into_head(repo: &mut Repository, step: &RebaseStep, keypair: &SigningKey) -> Result<Vec<String>> {
+    let commit = repo.get_commit_object(&step.commit_id)?;
+    let (before, after) = merge::commit_change(repo, &commit)?;
+    let conflicts = merge::apply_tree_change(repo, &before, &after)?;
+    if !conflicts.is_empty() {
+        repo.save()?;
+        return Ok(conflicts);
+    }
+
+    if repo.index.is_empty() && step.action == RebaseAction::Fixup {
+        println!(
+            "{}",
+            format!("{} is already applied, skipping", hash_utils::get_short_hash(&step.commit_id)).yellow()
+        );
+        return Ok(Vec::new());
+    }
+
+    let head = repo
+        .head_commit()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Nothing to squash '{}' into", step.subject))?;
+    let mut message = repo.get_commit_object(&head)?.message;
+    if step.action == RebaseAction::Squash {
+        message = format!("{}\n\n{}", message.trim_end(), commit.message);
+    }
+    let options = CommitOptions {
+        amend: true,
+        ..Default::default()
+    };
+    commit::commit_changes(repo, &message, &options, keypair).await?;
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{self, TestRepo};
+
+    /// Subjects from HEAD back to (not including) `stop`.
+    fn subjects(t: &TestRepo, stop: &str) -> Vec<String> {
+        let mut subjects = Vec::new();
+        let mut id = t.head();
+        while id != stop {
+            let commit = t.repo.get_commit_object(&id).unwrap();
+            subjects.push(commit.message.lines().next().unwrap_or("").to_string());
+            id = commit.parent_ids[0].clone();
+        }
+        subjects
+    }
+
+    #[tokio::test]
+    async fn autosquash_folds_fixups_into_their_targets() {
+        let mut t = TestRepo::new();
+        t.write("base", "base\n");
+        let base = t.commit("base").await;
+        t.write("parser", "parse\n");
+        t.commit("Add parser").await;
+        t.write("lexer", "lex\n");
+        t.commit("Add lexer").await;
+
+        let fixup = commit::autosquash_message(&t.repo, "fixup", "HEAD~1", None).unwrap();
+        assert_eq!(fixup, "fixup! Add parser");
+        t.write("parser", "parse, fixed\n");
+        t.commit(&fixup).await;
+        let squash = commit::autosquash_message(&t.repo, "squash", "HEAD~1", Some("Lex more")).unwrap();
+        assert_eq!(squash, "squash! Add lexer\n\nLex more");
+        t.write("lexer", "lex more\n");
+        t.commit(&squash).await;
+
+        let options = RebaseOptions { autosquash: true };
+        rebase_onto(&mut t.repo, &base, &options, &test_utils::keypair()).await.unwrap();
+
+        assert_eq!(subjects(&t, &base), ["Add lexer", "Add parser"]);
+        let head = t.repo.get_commit_object(&t.head()).unwrap();
+        assert_eq!(head.message, "Add lexer\n\nsquash! Add lexer\n\nLex more");
+        let parser = t.repo.get_commit_object(&head.parent_ids[0]).unwrap();
+        assert_eq!(parser.message, "Add parser");
+        assert!(parser.tree_files().contains_key("parser"));
+        assert!(!parser.tree_files().contains_key("lexer"));
+        assert_eq!(t.read("parser"), "parse, fixed\n");
+        assert_eq!(t.read("lexer"), "lex more\n");
+    }
+
+    #[tokio::test]
+    async fn without_autosquash_fixups_stay_put() {
+        let mut t = TestRepo::new();
+        t.write("base", "base\n");
+        let base = t.commit("base").await;
+        t.write("parser", "parse\n");
+        t.commit("Add parser").await;
+        t.write("parser", "parse, fixed\n");
+        let head = t.commit("fixup! Add parser").await;
+
+        rebase_onto(&mut t.repo, &base, &RebaseOptions::default(), &test_utils::keypair()).await.unwrap();
+        assert_eq!(t.head(), head);
+    }
+}
@@ -0,0 +1,31 @@
+use crate::core::reflog::Reflog;
+use crate::core::repository::Repository;
+use crate::utils::hash_utils;
+use anyhow::Result;
+use colored::*;
+
+pub async fn show_reflog(repo: &Repository, limit: usize) -> Result<()> {
+    let reflog = Reflog::load(&repo.git_dir)?;
+    let entries = reflog.for_ref("HEAD");
+
+    if entries.is_empty() {
+        println!("{}", "No reflog entries yet".yellow());
+        return Ok(());
+    }
+
+    for (i, entry) in entries.iter().take(limit).enumerate() {
+        println!(
+            "{} {} {} {}",
+            hash_utils::get_short_hash(&entry.new_commit).cyan(),
+            format!("HEAD@{{{}}}:", i).yellow(),
+            entry.message,
+            entry
+                .timestamp
+                .format("(%Y-%m-%d %H:%M:%S)")
+                .to_string()
+                .dimmed()
+        );
+    }
+
+    Ok(())
+}
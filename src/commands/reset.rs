@@ -1,13 +1,19 @@
+use crate::commands::checkout;
+use crate::core::commit::Commit;
 use crate::core::index::IndexEntry;
-use crate::core::index::IndexNode;
 use crate::core::repository::Repository;
 use anyhow::Result;
 use chrono::Utc;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::fs;
+use std::path::PathBuf;
 
-pub async fn reset_repository(repo: &mut Repository, target: &str, mode: &str) -> Result<()> {
+pub async fn reset_repository(
+    repo: &mut Repository,
+    target: &str,
+    mode: &str,
+    force: bool,
+) -> Result<()> {
     let pb = ProgressBar::new(3);
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -17,22 +23,12 @@ pub async fn reset_repository(repo: &mut Repository, target: &str, mode: &str) -
 
     pb.set_message("Resetting repository...");
 
-    // Find the target commit
-    let current_branch_name = repo.current_branch.clone();
-    let commit_id = if target == "HEAD" {
-        // Limit the scope of the branch reference to this block
-        let branch = repo
-            .branches
-            .get(&current_branch_name)
-            .ok_or_else(|| anyhow::anyhow!("No current branch"))?;
-        branch
-            .get_head_commit()
-            .ok_or_else(|| anyhow::anyhow!("No HEAD commit"))?
-            .to_string()
-    } else {
-        target.to_string()
-    };
-    let commit = repo.get_commit_object(&commit_id)?;
+    // Find the target commit: branch, short hash, HEAD~n, ...
+    let commit_id = repo.resolve_rev(target)?;
+    let commit = repo
+        .get_commit_object(&commit_id)
+        .map_err(|_| anyhow::anyhow!("'{}' does not name a commit", target))?;
+    let old_head = repo.head_commit().cloned();
 
     match mode {
         "soft" => {
@@ -44,46 +40,16 @@ pub async fn reset_repository(repo: &mut Repository, target: &str, mode: &str) -
         "mixed" => {
             // Move HEAD and reset index
             let _ = repo.set_head(&commit_id);
-            repo.index.clear();
-            for (path, file_change) in commit.get_files() {
-                let entry = IndexEntry {
-                    path: path.clone(),
-                    content_hash: file_change.content_hash.clone(),
-                    size: file_change.size,
-                    mode: file_change.mode,
-                    timestamp: Utc::now(),
-                    stage: 0,
-                };
-                repo.index
-                    .entries
-                    .insert(path.clone(), IndexNode::File(entry));
-            }
+            reset_index(repo, &commit);
             pb.inc(1);
             pb.set_message("Index reset (mixed reset)...");
         }
         "hard" => {
-            // Move HEAD, reset index, and update working directory
+            // Update the working directory (refusing to clobber local
+            // changes), then move HEAD and reset the index
+            checkout::switch_worktree(repo, Some(&commit_id), &format!("reset --hard {}", target), force)?;
             let _ = repo.set_head(&commit_id);
-            repo.index.clear();
-            for (path, file_change) in commit.get_files() {
-                let entry = IndexEntry {
-                    path: path.clone(),
-                    content_hash: file_change.content_hash.clone(),
-                    size: file_change.size,
-                    mode: file_change.mode,
-                    timestamp: Utc::now(),
-                    stage: 0,
-                };
-                repo.index
-                    .entries
-                    .insert(path.clone(), IndexNode::File(entry));
-                // Overwrite working directory file
-                let blob_obj = crate::core::object::Object::load(
-                    &repo.get_objects_dir(),
-                    &file_change.content_hash,
-                )?;
-                fs::write(path, blob_obj.data)?;
-            }
+            reset_index(repo, &commit);
             pb.inc(1);
             pb.set_message("Index and working directory reset (hard reset)...");
         }
@@ -98,6 +64,11 @@ pub async fn reset_repository(repo: &mut Repository, target: &str, mode: &str) -
 
     pb.inc(1);
     pb.set_message("Updating repository state...");
+    repo.log_ref_update(
+        old_head.as_deref(),
+        &commit_id,
+        &format!("reset: moving to {}", target),
+    )?;
     repo.save()?;
     pb.finish_with_message("Repository reset successfully!");
 
@@ -107,3 +78,129 @@ pub async fn reset_repository(repo: &mut Repository, target: &str, mode: &str) -
     println!("Reset mode: {}", mode.cyan());
     Ok(())
 }
+
+/// Replace the index with the files of `commit`.
+fn reset_index(repo: &mut Repository, commit: &Commit) {
+    repo.index.clear();
+    for (path, file_change) in commit.get_files() {
+        repo.index.add_file(
+            path,
+            IndexEntry {
+                path: path.clone(),
+                content_hash: file_change.content_hash.clone(),
+                size: file_change.size,
+                mode: file_change.mode,
+                timestamp: Utc::now(),
+                stage: 0,
+            },
+        );
+    }
+}
+
+/// Reset just the index entries for `paths` to their state in `target`,
+/// unstaging whatever was added for them. The working tree is left alone.
+pub async fn reset_paths(repo: &mut Repository, target: &str, paths: Vec<PathBuf>) -> Result<()> {
+    let options = crate::commands::restore::RestoreOptions {
+        source: Some(target.to_string()),
+        staged: true,
+        worktree: false,
+    };
+    crate::commands::restore::restore_files(repo, paths, &options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn dirty_file_blocks_a_hard_reset_until_forced() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "one\n");
+        let first = t.commit("first").await;
+        t.write("a.txt", "two\n");
+        t.write("b.txt", "added later\n");
+        let second = t.commit("second").await;
+        t.write("a.txt", "uncommitted\n");
+
+        let err = reset_repository(&mut t.repo, &first, "hard", false).await.unwrap_err();
+        assert!(err.to_string().contains("a.txt"), "{}", err);
+        assert_eq!(t.head(), second);
+        assert_eq!(t.read("a.txt"), "uncommitted\n");
+
+        reset_repository(&mut t.repo, &first, "hard", true).await.unwrap();
+        assert_eq!(t.head(), first);
+        assert_eq!(t.read("a.txt"), "one\n");
+        assert!(!t.exists("b.txt"));
+    }
+
+    #[tokio::test]
+    async fn local_copy_matching_the_target_is_not_in_the_way() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "one\n");
+        let first = t.commit("first").await;
+        t.write("a.txt", "two\n");
+        t.commit("second").await;
+        t.write("a.txt", "one\n");
+
+        assert!(t.repo.would_overwrite_changes(&t.repo.get_commit_object(&first).unwrap().tree_files()).is_empty());
+        reset_repository(&mut t.repo, &first, "hard", false).await.unwrap();
+        assert_eq!(t.head(), first);
+    }
+
+    #[tokio::test]
+    async fn targets_resolve_like_any_revision() {
+        let mut t = TestRepo::new();
+        t.write("dir/b.txt", "one\n");
+        let first = t.commit("first").await;
+        t.checkout_new("keep").await;
+        t.checkout("main").await;
+        t.write("dir/b.txt", "two\n");
+        let second = t.commit("second").await;
+        t.write("dir/b.txt", "three\n");
+        t.commit("third").await;
+
+        reset_repository(&mut t.repo, "HEAD~1", "mixed", false).await.unwrap();
+        assert_eq!(t.head(), second);
+        let staged = t.repo.index.get_file("dir/b.txt").unwrap();
+        assert_eq!(staged.content_hash, t.repo.head_files()["dir/b.txt"]);
+        // A mixed reset leaves the working tree alone
+        assert_eq!(t.read("dir/b.txt"), "three\n");
+
+        reset_repository(&mut t.repo, "keep", "hard", true).await.unwrap();
+        assert_eq!(t.head(), first);
+        assert_eq!(t.repo.current_branch, "main");
+        assert_eq!(t.read("dir/b.txt"), "one\n");
+
+        reset_repository(&mut t.repo, &second[..10], "soft", false).await.unwrap();
+        assert_eq!(t.head(), second);
+        assert_eq!(t.read("dir/b.txt"), "one\n");
+
+        let err = reset_repository(&mut t.repo, "no-such-branch", "hard", false).await.unwrap_err();
+        assert!(err.to_string().contains("no-such-branch"), "{}", err);
+        assert_eq!(t.head(), second);
+    }
+
+    #[tokio::test]
+    async fn unstaging_a_path_leaves_the_others_staged() {
+        let mut t = TestRepo::new();
+        t.write("base.txt", "base\n");
+        t.commit("base").await;
+        t.write("a.txt", "a\n");
+        t.write("b.txt", "b\n");
+        t.write("base.txt", "edited\n");
+        t.add_all().await;
+
+        let paths = vec![t.repo.path.join("a.txt"), t.repo.path.join("base.txt")];
+        reset_paths(&mut t.repo, "HEAD", paths).await.unwrap();
+
+        let saved = Repository::open(t.repo.path.to_str().unwrap()).unwrap();
+        assert!(!saved.index.has_file("a.txt"));
+        assert!(saved.index.has_file("b.txt"));
+        // Back to its HEAD content, so nothing is staged for it
+        assert!(!saved.index.has_file("base.txt"));
+        // The working tree is untouched
+        assert_eq!(t.read("a.txt"), "a\n");
+        assert_eq!(t.read("base.txt"), "edited\n");
+    }
+}
@@ -1,11 +1,29 @@
+use crate::core::index::IndexEntry;
 use crate::core::object::Object;
 use crate::core::repository::Repository;
-use crate::utils::file_utils;
+use crate::utils::{eol, file_utils, path_utils};
 use anyhow::Result;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
 
-pub async fn restore_files(repo: &Repository, paths: Vec<std::path::PathBuf>) -> Result<()> {
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// Commit to restore from (default: HEAD)
+    pub source: Option<String>,
+    /// Update the staged entry
+    pub staged: bool,
+    /// Update the working tree copy; implied when `staged` is not set
+    pub worktree: bool,
+}
+
+pub async fn restore_files(
+    repo: &mut Repository,
+    paths: Vec<PathBuf>,
+    options: &RestoreOptions,
+) -> Result<()> {
+    let worktree = options.worktree || !options.staged;
     let pb = ProgressBar::new(paths.len() as u64);
     pb.set_style(
         ProgressStyle::default_spinner()
@@ -13,57 +31,186 @@ pub async fn restore_files(repo: &Repository, paths: Vec<std::path::PathBuf>) ->
             .unwrap(),
     );
 
-    pb.set_message("Restoring files from last commit...");
-
-    // Get the last commit
-    let current_branch = repo
-        .get_current_branch()
-        .ok_or_else(|| anyhow::anyhow!("No current branch found"))?;
-
-    let head_commit_id = current_branch
-        .get_head_commit()
-        .ok_or_else(|| anyhow::anyhow!("No commits found"))?;
-
-    // Load the commit object
-    let commit_object = crate::core::object::Object::load(&repo.get_objects_dir(), head_commit_id)?;
-    let commit = crate::core::commit::Commit::from_object(&commit_object)?;
+    let source = options.source.as_deref().unwrap_or("HEAD");
+    pb.set_message(format!("Restoring files from {}...", source));
 
-    let mut restored_count = 0;
-    let mut skipped_count = 0;
+    let source_id = repo.resolve_rev(source)?;
+    let source_files = repo.get_commit_object(&source_id)?.tree_files();
+    let head_files = repo.head_files();
 
-    for path in paths {
-        let relative_path = path
+    // Expand pathspecs against the source commit, plus staged paths when
+    // the index is being restored (so new files can be unstaged)
+    let cwd = std::env::current_dir()?;
+    let mut candidates: BTreeSet<String> = source_files.keys().cloned().collect();
+    if options.staged {
+        candidates.extend(repo.index.get_file_paths());
+    }
+    let mut selected = BTreeSet::new();
+    for path in &paths {
+        let absolute = cwd.join(path);
+        let spec = absolute
             .strip_prefix(&repo.path)
-            .unwrap_or(&path)
+            .unwrap_or(path)
             .to_string_lossy()
             .to_string();
+        let matches: Vec<&String> = candidates
+            .iter()
+            .filter(|candidate| path_utils::pathspec_matches(&spec, candidate))
+            .collect();
+        if matches.is_empty() {
+            pb.finish_and_clear();
+            anyhow::bail!(
+                "pathspec '{}' did not match any file(s) known to helix",
+                path.display()
+            );
+        }
+        selected.extend(matches.into_iter().cloned());
+    }
+    pb.set_length(selected.len() as u64);
 
+    let objects_dir = repo.get_objects_dir();
+    let mut restored_count = 0;
+    let mut index_count = 0;
+    let mut skipped_count = 0;
+
+    for relative_path in &selected {
         pb.set_message(format!("Restoring {}", relative_path));
+        let full_path = repo.path.join(relative_path);
+        let blob_hash = source_files.get(relative_path);
 
-        // Check if file exists in the commit
-        if let Some(file_change) = commit.get_file_change(&relative_path) {
-            // Load the blob object and restore the content
-            let blob_object = Object::load(&repo.get_objects_dir(), &file_change.content_hash)?;
-            let content = blob_object.data.as_bytes();
-            if let Ok(_) = file_utils::write_file_content(&path, content) {
-                restored_count += 1;
+        if options.staged {
+            if blob_hash == head_files.get(relative_path) {
+                // Matches HEAD: nothing left to stage for this path
+                repo.index.remove_file(relative_path);
+            } else if let Some(blob_hash) = blob_hash {
+                let blob = Object::load(&objects_dir, blob_hash)?;
+                let mode = match repo.index.get_file(relative_path) {
+                    Some(entry) => entry.mode,
+                    None => file_utils::get_file_mode(&full_path).unwrap_or(0o100644),
+                };
+                repo.index.add_file(
+                    relative_path,
+                    IndexEntry {
+                        path: relative_path.clone(),
+                        content_hash: blob_hash.clone(),
+                        size: blob.data.len() as u64,
+                        mode,
+                        timestamp: chrono::Utc::now(),
+                        stage: 0,
+                    },
+                );
             } else {
-                skipped_count += 1;
+                repo.index.remove_file(relative_path);
+            }
+            index_count += 1;
+        }
+
+        if worktree {
+            match blob_hash {
+                Some(blob_hash) => {
+                    let blob_object = Object::load(&objects_dir, blob_hash)?;
+                    let content =
+                        eol::to_worktree(repo, relative_path, &blob_object.data);
+                    if file_utils::write_file_content(&full_path, &content).is_ok() {
+                        restored_count += 1;
+                    } else {
+                        skipped_count += 1;
+                    }
+                }
+                None => skipped_count += 1,
             }
-        } else {
-            skipped_count += 1;
         }
 
         pb.inc(1);
     }
 
+    if options.staged {
+        repo.save()?;
+    }
+
     pb.finish_with_message("Files restored successfully!");
 
     println!("\n{}", "Files restored successfully!".green().bold());
-    println!("Restored: {} files", restored_count.to_string().cyan());
+    if worktree {
+        println!("Restored: {} files", restored_count.to_string().cyan());
+    }
+    if options.staged {
+        println!("Index entries restored: {}", index_count.to_string().cyan());
+    }
     if skipped_count > 0 {
         println!("Skipped: {} files", skipped_count.to_string().yellow());
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn a_glob_restores_every_matching_file() {
+        let mut t = TestRepo::new();
+        for path in ["src/a.rs", "src/b.rs", "src/c.txt", "README"] {
+            t.write(path, "committed\n");
+        }
+        t.commit("base").await;
+        for path in ["src/a.rs", "src/b.rs", "src/c.txt", "README"] {
+            t.write(path, "edited\n");
+        }
+
+        let spec = t.repo.path.join("src/*.rs");
+        restore_files(&mut t.repo, vec![spec], &RestoreOptions::default()).await.unwrap();
+
+        assert_eq!(t.read("src/a.rs"), "committed\n");
+        assert_eq!(t.read("src/b.rs"), "committed\n");
+        assert_eq!(t.read("src/c.txt"), "edited\n");
+        assert_eq!(t.read("README"), "edited\n");
+
+        let nothing = t.repo.path.join("*.md");
+        assert!(restore_files(&mut t.repo, vec![nothing], &RestoreOptions::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn staged_takes_the_entry_from_the_source_commit() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "v1\n");
+        t.commit("one").await;
+        t.write("a.txt", "v2\n");
+        t.commit("two").await;
+        let v1 = Object::new("blob".to_string(), b"v1\n".to_vec()).id;
+        let path = t.repo.path.join("a.txt");
+
+        let staged = RestoreOptions { source: Some("HEAD~1".to_string()), staged: true, ..Default::default() };
+        restore_files(&mut t.repo, vec![path.clone()], &staged).await.unwrap();
+        assert_eq!(t.repo.index.get_file("a.txt").unwrap().content_hash, v1);
+        assert_eq!(t.read("a.txt"), "v2\n");
+        // The index change is saved, not just made in memory
+        let reopened = Repository::open(&t.repo.path.to_string_lossy()).unwrap();
+        assert_eq!(reopened.index.get_file("a.txt").unwrap().content_hash, v1);
+
+        let both = RestoreOptions { worktree: true, ..staged };
+        restore_files(&mut t.repo, vec![path.clone()], &both).await.unwrap();
+        assert_eq!(t.read("a.txt"), "v1\n");
+
+        // Restoring the staged entry from HEAD unstages it
+        let from_head = RestoreOptions { staged: true, ..Default::default() };
+        restore_files(&mut t.repo, vec![path], &from_head).await.unwrap();
+        assert!(t.repo.index.get_file("a.txt").is_none());
+        assert_eq!(t.read("a.txt"), "v1\n");
+    }
+
+    #[tokio::test]
+    async fn binary_content_round_trips_byte_for_byte() {
+        let mut t = TestRepo::new();
+        let bytes: Vec<u8> = (0..=255u8).chain([0xff, 0xfe, 0x00, 0xc3, 0x28, b'\r', b'\n']).collect();
+        t.write_bytes("blob.bin", &bytes);
+        t.commit("binary").await;
+        t.remove("blob.bin");
+
+        let path = t.repo.path.join("blob.bin");
+        restore_files(&mut t.repo, vec![path.clone()], &RestoreOptions::default()).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), bytes);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::core::repository::Repository;
+use anyhow::Result;
+
+/// Print the full commit id for `rev`, or with `abbrev_ref` the symbolic
+/// name it refers to (the current branch for `HEAD`).
+pub async fn rev_parse(repo: &Repository, rev: &str, abbrev_ref: bool) -> Result<()> {
+    if abbrev_ref {
+        println!("{}", symbolic_name(repo, rev)?);
+    } else {
+        println!("{}", repo.resolve_rev(rev)?);
+    }
+    Ok(())
+}
+
+/// `--abbrev-ref`: the branch `HEAD` is on (`HEAD` when detached), or a
+/// branch name as given.
+fn symbolic_name(repo: &Repository, rev: &str) -> Result<String> {
+    if rev == "HEAD" {
+        Ok(repo.current_branch.clone())
+    } else if repo.branches.contains_key(rev) {
+        Ok(rev.to_string())
+    } else {
+        anyhow::bail!("Unknown ref '{}'", rev)
+    }
+}
+
+pub async fn show_toplevel(repo: &Repository) -> Result<()> {
+    println!("{}", repo.path.canonicalize()?.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn every_form_resolves_to_the_full_id() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.write("a", "2\n");
+        let second = t.commit("second").await;
+        t.checkout_new("topic").await;
+
+        assert_eq!(t.repo.resolve_rev("HEAD").unwrap(), second);
+        assert_eq!(t.repo.resolve_rev("main").unwrap(), second);
+        assert_eq!(t.repo.resolve_rev(&second[..10]).unwrap(), second);
+        assert_eq!(t.repo.resolve_rev("HEAD~1").unwrap(), first);
+        assert_eq!(t.repo.resolve_rev("main^").unwrap(), first);
+        assert_eq!(t.repo.resolve_rev("HEAD^0").unwrap(), second);
+        assert_eq!(second.len(), 64);
+        assert!(t.repo.resolve_rev("HEAD~2").is_err());
+        assert!(t.repo.resolve_rev("nonexistent").is_err());
+    }
+
+    #[tokio::test]
+    async fn abbrev_ref_names_the_branch() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        let first = t.commit("first").await;
+        t.checkout_new("topic").await;
+
+        assert_eq!(symbolic_name(&t.repo, "HEAD").unwrap(), "topic");
+        assert_eq!(symbolic_name(&t.repo, "main").unwrap(), "main");
+        assert!(symbolic_name(&t.repo, "missing").is_err());
+        t.checkout(&first).await;
+        assert_eq!(symbolic_name(&t.repo, "HEAD").unwrap(), "HEAD");
+    }
+}
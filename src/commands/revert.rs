@@ -0,0 +1,79 @@
+use crate::commands::commit::{self, CommitOptions};
+use crate::commands::merge;
+use crate::core::repository::Repository;
+use crate::utils::hash_utils;
+use anyhow::Result;
+use colored::*;
+use ed25519_dalek::SigningKey;
+
+/// Undo the changes `rev` made with a new commit on top of HEAD. History
+/// is not rewritten; the working tree has to be clean.
+pub async fn revert_commit(repo: &mut Repository, rev: &str, keypair: &SigningKey) -> Result<()> {
+    repo.require_clean("revert")?;
+    let commit_id = repo.resolve_rev(rev)?;
+    let commit = repo.get_commit_object(&commit_id)?;
+    let (before, after) = merge::commit_change(repo, &commit)?;
+
+    let subject = commit.message.lines().next().unwrap_or("");
+    let message = format!("Revert \"{}\"\n\nThis reverts commit {}.", subject, commit_id);
+
+    // Replaying the change backwards: from the commit's tree to its parent's
+    let conflicts = merge::apply_tree_change(repo, &after, &before)?;
+    if !conflicts.is_empty() {
+        repo.save()?;
+        println!(
+            "{}",
+            format!("Could not revert {}: conflicts in", hash_utils::get_short_hash(&commit_id))
+                .red()
+                .bold()
+        );
+        for path in &conflicts {
+            println!("  {}", path.red().bold());
+        }
+        println!("Resolve them, 'hx add' the files and commit with:");
+        println!("  hx commit -m 'Revert \"{}\"'", subject);
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        format!("Reverting {} {}", hash_utils::get_short_hash(&commit_id), subject).blue()
+    );
+    commit::commit_changes(repo, &message, &CommitOptions::default(), keypair).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{keypair, TestRepo};
+
+    #[tokio::test]
+    async fn reverting_an_addition_removes_the_file() {
+        let mut t = TestRepo::new();
+        t.write("base.txt", "base\n");
+        t.commit("base").await;
+        t.write("added.txt", "added\n");
+        let added = t.commit("add a file").await;
+
+        revert_commit(&mut t.repo, &added[..8], &keypair()).await.unwrap();
+
+        assert!(!t.exists("added.txt"));
+        assert_eq!(t.read("base.txt"), "base\n");
+        let revert = t.repo.get_commit_object(&t.head()).unwrap();
+        assert_eq!(revert.parent_ids, vec![added.clone()]);
+        assert!(revert.message.starts_with("Revert \"add a file\""));
+        assert!(revert.message.contains(&added));
+        assert!(!revert.tree_files().contains_key("added.txt"));
+        assert!(revert.verify());
+    }
+
+    #[tokio::test]
+    async fn a_dirty_tree_is_refused() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "a\n");
+        let head = t.commit("a").await;
+        t.write("a.txt", "dirty\n");
+        assert!(revert_commit(&mut t.repo, &head, &keypair()).await.is_err());
+        assert_eq!(t.head(), head);
+    }
+}
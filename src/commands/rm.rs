@@ -0,0 +1,173 @@
+use crate::core::repository::Repository;
+use crate::utils::path_utils;
+use anyhow::Result;
+use colored::*;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct RmOptions {
+    /// Only unstage the paths, keeping the working tree copies
+    pub cached: bool,
+    /// Remove even when local or staged changes would be lost
+    pub force: bool,
+    /// Allow a directory to name every tracked file under it
+    pub recursive: bool,
+}
+
+/// Stage the deletion of tracked `paths` and, unless `cached`, remove them
+/// from the working tree.
+pub async fn remove_files(repo: &mut Repository, paths: &[PathBuf], options: &RmOptions) -> Result<()> {
+    let head_files = repo.head_files();
+    let mut tracked: BTreeSet<String> = head_files.keys().cloned().collect();
+    tracked.extend(repo.index.get_file_paths());
+
+    let cwd = std::env::current_dir()?;
+    let mut selected = BTreeSet::new();
+    for path in paths {
+        let absolute = cwd.join(path);
+        let spec = path_utils::normalize_path(absolute.strip_prefix(&repo.path).unwrap_or(path))
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+        let matches: Vec<&String> = tracked
+            .iter()
+            .filter(|candidate| path_utils::pathspec_matches(&spec, candidate))
+            .collect();
+        if matches.is_empty() {
+            anyhow::bail!("pathspec '{}' did not match any tracked files", path.display());
+        }
+        if !options.recursive && !matches.iter().any(|m| **m == spec) && !spec.contains(['*', '?']) {
+            anyhow::bail!("not removing '{}' recursively without -r", path.display());
+        }
+        selected.extend(matches.into_iter().cloned());
+    }
+
+    // Refuse to throw away work nobody committed
+    if !options.force {
+        for path in &selected {
+            let committed = head_files.get(path);
+            let staged = repo.index.get_file(path).map(|entry| &entry.content_hash);
+            let worktree = repo.worktree_blob_id(path);
+            if staged.is_some_and(|staged| Some(staged) != committed && Some(staged) != worktree.as_ref()) {
+                anyhow::bail!(
+                    "'{}' has staged content different from both the file and HEAD (use -f to force removal)",
+                    path
+                );
+            }
+            if options.cached {
+                continue;
+            }
+            if staged.is_some_and(|staged| Some(staged) != committed) {
+                anyhow::bail!(
+                    "'{}' has changes staged in the index (use --cached to keep the file, or -f to force removal)",
+                    path
+                );
+            }
+            if worktree.is_some() && worktree.as_ref() != committed {
+                anyhow::bail!(
+                    "'{}' has local modifications (use --cached to keep the file, or -f to force removal)",
+                    path
+                );
+            }
+        }
+    }
+
+    for path in &selected {
+        if head_files.contains_key(path) {
+            repo.index.stage_deletion(path);
+        } else {
+            // Staged but never committed: just drop it
+            repo.index.remove_file(path);
+        }
+        if !options.cached {
+            let full_path = repo.path.join(path);
+            if full_path.exists() {
+                fs::remove_file(&full_path)?;
+            }
+            remove_empty_parents(&repo.path, Path::new(path));
+        }
+        println!("rm '{}'", path);
+    }
+    repo.save()?;
+
+    println!(
+        "{}",
+        format!("Staged {} deletion(s)", selected.len()).green().bold()
+    );
+    Ok(())
+}
+
+/// Remove the directories above `path` that its removal left empty.
+fn remove_empty_parents(repo_path: &Path, path: &Path) {
+    let mut dir = path.parent();
+    while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+        if fs::remove_dir(repo_path.join(d)).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn the_next_commit_records_the_deletion() {
+        let mut t = TestRepo::new();
+        t.write("keep.txt", "keep\n");
+        t.write("docs/gone.txt", "gone\n");
+        t.commit("first").await;
+
+        let path = t.repo.path.join("docs/gone.txt");
+        remove_files(&mut t.repo, &[path], &RmOptions::default()).await.unwrap();
+        assert!(!t.exists("docs/gone.txt"));
+        assert!(!t.exists("docs"), "the emptied directory should go too");
+
+        let id = t.commit("remove gone.txt").await;
+        let commit = t.repo.get_commit_object(&id).unwrap();
+        assert!(commit.files["docs/gone.txt"].is_deleted());
+        assert!(!commit.tree_files().contains_key("docs/gone.txt"));
+        assert!(t.repo.head_files().contains_key("keep.txt"));
+        assert!(!t.repo.head_files().contains_key("docs/gone.txt"));
+    }
+
+    #[tokio::test]
+    async fn cached_keeps_the_file_on_disk() {
+        let mut t = TestRepo::new();
+        t.write("secret.env", "token\n");
+        t.commit("first").await;
+
+        let path = t.repo.path.join("secret.env");
+        let cached = RmOptions { cached: true, ..Default::default() };
+        remove_files(&mut t.repo, &[path], &cached).await.unwrap();
+        assert_eq!(t.read("secret.env"), "token\n");
+
+        // Commit the index as is; `t.commit` would stage the file again
+        let keypair = crate::utils::test_utils::keypair();
+        crate::commands::commit::commit_changes(&mut t.repo, "untrack", &Default::default(), &keypair)
+            .await
+            .unwrap();
+        assert!(!t.repo.head_files().contains_key("secret.env"));
+        assert!(t.exists("secret.env"));
+    }
+
+    #[tokio::test]
+    async fn local_edits_are_not_thrown_away() {
+        let mut t = TestRepo::new();
+        t.write("notes.txt", "v1\n");
+        t.commit("first").await;
+        t.write("notes.txt", "v2\n");
+
+        let path = t.repo.path.join("notes.txt");
+        assert!(remove_files(&mut t.repo, std::slice::from_ref(&path), &RmOptions::default()).await.is_err());
+        assert_eq!(t.read("notes.txt"), "v2\n");
+
+        let force = RmOptions { force: true, ..Default::default() };
+        remove_files(&mut t.repo, &[path], &force).await.unwrap();
+        assert!(!t.exists("notes.txt"));
+    }
+}
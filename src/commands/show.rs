@@ -0,0 +1,93 @@
+use crate::commands::{diff, log};
+use crate::core::repository::Repository;
+use crate::core::tag::{Tag, Tags};
+use colored::*;
+use anyhow::Result;
+
+#[derive(Debug, Clone, Default)]
+pub struct ShowOptions {
+    /// Per-file insertion/deletion counts instead of the patch
+    pub stat: bool,
+    /// Only the changed paths
+    pub name_only: bool,
+    /// Changed paths with an `A`/`M`/`D` status
+    pub name_status: bool,
+    /// Show merge commits against their first parent instead of no diff
+    pub first_parent: bool,
+}
+
+/// Show a commit's header followed by its changes against the first parent.
+/// Merge commits show no changes unless `first_parent` is set.
+pub async fn show_commit(repo: &Repository, rev: &str, options: &ShowOptions) -> Result<()> {
+    let tags = Tags::load(&repo.git_dir)?;
+    let objects_dir = repo.get_objects_dir();
+    if let Some(tag) = tags.refs.get(rev).and_then(|id| Tag::load(&objects_dir, id)) {
+        print_tag(&tag);
+    }
+    let commit_id = repo.resolve_rev(rev)?;
+    let commit = repo.get_commit_object(&commit_id)?;
+    let is_head = repo.resolve_rev("HEAD").ok().as_deref() == Some(commit_id.as_str());
+    log::display_commit_dag(
+        &commit,
+        is_head,
+        diff::changed_file_count(repo, &commit)?,
+        commit.verify(),
+        log::decorations(repo)?
+            .get(&commit_id)
+            .map_or(&[], Vec::as_slice),
+    );
+
+    if commit.parent_ids.len() > 1 && !options.first_parent {
+        return Ok(());
+    }
+    if options.stat {
+        diff::print_commit_stat(repo, &commit)?;
+    } else if options.name_only || options.name_status {
+        diff::print_commit_names(repo, &commit, options.name_status)?;
+    } else {
+        diff::print_commit_patch(repo, &commit, None)?;
+    }
+    Ok(())
+}
+
+/// Header for an annotated tag: name, tagger, date, signature and message.
+fn print_tag(tag: &Tag) {
+    let validity = match (&tag.signature, tag.verify()) {
+        (None, _) => "UNSIGNED".yellow(),
+        (Some(_), true) => "VALID".green(),
+        (Some(_), false) => "INVALID".red(),
+    };
+    println!("{} {} {}", "tag".yellow(), tag.name.yellow().bold(), validity);
+    println!("Tagger: {}", tag.tagger);
+    println!("Date:   {}", tag.timestamp.format("%Y-%m-%d %H:%M:%S"));
+    if let Some(message) = &tag.message {
+        println!();
+        for line in message.lines() {
+            println!("    {}", line);
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{strip_ansi, TestRepo};
+
+    #[tokio::test]
+    async fn stat_reports_the_file_and_its_counts() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "one\ntwo\n");
+        t.write("b.txt", "untouched\n");
+        t.commit("root").await;
+        t.write("a.txt", "one\nTWO\nthree\n");
+        let head = t.commit("edit a").await;
+
+        let commit = t.repo.get_commit_object(&head).unwrap();
+        let stat = strip_ansi(&diff::render_commit_stat(&t.repo, &commit).unwrap());
+        assert_eq!(
+            stat.lines().collect::<Vec<_>>(),
+            [" a.txt |     3 ++-", " 1 file changed, 2 insertions(+), 1 deletion(-)"]
+        );
+    }
+}
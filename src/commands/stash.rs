@@ -0,0 +1,329 @@
+use crate::commands::diff::print_text_diff;
+use crate::commands::merge;
+use crate::core::object::Object;
+use crate::core::repository::Repository;
+use crate::core::stash::{StashEntry, StashList};
+use crate::core::undo::UndoEntry;
+use crate::utils::{file_utils, hash_utils};
+use anyhow::Result;
+use colored::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+/// Save working tree and staged changes to a new stash entry and reset
+/// those paths to HEAD.
+pub async fn stash_push(repo: &mut Repository, message: Option<&str>) -> Result<()> {
+    let objects_dir = repo.get_objects_dir();
+    let head_commit = repo.head_commit().cloned();
+    let head_files = repo.head_files();
+
+    let staged: BTreeSet<String> = repo.index.get_file_paths().into_iter().collect();
+    let mut candidates: BTreeSet<String> = head_files.keys().cloned().collect();
+    candidates.extend(staged.iter().cloned());
+
+    let mut files = BTreeMap::new();
+    for path in &candidates {
+        let full_path = repo.path.join(path);
+        let blob = if full_path.is_file() {
+            let content = file_utils::read_file_content(&full_path)?;
+            let blob = Object::new("blob".to_string(), content);
+            blob.save(&objects_dir)?;
+            Some(blob.id)
+        } else {
+            None
+        };
+        if blob.as_ref() != head_files.get(path) || staged.contains(path) {
+            files.insert(path.clone(), blob);
+        }
+    }
+
+    if files.is_empty() {
+        println!("{}", "No local changes to save".yellow());
+        return Ok(());
+    }
+
+    let message = match message {
+        Some(message) => message.to_string(),
+        None => {
+            let subject = head_commit
+                .as_deref()
+                .and_then(|id| repo.get_commit_object(id).ok())
+                .map(|c| c.message.lines().next().unwrap_or("").to_string())
+                .unwrap_or_default();
+            let short = head_commit
+                .as_deref()
+                .map(hash_utils::get_short_hash)
+                .unwrap_or_else(|| "(no commits)".to_string());
+            format!("WIP on {}: {} {}", repo.current_branch, short, subject)
+        }
+    };
+
+    // Put the stashed paths back to their HEAD state
+    for path in files.keys() {
+        let full_path = repo.path.join(path);
+        match head_files.get(path) {
+            Some(blob_hash) => {
+                let blob = Object::load(&objects_dir, blob_hash)?;
+                file_utils::write_file_content(&full_path, &blob.data)?;
+            }
+            None => {
+                if full_path.exists() {
+                    fs::remove_file(&full_path)?;
+                }
+            }
+        }
+        repo.index.remove_file(path);
+    }
+
+    let mut stash = StashList::load(&repo.git_dir)?;
+    stash.push(StashEntry {
+        message: message.clone(),
+        branch: repo.current_branch.clone(),
+        base_commit: head_commit,
+        staged: staged.iter().filter(|p| files.contains_key(*p)).cloned().collect(),
+        files,
+        created_at: chrono::Utc::now(),
+    });
+    stash.save(&repo.git_dir)?;
+    repo.save()?;
+
+    println!(
+        "{} {}",
+        "Saved working directory and index state".green().bold(),
+        message
+    );
+    Ok(())
+}
+
+pub async fn stash_list(repo: &Repository) -> Result<()> {
+    let stash = StashList::load(&repo.git_dir)?;
+    if stash.entries.is_empty() {
+        println!("{}", "No stash entries".yellow());
+        return Ok(());
+    }
+    for (i, entry) in stash.entries.iter().enumerate() {
+        println!(
+            "{} On {}: {}",
+            format!("stash@{{{}}}:", i).yellow(),
+            entry.branch.cyan(),
+            entry.message
+        );
+    }
+    Ok(())
+}
+
+/// Re-apply a stash entry; with `drop`, remove it once applied (`pop`).
+pub async fn stash_apply(repo: &mut Repository, stash_ref: Option<&str>, drop: bool) -> Result<()> {
+    let index = StashList::parse_ref(stash_ref)?;
+    let mut stash = StashList::load(&repo.git_dir)?;
+    let entry = stash.get(index)?.clone();
+    let head_files = repo.head_files();
+
+    // Refuse to clobber local edits to any path the stash touches
+    for path in entry.files.keys() {
+        let full_path = repo.path.join(path);
+        let current = if full_path.is_file() {
+            let content = file_utils::read_file_content(&full_path)?;
+            Some(Object::new("blob".to_string(), content).id)
+        } else {
+            None
+        };
+        if current.as_ref() != head_files.get(path) {
+            anyhow::bail!(
+                "Your local changes to '{}' would be overwritten by stash apply",
+                path
+            );
+        }
+    }
+
+    let operation = if drop { "stash pop" } else { "stash apply" };
+    UndoEntry::snapshot(repo, operation, &entry.files)?;
+
+    // Replay the stash as the change from the commit it was made on, so
+    // edits HEAD has made since are merged rather than overwritten
+    let base_files = match &entry.base_commit {
+        Some(id) => repo.get_commit_object(id)?.tree_files(),
+        None => BTreeMap::new(),
+    };
+    let mut base = BTreeMap::new();
+    let mut theirs = BTreeMap::new();
+    for (path, blob_hash) in &entry.files {
+        if let Some(blob_hash) = base_files.get(path) {
+            base.insert(path.clone(), blob_hash.clone());
+        }
+        if let Some(blob_hash) = blob_hash {
+            theirs.insert(path.clone(), blob_hash.clone());
+        }
+    }
+    let conflicted = merge::apply_tree_change(repo, &base, &theirs)?;
+    // Only what was staged when stashed goes back into the index
+    for path in entry.files.keys() {
+        if !entry.staged.contains(path) && !conflicted.contains(path) {
+            repo.index.remove_file(path);
+        }
+    }
+    repo.save()?;
+
+    if !conflicted.is_empty() {
+        for path in &conflicted {
+            println!("{} Merge conflict in {}", "CONFLICT (content):".red().bold(), path);
+        }
+        println!(
+            "{}",
+            format!("stash@{{{}}} was kept; resolve the conflicts, then drop it", index).yellow()
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        format!("Applied stash@{{{}}}: {}", index, entry.message)
+            .green()
+            .bold()
+    );
+    if drop {
+        stash.remove(index)?;
+        stash.save(&repo.git_dir)?;
+        println!("Dropped stash@{{{}}}", index);
+    }
+    Ok(())
+}
+
+pub async fn stash_drop(repo: &Repository, stash_ref: Option<&str>) -> Result<()> {
+    let index = StashList::parse_ref(stash_ref)?;
+    let mut stash = StashList::load(&repo.git_dir)?;
+    let entry = stash.remove(index)?;
+    stash.save(&repo.git_dir)?;
+    println!(
+        "{}",
+        format!("Dropped stash@{{{}}}: {}", index, entry.message).green()
+    );
+    Ok(())
+}
+
+/// Diff a stash entry against the commit it was made on.
+pub async fn stash_show(repo: &Repository, stash_ref: Option<&str>) -> Result<()> {
+    let index = StashList::parse_ref(stash_ref)?;
+    let stash = StashList::load(&repo.git_dir)?;
+    let entry = stash.get(index)?;
+    let objects_dir = repo.get_objects_dir();
+    let base_files = entry
+        .base_commit
+        .as_deref()
+        .and_then(|id| repo.get_commit_object(id).ok())
+        .map(|c| c.tree_files())
+        .unwrap_or_default();
+
+    let load = |hash: Option<&String>| -> String {
+        hash.and_then(|h| Object::load(&objects_dir, h).ok())
+            .map(|o| o.text())
+            .unwrap_or_default()
+    };
+
+    println!(
+        "{}",
+        format!("stash@{{{}}}: {}", index, entry.message).bold().blue()
+    );
+    for (path, blob_hash) in &entry.files {
+        println!("\n{}", format!("File: {}", path).bold());
+        match (base_files.get(path), blob_hash) {
+            (None, Some(_)) => println!("{}", "new file".magenta()),
+            (Some(_), None) => println!("{}", "deleted file".magenta()),
+            _ => {}
+        }
+        print_text_diff(&load(base_files.get(path)), &load(blob_hash.as_ref()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    async fn repo_with_stashes(messages: &[&str]) -> TestRepo {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "committed\n");
+        t.commit("first").await;
+        for message in messages {
+            t.write("a.txt", &format!("{}\n", message));
+            stash_push(&mut t.repo, Some(message)).await.unwrap();
+        }
+        t
+    }
+
+    fn messages(t: &TestRepo) -> Vec<String> {
+        StashList::load(&t.repo.git_dir)
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn apply_keeps_the_entry() {
+        let mut t = repo_with_stashes(&["named"]).await;
+        assert_eq!(t.read("a.txt"), "committed\n");
+
+        stash_apply(&mut t.repo, Some("stash@{0}"), false).await.unwrap();
+        assert_eq!(t.read("a.txt"), "named\n");
+        assert_eq!(messages(&t), vec!["named"]);
+    }
+
+    #[tokio::test]
+    async fn drop_removes_only_the_addressed_entry() {
+        let t = repo_with_stashes(&["older", "newer"]).await;
+        assert_eq!(messages(&t), vec!["newer", "older"]);
+
+        stash_drop(&t.repo, Some("stash@{1}")).await.unwrap();
+        assert_eq!(messages(&t), vec!["newer"]);
+        assert_eq!(t.read("a.txt"), "committed\n");
+        assert!(stash_drop(&t.repo, Some("stash@{1}")).await.is_err());
+    }
+
+    #[test]
+    fn stash_refs_parse() {
+        assert_eq!(StashList::parse_ref(None).unwrap(), 0);
+        assert_eq!(StashList::parse_ref(Some("stash@{2}")).unwrap(), 2);
+        assert_eq!(StashList::parse_ref(Some("3")).unwrap(), 3);
+        assert!(StashList::parse_ref(Some("stash@{x}")).is_err());
+    }
+
+    #[tokio::test]
+    async fn pop_brings_the_edits_back_and_drops_the_entry() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "committed\n");
+        t.commit("first").await;
+        t.write("a.txt", "edited\n");
+        t.write("new.txt", "staged new file\n");
+        let path = t.repo.path.join("new.txt");
+        crate::commands::add::add_files(&mut t.repo, &[path], &Default::default()).await.unwrap();
+
+        stash_push(&mut t.repo, None).await.unwrap();
+        assert_eq!(t.read("a.txt"), "committed\n");
+        assert!(!t.exists("new.txt"));
+        assert!(t.repo.index.is_empty());
+        assert_eq!(messages(&t).len(), 1);
+        assert!(messages(&t)[0].starts_with("WIP on main:"));
+
+        stash_apply(&mut t.repo, None, true).await.unwrap();
+        assert_eq!(t.read("a.txt"), "edited\n");
+        assert_eq!(t.read("new.txt"), "staged new file\n");
+        assert!(t.repo.index.has_file("new.txt"));
+        assert!(!t.repo.index.has_file("a.txt"));
+        assert!(messages(&t).is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_conflicting_pop_keeps_the_entry() {
+        let mut t = repo_with_stashes(&["stashed"]).await;
+        t.write("a.txt", "committed since\n");
+        t.commit("second").await;
+
+        stash_apply(&mut t.repo, None, true).await.unwrap();
+        let content = t.read("a.txt");
+        assert!(content.contains("<<<<<<<") && content.contains("stashed"), "{}", content);
+        assert_eq!(messages(&t), vec!["stashed"]);
+    }
+}
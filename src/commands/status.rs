@@ -1,16 +1,35 @@
 use crate::core::repository::Repository;
-use crate::utils::path_utils;
+use crate::core::submodule::Submodules;
+use crate::core::object::Object;
+use crate::utils::{diff_utils, hash_utils, path_utils};
 use anyhow::Result;
 use colored::*;
-use std::collections::HashMap;
-use walkdir::WalkDir;
+use ignore::{WalkBuilder, WalkState};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 
-pub async fn show_status(repo: &Repository) -> Result<()> {
+#[derive(Debug, Clone, Default)]
+pub struct StatusOptions {
+    /// Threads used to walk the working tree (default: one per CPU)
+    pub jobs: Option<usize>,
+    /// Similarity percent for showing a staged deletion and addition as a
+    /// rename (default: `DEFAULT_RENAME_THRESHOLD`)
+    pub rename_threshold: Option<u8>,
+}
+
+pub async fn show_status(repo: &Repository, options: &StatusOptions) -> Result<()> {
     println!("{}", "Repository Status".bold().blue());
     println!("{}", "=".repeat(40).blue());
 
     // Show current branch
-    println!("On branch: {}", repo.current_branch.yellow().bold());
+    match &repo.detached_head {
+        Some(commit_id) => println!(
+            "{} {}",
+            "HEAD detached at".red().bold(),
+            hash_utils::get_short_hash(commit_id).cyan()
+        ),
+        None => println!("On branch: {}", repo.current_branch.yellow().bold()),
+    }
 
     if let Some(current_branch) = repo.get_current_branch() {
         if let Some(head_commit) = current_branch.get_head_commit() {
@@ -18,77 +37,30 @@ pub async fn show_status(repo: &Repository) -> Result<()> {
         } else {
             println!("HEAD: {}", "No commits yet".red());
         }
+        if let Some(upstream) = current_branch.get_upstream() {
+            println!(
+                "Tracking: {}{}",
+                upstream.magenta(),
+                crate::commands::branch::tracking_summary(
+                    repo,
+                    current_branch.get_head_commit(),
+                    upstream
+                )
+            );
+        }
     }
 
     println!();
 
     // Get working directory files
-    let working_files = get_working_directory_files(&repo.path)?;
-
-    // Get staged files
-    let staged_files: Vec<_> = repo.index.get_file_paths();
-    let staged_count = repo.index.get_staged_files().len();
-
-    // Get last commit files (if any)
-    let last_commit_files = if let Some(current_branch) = repo.get_current_branch() {
-        if let Some(head_commit) = current_branch.get_head_commit() {
-            if let Ok(commit_object) =
-                crate::core::object::Object::load(&repo.get_objects_dir(), head_commit)
-            {
-                if let Ok(commit) = crate::core::commit::Commit::from_object(&commit_object) {
-                    commit.get_files().keys().cloned().collect()
-                } else {
-                    Vec::new()
-                }
-            } else {
-                Vec::new()
-            }
-        } else {
-            Vec::new()
-        }
-    } else {
-        Vec::new()
-    };
-
-    // Analyze changes
-    let mut changes = HashMap::new();
-
-    // Check for new files
-    for file in &working_files {
-        if !last_commit_files.contains(file) && !staged_files.contains(file) {
-            changes.insert(file.clone(), "untracked".to_string());
-        }
-    }
-
-    // Check for modified files
-    for file in &working_files {
-        if last_commit_files.contains(file) && !staged_files.contains(file) {
-            changes.insert(file.clone(), "modified".to_string());
-        }
-    }
-
-    // Check for staged files
-    for file in &staged_files {
-        if !last_commit_files.contains(file) {
-            changes.insert(file.clone(), "staged".to_string());
-        } else {
-            changes.insert(file.clone(), "staged".to_string());
-        }
-    }
+    let (working_files, nested_repos) = get_working_directory_files(&repo.path, options.jobs)?;
 
-    // Group changes by status
-    let mut untracked = Vec::new();
-    let mut modified = Vec::new();
-    let mut staged = Vec::new();
+    let staged_count = repo.index.get_staged_files().len() + repo.index.deletions.len();
 
-    for (file, status) in changes {
-        match status.as_str() {
-            "untracked" => untracked.push(file),
-            "modified" => modified.push(file),
-            "staged" => staged.push(file),
-            _ => {}
-        }
-    }
+    // Blob ids recorded in the last commit (if any)
+    let last_commit_files = repo.head_files();
+    let WorkingChanges { untracked, modified, staged } =
+        working_changes(repo, &working_files, &last_commit_files);
 
     // Show file change types if we have staged files
     if !staged.is_empty() {
@@ -127,10 +99,29 @@ pub async fn show_status(repo: &Repository) -> Result<()> {
     }
 
     // Display changes
-    if !staged.is_empty() {
+    if !staged.is_empty() || !repo.index.deletions.is_empty() {
+        let renames = staged_renames(repo, &last_commit_files, options.rename_threshold);
         println!("{}", "Changes to be committed:".green().bold());
+        for pair in &renames {
+            let similarity = if pair.similarity < 100 {
+                format!(" ({}%)", pair.similarity)
+            } else {
+                String::new()
+            };
+            println!(
+                "  {}",
+                format!("  → {} -> {}{}", pair.old_path, pair.new_path, similarity).green()
+            );
+        }
         for file in &staged {
-            println!("  {}", format!("  + {}", file).green());
+            if !renames.iter().any(|pair| &pair.new_path == file) {
+                println!("  {}", format!("  + {}", file).green());
+            }
+        }
+        for file in &repo.index.deletions {
+            if !renames.iter().any(|pair| &pair.old_path == file) {
+                println!("  {}", format!("  - {}", file).red());
+            }
         }
         println!();
     }
@@ -151,7 +142,21 @@ pub async fn show_status(repo: &Repository) -> Result<()> {
         println!();
     }
 
-    if staged.is_empty() && modified.is_empty() && untracked.is_empty() {
+    if !nested_repos.is_empty() {
+        println!("{}", "Nested repositories:".cyan().bold());
+        let modules = Submodules::load(&repo.path).unwrap_or_default();
+        for dir in &nested_repos {
+            let kind = if modules.submodule.contains_key(dir) {
+                " (submodule)"
+            } else {
+                ""
+            };
+            println!("  {}", format!("  ◦ {}/{}", dir, kind).cyan());
+        }
+        println!();
+    }
+
+    if repo.index.is_empty() && modified.is_empty() && untracked.is_empty() {
         println!("{}", "Working tree clean".green().bold());
     } else {
         println!("Summary:");
@@ -163,21 +168,278 @@ pub async fn show_status(repo: &Repository) -> Result<()> {
     Ok(())
 }
 
-fn get_working_directory_files(repo_path: &std::path::Path) -> Result<Vec<String>> {
-    let mut files = Vec::new();
+/// Working tree and index paths grouped by status, each sorted.
+struct WorkingChanges {
+    untracked: Vec<String>,
+    modified: Vec<String>,
+    staged: Vec<String>,
+}
+
+/// Classify `working_files` against the index and `last_commit_files`,
+/// the blob ids HEAD records.
+fn working_changes(
+    repo: &Repository,
+    working_files: &[String],
+    last_commit_files: &BTreeMap<String, String>,
+) -> WorkingChanges {
+    let staged_files: Vec<_> = repo.index.get_file_paths();
+
+    // Analyze changes
+    let mut changes = HashMap::new();
+
+    // Check for new files
+    for file in working_files {
+        if !last_commit_files.contains_key(file) && !staged_files.contains(file) {
+            changes.insert(file.clone(), "untracked".to_string());
+        }
+    }
+
+    // Check for modified files: tracked, not staged, and content no longer
+    // hashing to the committed blob
+    for file in working_files {
+        if let Some(committed) = last_commit_files.get(file) {
+            if !staged_files.contains(file)
+                && repo.worktree_blob_id(file).as_ref() != Some(committed)
+            {
+                changes.insert(file.clone(), "modified".to_string());
+            }
+        }
+    }
+
+    // Check for staged files
+    for file in &staged_files {
+        changes.insert(file.clone(), "staged".to_string());
+    }
+
+    // Group changes by status
+    let mut untracked = Vec::new();
+    let mut modified = Vec::new();
+    let mut staged = Vec::new();
+
+    for (file, status) in changes {
+        match status.as_str() {
+            "untracked" => untracked.push(file),
+            "modified" => modified.push(file),
+            "staged" => staged.push(file),
+            _ => {}
+        }
+    }
+    untracked.sort();
+    modified.sort();
+    staged.sort();
+    WorkingChanges { untracked, modified, staged }
+}
+
+/// Staged deletions paired with staged new files of the same or similar
+/// content, as the commit would record them.
+fn staged_renames(
+    repo: &Repository,
+    head_files: &BTreeMap<String, String>,
+    threshold: Option<u8>,
+) -> Vec<diff_utils::FilePair> {
+    let deleted: BTreeMap<String, String> = repo
+        .index
+        .deletions
+        .iter()
+        .filter_map(|path| Some((path.clone(), head_files.get(path)?.clone())))
+        .collect();
+    let added: BTreeMap<String, String> = repo
+        .index
+        .get_staged_files()
+        .into_iter()
+        .filter(|entry| !head_files.contains_key(&entry.path))
+        .map(|entry| (entry.path.clone(), entry.content_hash.clone()))
+        .collect();
+    if deleted.is_empty() || added.is_empty() {
+        return Vec::new();
+    }
+    let objects_dir = repo.get_objects_dir();
+    diff_utils::detect_renames(
+        &deleted,
+        &added,
+        threshold.unwrap_or(diff_utils::DEFAULT_RENAME_THRESHOLD),
+        false,
+        |hash| Object::load(&objects_dir, hash).ok().map(|o| o.text()),
+    )
+}
 
-    for entry in WalkDir::new(repo_path)
+/// Files that are neither committed at HEAD nor staged, as status lists
+/// them; ignored files and nested repositories are never included.
+pub fn untracked_files(repo: &Repository, jobs: Option<usize>) -> Result<Vec<String>> {
+    let (working_files, _) = get_working_directory_files(&repo.path, jobs)?;
+    let staged_files = repo.index.get_file_paths();
+    let last_commit_files = repo.head_files();
+    Ok(working_files
         .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-    {
-        let entry_path = entry.path();
-        if !path_utils::is_ignored(entry_path, repo_path) {
-            if let Some(relative_path) = path_utils::get_relative_path(repo_path, entry_path) {
-                files.push(relative_path);
+        .filter(|file| !last_commit_files.contains_key(file) && !staged_files.contains(file))
+        .collect())
+}
+
+/// Every non-ignored file under the repository, plus the nested repositories
+/// that were skipped, both as sorted relative paths. The tree is walked on
+/// `jobs` threads (default: one per CPU).
+fn get_working_directory_files(
+    repo_path: &std::path::Path,
+    jobs: Option<usize>,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let files = Mutex::new(Vec::new());
+    let nested_repos = Mutex::new(Vec::new());
+    let ignores = path_utils::IgnoreRules::new(repo_path);
+
+    // Ignore rules are ours, not the walker's .gitignore handling
+    WalkBuilder::new(repo_path)
+        .standard_filters(false)
+        .threads(jobs.unwrap_or(0))
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(_) => return WalkState::Continue,
+                };
+                let entry_path = entry.path();
+                let file_type = entry.file_type();
+                if file_type.is_some_and(|t| t.is_dir())
+                    && path_utils::is_nested_repo(entry_path, repo_path)
+                {
+                    if let Some(relative_path) = path_utils::get_relative_path(repo_path, entry_path) {
+                        nested_repos.lock().unwrap().push(relative_path);
+                    }
+                    return WalkState::Skip;
+                }
+                if file_type.is_some_and(|t| t.is_file()) && !ignores.is_ignored(entry_path) {
+                    if let Some(relative_path) = path_utils::get_relative_path(repo_path, entry_path) {
+                        files.lock().unwrap().push(relative_path);
+                    }
+                }
+                WalkState::Continue
+            })
+        });
+
+    // Threads finish in any order; keep the output stable
+    let mut files = files.into_inner().unwrap();
+    let mut nested_repos = nested_repos.into_inner().unwrap();
+    files.sort();
+    nested_repos.sort();
+    Ok((files, nested_repos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::add;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn nested_repositories_are_not_untracked_files() {
+        let mut t = TestRepo::new();
+        t.write("tracked.txt", "tracked\n");
+        t.commit("first").await;
+        t.write("new.txt", "new\n");
+        t.write("vendor/lib/.helix/HEAD", "main\n");
+        t.write("vendor/lib/src/lib.rs", "fn main() {}\n");
+        t.write("third_party/.git", "gitdir: ../.git/modules/third_party\n");
+        t.write("third_party/README", "readme\n");
+
+        assert_eq!(untracked_files(&t.repo, None).unwrap(), vec!["new.txt"]);
+        let (_, nested) = get_working_directory_files(&t.repo.path, None).unwrap();
+        assert_eq!(nested, vec!["third_party", "vendor/lib"]);
+    }
+
+    fn synthetic_tree(t: &TestRepo, dirs: usize, files_per_dir: usize) {
+        t.write(".helixignore", "*.log\nbuild/\n");
+        for d in 0..dirs {
+            for f in 0..files_per_dir {
+                t.write(&format!("src/d{}/f{}.rs", d, f), "x\n");
             }
+            t.write(&format!("src/d{}/debug.log", d), "noise\n");
+        }
+        t.write("build/out.o", "obj\n");
+        t.write("vendor/lib/.helix/HEAD", "main\n");
+    }
+
+    #[test]
+    fn parallel_walk_matches_the_serial_one() {
+        let t = TestRepo::new();
+        synthetic_tree(&t, 20, 10);
+
+        let serial = get_working_directory_files(&t.repo.path, Some(1)).unwrap();
+        let parallel = get_working_directory_files(&t.repo.path, Some(4)).unwrap();
+        assert_eq!(serial, parallel);
+        assert_eq!(parallel, get_working_directory_files(&t.repo.path, None).unwrap());
+
+        let (files, nested) = serial;
+        assert_eq!(files.iter().filter(|f| f.ends_with(".rs")).count(), 200);
+        assert!(files.iter().all(|f| !f.ends_with(".log") && !f.starts_with("build/")));
+        assert_eq!(nested, vec!["vendor/lib"]);
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "slow-tests"), ignore)]
+    fn parallel_walk_is_faster_on_a_large_tree() {
+        let t = TestRepo::new();
+        synthetic_tree(&t, 200, 100);
+        let time = |jobs| {
+            let start = std::time::Instant::now();
+            get_working_directory_files(&t.repo.path, jobs).unwrap();
+            start.elapsed()
+        };
+        // Warm the cache so neither run pays for the first disk read
+        time(Some(1));
+        let serial = time(Some(1));
+        let parallel = time(None);
+        println!("serial {:?}, parallel {:?}", serial, parallel);
+        if std::thread::available_parallelism().map_or(1, |n| n.get()) > 1 {
+            assert!(parallel < serial, "serial {:?}, parallel {:?}", serial, parallel);
         }
     }
 
-    Ok(files)
+    #[tokio::test]
+    async fn files_are_modified_only_when_their_content_changes() {
+        let mut t = TestRepo::new();
+        t.write("same.txt", "same\n");
+        t.write("changed.txt", "before\n");
+        t.commit("base").await;
+        // Rewriting identical content is not a change
+        t.write("same.txt", "same\n");
+        t.write("changed.txt", "after\n");
+        t.write("new.txt", "new\n");
+        t.write("staged.txt", "staged\n");
+        let path = t.repo.path.join("staged.txt");
+        add::add_files(&mut t.repo, &[path], &add::AddOptions::default()).await.unwrap();
+
+        let (working_files, _) = get_working_directory_files(&t.repo.path, None).unwrap();
+        let changes = working_changes(&t.repo, &working_files, &t.repo.head_files());
+        assert_eq!(changes.modified, vec!["changed.txt"]);
+        assert_eq!(changes.untracked, vec!["new.txt"]);
+        assert_eq!(changes.staged, vec!["staged.txt"]);
+    }
+
+    #[tokio::test]
+    async fn staged_renames_honour_the_threshold() {
+        let mut t = TestRepo::new();
+        t.write("a.rs", "1\n2\n3\n4\n5\n6\n7\n8\n");
+        t.write("c.rs", "fn c() {}\n");
+        t.commit("first").await;
+
+        t.remove("a.rs");
+        t.write("b.rs", "1\n2\n3\n4\n5\n6\n7\nEIGHT\n");
+        t.remove("c.rs");
+        t.write("d.rs", "fn c() {}\n");
+        t.add_all().await;
+
+        let head_files = t.repo.head_files();
+        let pairs: Vec<(String, String, u8)> = staged_renames(&t.repo, &head_files, None)
+            .into_iter()
+            .map(|p| (p.old_path, p.new_path, p.similarity))
+            .collect();
+        assert_eq!(
+            pairs,
+            [("c.rs".to_string(), "d.rs".to_string(), 100), ("a.rs".to_string(), "b.rs".to_string(), 88)]
+        );
+
+        let strict = staged_renames(&t.repo, &head_files, Some(89));
+        assert_eq!(strict.len(), 1);
+        assert_eq!(strict[0].new_path, "d.rs");
+    }
 }
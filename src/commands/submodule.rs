@@ -0,0 +1,174 @@
+use crate::commands::clone;
+use crate::core::index::IndexEntry;
+use crate::core::object::Object;
+use crate::core::repository::Repository;
+use crate::core::submodule::{SubmoduleEntry, Submodules, GITLINK_MODE, MODULES_FILE};
+use crate::utils::{file_utils, hash_utils, path_utils};
+use anyhow::Result;
+use colored::*;
+use std::path::Path;
+
+/// Clone `url` into `path`, pin its current commit in `.helixmodules` and
+/// stage the submodule as a single gitlink entry.
+pub async fn add_submodule(repo: &mut Repository, url: &str, path: &Path) -> Result<()> {
+    let relative_path = path_utils::normalize_path(path.strip_prefix(&repo.path).unwrap_or(path))
+        .to_string_lossy()
+        .to_string();
+    let mut modules = Submodules::load(&repo.path)?;
+    if modules.submodule.contains_key(&relative_path) {
+        anyhow::bail!("Submodule '{}' already exists", relative_path);
+    }
+
+    let full_path = repo.path.join(&relative_path);
+    if full_path.exists() && full_path.read_dir()?.next().is_some() {
+        anyhow::bail!("'{}' already exists and is not empty", relative_path);
+    }
+
+    fetch_submodule(url, &full_path).await?;
+    let commit = submodule_head(&full_path)?;
+
+    modules.submodule.insert(
+        relative_path.clone(),
+        SubmoduleEntry {
+            url: url.to_string(),
+            commit: commit.clone(),
+        },
+    );
+    modules.save(&repo.path)?;
+
+    repo.index.add_file(
+        &relative_path,
+        IndexEntry {
+            path: relative_path.clone(),
+            content_hash: commit.clone(),
+            size: 0,
+            mode: GITLINK_MODE,
+            timestamp: chrono::Utc::now(),
+            stage: 0,
+        },
+    );
+    stage_modules_file(repo)?;
+    repo.save()?;
+
+    println!(
+        "{}",
+        format!("Added submodule '{}' at {}", relative_path, hash_utils::get_short_hash(&commit))
+            .green()
+            .bold()
+    );
+    println!("Source: {}", url.magenta());
+    Ok(())
+}
+
+/// Clone missing submodules and check out their pinned commits.
+pub async fn update_submodules(repo: &Repository) -> Result<()> {
+    let modules = Submodules::load(&repo.path)?;
+    if modules.submodule.is_empty() {
+        println!("{}", "No submodules configured".yellow());
+        return Ok(());
+    }
+
+    for (path, entry) in &modules.submodule {
+        let full_path = repo.path.join(path);
+        if !full_path.join(".helix").is_dir() {
+            fetch_submodule(&entry.url, &full_path).await?;
+        }
+
+        let mut sub = Repository::open(&full_path.to_string_lossy())?;
+        let current = sub.head_commit().cloned();
+        if current.as_deref() == Some(entry.commit.as_str()) {
+            println!("{} {}", path.cyan(), "up to date".green());
+            continue;
+        }
+
+        let commit = sub.get_commit_object(&entry.commit).map_err(|_| {
+            anyhow::anyhow!(
+                "Pinned commit {} not found in submodule '{}'",
+                hash_utils::get_short_hash(&entry.commit),
+                path
+            )
+        })?;
+        let tree_files = commit.tree_files();
+        sub.check_overwrite(&tree_files, false)?;
+        for (file, blob_hash) in &tree_files {
+            let blob = Object::load(&sub.get_objects_dir(), blob_hash)?;
+            file_utils::write_file_content(&full_path.join(file), &blob.data)?;
+        }
+        sub.set_head(&entry.commit)?;
+
+        println!(
+            "{} checked out {}",
+            path.cyan(),
+            hash_utils::get_short_hash(&entry.commit).yellow()
+        );
+    }
+    Ok(())
+}
+
+/// Local Helix repositories are copied; anything else goes through clone.
+async fn fetch_submodule(url: &str, dest: &Path) -> Result<()> {
+    let source = Path::new(url);
+    if source.join(".helix").is_dir() {
+        file_utils::copy_dir_all(source, dest)
+    } else {
+        clone::clone_repository(url, dest, &clone::CloneOptions::default()).await
+    }
+}
+
+fn submodule_head(path: &Path) -> Result<String> {
+    let sub = Repository::open(&path.to_string_lossy())?;
+    sub.head_commit()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Submodule at '{}' has no commits", path.display()))
+}
+
+fn stage_modules_file(repo: &mut Repository) -> Result<()> {
+    let content = file_utils::read_file_content(&Submodules::path(&repo.path))?;
+    let blob = Object::new("blob".to_string(), content.clone());
+    blob.save(&repo.get_objects_dir())?;
+    repo.index.add_file(
+        MODULES_FILE,
+        IndexEntry {
+            path: MODULES_FILE.to_string(),
+            content_hash: blob.id,
+            size: content.len() as u64,
+            mode: 0o644,
+            timestamp: chrono::Utc::now(),
+            stage: 0,
+        },
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn adding_a_submodule_records_its_url_and_commit() {
+        let mut lib = TestRepo::new();
+        lib.write("lib.rs", "pub fn lib() {}\n");
+        lib.add_all().await;
+        let pinned = lib.commit("lib").await;
+
+        let mut app = TestRepo::new();
+        let url = lib.repo.path.to_string_lossy().to_string();
+        let dest = app.repo.path.join("vendor/lib");
+        add_submodule(&mut app.repo, &url, &dest).await.unwrap();
+
+        let modules = Submodules::load(&app.repo.path).unwrap();
+        let entry = &modules.submodule["vendor/lib"];
+        assert_eq!(entry.url, url);
+        assert_eq!(entry.commit, pinned);
+
+        let staged = app.repo.index.get_file("vendor/lib").unwrap();
+        assert_eq!(staged.mode, GITLINK_MODE);
+        assert_eq!(staged.content_hash, pinned);
+        assert!(app.repo.index.has_file(MODULES_FILE));
+        assert!(app.exists("vendor/lib/lib.rs"));
+
+        let again = add_submodule(&mut app.repo, &url, &dest).await;
+        assert!(again.unwrap_err().to_string().contains("already exists"));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::core::repository::Repository;
+use crate::core::tag::{self, Tag, Tags};
+use crate::utils::{editor, hash_utils, key_utils, path_utils};
+use anyhow::Result;
+use colored::*;
+
+#[derive(Debug, Clone, Default)]
+pub struct TagOptions {
+    /// Create an annotated tag object instead of a plain ref
+    pub annotate: bool,
+    /// Annotated tag message; implies `annotate`
+    pub message: Option<String>,
+}
+
+/// Create a tag pointing at `rev` (default HEAD): a plain ref, or with
+/// `annotate`/`message` a tag object carrying the message, tagger and,
+/// when a key is available, a signature.
+pub async fn create_tag(
+    repo: &Repository,
+    name: &str,
+    rev: Option<&str>,
+    options: &TagOptions,
+) -> Result<()> {
+    let mut tags = Tags::load(&repo.git_dir)?;
+    if tags.refs.contains_key(name) {
+        anyhow::bail!("Tag '{}' already exists", name);
+    }
+    let target = repo.resolve_rev(rev.unwrap_or("HEAD"))?;
+
+    let annotated = options.annotate || options.message.is_some();
+    let ref_id = if annotated {
+        let message = match &options.message {
+            Some(message) => message.clone(),
+            None => {
+                let editor = editor::editor().ok_or_else(|| {
+                    anyhow::anyhow!("No tag message given; use -m or set HX_EDITOR")
+                })?;
+                let template = format!(
+                    "\n# Write a message for tag '{}'.\n# Lines starting with '#' are ignored.\n",
+                    name
+                );
+                editor::edit_message(&editor, &repo.git_dir.join("TAG_EDITMSG"), &template)?
+            }
+        };
+        if message.is_empty() {
+            anyhow::bail!("Empty tag message; not creating tag '{}'", name);
+        }
+        let (author, email) = repo.identity();
+        let mut tag = Tag::new(name, &target, Some(&message), &format!("{} <{}>", author, email));
+        // Unsigned only when there is no key at all; a key that fails to
+        // load (wrong passphrase, corrupt file) is an error
+        if key_utils::keypair_exists() {
+            tag.sign(&key_utils::load_keypair()?);
+        }
+        let object = tag.to_object();
+        object.save(&repo.get_objects_dir())?;
+        object.id
+    } else {
+        target.clone()
+    };
+    tags.refs.insert(name.to_string(), ref_id);
+    tags.save(&repo.git_dir)?;
+
+    println!(
+        "{}",
+        format!(
+            "Created {}tag '{}' at {}",
+            if annotated { "annotated " } else { "" },
+            name,
+            hash_utils::get_short_hash(&target)
+        )
+        .green()
+        .bold()
+    );
+    Ok(())
+}
+
+pub async fn delete_tag(repo: &Repository, name: &str) -> Result<()> {
+    let mut tags = Tags::load(&repo.git_dir)?;
+    let id = tags
+        .refs
+        .remove(name)
+        .ok_or_else(|| anyhow::anyhow!("Tag '{}' not found", name))?;
+    tags.save(&repo.git_dir)?;
+    println!("Deleted tag '{}' (was {})", name, hash_utils::get_short_hash(&id));
+    Ok(())
+}
+
+/// List tags matching an optional glob, ordered by `sort`: `refname`
+/// (default), `version` or `creatordate`, reversed with a leading `-`.
+pub async fn list_tags(repo: &Repository, pattern: Option<&str>, sort: Option<&str>) -> Result<()> {
+    for name in matching_tags(repo, pattern, sort)? {
+        println!("{}", name.yellow());
+    }
+    Ok(())
+}
+
+fn matching_tags(repo: &Repository, pattern: Option<&str>, sort: Option<&str>) -> Result<Vec<String>> {
+    let tags = Tags::load(&repo.git_dir)?;
+    let objects_dir = repo.get_objects_dir();
+    let mut names: Vec<&String> = tags
+        .refs
+        .keys()
+        .filter(|name| pattern.is_none_or(|p| path_utils::glob_match(p, name)))
+        .collect();
+
+    let sort = sort.unwrap_or("refname");
+    let (key, descending) = match sort.strip_prefix('-') {
+        Some(key) => (key, true),
+        None => (sort, false),
+    };
+    match key {
+        "refname" => names.sort(),
+        "version" | "v:refname" => names.sort_by(|a, b| tag::version_cmp(a, b)),
+        "creatordate" => names.sort_by_key(|name| {
+            let id = &tags.refs[*name];
+            match Tag::load(&objects_dir, id) {
+                Some(tag) => Some(tag.timestamp),
+                None => repo.get_commit_object(id).map(|c| c.timestamp).ok(),
+            }
+        }),
+        _ => anyhow::bail!("Unsupported sort key '{}'", sort),
+    }
+    if descending {
+        names.reverse();
+    }
+    Ok(names.into_iter().cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    async fn tagged(names: &[&str]) -> TestRepo {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("first").await;
+        for name in names {
+            create_tag(&t.repo, name, None, &TagOptions::default()).await.unwrap();
+        }
+        t
+    }
+
+    #[tokio::test]
+    async fn version_sort_orders_numeric_segments() {
+        let t = tagged(&["v1.2", "v1.10", "v1.9"]).await;
+        assert_eq!(matching_tags(&t.repo, None, None).unwrap(), vec!["v1.10", "v1.2", "v1.9"]);
+        assert_eq!(
+            matching_tags(&t.repo, None, Some("version")).unwrap(),
+            vec!["v1.2", "v1.9", "v1.10"]
+        );
+        assert_eq!(
+            matching_tags(&t.repo, None, Some("-version")).unwrap(),
+            vec!["v1.10", "v1.9", "v1.2"]
+        );
+        assert!(matching_tags(&t.repo, None, Some("size")).is_err());
+    }
+
+    #[tokio::test]
+    async fn pattern_filters_by_glob() {
+        let t = tagged(&["v1.0", "v1.1", "v2.0", "nightly"]).await;
+        assert_eq!(
+            matching_tags(&t.repo, Some("v1.*"), Some("version")).unwrap(),
+            vec!["v1.0", "v1.1"]
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use crate::core::repository::Repository;
+use crate::core::undo::UndoEntry;
+use anyhow::Result;
+use colored::*;
+
+/// Restore the working tree files saved by the most recent destructive
+/// command, or with `list`, show the snapshots that can be restored.
+pub async fn undo(repo: &Repository, list: bool) -> Result<()> {
+    let entries = UndoEntry::list(&repo.git_dir)?;
+    if list {
+        if entries.is_empty() {
+            println!("{}", "No undo snapshots".yellow());
+        }
+        for (i, entry) in entries.iter().rev().enumerate() {
+            println!(
+                "{} {} ({} files) {}",
+                format!("undo@{{{}}}:", i).yellow(),
+                entry.operation,
+                entry.files.len(),
+                entry
+                    .created_at
+                    .format("(%Y-%m-%d %H:%M:%S)")
+                    .to_string()
+                    .dimmed()
+            );
+        }
+        return Ok(());
+    }
+
+    let Some(entry) = entries.last() else {
+        println!("{}", "Nothing to undo".yellow());
+        return Ok(());
+    };
+    entry.restore(repo)?;
+    println!(
+        "{}",
+        format!("Undid {}", entry.operation).green().bold()
+    );
+    for (path, existed) in &entry.files {
+        if *existed {
+            println!("  restored {}", path.cyan());
+        } else {
+            println!("  removed {}", path.red());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::reset::reset_repository;
+    use crate::utils::test_utils::TestRepo;
+
+    #[tokio::test]
+    async fn a_forced_hard_reset_can_be_undone() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "one\n");
+        let first = t.commit("first").await;
+        t.write("a.txt", "two\n");
+        t.write("b.txt", "added later\n");
+        t.commit("second").await;
+        t.write("a.txt", "uncommitted\n");
+
+        reset_repository(&mut t.repo, &first, "hard", true).await.unwrap();
+        assert_eq!(t.read("a.txt"), "one\n");
+        assert!(!t.exists("b.txt"));
+
+        let entries = UndoEntry::list(&t.repo.git_dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].operation.contains("reset --hard"), "{}", entries[0].operation);
+
+        undo(&t.repo, false).await.unwrap();
+        assert_eq!(t.read("a.txt"), "uncommitted\n");
+        assert_eq!(t.read("b.txt"), "added later\n");
+    }
+}
@@ -0,0 +1,144 @@
+use crate::core::object::Object;
+use crate::utils::pack::Pack;
+use anyhow::{Context, Result};
+use colored::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Pack type number used for delta entries.
+const DELTA_TYPE: u8 = 7;
+
+/// Parse a pack file, decode every object and check it against the id the
+/// pack records for it (when it records one). Returns whether the pack is
+/// free of errors.
+pub async fn verify_pack(path: &Path, verbose: bool) -> Result<bool> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let pack = match Pack::from_bytes(&data) {
+        Ok(pack) => pack,
+        Err(e) => {
+            println!("{} {}: {}", "error:".red().bold(), path.display(), e);
+            return Ok(false);
+        }
+    };
+
+    // Packs written before ids were embedded only carry positional keys
+    let claimed_ids: HashMap<usize, &str> = pack
+        .index
+        .iter()
+        .filter(|(key, _)| key.len() == 64 && key.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|(key, &i)| (i, key.as_str()))
+        .collect();
+
+    let mut errors = 0;
+    for (i, entry) in pack.objects.iter().enumerate() {
+        if entry.object_type == DELTA_TYPE {
+            // The pack format carries no base reference to resolve against
+            println!(
+                "{} object {}: delta without a resolvable base ({} bytes)",
+                "error:".red().bold(),
+                i,
+                entry.size
+            );
+            errors += 1;
+            continue;
+        }
+
+        let object = match Object::from_compressed(&entry.data) {
+            Ok(object) => object,
+            Err(e) => {
+                println!("{} object {}: {}", "error:".red().bold(), i, e);
+                errors += 1;
+                continue;
+            }
+        };
+
+        if let Some(claimed) = claimed_ids.get(&i) {
+            if *claimed != object.id {
+                println!(
+                    "{} object {}: claims {} but hashes to {}",
+                    "error:".red().bold(),
+                    i,
+                    claimed,
+                    object.id
+                );
+                errors += 1;
+                continue;
+            }
+        }
+
+        if verbose {
+            println!("{} {:<6} {}", object.id.yellow(), object.object_type, object.size);
+        }
+    }
+
+    if pack.objects.len() != pack.header.object_count as usize {
+        println!(
+            "{} header lists {} objects, found {}",
+            "error:".red().bold(),
+            pack.header.object_count,
+            pack.objects.len()
+        );
+        errors += 1;
+    }
+
+    println!(
+        "{}: {} objects, {}",
+        path.display(),
+        pack.objects.len(),
+        if errors == 0 {
+            "ok".green().to_string()
+        } else {
+            format!("{} errors", errors).red().to_string()
+        }
+    );
+    Ok(errors == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gc;
+    use crate::utils::pack;
+    use crate::utils::test_utils::TestRepo;
+
+    async fn repo_pack() -> (TestRepo, Pack) {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        test.commit("first").await;
+        test.write("dir/b.txt", "b\n");
+        test.commit("second").await;
+
+        let objects_dir = test.repo.get_objects_dir();
+        let mut pack = Pack::new();
+        for id in gc::list_loose_objects(&test.repo).unwrap() {
+            let object = Object::load(&objects_dir, &id).unwrap();
+            let data = fs::read(objects_dir.join(&id[..2]).join(&id[2..])).unwrap();
+            pack.add_object(&id, pack::pack_type(&object.object_type), data);
+        }
+        (test, pack)
+    }
+
+    #[tokio::test]
+    async fn a_generated_pack_verifies() {
+        let (test, pack) = repo_pack().await;
+        assert_eq!(pack.objects.len(), gc::list_loose_objects(&test.repo).unwrap().len());
+        let path = test.repo.path.join("good.pack");
+        fs::write(&path, pack.to_bytes().unwrap()).unwrap();
+        assert!(verify_pack(&path, true).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn a_misnamed_object_fails() {
+        let (test, mut pack) = repo_pack().await;
+        let (id, i) = pack.index.iter().map(|(id, &i)| (id.clone(), i)).next().unwrap();
+        pack.index.remove(&id);
+        pack.index.insert("ab".repeat(32), i);
+        let path = test.repo.path.join("bad.pack");
+        fs::write(&path, pack.to_bytes().unwrap()).unwrap();
+        assert!(!verify_pack(&path, false).await.unwrap());
+
+        fs::write(&path, b"not a pack").unwrap();
+        assert!(!verify_pack(&path, false).await.unwrap());
+    }
+}
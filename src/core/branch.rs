@@ -21,7 +21,6 @@ impl Branch {
         }
     }
 
-    #[allow(dead_code)]
     pub fn with_head(name: &str, head_commit: String) -> Self {
         let now = chrono::Utc::now();
         Self {
@@ -43,11 +42,14 @@ impl Branch {
         self.last_updated = chrono::Utc::now();
     }
 
-    #[allow(dead_code)]
     pub fn set_upstream(&mut self, upstream: String) {
         self.upstream = Some(upstream);
     }
 
+    pub fn unset_upstream(&mut self) {
+        self.upstream = None;
+    }
+
     pub fn get_head_commit(&self) -> Option<&String> {
         self.head_commit.as_ref()
     }
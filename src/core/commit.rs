@@ -1,5 +1,6 @@
 use crate::core::object::Object;
-use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use crate::utils::key_utils;
+use ed25519_dalek::SigningKey;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
@@ -16,6 +17,11 @@ pub struct Commit {
     pub files: HashMap<String, FileChange>,
     pub public_key: Option<Vec<u8>>, // Ed25519 public key
     pub signature: Option<Vec<u8>>,  // Ed25519 signature
+    /// "Name <email>" of whoever rewrote the commit, when not the author
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub committed_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,18 +51,40 @@ impl Commit {
         files: HashMap<String, FileChange>,
         keypair: Option<&SigningKey>,
     ) -> Self {
-        let timestamp = chrono::Utc::now();
-        let id = Self::calculate_id(&parent_ids, &tree_id, &author, &email, &message, &timestamp);
-        let (public_key, signature) = if let Some(kp) = keypair {
-            let sig = kp.sign(id.as_bytes());
-            (
-                Some(kp.verifying_key().to_bytes().to_vec()),
-                Some(sig.to_bytes().to_vec()),
-            )
-        } else {
-            (None, None)
-        };
-        Self {
+        Self::new_at(
+            parent_ids,
+            tree_id,
+            author,
+            email,
+            message,
+            files,
+            chrono::Utc::now(),
+            keypair,
+        )
+    }
+
+    /// Like `new`, but with an explicit timestamp so ids are reproducible.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_at(
+        parent_ids: Vec<String>,
+        tree_id: String,
+        author: String,
+        email: String,
+        message: String,
+        files: HashMap<String, FileChange>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+        keypair: Option<&SigningKey>,
+    ) -> Self {
+        let id = Self::calculate_id(
+            &parent_ids,
+            &tree_id,
+            &author,
+            &email,
+            &message,
+            &timestamp,
+            &files,
+        );
+        let mut commit = Self {
             id,
             parent_ids,
             tree_id,
@@ -65,9 +93,15 @@ impl Commit {
             message,
             timestamp,
             files,
-            public_key,
-            signature,
+            public_key: None,
+            signature: None,
+            committer: None,
+            committed_at: None,
+        };
+        if let Some(kp) = keypair {
+            commit.sign(kp);
         }
+        commit
     }
 
     pub fn calculate_id(
@@ -77,12 +111,14 @@ impl Commit {
         email: &str,
         message: &str,
         timestamp: &chrono::DateTime<chrono::Utc>,
+        files: &HashMap<String, FileChange>,
     ) -> String {
         let mut hasher = Sha256::new();
         let commit_data = format!(
-            "tree {}\nparents {}\nauthor {} <{}> {}\n\n{}",
+            "tree {}\nparents {}\nfiles {}\nauthor {} <{}> {}\n\n{}",
             tree_id,
             parent_ids.join(","),
+            Self::files_digest(files),
             author,
             email,
             timestamp.timestamp(),
@@ -92,42 +128,108 @@ impl Commit {
         format!("{:x}", hasher.finalize())
     }
 
-    #[allow(dead_code)]
+    /// SHA-256 over the sorted `path content_hash` entries of the change
+    /// set, so the id commits to every file's content.
+    fn files_digest(files: &HashMap<String, FileChange>) -> String {
+        let mut entries: Vec<(&String, &String)> = files
+            .values()
+            .map(|fc| (&fc.path, &fc.content_hash))
+            .collect();
+        entries.sort();
+        let mut hasher = Sha256::new();
+        for (path, content_hash) in entries {
+            hasher.update(format!("{} {}\n", path, content_hash).as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Canonical bytes covered by the signature: the id plus every field a
+    /// reader relies on, including the tree and the full file list, so a
+    /// tampered commit fails `verify` even if its id is left alone.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = format!("commit {}\ntree {}\n", self.id, self.tree_id);
+        for parent in &self.parent_ids {
+            payload.push_str(&format!("parent {}\n", parent));
+        }
+        payload.push_str(&format!(
+            "author {} <{}> {}\n",
+            self.author,
+            self.email,
+            self.timestamp.timestamp()
+        ));
+        if let Some(committer) = &self.committer {
+            let committed_at = self.committed_at.map(|t| t.timestamp()).unwrap_or_default();
+            payload.push_str(&format!("committer {} {}\n", committer, committed_at));
+        }
+        let mut files: Vec<&FileChange> = self.files.values().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        for fc in files {
+            let change = match &fc.change_type {
+                ChangeType::Added => "A".to_string(),
+                ChangeType::Modified => "M".to_string(),
+                ChangeType::Deleted => "D".to_string(),
+                ChangeType::Renamed { old_path } => format!("R {}", old_path),
+            };
+            payload.push_str(&format!(
+                "file {} {} {:o} {} {}\n",
+                fc.content_hash, fc.size, fc.mode, change, fc.path
+            ));
+        }
+        payload.push('\n');
+        payload.push_str(&self.message);
+        payload.into_bytes()
+    }
+
+    /// Sign the current contents; call again after changing any field.
     pub fn sign(&mut self, keypair: &SigningKey) {
-        let sig = keypair.sign(self.id.as_bytes());
-        self.public_key = Some(keypair.verifying_key().to_bytes().to_vec());
-        self.signature = Some(sig.to_bytes().to_vec());
+        let (public_key, signature) = key_utils::sign_payload(keypair, &self.signing_payload());
+        self.public_key = Some(public_key);
+        self.signature = Some(signature);
     }
 
     pub fn verify(&self) -> bool {
-        if let (Some(pk_bytes), Some(sig_bytes)) = (&self.public_key, &self.signature) {
-            if let (Ok(pk_array), Ok(sig_array)) = (
-                pk_bytes.as_slice().try_into(),
-                sig_bytes.as_slice().try_into(),
-            ) {
-                if let Ok(pk) = VerifyingKey::from_bytes(pk_array) {
-                    let sig = Signature::from_bytes(sig_array);
-                    return pk.verify(self.id.as_bytes(), &sig).is_ok();
-                }
-            }
+        match (&self.public_key, &self.signature) {
+            (Some(pk), Some(sig)) => key_utils::verify_payload(pk, sig, &self.signing_payload()),
+            _ => false,
         }
-        false
     }
 
     /// Recursively verify this commit and all ancestors (full ancestry).
     pub fn verify_ancestry<F>(
         repo: &crate::core::repository::Repository,
         commit_id: &str,
+        on_commit: F,
+    ) -> bool
+    where
+        F: FnMut(&Commit, bool),
+    {
+        Self::verify_range(repo, None, commit_id, None, on_commit)
+    }
+
+    /// Verify `to` and its ancestors, stopping at the already-trusted `from`
+    /// commit and everything behind it (none of which is verified) and, with
+    /// `max_depth`, after that many generations below `to`.
+    pub fn verify_range<F>(
+        repo: &crate::core::repository::Repository,
+        from: Option<&str>,
+        to: &str,
+        max_depth: Option<usize>,
         mut on_commit: F,
     ) -> bool
     where
         F: FnMut(&Commit, bool),
     {
-        let mut visited = std::collections::HashSet::new();
-        let mut stack = vec![commit_id.to_string()];
+        // The whole history of `from`, not just the commit itself: a merge
+        // of `from` into `to` would otherwise reach behind it
+        let mut visited: std::collections::HashSet<String> = match from {
+            Some(from) => crate::core::commit_graph::CommitParents::new(repo).ancestors(from).collect(),
+            None => std::collections::HashSet::new(),
+        };
+        // Breadth first, so each commit is reached at its smallest depth
+        let mut queue = std::collections::VecDeque::from([(to.to_string(), 0)]);
         let mut all_valid = true;
-        while let Some(cid) = stack.pop() {
-            if !visited.insert(cid.clone()) {
+        while let Some((cid, depth)) = queue.pop_front() {
+            if max_depth.is_some_and(|max| depth >= max) || !visited.insert(cid.clone()) {
                 continue;
             }
             let obj = match crate::core::object::Object::load(&repo.get_objects_dir(), &cid) {
@@ -150,7 +252,7 @@ impl Commit {
                 all_valid = false;
             }
             for parent in &commit.parent_ids {
-                stack.push(parent.clone());
+                queue.push_back((parent.clone(), depth + 1));
             }
         }
         all_valid
@@ -161,7 +263,7 @@ impl Commit {
     }
 
     pub fn from_object(object: &Object) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(&object.data)
+        serde_json::from_slice(&object.data)
     }
 
     pub fn get_short_id(&self) -> String {
@@ -180,6 +282,17 @@ impl Commit {
     pub fn has_file(&self, path: &str) -> bool {
         self.files.contains_key(path)
     }
+
+    /// Path -> blob hash for every file present in this commit (deletions
+    /// and submodule entries excluded).
+    pub fn tree_files(&self) -> std::collections::BTreeMap<String, String> {
+        self.files
+            .values()
+            .filter(|fc| !matches!(fc.change_type, ChangeType::Deleted))
+            .filter(|fc| fc.mode != crate::core::submodule::GITLINK_MODE)
+            .map(|fc| (fc.path.clone(), fc.content_hash.clone()))
+            .collect()
+    }
 }
 
 impl FileChange {
@@ -209,7 +322,6 @@ impl FileChange {
         matches!(self.change_type, ChangeType::Modified)
     }
 
-    #[allow(dead_code)]
     pub fn is_deleted(&self) -> bool {
         matches!(self.change_type, ChangeType::Deleted)
     }
@@ -219,3 +331,144 @@ impl FileChange {
         matches!(self.change_type, ChangeType::Renamed { .. })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::merge::{self, MergeOptions};
+    use crate::utils::test_utils::{keypair, TestRepo};
+
+    fn verified(t: &TestRepo, from: Option<&str>, to: &str, max_depth: Option<usize>) -> Vec<String> {
+        let mut seen = Vec::new();
+        Commit::verify_range(&t.repo, from, to, max_depth, |commit, _| {
+            seen.push(commit.message.clone());
+        });
+        seen.sort();
+        seen
+    }
+
+    async fn merge(t: &mut TestRepo, branch: &str) -> String {
+        let options = MergeOptions { no_edit: true, ..Default::default() };
+        merge::merge_branch(&mut t.repo, branch, None, false, &options).await.unwrap();
+        t.head()
+    }
+
+    #[tokio::test]
+    async fn verification_stops_at_the_boundary() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("c1").await;
+        t.write("a", "2\n");
+        let c2 = t.commit("c2").await;
+        t.write("a", "3\n");
+        let c3 = t.commit("c3").await;
+
+        assert_eq!(verified(&t, Some(&c2), &c3, None), ["c3"]);
+        assert_eq!(verified(&t, None, &c3, None), ["c1", "c2", "c3"]);
+        assert!(Commit::verify_range(&t.repo, Some(&c2), &c3, None, |_, _| {}));
+    }
+
+    #[tokio::test]
+    async fn history_behind_the_boundary_is_skipped_through_merges() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("b", "b\n");
+        let feat = t.commit("feat").await;
+        t.checkout("main").await;
+        t.write("c", "c\n");
+        t.commit("main").await;
+        let merged = merge(&mut t, "feat").await;
+
+        // `base` is behind `feat`, even though the merge also reaches it via `main`
+        assert_eq!(
+            verified(&t, Some(&feat), &merged, None),
+            ["Merge branch 'feat' into 'main'", "main"]
+        );
+    }
+
+    #[tokio::test]
+    async fn depth_counts_the_shortest_path() {
+        let mut t = TestRepo::new();
+        t.write("a", "1\n");
+        t.commit("base").await;
+        t.checkout_new("feat").await;
+        t.write("b", "b\n");
+        t.commit("f1").await;
+        t.checkout("main").await;
+        t.write("c", "1\n");
+        t.commit("m1").await;
+        t.write("c", "2\n");
+        t.commit("m2").await;
+        // The longer history is the merge's second parent, walked first
+        t.checkout("feat").await;
+        let merged = merge(&mut t, "main").await;
+
+        // `base` is two generations down through f1, three through m1
+        assert_eq!(
+            verified(&t, None, &merged, Some(3)),
+            ["Merge branch 'main' into 'feat'", "base", "f1", "m1", "m2"]
+        );
+        assert_eq!(
+            verified(&t, None, &merged, Some(2)),
+            ["Merge branch 'main' into 'feat'", "f1", "m2"]
+        );
+    }
+
+    fn signed_commit() -> Commit {
+        let files = HashMap::from([(
+            "a.txt".to_string(),
+            FileChange::new("a.txt".to_string(), ChangeType::Added, "1".repeat(64), 2, 0o100644),
+        )]);
+        Commit::new_at(
+            Vec::new(),
+            "2".repeat(64),
+            "Test".to_string(),
+            "test@example.com".to_string(),
+            "message".to_string(),
+            files,
+            "2024-01-31T12:00:00Z".parse().unwrap(),
+            Some(&keypair()),
+        )
+    }
+
+    #[test]
+    fn mutating_a_recorded_file_hash_breaks_the_signature() {
+        let commit = signed_commit();
+        assert!(commit.verify());
+
+        let mut tampered = commit.clone();
+        tampered.files.get_mut("a.txt").unwrap().content_hash = "3".repeat(64);
+        assert!(!tampered.verify());
+
+        let mut tampered = commit.clone();
+        tampered.tree_id = "3".repeat(64);
+        assert!(!tampered.verify());
+
+        let mut unsigned = commit;
+        unsigned.signature = None;
+        assert!(!unsigned.verify());
+    }
+
+    #[test]
+    fn a_content_hash_change_changes_the_id() {
+        let commit = signed_commit();
+        let id_with = |files: &HashMap<String, FileChange>| {
+            Commit::calculate_id(
+                &commit.parent_ids,
+                &commit.tree_id,
+                &commit.author,
+                &commit.email,
+                &commit.message,
+                &commit.timestamp,
+                files,
+            )
+        };
+        assert_eq!(id_with(&commit.files), commit.id);
+
+        let mut files = commit.files.clone();
+        files.get_mut("a.txt").unwrap().content_hash = "3".repeat(64);
+        assert_ne!(id_with(&files), commit.id);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::core::repository::Repository;
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"HXCG";
+const VERSION: u32 = 2;
+const ID_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct GraphEntry {
+    pub parents: Vec<String>,
+    /// Commit timestamp in seconds since the epoch
+    pub timestamp: i64,
+}
+
+/// Parents and timestamps of every commit reachable from branches and tags,
+/// cached in `.helix/commit-graph` so ancestry walks can skip loading and
+/// parsing commit objects.
+///
+/// Layout: `HXCG`, version (u32), shallow boundary count (u32) and the raw
+/// boundary ids, commit count (u32), the sorted raw commit ids (32 bytes
+/// each), then per commit its timestamp (i64), parent count (u32) and the
+/// position of each parent in the id table (u32). All integers are
+/// big-endian. Commits made after the file was written are simply not
+/// covered, but boundary commits are stored without parents, so the file
+/// only holds while `.helix/shallow` still lists the boundary it was built
+/// against; see [`CommitGraph::matches_shallow`].
+#[derive(Debug, Clone, Default)]
+pub struct CommitGraph {
+    pub entries: HashMap<String, GraphEntry>,
+    /// Shallow boundary at build time
+    pub shallow: BTreeSet<String>,
+}
+
+impl CommitGraph {
+    pub fn path(git_dir: &Path) -> PathBuf {
+        git_dir.join("commit-graph")
+    }
+
+    /// Walk the history behind every branch and tag.
+    pub fn build(repo: &Repository) -> Self {
+        let mut stack: Vec<String> = repo
+            .branches
+            .values()
+            .filter_map(|b| b.get_head_commit().cloned())
+            .collect();
+        if let Ok(tags) = crate::core::tag::Tags::load(&repo.git_dir) {
+            stack.extend(tags.targets(&repo.get_objects_dir()));
+        }
+
+        let shallow = repo.shallow_commits();
+        let mut entries = HashMap::new();
+        let mut visited = HashSet::new();
+        while let Some(commit_id) = stack.pop() {
+            if !visited.insert(commit_id.clone()) {
+                continue;
+            }
+            // Tags may point at non-commits; missing parents (shallow
+            // history) just end the walk
+            let commit = match repo.get_commit_object(&commit_id) {
+                Ok(commit) => commit,
+                Err(_) => continue,
+            };
+            // Boundary commits are stored without the parents we don't have
+            let parents = if shallow.contains(&commit_id) {
+                Vec::new()
+            } else {
+                commit.parent_ids
+            };
+            stack.extend(parents.iter().cloned());
+            entries.insert(
+                commit_id,
+                GraphEntry {
+                    parents,
+                    timestamp: commit.timestamp.timestamp(),
+                },
+            );
+        }
+        Self { entries, shallow }
+    }
+
+    /// Whether the graph was built against `shallow`. Deepening a shallow
+    /// clone gives boundary commits parents the graph doesn't record.
+    pub fn matches_shallow(&self, shallow: &BTreeSet<String>) -> bool {
+        &self.shallow == shallow
+    }
+
+    /// Read the cached graph; `None` if it hasn't been written.
+    pub fn load(git_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(git_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path).context("Failed to read commit graph")?;
+        Self::from_bytes(&data)
+            .map(Some)
+            .with_context(|| format!("Corrupt commit graph {}", path.display()))
+    }
+
+    /// Write the graph; returns the number of commits stored.
+    pub fn save(&self, git_dir: &Path) -> Result<usize> {
+        let (data, count) = self.to_bytes();
+        crate::utils::file_utils::write_file_durable(&Self::path(git_dir), &data)?;
+        Ok(count)
+    }
+
+    pub fn get(&self, commit_id: &str) -> Option<&GraphEntry> {
+        self.entries.get(commit_id)
+    }
+
+    fn to_bytes(&self) -> (Vec<u8>, usize) {
+        // Only commits whose id and parents all fit the table can be stored;
+        // the rest fall back to object loads
+        let mut ids: Vec<&String> = self
+            .entries
+            .keys()
+            .filter(|id| decode_id(id).is_some())
+            .collect();
+        ids.sort();
+        let mut storable: HashSet<&String> = ids.iter().copied().collect();
+        loop {
+            let before = storable.len();
+            storable.retain(|id| {
+                self.entries[*id]
+                    .parents
+                    .iter()
+                    .all(|p| ids.binary_search(&p).is_ok())
+            });
+            ids.retain(|id| storable.contains(id));
+            if storable.len() == before {
+                break;
+            }
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_be_bytes());
+        // A boundary id that can't be stored makes the graph never match,
+        // which only costs the fallback to object loads
+        let shallow: Vec<[u8; ID_LEN]> = self.shallow.iter().filter_map(|id| decode_id(id)).collect();
+        data.extend_from_slice(&(shallow.len() as u32).to_be_bytes());
+        for raw in &shallow {
+            data.extend_from_slice(raw);
+        }
+        data.extend_from_slice(&(ids.len() as u32).to_be_bytes());
+        for id in &ids {
+            data.extend_from_slice(&decode_id(id).unwrap());
+        }
+        for id in &ids {
+            let entry = &self.entries[*id];
+            data.extend_from_slice(&entry.timestamp.to_be_bytes());
+            data.extend_from_slice(&(entry.parents.len() as u32).to_be_bytes());
+            for parent in &entry.parents {
+                let position = ids.binary_search(&parent).unwrap() as u32;
+                data.extend_from_slice(&position.to_be_bytes());
+            }
+        }
+        (data, ids.len())
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.take(4)? != MAGIC {
+            anyhow::bail!("bad signature");
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            anyhow::bail!("unsupported version {}", version);
+        }
+        let shallow_count = reader.u32()? as usize;
+        let mut shallow = BTreeSet::new();
+        for _ in 0..shallow_count {
+            shallow.insert(encode_id(reader.take(ID_LEN)?));
+        }
+        let count = reader.u32()? as usize;
+        let mut ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            ids.push(encode_id(reader.take(ID_LEN)?));
+        }
+
+        let mut entries = HashMap::with_capacity(count);
+        for id in &ids {
+            let timestamp = reader.u64()? as i64;
+            let parent_count = reader.u32()? as usize;
+            let mut parents = Vec::with_capacity(parent_count);
+            for _ in 0..parent_count {
+                let position = reader.u32()? as usize;
+                let parent = ids
+                    .get(position)
+                    .ok_or_else(|| anyhow::anyhow!("parent position {} out of range", position))?;
+                parents.push(parent.clone());
+            }
+            entries.insert(id.clone(), GraphEntry { parents, timestamp });
+        }
+        Ok(Self { entries, shallow })
+    }
+}
+
+/// Parent lookups for ancestry walks: answered from the commit graph when it
+/// covers the commit, otherwise by loading the commit object. Shallow
+/// boundary commits are treated as roots.
+pub struct CommitParents<'a> {
+    repo: &'a Repository,
+    graph: Option<CommitGraph>,
+    shallow: BTreeSet<String>,
+}
+
+impl<'a> CommitParents<'a> {
+    pub fn new(repo: &'a Repository) -> Self {
+        let shallow = repo.shallow_commits();
+        let graph = CommitGraph::load(&repo.git_dir)
+            .ok()
+            .flatten()
+            .filter(|graph| graph.matches_shallow(&shallow));
+        Self { repo, graph, shallow }
+    }
+
+    pub fn parents(&self, commit_id: &str) -> Vec<String> {
+        if self.shallow.contains(commit_id) {
+            return Vec::new();
+        }
+        if let Some(entry) = self.graph.as_ref().and_then(|g| g.get(commit_id)) {
+            return entry.parents.clone();
+        }
+        self.repo
+            .get_commit_object(commit_id)
+            .map(|c| c.parent_ids)
+            .unwrap_or_default()
+    }
+
+    /// Commits reachable from `local` but not `upstream`, and the reverse.
+    pub fn ahead_behind(&self, local: &str, upstream: &str) -> (usize, usize) {
+        let local_history: HashSet<String> = self.ancestors(local).collect();
+        let upstream_history: HashSet<String> = self.ancestors(upstream).collect();
+        (
+            local_history.difference(&upstream_history).count(),
+            upstream_history.difference(&local_history).count(),
+        )
+    }
+
+    /// Breadth-first walk over `start` and all of its ancestors.
+    pub fn ancestors(&self, start: &str) -> Ancestors<'_, 'a> {
+        Ancestors {
+            lookup: self,
+            queue: VecDeque::from([start.to_string()]),
+            seen: HashSet::new(),
+        }
+    }
+}
+
+/// Lowest common ancestor of two commits: a commit reachable from both
+/// that is not itself an ancestor of another such commit. When several
+/// qualify (criss-cross merges), the one closest to `commit2` wins. `None`
+/// means the histories are unrelated.
+pub fn find_merge_base(repo: &Repository, commit1: &str, commit2: &str) -> Option<String> {
+    let lookup = CommitParents::new(repo);
+    let ancestors1: HashSet<String> = lookup.ancestors(commit1).collect();
+    let common: Vec<String> = lookup
+        .ancestors(commit2)
+        .filter(|id| ancestors1.contains(id))
+        .collect();
+
+    // A common ancestor reachable from another one is never the best base
+    let mut redundant: HashSet<String> = HashSet::new();
+    for candidate in &common {
+        if redundant.contains(candidate) {
+            continue;
+        }
+        redundant.extend(lookup.ancestors(candidate).skip(1));
+    }
+    common.into_iter().find(|id| !redundant.contains(id))
+}
+
+pub struct Ancestors<'l, 'a> {
+    lookup: &'l CommitParents<'a>,
+    queue: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl Iterator for Ancestors<'_, '_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some(commit_id) = self.queue.pop_front() {
+            if !self.seen.insert(commit_id.clone()) {
+                continue;
+            }
+            self.queue.extend(self.lookup.parents(&commit_id));
+            return Some(commit_id);
+        }
+        None
+    }
+}
+
+struct Reader<'d> {
+    data: &'d [u8],
+    pos: usize,
+}
+
+impl<'d> Reader<'d> {
+    fn take(&mut self, len: usize) -> Result<&'d [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow::anyhow!("truncated at byte {}", self.pos))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+fn decode_id(id: &str) -> Option<[u8; ID_LEN]> {
+    if id.len() != ID_LEN * 2 || !id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let mut raw = [0u8; ID_LEN];
+    for (i, byte) in raw.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&id[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(raw)
+}
+
+fn encode_id(raw: &[u8]) -> String {
+    raw.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::TestRepo;
+
+    async fn chain(test: &mut TestRepo, length: usize) -> Vec<String> {
+        let mut commits = Vec::new();
+        for i in 0..length {
+            test.write("file.txt", &format!("{}\n", i));
+            commits.push(test.commit(&format!("commit {}", i)).await);
+        }
+        commits
+    }
+
+    fn remove_loose(test: &TestRepo, commit_id: &str) {
+        let path = test.repo.get_objects_dir().join(&commit_id[..2]).join(&commit_id[2..]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn round_trips_through_the_file() {
+        let mut test = TestRepo::new();
+        let commits = chain(&mut test, 3).await;
+        let shallow = BTreeSet::from([commits[1].clone()]);
+        test.repo.set_shallow_commits(&shallow).unwrap();
+
+        assert_eq!(CommitGraph::build(&test.repo).save(&test.repo.git_dir).unwrap(), 2);
+        let graph = CommitGraph::load(&test.repo.git_dir).unwrap().unwrap();
+        assert!(graph.matches_shallow(&shallow));
+        assert_eq!(graph.get(&commits[2]).unwrap().parents, vec![commits[1].clone()]);
+        assert!(graph.get(&commits[1]).unwrap().parents.is_empty());
+    }
+
+    #[tokio::test]
+    async fn merge_base_walks_the_graph_instead_of_objects() {
+        let mut test = TestRepo::new();
+        let commits = chain(&mut test, 4).await;
+        CommitGraph::build(&test.repo).save(&test.repo.git_dir).unwrap();
+        // Only the graph still knows how the tip reaches the first commits
+        remove_loose(&test, &commits[2]);
+        remove_loose(&test, &commits[1]);
+
+        assert_eq!(
+            find_merge_base(&test.repo, &commits[3], &commits[1]),
+            Some(commits[1].clone())
+        );
+    }
+
+    #[tokio::test]
+    async fn deepening_the_boundary_invalidates_the_graph() {
+        let mut test = TestRepo::new();
+        let commits = chain(&mut test, 3).await;
+        test.repo.set_shallow_commits(&BTreeSet::from([commits[1].clone()])).unwrap();
+        CommitGraph::build(&test.repo).save(&test.repo.git_dir).unwrap();
+        assert!(CommitParents::new(&test.repo).parents(&commits[1]).is_empty());
+
+        test.repo.set_shallow_commits(&BTreeSet::new()).unwrap();
+        assert_eq!(CommitParents::new(&test.repo).parents(&commits[1]), vec![commits[0].clone()]);
+        assert_eq!(find_merge_base(&test.repo, &commits[2], &commits[0]), Some(commits[0].clone()));
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(feature = "slow-tests"), ignore)]
+    async fn merge_base_is_faster_with_the_graph_on_a_deep_history() {
+        let mut test = TestRepo::new();
+        let commits = chain(&mut test, 500).await;
+        let (tip, root) = (commits.last().unwrap(), &commits[0]);
+        let time = || {
+            let start = std::time::Instant::now();
+            assert_eq!(find_merge_base(&test.repo, tip, root), Some(root.clone()));
+            start.elapsed()
+        };
+        // Warm the cache so neither run pays for the first disk read
+        time();
+        let objects = time();
+        CommitGraph::build(&test.repo).save(&test.repo.git_dir).unwrap();
+        let graph = time();
+        println!("objects {:?}, graph {:?}", objects, graph);
+        assert!(graph < objects, "objects {:?}, graph {:?}", objects, graph);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::core::object::Object;
+use crate::core::repository::Repository;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Objects written so far by a clone or pull, one id per line in
+/// `.helix/FETCH_PROGRESS`, so an interrupted transfer picks up where it
+/// stopped. The file is removed once the transfer completes.
+#[derive(Debug)]
+pub struct FetchProgress {
+    path: PathBuf,
+    done: HashSet<String>,
+}
+
+impl FetchProgress {
+    pub fn path(git_dir: &Path) -> PathBuf {
+        git_dir.join("FETCH_PROGRESS")
+    }
+
+    pub fn load(git_dir: &Path) -> Result<Self> {
+        let path = Self::path(git_dir);
+        let done = if path.exists() {
+            fs::read_to_string(&path)
+                .context("Failed to read fetch progress")?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        Ok(Self { path, done })
+    }
+
+    /// Number of objects an earlier, interrupted run already wrote.
+    pub fn recorded(&self) -> usize {
+        self.done.len()
+    }
+
+    /// Whether `id` needs no download: recorded by an earlier run, or
+    /// already stored and intact. A loose file whose content doesn't hash
+    /// to its name (say, cut short by a crash) is fetched again.
+    pub fn has(&self, repo: &Repository, id: &str) -> bool {
+        if self.done.contains(id) {
+            return true;
+        }
+        let objects_dir = repo.get_objects_dir();
+        let loose = objects_dir.join(&id[..2]).join(&id[2..]);
+        if loose.exists() {
+            return fs::read(&loose)
+                .ok()
+                .is_some_and(|data| Object::from_compressed_checked(&data, id).is_ok());
+        }
+        repo.object_exists(id)
+    }
+
+    /// Note that `id` has been written.
+    pub fn record(&mut self, id: &str) -> Result<()> {
+        if self.done.insert(id.to_string()) {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .context("Failed to write fetch progress")?;
+            writeln!(file, "{}", id)?;
+        }
+        Ok(())
+    }
+
+    /// The transfer is complete; forget the progress.
+    pub fn finish(self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use crate::core::commit::FileChange;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum IndexNode {
@@ -12,6 +14,50 @@ pub enum IndexNode {
 pub struct Index {
     pub entries: HashMap<String, IndexNode>,
     pub version: u32,
+    #[serde(default)]
+    pub conflicts: HashMap<String, ConflictEntry>,
+    /// Tracked paths staged for deletion
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub deletions: BTreeSet<String>,
+}
+
+/// `.helix/index.lock`: held by commands that write objects before anything
+/// refers to them (`add`, `commit`) and by `gc`/`prune` while they delete
+/// objects, so the two never overlap. Released when dropped.
+#[derive(Debug)]
+pub struct IndexLock {
+    path: PathBuf,
+}
+
+impl IndexLock {
+    pub fn acquire(git_dir: &Path) -> anyhow::Result<Self> {
+        let path = git_dir.join("index.lock");
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(Self { path }),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => anyhow::bail!(
+                "Unable to create '{}': File exists.\n\
+                 Another hx process seems to be running in this repository.\n\
+                 If it is not, remove the file and try again.",
+                path.display()
+            ),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Unresolved merge conflict: the base, ours and theirs blob hashes for a path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictEntry {
+    pub path: String,
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,10 +105,13 @@ impl Index {
         Self {
             entries: HashMap::new(),
             version: 2,
+            conflicts: HashMap::new(),
+            deletions: BTreeSet::new(),
         }
     }
 
     pub fn add_file(&mut self, path: &str, entry: IndexEntry) {
+        self.deletions.remove(path);
         let parts: Vec<&str> = path.split('/').collect();
         let mut node = &mut self.entries;
         for part in &parts[..parts.len() - 1] {
@@ -75,8 +124,8 @@ impl Index {
         node.insert(parts.last().unwrap().to_string(), IndexNode::File(entry));
     }
 
-    #[allow(dead_code)]
     pub fn remove_file(&mut self, path: &str) {
+        self.deletions.remove(path);
         let parts: Vec<&str> = path.split('/').collect();
         let mut node = &mut self.entries;
         for part in &parts[..parts.len() - 1] {
@@ -89,7 +138,12 @@ impl Index {
         node.remove(*parts.last().unwrap());
     }
 
-    #[allow(dead_code)]
+    /// Stage the removal of a tracked path, replacing any staged content.
+    pub fn stage_deletion(&mut self, path: &str) {
+        self.remove_file(path);
+        self.deletions.insert(path.to_string());
+    }
+
     pub fn get_file(&self, path: &str) -> Option<&IndexEntry> {
         let parts: Vec<&str> = path.split('/').collect();
         let mut node = &self.entries;
@@ -127,10 +181,16 @@ impl Index {
 
     pub fn clear(&mut self) {
         self.entries.clear();
+        self.conflicts.clear();
+        self.deletions.clear();
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.entries.is_empty()
+        self.entries.is_empty() && self.deletions.is_empty()
     }
 
     pub fn len(&self) -> usize {
@@ -167,6 +227,18 @@ impl Index {
         }
         let mut changes = HashMap::new();
         collect_changes(&self.entries, &mut changes);
+        for path in &self.deletions {
+            changes.insert(
+                path.clone(),
+                FileChange::new(
+                    path.clone(),
+                    crate::core::commit::ChangeType::Deleted,
+                    String::new(),
+                    0,
+                    0,
+                ),
+            );
+        }
         changes
     }
 
@@ -1,6 +1,15 @@
 pub mod branch;
 pub mod commit;
+pub mod commit_graph;
+pub mod fetch_progress;
 pub mod index;
 pub mod object;
+pub mod rebase;
+pub mod reflog;
 pub mod remote;
 pub mod repository;
+pub mod stash;
+pub mod submodule;
+pub mod tag;
+pub mod tree_diff;
+pub mod undo;
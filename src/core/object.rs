@@ -7,12 +7,14 @@ use std::{fs, path::Path};
 pub struct Object {
     pub id: String,
     pub object_type: String,
-    pub data: String,
+    /// Raw content: file bytes for blobs, JSON for commits and trees
+    pub data: Vec<u8>,
     pub size: usize,
 }
 
 impl Object {
-    pub fn new(object_type: String, data: String) -> Self {
+    pub fn new(object_type: String, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
         let id = Self::calculate_id(&object_type, &data);
         let size = data.len();
 
@@ -24,13 +26,19 @@ impl Object {
         }
     }
 
-    fn calculate_id(object_type: &str, data: &str) -> String {
+    fn calculate_id(object_type: &str, data: &[u8]) -> String {
         let mut hasher = Sha256::new();
-        let content = format!("{} {}\0{}", object_type, data.len(), data);
-        hasher.update(content.as_bytes());
+        hasher.update(format!("{} {}\0", object_type, data.len()).as_bytes());
+        hasher.update(data);
         format!("{:x}", hasher.finalize())
     }
 
+    /// Content as text, with invalid UTF-8 replaced; for diffs and
+    /// other line-based views, never for writing files back out.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+
     pub fn save(&self, objects_dir: &Path) -> Result<()> {
         let object_dir = objects_dir.join(&self.id[..2]);
         let object_path = object_dir.join(&self.id[2..]);
@@ -46,21 +54,52 @@ impl Object {
     pub fn load(objects_dir: &Path, object_id: &str) -> Result<Self> {
         let object_path = objects_dir.join(&object_id[..2]).join(&object_id[2..]);
 
-        if !object_path.exists() {
-            anyhow::bail!("Object {} not found", object_id);
-        }
+        // Objects `gc` has packed are no longer loose
+        let compressed_data = if object_path.exists() {
+            fs::read(&object_path)?
+        } else {
+            crate::utils::pack::read_packed(objects_dir, object_id)?
+                .ok_or_else(|| anyhow::anyhow!("Object {} not found", object_id))?
+        };
+        let (object_type, content) = Self::parse(Self::decompress(&compressed_data)?)?;
 
-        let compressed_data = fs::read(&object_path)?;
-        let data = Self::decompress(&compressed_data)?;
+        Ok(Self {
+            id: object_id.to_string(),
+            size: content.len(),
+            object_type,
+            data: content,
+        })
+    }
+
+    /// Decode a compressed loose object, computing its id from the content.
+    pub fn from_compressed(compressed_data: &[u8]) -> Result<Self> {
+        let (object_type, content) = Self::parse(Self::decompress(compressed_data)?)?;
+        Ok(Self::new(object_type, content))
+    }
 
-        // Parse the object data
-        let parts: Vec<&str> = data.splitn(2, '\0').collect();
-        if parts.len() != 2 {
-            anyhow::bail!("Invalid object format");
+    /// Decode object data received from elsewhere, checking it really is
+    /// `expected_id` so a bad remote can't slip other content in under it.
+    pub fn from_compressed_checked(compressed_data: &[u8], expected_id: &str) -> Result<Self> {
+        let object = Self::from_compressed(compressed_data)
+            .with_context(|| format!("Object {} is corrupt", expected_id))?;
+        if object.id != expected_id {
+            anyhow::bail!(
+                "Object {} is corrupt: its content hashes to {}",
+                expected_id,
+                object.id
+            );
         }
+        Ok(object)
+    }
 
-        let header = parts[0];
-        let content = parts[1];
+    /// Split decompressed `<type> <size>\0<content>` into type and content.
+    fn parse(mut data: Vec<u8>) -> Result<(String, Vec<u8>)> {
+        let nul = data
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid object format"))?;
+        let content = data.split_off(nul + 1);
+        let header = std::str::from_utf8(&data[..nul]).context("Invalid object header")?;
 
         let header_parts: Vec<&str> = header.split_whitespace().collect();
         if header_parts.len() != 2 {
@@ -74,33 +113,17 @@ impl Object {
             anyhow::bail!("Object size mismatch");
         }
 
-        Ok(Self {
-            id: object_id.to_string(),
-            object_type,
-            data: content.to_string(),
-            size,
-        })
+        Ok((object_type, content))
     }
 
     fn compress(&self) -> Result<Vec<u8>> {
-        use flate2::write::DeflateEncoder;
-        use flate2::Compression;
-        use std::io::Write;
-
-        let content = format!("{} {}\0{}", self.object_type, self.size, self.data);
-        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(content.as_bytes())?;
-        Ok(encoder.finish()?)
+        let mut data = format!("{} {}\0", self.object_type, self.size).into_bytes();
+        data.extend_from_slice(&self.data);
+        deflate(&data)
     }
 
-    fn decompress(data: &[u8]) -> Result<String> {
-        use flate2::read::DeflateDecoder;
-        use std::io::Read;
-
-        let mut decoder = DeflateDecoder::new(data);
-        let mut content = String::new();
-        decoder.read_to_string(&mut content)?;
-        Ok(content)
+    fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+        inflate(data)
     }
 
     pub fn get_short_id(&self) -> String {
@@ -121,6 +144,27 @@ impl Object {
     }
 }
 
+/// Raw deflate, as loose objects and pack entries are stored.
+pub fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    let mut decoder = DeflateDecoder::new(data);
+    let mut content = Vec::new();
+    decoder.read_to_end(&mut content)?;
+    Ok(content)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tree {
     pub entries: Vec<TreeEntry>,
@@ -150,13 +194,19 @@ impl Tree {
         });
     }
 
+    /// Id of the tree with no entries. Every repository has it implicitly,
+    /// whether or not the object was ever written.
+    pub fn empty_id() -> String {
+        Self::new().to_object().id
+    }
+
     pub fn to_object(&self) -> Object {
         Object::new("tree".to_string(), serde_json::to_string(self).unwrap())
     }
 
     #[allow(dead_code)]
     pub fn from_object(object: &Object) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(&object.data)
+        serde_json::from_slice(&object.data)
     }
 }
 
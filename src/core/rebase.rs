@@ -0,0 +1,125 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebaseAction {
+    Pick,
+    Squash,
+    Fixup,
+}
+
+/// One line of a rebase todo list.
+#[derive(Debug, Clone)]
+pub struct RebaseStep {
+    pub action: RebaseAction,
+    pub commit_id: String,
+    pub subject: String,
+}
+
+impl RebaseStep {
+    pub fn pick(commit_id: &str, subject: &str) -> Self {
+        Self {
+            action: RebaseAction::Pick,
+            commit_id: commit_id.to_string(),
+            subject: subject.to_string(),
+        }
+    }
+
+    /// The action and target named by a `fixup! `/`squash! ` subject. Nested
+    /// prefixes (`fixup! fixup! x`) all point at the innermost target.
+    fn autosquash_target(&self) -> Option<(RebaseAction, &str)> {
+        let (action, target) = strip_autosquash_prefix(&self.subject)?;
+        Some((action, original_subject(target)))
+    }
+}
+
+/// `subject` with any leading `fixup! `/`squash! ` prefixes removed.
+pub fn original_subject(mut subject: &str) -> &str {
+    while let Some((_, rest)) = strip_autosquash_prefix(subject) {
+        subject = rest;
+    }
+    subject
+}
+
+fn strip_autosquash_prefix(subject: &str) -> Option<(RebaseAction, &str)> {
+    if let Some(rest) = subject.strip_prefix("fixup! ") {
+        Some((RebaseAction::Fixup, rest))
+    } else {
+        subject
+            .strip_prefix("squash! ")
+            .map(|rest| (RebaseAction::Squash, rest))
+    }
+}
+
+/// Move each `fixup!`/`squash!` commit right after the earlier commit it
+/// names (by subject or commit id prefix) and mark it accordingly.
+///
+/// `steps` are in replay order, oldest first. Commits whose target isn't in
+/// the list are left as plain picks where they are.
+pub fn autosquash(steps: Vec<RebaseStep>) -> Vec<RebaseStep> {
+    let mut groups: Vec<Vec<RebaseStep>> = Vec::new();
+
+    for mut step in steps {
+        let target_group = step.autosquash_target().and_then(|(action, target)| {
+            groups
+                .iter()
+                .position(|group| {
+                    group.iter().any(|s| {
+                        s.subject == target || (target.len() >= 4 && s.commit_id.starts_with(target))
+                    })
+                })
+                .map(|i| (i, action))
+        });
+
+        match target_group {
+            Some((i, action)) => {
+                step.action = action;
+                groups[i].push(step);
+            }
+            None => groups.push(vec![step]),
+        }
+    }
+
+    groups.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(steps: &[RebaseStep]) -> Vec<(RebaseAction, &str)> {
+        steps.iter().map(|s| (s.action, s.subject.as_str())).collect()
+    }
+
+    #[test]
+    fn fixups_move_after_their_target() {
+        let steps = vec![
+            RebaseStep::pick("aaaa1111", "Add parser"),
+            RebaseStep::pick("bbbb2222", "Add lexer"),
+            RebaseStep::pick("cccc3333", "fixup! Add parser"),
+            RebaseStep::pick("dddd4444", "squash! aaaa1111"),
+            RebaseStep::pick("eeee5555", "fixup! fixup! Add parser"),
+            RebaseStep::pick("ffff6666", "fixup! Not in this rebase"),
+        ];
+        assert_eq!(
+            order(&autosquash(steps)),
+            [
+                (RebaseAction::Pick, "Add parser"),
+                (RebaseAction::Fixup, "fixup! Add parser"),
+                (RebaseAction::Squash, "squash! aaaa1111"),
+                (RebaseAction::Fixup, "fixup! fixup! Add parser"),
+                (RebaseAction::Pick, "Add lexer"),
+                (RebaseAction::Pick, "fixup! Not in this rebase"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixup_before_its_target_stays_a_pick() {
+        let steps = vec![
+            RebaseStep::pick("aaaa1111", "fixup! Add parser"),
+            RebaseStep::pick("bbbb2222", "Add parser"),
+        ];
+        assert_eq!(
+            order(&autosquash(steps)),
+            [(RebaseAction::Pick, "fixup! Add parser"), (RebaseAction::Pick, "Add parser")]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReflogEntry {
+    pub ref_name: String,
+    pub old_commit: Option<String>,
+    pub new_commit: String,
+    pub message: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reflog {
+    pub entries: Vec<ReflogEntry>,
+}
+
+impl Reflog {
+    pub fn path(git_dir: &Path) -> PathBuf {
+        git_dir.join("reflog.json")
+    }
+
+    pub fn load(git_dir: &Path) -> Result<Self> {
+        let path = Self::path(git_dir);
+        if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read reflog")?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, git_dir: &Path) -> Result<()> {
+        fs::write(Self::path(git_dir), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn append(git_dir: &Path, entry: ReflogEntry) -> Result<()> {
+        let mut reflog = Self::load(git_dir)?;
+        reflog.entries.push(entry);
+        reflog.save(git_dir)
+    }
+
+    /// Entries for one ref, newest first.
+    pub fn for_ref(&self, ref_name: &str) -> Vec<&ReflogEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.ref_name == ref_name)
+            .collect()
+    }
+
+    /// Drop entries older than `cutoff`, returning how many were removed.
+    pub fn expire(&mut self, cutoff: chrono::DateTime<chrono::Utc>) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.timestamp >= cutoff);
+        before - self.entries.len()
+    }
+}
@@ -47,7 +47,6 @@ impl Remote {
         self.push_url.as_deref().unwrap_or(&self.url)
     }
 
-    #[allow(dead_code)]
     pub fn update_last_fetch(&mut self) {
         self.last_fetch = Some(chrono::Utc::now());
     }
@@ -1,12 +1,21 @@
 use crate::core::commit::Commit;
-use crate::core::object::Object;
+use crate::core::object::{Object, Tree};
+use crate::core::reflog::{Reflog, ReflogEntry};
+use crate::core::tag::Tags;
+use crate::core::tree_diff::{self, FileDiff, TreeDiffOptions};
 use crate::core::{branch::Branch, index::Index, remote::Remote};
+use crate::utils::config::GlobalConfig;
+use crate::utils::eol::{self, AutoCrlf};
+use crate::utils::file_utils;
+use crate::utils::pack;
+use crate::utils::path_utils::Attributes;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs,
     path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,10 +25,26 @@ pub struct Repository {
     pub config: RepositoryConfig,
     pub index: Index,
     pub branches: HashMap<String, Branch>,
+    /// `HEAD` while detached; see `detached_head`
     pub current_branch: String,
     pub remotes: HashMap<String, Remote>,
+    /// Commit checked out directly rather than through a branch
+    #[serde(default)]
+    pub detached_head: Option<String>,
+    /// `core.autocrlf`, resolved on first use
+    #[serde(skip)]
+    autocrlf: OnceLock<AutoCrlf>,
+    /// `.helixattributes`, parsed on first use
+    #[serde(skip)]
+    attributes: OnceLock<Attributes>,
 }
 
+/// What `current_branch` holds while HEAD is detached.
+pub const DETACHED_HEAD: &str = "HEAD";
+
+/// `.helix/HEAD` holds a branch name, or this followed by a commit id.
+const DETACHED_MARKER: &str = "detached:";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepositoryConfig {
     pub name: String,
@@ -27,6 +52,53 @@ pub struct RepositoryConfig {
     pub author: String,
     pub email: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Repository-level `core.autocrlf`; falls back to the global setting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autocrlf: Option<String>,
+}
+
+impl RepositoryConfig {
+    /// Keys a repository can set for itself, overriding the global config.
+    pub const KEYS: [&'static str; 3] = ["user.name", "user.email", "core.autocrlf"];
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "user.name" => self.author = value.to_string(),
+            "user.email" => self.email = value.to_string(),
+            "core.autocrlf" => {
+                AutoCrlf::parse(value)?;
+                self.autocrlf = Some(value.to_string());
+            }
+            _ => anyhow::bail!("{} can only be set globally", key),
+        }
+        Ok(())
+    }
+
+    /// The repository's own value for `key`; the defaults `init` writes
+    /// count as unset.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "user.name" => Some(self.author.as_str()).filter(|v| *v != "Unknown"),
+            "user.email" => Some(self.email.as_str()).filter(|v| *v != "unknown@example.com"),
+            "core.autocrlf" => self.autocrlf.as_deref(),
+            _ => None,
+        };
+        value.filter(|v| !v.is_empty()).map(str::to_string)
+    }
+
+    /// The value `hx config --get` shows: the repository's own, else the
+    /// global one.
+    pub fn get_or_global(&self, global: &GlobalConfig, key: &str) -> Result<Option<String>> {
+        let global_value = global.get(key)?;
+        Ok(self.get(key).or(global_value))
+    }
+
+    pub fn entries(&self) -> Vec<(String, String)> {
+        Self::KEYS
+            .iter()
+            .filter_map(|key| Some((key.to_string(), self.get(key)?)))
+            .collect()
+    }
 }
 
 impl Repository {
@@ -39,9 +111,10 @@ impl Repository {
                 .to_string_lossy()
                 .to_string(),
             description: None,
-            author: std::env::var("HX_AUTHOR").unwrap_or_else(|_| "Unknown".to_string()),
-            email: std::env::var("HX_EMAIL").unwrap_or_else(|_| "unknown@example.com".to_string()),
+            author: "Unknown".to_string(),
+            email: "unknown@example.com".to_string(),
             created_at: chrono::Utc::now(),
+            autocrlf: None,
         };
 
         Ok(Self {
@@ -52,6 +125,9 @@ impl Repository {
             branches: HashMap::new(),
             current_branch: "main".to_string(),
             remotes: HashMap::new(),
+            detached_head: None,
+            autocrlf: OnceLock::new(),
+            attributes: OnceLock::new(),
         })
     }
 
@@ -94,6 +170,10 @@ impl Repository {
         } else {
             "main".to_string()
         };
+        let (current_branch, detached_head) = match current_branch.strip_prefix(DETACHED_MARKER) {
+            Some(commit_id) => (DETACHED_HEAD.to_string(), Some(commit_id.trim().to_string())),
+            None => (current_branch, None),
+        };
 
         let remotes_path = git_dir.join("remotes.json");
         let remotes: HashMap<String, Remote> = if remotes_path.exists() {
@@ -112,6 +192,9 @@ impl Repository {
             branches,
             current_branch,
             remotes,
+            detached_head,
+            autocrlf: OnceLock::new(),
+            attributes: OnceLock::new(),
         })
     }
 
@@ -134,9 +217,12 @@ impl Repository {
             serde_json::to_string_pretty(&self.branches)?,
         )?;
 
-        // Save current branch
+        // Save current branch, or the commit of a detached HEAD
         let head_path = self.git_dir.join("HEAD");
-        fs::write(&head_path, &self.current_branch)?;
+        match &self.detached_head {
+            Some(commit_id) => fs::write(&head_path, format!("{}{}", DETACHED_MARKER, commit_id))?,
+            None => fs::write(&head_path, &self.current_branch)?,
+        }
 
         // Save remotes
         let remotes_path = self.git_dir.join("remotes.json");
@@ -145,14 +231,56 @@ impl Repository {
         Ok(())
     }
 
+    /// Name and email to record: the repository's, then the global
+    /// config's, then `HX_AUTHOR` / `HX_EMAIL`, where the repository has
+    /// only the defaults.
+    pub fn identity(&self) -> (String, String) {
+        let global_config = GlobalConfig::load().unwrap_or_default();
+        self.identity_from(&global_config, |var| std::env::var(var).ok())
+    }
+
+    /// `identity` against a given global config and environment.
+    fn identity_from<F>(&self, global_config: &GlobalConfig, env: F) -> (String, String)
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let lookup = |key: &str, var: &str| {
+            self.config
+                .get(key)
+                .or_else(|| global_config.get(key).ok().flatten())
+                .or_else(|| env(var).filter(|v| !v.is_empty()))
+        };
+        (
+            lookup("user.name", "HX_AUTHOR").unwrap_or_else(|| "Unknown".to_string()),
+            lookup("user.email", "HX_EMAIL").unwrap_or_else(|| "unknown@example.com".to_string()),
+        )
+    }
+
     pub fn get_current_branch(&self) -> Option<&Branch> {
         self.branches.get(&self.current_branch)
     }
 
+    #[allow(dead_code)]
     pub fn get_current_branch_mut(&mut self) -> Option<&mut Branch> {
         self.branches.get_mut(&self.current_branch)
     }
 
+    /// The commit HEAD points at, through the current branch or directly
+    /// when detached.
+    pub fn head_commit(&self) -> Option<&String> {
+        match &self.detached_head {
+            Some(commit_id) => Some(commit_id),
+            None => self.get_current_branch().and_then(|b| b.get_head_commit()),
+        }
+    }
+
+    /// Point HEAD straight at `commit_id`, leaving every branch alone.
+    pub fn detach_head(&mut self, commit_id: &str) -> Result<()> {
+        self.current_branch = DETACHED_HEAD.to_string();
+        self.detached_head = Some(commit_id.to_string());
+        self.save()
+    }
+
     pub fn create_branch(&mut self, name: &str) -> Result<()> {
         if self.branches.contains_key(name) {
             anyhow::bail!("Branch '{}' already exists", name);
@@ -171,12 +299,16 @@ impl Repository {
         }
 
         self.current_branch = name.to_string();
+        self.detached_head = None;
         self.save()?;
 
         Ok(())
     }
 
     pub fn add_remote(&mut self, name: &str, url: &str) -> Result<()> {
+        if self.remotes.contains_key(name) {
+            anyhow::bail!("Remote '{}' already exists", name);
+        }
         let remote = Remote::new(name, url);
         self.remotes.insert(name.to_string(), remote);
         self.save()?;
@@ -184,21 +316,475 @@ impl Repository {
         Ok(())
     }
 
+    /// Forget `name`, its remote-tracking refs and any upstreams set to it.
+    pub fn remove_remote(&mut self, name: &str) -> Result<()> {
+        if self.remotes.remove(name).is_none() {
+            anyhow::bail!("No such remote: '{}'", name);
+        }
+        let refs_dir = self.get_refs_dir().join("remotes").join(name);
+        if refs_dir.exists() {
+            fs::remove_dir_all(&refs_dir)?;
+        }
+        let prefix = format!("{}/", name);
+        for branch in self.branches.values_mut() {
+            if branch.get_upstream().is_some_and(|u| u.starts_with(&prefix)) {
+                branch.unset_upstream();
+            }
+        }
+        self.save()
+    }
+
+    /// Rename remote `old` to `new`, moving its remote-tracking refs and
+    /// the upstreams that point at it along.
+    pub fn rename_remote(&mut self, old: &str, new: &str) -> Result<()> {
+        if self.remotes.contains_key(new) {
+            anyhow::bail!("Remote '{}' already exists", new);
+        }
+        let mut remote = self
+            .remotes
+            .remove(old)
+            .ok_or_else(|| anyhow::anyhow!("No such remote: '{}'", old))?;
+        remote.name = new.to_string();
+        self.remotes.insert(new.to_string(), remote);
+
+        let remotes_dir = self.get_refs_dir().join("remotes");
+        if remotes_dir.join(old).exists() {
+            fs::rename(remotes_dir.join(old), remotes_dir.join(new))?;
+        }
+        let prefix = format!("{}/", old);
+        for branch in self.branches.values_mut() {
+            let renamed = branch
+                .get_upstream()
+                .and_then(|u| u.strip_prefix(&prefix))
+                .map(|rest| format!("{}/{}", new, rest));
+            if let Some(upstream) = renamed {
+                branch.set_upstream(upstream);
+            }
+        }
+        self.save()
+    }
+
     pub fn get_objects_dir(&self) -> PathBuf {
         self.git_dir.join("objects")
     }
 
+    /// Whether an object is stored locally, without reading it.
+    pub fn object_exists(&self, hash: &str) -> bool {
+        let objects_dir = self.get_objects_dir();
+        hash.len() > 2
+            && (objects_dir.join(&hash[..2]).join(&hash[2..]).is_file()
+                || pack::is_packed(&objects_dir, hash))
+    }
+
     pub fn get_refs_dir(&self) -> PathBuf {
         self.git_dir.join("refs")
     }
 
+    /// Remote-tracking ref for `branch` on `remote`, stored in
+    /// `.helix/refs/remotes/<remote>/<branch>`.
+    pub fn get_remote_ref(&self, remote: &str, branch: &str) -> Option<String> {
+        let path = self.get_refs_dir().join("remotes").join(remote).join(branch);
+        fs::read_to_string(path)
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Resolve a `<remote>/<branch>` name to its remote-tracking ref.
+    pub fn get_tracking_ref(&self, name: &str) -> Option<String> {
+        let (remote, branch) = name.split_once('/')?;
+        if !self.remotes.contains_key(remote) {
+            return None;
+        }
+        self.get_remote_ref(remote, branch)
+    }
+
+    /// Every remote-tracking ref, keyed `<remote>/<branch>`.
+    pub fn remote_tracking_refs(&self) -> BTreeMap<String, String> {
+        fn walk(dir: &Path, prefix: &str, refs: &mut BTreeMap<String, String>) {
+            let Ok(entries) = fs::read_dir(dir) else { return };
+            for entry in entries.flatten() {
+                let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+                let path = entry.path();
+                if path.is_dir() {
+                    walk(&path, &format!("{}/", name), refs);
+                } else if let Ok(id) = fs::read_to_string(&path) {
+                    let id = id.trim();
+                    if !id.is_empty() {
+                        refs.insert(name, id.to_string());
+                    }
+                }
+            }
+        }
+        let mut refs = BTreeMap::new();
+        for remote in self.remotes.keys() {
+            let dir = self.get_refs_dir().join("remotes").join(remote);
+            walk(&dir, &format!("{}/", remote), &mut refs);
+        }
+        refs
+    }
+
+    pub fn set_remote_ref(&self, remote: &str, branch: &str, commit_id: &str) -> Result<()> {
+        let path = self.get_refs_dir().join("remotes").join(remote).join(branch);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, format!("{}\n", commit_id))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Boundary commits of a shallow history, listed in `.helix/shallow`.
+    /// Their parents were deliberately not fetched.
+    pub fn shallow_commits(&self) -> BTreeSet<String> {
+        fs::read_to_string(self.git_dir.join("shallow"))
+            .map(|content| {
+                content
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_shallow_commits(&self, commits: &BTreeSet<String>) -> Result<()> {
+        let path = self.git_dir.join("shallow");
+        if commits.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        let content: String = commits.iter().map(|id| format!("{}\n", id)).collect();
+        fs::write(&path, content)?;
+        Ok(())
+    }
+
     pub fn get_commit_object(&self, commit_id: &str) -> anyhow::Result<Commit> {
         let obj = Object::load(&self.get_objects_dir(), commit_id)?;
         Commit::from_object(&obj).map_err(|e| anyhow::anyhow!(e))
     }
 
+    /// Path -> blob hash for a revision's files, or for a tree object id.
+    pub fn tree_files_of(&self, rev: &str) -> Result<BTreeMap<String, String>> {
+        if rev == Tree::empty_id() {
+            return Ok(BTreeMap::new());
+        }
+        // A full tree id needs no resolving
+        let full_id = rev.len() == 64 && rev.chars().all(|c| c.is_ascii_hexdigit());
+        let tree = full_id
+            .then(|| Object::load(&self.get_objects_dir(), rev).ok())
+            .flatten()
+            .filter(|object| object.is_tree());
+        let object = match tree {
+            Some(object) => object,
+            None => {
+                if let Ok(commit_id) = self.resolve_rev(rev) {
+                    return Ok(self.get_commit_object(&commit_id)?.tree_files());
+                }
+                Object::load(&self.get_objects_dir(), rev)
+                    .with_context(|| format!("'{}' does not name a commit or tree", rev))?
+            }
+        };
+        if !object.is_tree() {
+            anyhow::bail!("'{}' does not name a commit or tree", rev);
+        }
+        Ok(Tree::from_object(&object)?
+            .entries
+            .into_iter()
+            .filter(|entry| entry.object_type == "blob")
+            .map(|entry| (entry.name, entry.object_id))
+            .collect())
+    }
+
+    /// Changes from `a` to `b` (revisions or tree ids), with renames paired.
+    pub fn diff_trees(&self, a: &str, b: &str) -> Result<Vec<FileDiff>> {
+        self.diff_trees_with(
+            a,
+            b,
+            &TreeDiffOptions {
+                find_renames: true,
+                find_copies: false,
+                rename_threshold: None,
+            },
+        )
+    }
+
+    pub fn diff_trees_with(
+        &self,
+        a: &str,
+        b: &str,
+        options: &TreeDiffOptions,
+    ) -> Result<Vec<FileDiff>> {
+        let old = self.tree_files_of(a)?;
+        let new = self.tree_files_of(b)?;
+        let objects_dir = self.get_objects_dir();
+        Ok(tree_diff::diff_file_maps(&old, &new, options, |hash| {
+            Object::load(&objects_dir, hash).ok().map(|o| o.text())
+        }))
+    }
+
+    /// Path -> blob hash for the files of the current HEAD commit.
+    pub fn head_files(&self) -> BTreeMap<String, String> {
+        self.head_commit()
+            .and_then(|id| self.get_commit_object(id).ok())
+            .map(|commit| commit.tree_files())
+            .unwrap_or_default()
+    }
+
+    /// Line-ending policy: the repository's `core.autocrlf`, then the
+    /// global one, then `false`. Unparseable values count as `false`.
+    /// Resolved once, since every file added or written out asks.
+    pub fn autocrlf(&self) -> AutoCrlf {
+        *self.autocrlf.get_or_init(|| {
+            self.config
+                .autocrlf
+                .clone()
+                .or_else(|| GlobalConfig::load().ok()?.get_core_autocrlf().map(str::to_string))
+                .and_then(|value| AutoCrlf::parse(&value).ok())
+                .unwrap_or_default()
+        })
+    }
+
+    /// The `.helixattributes` rules, read once per command.
+    pub fn attributes(&self) -> &Attributes {
+        self.attributes.get_or_init(|| Attributes::load(&self.path))
+    }
+
+    /// Blob id the working tree copy of `relative_path` would get if added,
+    /// or `None` if the file doesn't exist.
+    pub fn worktree_blob_id(&self, relative_path: &str) -> Option<String> {
+        let content = fs::read(self.path.join(relative_path)).ok()?;
+        let content = eol::to_repo(self, relative_path, content);
+        Some(Object::new("blob".to_string(), content).id)
+    }
+
+    /// Paths whose working tree content differs from HEAD and would be lost
+    /// by writing `target_tree` (path -> blob id) over the working tree:
+    /// every path HEAD or the target knows about, unless the local copy
+    /// already matches the target.
+    pub fn would_overwrite_changes(&self, target_tree: &BTreeMap<String, String>) -> Vec<String> {
+        let head_files = self.head_files();
+        let mut paths: Vec<&String> = head_files.keys().chain(target_tree.keys()).collect();
+        paths.sort();
+        paths.dedup();
+
+        paths
+            .into_iter()
+            .filter(|path| {
+                let local = self.worktree_blob_id(path);
+                local.as_ref() != head_files.get(*path) && local.as_ref() != target_tree.get(*path)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Make the working tree match `new_tree` when it currently reflects
+    /// `old_tree` (both path -> blob id): write every path whose content
+    /// differs and remove paths only `old_tree` has. Returns the number of
+    /// paths written and removed.
+    pub fn update_worktree(
+        &self,
+        old_tree: &BTreeMap<String, String>,
+        new_tree: &BTreeMap<String, String>,
+    ) -> Result<(usize, usize)> {
+        let objects_dir = self.get_objects_dir();
+        let mut written = 0;
+        for (path, blob_id) in new_tree {
+            if self.worktree_blob_id(path).as_ref() == Some(blob_id) {
+                continue;
+            }
+            let blob = Object::load(&objects_dir, blob_id)?;
+            let content = eol::to_worktree(self, path, &blob.data);
+            file_utils::write_file_content(&self.path.join(path), &content)
+                .with_context(|| format!("Failed to write {}", path))?;
+            written += 1;
+        }
+
+        let mut removed = 0;
+        for path in old_tree.keys().filter(|path| !new_tree.contains_key(*path)) {
+            let full_path = self.path.join(path);
+            if full_path.is_file() {
+                fs::remove_file(&full_path)?;
+                removed += 1;
+            }
+            // Drop directories the removal left empty
+            let mut dir = full_path.parent();
+            while let Some(d) = dir.filter(|d| *d != self.path) {
+                if fs::remove_dir(d).is_err() {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+        Ok((written, removed))
+    }
+
+    /// Fail with the list of clobbered paths unless `force` is set.
+    pub fn check_overwrite(&self, target_tree: &BTreeMap<String, String>, force: bool) -> Result<()> {
+        let clobbered = self.would_overwrite_changes(target_tree);
+        if force || clobbered.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Your local changes to the following files would be overwritten:\n  {}\nCommit or stash them, or use --force to discard them",
+            clobbered.join("\n  ")
+        )
+    }
+
+    /// Fail unless nothing is staged and every tracked file matches HEAD;
+    /// `operation` names what needs the clean tree in the error.
+    pub fn require_clean(&self, operation: &str) -> Result<()> {
+        let mut dirty = self.would_overwrite_changes(&self.head_files());
+        dirty.extend(self.index.get_file_paths());
+        dirty.extend(self.index.deletions.iter().cloned());
+        dirty.sort();
+        dirty.dedup();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Cannot {}: you have local changes to\n  {}\nCommit or stash them first",
+            operation,
+            dirty.join("\n  ")
+        )
+    }
+
+    /// Record a HEAD movement in the reflog.
+    pub fn log_ref_update(&self, old_commit: Option<&str>, new_commit: &str, message: &str) -> Result<()> {
+        Reflog::append(
+            &self.git_dir,
+            ReflogEntry {
+                ref_name: "HEAD".to_string(),
+                old_commit: old_commit.map(|c| c.to_string()),
+                new_commit: new_commit.to_string(),
+                message: message.to_string(),
+                timestamp: chrono::Utc::now(),
+            },
+        )
+    }
+
+    /// The commit being merged in while a conflicted merge awaits a commit.
+    pub fn get_merge_head(&self) -> Option<String> {
+        fs::read_to_string(self.git_dir.join("MERGE_HEAD"))
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+    }
+
+    pub fn set_merge_head(&self, commit_id: Option<&str>) -> Result<()> {
+        let merge_head_path = self.git_dir.join("MERGE_HEAD");
+        match commit_id {
+            Some(id) => fs::write(&merge_head_path, id)?,
+            None => {
+                if merge_head_path.exists() {
+                    fs::remove_file(&merge_head_path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve `HEAD`, a branch name, or a (possibly abbreviated) commit hash
+    /// to a full commit id. Any of these may be followed by `~<n>` (n-th
+    /// first-parent ancestor) and `^<n>` (n-th parent) suffixes.
+    pub fn resolve_rev(&self, rev: &str) -> Result<String> {
+        let (base, mut suffix) = match rev.find(['~', '^']) {
+            Some(pos) => rev.split_at(pos),
+            None => (rev, ""),
+        };
+        let mut commit_id = self.resolve_base_rev(base)?;
+
+        while let Some(op) = suffix.chars().next() {
+            let digits = suffix[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(suffix.len(), |i| i + 1);
+            let count = match &suffix[1..digits] {
+                "" => 1,
+                n => n
+                    .parse::<usize>()
+                    .map_err(|_| anyhow::anyhow!("Invalid revision '{}'", rev))?,
+            };
+            suffix = &suffix[digits..];
+
+            let (steps, parent_index) = match op {
+                '~' => (count, 1),
+                '^' if count == 0 => (0, 1),
+                _ => (1, count),
+            };
+            for _ in 0..steps {
+                let commit = self.get_commit_object(&commit_id)?;
+                commit_id = commit
+                    .parent_ids
+                    .get(parent_index - 1)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Revision '{}' does not exist", rev))?;
+            }
+        }
+
+        Ok(commit_id)
+    }
+
+    fn resolve_base_rev(&self, rev: &str) -> Result<String> {
+        if rev == "HEAD" {
+            return self
+                .head_commit()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("HEAD does not point to a commit"));
+        }
+
+        if let Some(branch) = self.branches.get(rev) {
+            return branch
+                .get_head_commit()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Branch '{}' has no commits", rev));
+        }
+
+        if let Some(remote_head) = self.get_tracking_ref(rev) {
+            return Ok(remote_head);
+        }
+
+        if let Some(target) = Tags::load(&self.git_dir)
+            .ok()
+            .and_then(|tags| tags.target(&self.get_objects_dir(), rev))
+        {
+            return Ok(target);
+        }
+
+        if rev.len() < 4 || !rev.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!("Unknown revision '{}'", rev);
+        }
+
+        let rev = rev.to_lowercase();
+        let shard = self.get_objects_dir().join(&rev[..2]);
+        let mut matches = Vec::new();
+        if let Ok(entries) = fs::read_dir(&shard) {
+            for entry in entries.flatten() {
+                let id = format!("{}{}", &rev[..2], entry.file_name().to_string_lossy());
+                if id.starts_with(&rev) && self.get_commit_object(&id).is_ok() {
+                    matches.push(id);
+                }
+            }
+        }
+        for id in pack::packed_ids(&self.get_objects_dir()) {
+            if id.starts_with(&rev) && !matches.contains(&id) && self.get_commit_object(&id).is_ok() {
+                matches.push(id);
+            }
+        }
+
+        match matches.len() {
+            0 => anyhow::bail!("Unknown revision '{}'", rev),
+            1 => Ok(matches.remove(0)),
+            _ => anyhow::bail!("Ambiguous revision '{}'", rev),
+        }
+    }
+
     pub fn set_head(&mut self, commit_id: &str) -> anyhow::Result<()> {
-        if let Some(branch) = self.branches.get_mut(&self.current_branch) {
+        if self.detached_head.is_some() {
+            self.detached_head = Some(commit_id.to_string());
+            self.save()
+        } else if let Some(branch) = self.branches.get_mut(&self.current_branch) {
             branch.set_head_commit(commit_id.to_string());
             self.save()?;
             Ok(())
@@ -207,3 +793,195 @@ impl Repository {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Repository;
+    use crate::core::object::Tree;
+    use crate::core::tree_diff::DiffStatus;
+    use crate::utils::test_utils::TestRepo;
+
+    fn tree_of(test: &TestRepo, commit: &str) -> String {
+        test.repo.get_commit_object(commit).unwrap().tree_id
+    }
+
+    #[tokio::test]
+    async fn diff_trees_reports_added_modified_and_deleted() {
+        let mut test = TestRepo::new();
+        test.write("keep.txt", "one\n");
+        test.write("gone.txt", "bye\n");
+        let first = test.commit("first").await;
+        test.write("keep.txt", "two\n");
+        test.write("new.txt", "hello\n");
+        test.remove("gone.txt");
+        let second = test.commit("second").await;
+
+        let changes = test.repo.diff_trees(&tree_of(&test, &first), &tree_of(&test, &second)).unwrap();
+        let summary: Vec<(char, &str)> = changes.iter().map(|d| (d.letter(), d.path.as_str())).collect();
+        assert_eq!(summary, vec![('D', "gone.txt"), ('M', "keep.txt"), ('A', "new.txt")]);
+    }
+
+    #[tokio::test]
+    async fn diff_trees_pairs_renames() {
+        let mut test = TestRepo::new();
+        test.write("old.txt", "a\nb\nc\nd\ne\n");
+        let first = test.commit("first").await;
+        test.remove("old.txt");
+        test.write("moved.txt", "a\nb\nc\nd\ne\n");
+        let second = test.commit("second").await;
+
+        let changes = test.repo.diff_trees(&first, &second).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "moved.txt");
+        assert!(matches!(
+            &changes[0].status,
+            DiffStatus::Renamed { old_path, similarity: 100 } if old_path == "old.txt"
+        ));
+    }
+
+    #[tokio::test]
+    async fn root_commit_diffs_against_the_empty_tree() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        test.write("b.txt", "b\n");
+        let root = test.commit("root").await;
+
+        let changes = test.repo.diff_trees(&Tree::empty_id(), &tree_of(&test, &root)).unwrap();
+        assert!(changes.iter().all(|d| d.status == DiffStatus::Added));
+        let paths: Vec<&str> = changes.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "b.txt"]);
+    }
+
+    #[tokio::test]
+    async fn remotes_round_trip_through_remotes_json() {
+        let mut test = TestRepo::new();
+        test.write("a", "1\n");
+        let head = test.commit("first").await;
+        let reopen = |test: &TestRepo| Repository::open(test.repo.path.to_str().unwrap()).unwrap();
+
+        test.repo.add_remote("origin", "https://example.com/repo").unwrap();
+        test.repo.add_remote("backup", "ssh://backup/repo").unwrap();
+        assert!(test.repo.add_remote("origin", "https://elsewhere").is_err());
+        let mut names: Vec<String> = reopen(&test).remotes.into_keys().collect();
+        names.sort();
+        assert_eq!(names, ["backup", "origin"]);
+
+        test.repo.set_remote_ref("origin", "main", &head).unwrap();
+        test.repo.branches.get_mut("main").unwrap().set_upstream("origin/main".to_string());
+        test.repo.rename_remote("origin", "upstream").unwrap();
+        assert!(test.repo.rename_remote("backup", "upstream").is_err());
+        let renamed = reopen(&test);
+        assert!(!renamed.remotes.contains_key("origin"));
+        let remote = &renamed.remotes["upstream"];
+        assert_eq!((remote.name.as_str(), remote.url.as_str()), ("upstream", "https://example.com/repo"));
+        assert_eq!(renamed.get_remote_ref("upstream", "main"), Some(head));
+        assert_eq!(renamed.branches["main"].get_upstream().map(String::as_str), Some("upstream/main"));
+
+        test.repo.remove_remote("upstream").unwrap();
+        assert!(test.repo.remove_remote("upstream").is_err());
+        let removed = reopen(&test);
+        assert_eq!(removed.remotes.keys().collect::<Vec<_>>(), ["backup"]);
+        assert_eq!(removed.get_remote_ref("upstream", "main"), None);
+        assert!(removed.branches["main"].get_upstream().is_none());
+    }
+
+    #[test]
+    fn identity_prefers_repo_then_global_then_env() {
+        use crate::utils::config::GlobalConfig;
+        use std::collections::HashMap;
+
+        let mut test = TestRepo::new();
+        let env: HashMap<&str, String> =
+            [("HX_AUTHOR", "Env".to_string()), ("HX_EMAIL", "env@example.com".to_string())].into();
+        let env = |var: &str| env.get(var).cloned();
+        let mut global = GlobalConfig::default();
+        global.set("user.name", "Global").unwrap();
+        global.set("user.email", "global@example.com").unwrap();
+
+        let identity = |test: &TestRepo, global: &GlobalConfig| test.repo.identity_from(global, env);
+        assert_eq!(identity(&test, &global), ("Test".into(), "test@example.com".into()));
+
+        // The values `init` writes count as unset
+        test.repo.config.author = "Unknown".into();
+        assert_eq!(identity(&test, &global), ("Global".into(), "test@example.com".into()));
+        test.repo.config.email = "unknown@example.com".into();
+        assert_eq!(identity(&test, &global), ("Global".into(), "global@example.com".into()));
+
+        let empty = GlobalConfig::default();
+        assert_eq!(identity(&test, &empty), ("Env".into(), "env@example.com".into()));
+        assert_eq!(
+            test.repo.identity_from(&empty, |_| Some(String::new())),
+            ("Unknown".into(), "unknown@example.com".into())
+        );
+    }
+
+    #[test]
+    fn repo_config_sets_gets_lists_and_overrides_global() {
+        use crate::utils::config::GlobalConfig;
+
+        let mut test = TestRepo::new();
+        let mut global = GlobalConfig::default();
+        global.set("user.name", "Global").unwrap();
+        global.set("core.pager", "less -R").unwrap();
+
+        let config = &mut test.repo.config;
+        config.set("core.autocrlf", "input").unwrap();
+        assert!(config.set("core.autocrlf", "sometimes").is_err());
+        assert!(config.set("core.pager", "more").is_err(), "pager is global only");
+        assert_eq!(
+            config.entries(),
+            vec![
+                ("user.name".to_string(), "Test".to_string()),
+                ("user.email".to_string(), "test@example.com".to_string()),
+                ("core.autocrlf".to_string(), "input".to_string()),
+            ]
+        );
+
+        assert_eq!(config.get_or_global(&global, "user.name").unwrap().as_deref(), Some("Test"));
+        assert_eq!(config.get_or_global(&global, "core.pager").unwrap().as_deref(), Some("less -R"));
+        assert!(config.get_or_global(&global, "no.such").is_err());
+        config.author = "Unknown".into();
+        assert_eq!(config.get_or_global(&global, "user.name").unwrap().as_deref(), Some("Global"));
+
+        // Repository values survive a reload
+        test.repo.save().unwrap();
+        let reopened = Repository::open(test.repo.path.to_str().unwrap()).unwrap();
+        assert_eq!(reopened.config.get("core.autocrlf").as_deref(), Some("input"));
+    }
+
+    #[tokio::test]
+    async fn short_ids_resolve_when_unique() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let first = test.commit("first").await;
+        test.write("a.txt", "b\n");
+        let second = test.commit("second").await;
+
+        assert_eq!(test.repo.resolve_rev(&first[..7]).unwrap(), first);
+        assert_eq!(test.repo.resolve_rev(&second[..12].to_uppercase()).unwrap(), second);
+        assert_eq!(test.repo.resolve_rev("main").unwrap(), second);
+        assert_eq!(test.repo.resolve_rev("HEAD~1").unwrap(), first);
+        assert_eq!(test.repo.resolve_rev(&format!("{}^", &second[..8])).unwrap(), first);
+    }
+
+    #[tokio::test]
+    async fn ambiguous_and_unknown_revisions_are_errors() {
+        let mut test = TestRepo::new();
+        test.write("a.txt", "a\n");
+        let id = test.commit("first").await;
+
+        // A second commit object sharing all but the last character
+        let shard = test.repo.get_objects_dir().join(&id[..2]);
+        let last = if id.ends_with('0') { "1" } else { "0" };
+        let twin = format!("{}{}", &id[2..63], last);
+        std::fs::copy(shard.join(&id[2..]), shard.join(twin)).unwrap();
+
+        let err = test.repo.resolve_rev(&id[..8]).unwrap_err().to_string();
+        assert!(err.contains("Ambiguous"), "{}", err);
+        assert_eq!(test.repo.resolve_rev(&id).unwrap(), id);
+
+        for unknown in ["no-such-branch", "abc", "deadbeefdeadbeef", &format!("{}~5", id)] {
+            assert!(test.repo.resolve_rev(unknown).is_err(), "{} resolved", unknown);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StashEntry {
+    pub message: String,
+    pub branch: String,
+    /// HEAD when the stash was made
+    pub base_commit: Option<String>,
+    /// Path -> working tree blob; `None` when the file had been deleted
+    pub files: BTreeMap<String, Option<String>>,
+    /// Paths that were staged when stashed
+    pub staged: BTreeSet<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Stash stack stored in `.helix/stash.json`; `stash@{0}` is the newest entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StashList {
+    pub entries: Vec<StashEntry>,
+}
+
+impl StashList {
+    pub fn path(git_dir: &Path) -> PathBuf {
+        git_dir.join("stash.json")
+    }
+
+    pub fn load(git_dir: &Path) -> Result<Self> {
+        let path = Self::path(git_dir);
+        if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read stash")?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, git_dir: &Path) -> Result<()> {
+        fs::write(Self::path(git_dir), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn push(&mut self, entry: StashEntry) {
+        self.entries.insert(0, entry);
+    }
+
+    pub fn get(&self, index: usize) -> Result<&StashEntry> {
+        self.entries
+            .get(index)
+            .ok_or_else(|| anyhow::anyhow!("stash@{{{}}} does not exist", index))
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<StashEntry> {
+        self.get(index)?;
+        Ok(self.entries.remove(index))
+    }
+
+    /// Parse `stash@{n}` or a bare `n`; `None` means the newest entry.
+    pub fn parse_ref(stash_ref: Option<&str>) -> Result<usize> {
+        let stash_ref = match stash_ref {
+            Some(r) => r,
+            None => return Ok(0),
+        };
+        let index = stash_ref
+            .strip_prefix("stash@{")
+            .and_then(|r| r.strip_suffix('}'))
+            .unwrap_or(stash_ref);
+        index
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid stash reference '{}'", stash_ref))
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Index/commit mode marking a submodule entry, whose hash is the pinned
+/// commit of the nested repository rather than a blob.
+pub const GITLINK_MODE: u32 = 0o160000;
+
+pub const MODULES_FILE: &str = ".helixmodules";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmoduleEntry {
+    pub url: String,
+    pub commit: String,
+}
+
+/// Submodules recorded in the tracked `.helixmodules` file, keyed by path:
+///
+/// ```toml
+/// [submodule."vendor/lib"]
+/// url = "https://example.com/lib"
+/// commit = "<pinned commit id>"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Submodules {
+    #[serde(default)]
+    pub submodule: BTreeMap<String, SubmoduleEntry>,
+}
+
+impl Submodules {
+    pub fn path(repo_path: &Path) -> PathBuf {
+        repo_path.join(MODULES_FILE)
+    }
+
+    pub fn load(repo_path: &Path) -> Result<Self> {
+        let path = Self::path(repo_path);
+        if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read .helixmodules")?;
+            Ok(toml::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, repo_path: &Path) -> Result<()> {
+        fs::write(Self::path(repo_path), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
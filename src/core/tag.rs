@@ -0,0 +1,167 @@
+use crate::core::object::Object;
+use crate::utils::key_utils;
+use anyhow::{Context, Result};
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Annotated tag, stored as an object of type `tag`. Its ref in
+/// `tags.json` points at this object rather than at the commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tag {
+    pub name: String,
+    /// Tagged commit id
+    pub target: String,
+    pub message: Option<String>,
+    /// "Name <email>"
+    pub tagger: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub public_key: Option<Vec<u8>>,
+    pub signature: Option<Vec<u8>>,
+}
+
+impl Tag {
+    pub fn new(name: &str, target: &str, message: Option<&str>, tagger: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            target: target.to_string(),
+            message: message.map(|m| m.to_string()),
+            tagger: tagger.to_string(),
+            timestamp: chrono::Utc::now(),
+            public_key: None,
+            signature: None,
+        }
+    }
+
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = format!(
+            "object {}\ntag {}\ntagger {} {}\n\n",
+            self.target,
+            self.name,
+            self.tagger,
+            self.timestamp.timestamp()
+        );
+        payload.push_str(self.message.as_deref().unwrap_or(""));
+        payload.into_bytes()
+    }
+
+    pub fn sign(&mut self, keypair: &SigningKey) {
+        let (public_key, signature) = key_utils::sign_payload(keypair, &self.signing_payload());
+        self.public_key = Some(public_key);
+        self.signature = Some(signature);
+    }
+
+    pub fn verify(&self) -> bool {
+        match (&self.public_key, &self.signature) {
+            (Some(pk), Some(sig)) => key_utils::verify_payload(pk, sig, &self.signing_payload()),
+            _ => false,
+        }
+    }
+
+    pub fn to_object(&self) -> Object {
+        Object::new("tag".to_string(), serde_json::to_string(self).unwrap())
+    }
+
+    /// The annotated tag stored under `id`, or `None` if `id` is anything
+    /// else (such as the commit a lightweight tag points at).
+    pub fn load(objects_dir: &Path, id: &str) -> Option<Self> {
+        let object = Object::load(objects_dir, id).ok()?;
+        if object.object_type != "tag" {
+            return None;
+        }
+        serde_json::from_slice(&object.data).ok()
+    }
+}
+
+/// Tag refs stored in `.helix/tags.json` as a name -> target id map.
+#[derive(Debug, Clone, Default)]
+pub struct Tags {
+    pub refs: BTreeMap<String, String>,
+}
+
+impl Tags {
+    pub fn path(git_dir: &Path) -> PathBuf {
+        git_dir.join("tags.json")
+    }
+
+    pub fn load(git_dir: &Path) -> Result<Self> {
+        let path = Self::path(git_dir);
+        let refs = if path.exists() {
+            let content = fs::read_to_string(&path).context("Failed to read tags")?;
+            serde_json::from_str(&content)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self { refs })
+    }
+
+    pub fn save(&self, git_dir: &Path) -> Result<()> {
+        fs::write(Self::path(git_dir), serde_json::to_string_pretty(&self.refs)?)?;
+        Ok(())
+    }
+
+    /// The commit `name` points at, looking through annotated tags.
+    pub fn target(&self, objects_dir: &Path, name: &str) -> Option<String> {
+        let id = self.refs.get(name)?;
+        Some(Tag::load(objects_dir, id).map_or_else(|| id.clone(), |tag| tag.target))
+    }
+
+    /// Every tagged commit, with annotated tags looked through.
+    pub fn targets(&self, objects_dir: &Path) -> Vec<String> {
+        self.refs
+            .keys()
+            .filter_map(|name| self.target(objects_dir, name))
+            .collect()
+    }
+
+    /// Tag names by the commit they point at, for decorating history.
+    pub fn by_commit(&self, objects_dir: &Path) -> HashMap<String, Vec<String>> {
+        let mut by_commit: HashMap<String, Vec<String>> = HashMap::new();
+        for name in self.refs.keys() {
+            if let Some(target) = self.target(objects_dir, name) {
+                by_commit.entry(target).or_default().push(name.clone());
+            }
+        }
+        by_commit
+    }
+}
+
+/// Compare tag names as versions: runs of digits compare numerically, so
+/// `v1.9` < `v1.10`. Everything else compares as text.
+pub fn version_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chunks = version_chunks(a);
+    let mut b_chunks = version_chunks(b);
+    loop {
+        match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    _ => x.cmp(y),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+/// Split into alternating digit and non-digit runs.
+fn version_chunks(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk)
+    })
+}
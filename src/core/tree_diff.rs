@@ -0,0 +1,171 @@
+use crate::utils::diff_utils::{self, PairKind};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffStatus {
+    Added,
+    Modified,
+    Deleted,
+    /// Moved from `old_path`, with `similarity` percent of its content
+    Renamed { old_path: String, similarity: u8 },
+    /// Copied from `old_path`, which still exists
+    Copied { old_path: String, similarity: u8 },
+}
+
+/// One changed path between two trees. `old_hash` is the blob on the old
+/// side (the source for renames and copies), `new_hash` the blob on the new
+/// side; either is `None` where the path doesn't exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    pub path: String,
+    pub status: DiffStatus,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+}
+
+impl FileDiff {
+    /// Single-letter status as `--name-status` prints it.
+    pub fn letter(&self) -> char {
+        match self.status {
+            DiffStatus::Added => 'A',
+            DiffStatus::Modified => 'M',
+            DiffStatus::Deleted => 'D',
+            DiffStatus::Renamed { .. } => 'R',
+            DiffStatus::Copied { .. } => 'C',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TreeDiffOptions {
+    /// Pair deleted and added paths with similar content as renames
+    pub find_renames: bool,
+    /// Also pair added paths with unchanged ones as copies
+    pub find_copies: bool,
+    /// Similarity percent a pair needs (default: `DEFAULT_RENAME_THRESHOLD`)
+    pub rename_threshold: Option<u8>,
+}
+
+/// Changes from `old` to `new` (path -> blob hash), sorted by path. With
+/// rename detection, a renamed file is one entry under its new path and
+/// its old path is not reported as deleted. `load` returns blob content for
+/// content-based pairing.
+pub fn diff_file_maps<F>(
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+    options: &TreeDiffOptions,
+    load: F,
+) -> Vec<FileDiff>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let pairs = if options.find_renames || options.find_copies {
+        diff_utils::detect_renames(
+            old,
+            new,
+            options
+                .rename_threshold
+                .unwrap_or(diff_utils::DEFAULT_RENAME_THRESHOLD),
+            options.find_copies,
+            load,
+        )
+    } else {
+        Vec::new()
+    };
+    let renamed_from: BTreeSet<&str> = pairs
+        .iter()
+        .filter(|p| p.kind == PairKind::Rename)
+        .map(|p| p.old_path.as_str())
+        .collect();
+    let paired: HashMap<&str, &diff_utils::FilePair> =
+        pairs.iter().map(|p| (p.new_path.as_str(), p)).collect();
+
+    let mut paths: BTreeSet<&String> = old.keys().collect();
+    paths.extend(new.keys());
+
+    let mut diffs = Vec::new();
+    for path in paths {
+        if renamed_from.contains(path.as_str()) {
+            continue;
+        }
+        let (old_hash, new_hash) = (old.get(path), new.get(path));
+        let (status, old_hash) = match (old_hash, new_hash, paired.get(path.as_str())) {
+            (None, Some(_), Some(pair)) => {
+                let old_path = pair.old_path.clone();
+                let source = old.get(&old_path).cloned();
+                let status = match pair.kind {
+                    PairKind::Rename => DiffStatus::Renamed {
+                        old_path,
+                        similarity: pair.similarity,
+                    },
+                    PairKind::Copy => DiffStatus::Copied {
+                        old_path,
+                        similarity: pair.similarity,
+                    },
+                };
+                (status, source)
+            }
+            (None, Some(_), None) => (DiffStatus::Added, None),
+            (Some(_), None, _) => (DiffStatus::Deleted, old_hash.cloned()),
+            (Some(a), Some(b), _) if a != b => (DiffStatus::Modified, old_hash.cloned()),
+            _ => continue,
+        };
+        diffs.push(FileDiff {
+            path: path.clone(),
+            status,
+            old_hash,
+            new_hash: new_hash.cloned(),
+        });
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path -> hash maps over blobs whose hash is their content.
+    fn files(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|(path, content)| (path.to_string(), content.to_string())).collect()
+    }
+
+    fn diff(old: &[(&str, &str)], new: &[(&str, &str)], options: &TreeDiffOptions) -> Vec<FileDiff> {
+        diff_file_maps(&files(old), &files(new), options, |hash| Some(hash.to_string()))
+    }
+
+    const BODY: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+    const EDITED: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\nEIGHT\n";
+
+    #[test]
+    fn renamed_and_edited_file_is_one_rename() {
+        let renames = TreeDiffOptions { find_renames: true, ..Default::default() };
+        let diffs = diff(&[("old.txt", BODY)], &[("new.txt", EDITED)], &renames);
+
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "new.txt");
+        assert_eq!(diffs[0].old_hash.as_deref(), Some(BODY));
+        match &diffs[0].status {
+            DiffStatus::Renamed { old_path, similarity } => {
+                assert_eq!(old_path, "old.txt");
+                assert!(*similarity >= diff_utils::DEFAULT_RENAME_THRESHOLD && *similarity < 100);
+            }
+            other => panic!("expected a rename, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn without_rename_detection_it_is_a_delete_and_an_add() {
+        let diffs = diff(&[("old.txt", BODY)], &[("new.txt", EDITED)], &TreeDiffOptions::default());
+        let letters: Vec<(char, &str)> = diffs.iter().map(|d| (d.letter(), d.path.as_str())).collect();
+        assert_eq!(letters, vec![('A', "new.txt"), ('D', "old.txt")]);
+    }
+
+    #[test]
+    fn copies_keep_their_source() {
+        let copies = TreeDiffOptions { find_renames: true, find_copies: true, ..Default::default() };
+        let diffs = diff(&[("a.txt", BODY)], &[("a.txt", BODY), ("b.txt", EDITED)], &copies);
+
+        assert_eq!(diffs.len(), 1);
+        assert!(matches!(&diffs[0].status, DiffStatus::Copied { old_path, .. } if old_path == "a.txt"));
+    }
+}
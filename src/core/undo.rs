@@ -0,0 +1,138 @@
+use crate::core::repository::Repository;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Working tree content saved before a command overwrote it. The manifest
+/// lives in `.helix/undo/<id>.json` and the saved files under
+/// `.helix/undo/<id>/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoEntry {
+    pub id: String,
+    /// The command that overwrote the files, e.g. "reset --hard HEAD~1"
+    pub operation: String,
+    /// Path -> whether the file existed; files that didn't are removed
+    /// again on undo
+    pub files: BTreeMap<String, bool>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub fn undo_dir(git_dir: &Path) -> PathBuf {
+    git_dir.join("undo")
+}
+
+impl UndoEntry {
+    fn manifest_path(&self, git_dir: &Path) -> PathBuf {
+        undo_dir(git_dir).join(format!("{}.json", self.id))
+    }
+
+    fn content_dir(&self, git_dir: &Path) -> PathBuf {
+        undo_dir(git_dir).join(&self.id)
+    }
+
+    /// Save the working tree files that writing `targets` (path -> blob
+    /// id, `None` for a deletion) would change. Returns `None` when nothing
+    /// would be lost.
+    pub fn snapshot(
+        repo: &Repository,
+        operation: &str,
+        targets: &BTreeMap<String, Option<String>>,
+    ) -> Result<Option<Self>> {
+        let changed: Vec<&String> = targets
+            .iter()
+            .filter(|(path, target)| repo.worktree_blob_id(path) != **target)
+            .map(|(path, _)| path)
+            .collect();
+        if changed.is_empty() {
+            return Ok(None);
+        }
+
+        let created_at = chrono::Utc::now();
+        let mut entry = Self {
+            id: created_at.format("%Y%m%d%H%M%S%6f").to_string(),
+            operation: operation.to_string(),
+            files: BTreeMap::new(),
+            created_at,
+        };
+        let content_dir = entry.content_dir(&repo.git_dir);
+        for path in changed {
+            let source = repo.path.join(path);
+            let existed = source.is_file();
+            if existed {
+                let saved = content_dir.join(path);
+                if let Some(parent) = saved.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&source, &saved)
+                    .with_context(|| format!("Failed to save {} for undo", path))?;
+            }
+            entry.files.insert(path.clone(), existed);
+        }
+        fs::create_dir_all(undo_dir(&repo.git_dir))?;
+        fs::write(
+            entry.manifest_path(&repo.git_dir),
+            serde_json::to_string_pretty(&entry)?,
+        )?;
+        Ok(Some(entry))
+    }
+
+    /// Snapshots, oldest first.
+    pub fn list(git_dir: &Path) -> Result<Vec<Self>> {
+        let dir = undo_dir(git_dir);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                entries.push(serde_json::from_str::<Self>(&content)?);
+            }
+        }
+        entries.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        Ok(entries)
+    }
+
+    /// Put the saved files back in the working tree and drop the snapshot.
+    pub fn restore(&self, repo: &Repository) -> Result<()> {
+        let content_dir = self.content_dir(&repo.git_dir);
+        for (path, existed) in &self.files {
+            let target = repo.path.join(path);
+            if *existed {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(content_dir.join(path), &target)
+                    .with_context(|| format!("Failed to restore {}", path))?;
+            } else if target.exists() {
+                fs::remove_file(&target)?;
+            }
+        }
+        self.remove(&repo.git_dir)
+    }
+
+    pub fn remove(&self, git_dir: &Path) -> Result<()> {
+        let content_dir = self.content_dir(git_dir);
+        if content_dir.exists() {
+            fs::remove_dir_all(&content_dir)?;
+        }
+        fs::remove_file(self.manifest_path(git_dir))?;
+        Ok(())
+    }
+
+    /// Drop snapshots taken before `cutoff`; returns how many were dropped.
+    pub fn expire(git_dir: &Path, cutoff: chrono::DateTime<chrono::Utc>) -> Result<usize> {
+        let mut expired = 0;
+        for entry in Self::list(git_dir)? {
+            if entry.created_at < cutoff {
+                entry.remove(git_dir)?;
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+}
@@ -1,14 +1,16 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use colored::*;
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 mod commands;
 mod core;
 mod utils;
+use utils::alias::AliasExpansion;
 use utils::config::GlobalConfig;
 
 use commands::*;
-use core::repository::Repository;
+use core::repository::{Repository, RepositoryConfig};
 
 #[derive(Parser)]
 #[command(name = "hx")]
@@ -16,6 +18,9 @@ use core::repository::Repository;
 #[command(version = "0.1.0")]
 #[command(propagate_version = true)]
 struct Cli {
+    /// Write log and diff output straight to stdout instead of a pager
+    #[arg(long, global = true)]
+    no_pager: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -31,48 +36,240 @@ enum Commands {
     Add {
         #[arg(default_value = ".")]
         paths: Vec<PathBuf>,
+        /// Stage modifications and deletions of tracked files only
+        #[arg(short, long, conflicts_with = "all")]
+        update: bool,
+        /// Stage everything, including deletions of tracked files
+        #[arg(short = 'A', long)]
+        all: bool,
+    },
+    /// Remove files from the working tree and stage their deletion
+    Rm {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Only unstage; keep the files in the working tree
+        #[arg(long)]
+        cached: bool,
+        /// Remove even if the files have uncommitted changes
+        #[arg(short, long)]
+        force: bool,
+        /// Allow removing every tracked file under a directory
+        #[arg(short)]
+        recursive: bool,
     },
     /// Commit staged changes
     Commit {
+        /// Commit message; when amending, defaults to the amended commit's
+        #[arg(short, long, required_unless_present_any = ["fixup", "squash", "no_edit", "amend"])]
+        message: Option<String>,
+        /// Create a "fixup! <subject>" commit for a later autosquash rebase
+        #[arg(long, conflicts_with = "squash")]
+        fixup: Option<String>,
+        /// Create a "squash! <subject>" commit for a later autosquash rebase
+        #[arg(long)]
+        squash: Option<String>,
+        /// Commit date (RFC3339); overrides HX_COMMIT_DATE/HX_AUTHOR_DATE
+        #[arg(long)]
+        date: Option<String>,
+        /// Author as "Name <email>"; you are then recorded as the committer
+        #[arg(long, value_name = "AUTHOR")]
+        author: Option<String>,
+        /// Replace the tip of the current branch
+        #[arg(long, conflicts_with_all = ["fixup", "squash"])]
+        amend: bool,
+        /// Keep the amended commit's message
+        #[arg(long, requires = "amend", conflicts_with = "message")]
+        no_edit: bool,
+        /// When amending, make the current identity the author
+        #[arg(long, requires = "amend")]
+        reset_author: bool,
+        /// Choose hunks of the working tree changes to commit
         #[arg(short, long)]
-        message: String,
+        patch: bool,
+        /// Record a commit even when nothing changed since HEAD
+        #[arg(long)]
+        allow_empty: bool,
     },
     /// Show repository status
-    Status,
+    Status {
+        /// Walk the working tree on N threads (default: one per CPU)
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+        /// Show staged deletions and additions at least N% alike as renames (default 50)
+        #[arg(
+            short = 'M',
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "50",
+            value_parser = clap::value_parser!(u8).range(0..=100)
+        )]
+        find_renames: Option<u8>,
+    },
     /// Show commit history
     Log {
         #[arg(short, long, default_value = "10")]
         limit: usize,
+        /// Draw the commit graph, one commit per line
+        #[arg(long)]
+        graph: bool,
+        /// Only show merge commits
+        #[arg(long, conflicts_with = "no_merges")]
+        merges: bool,
+        /// Hide merge commits
+        #[arg(long)]
+        no_merges: bool,
+        /// Continue listing the history of a file beyond renames
+        #[arg(long, requires = "path")]
+        follow: bool,
+        /// Show each commit's patch
+        #[arg(short = 'p', long = "patch", conflicts_with = "graph")]
+        patch: bool,
+        /// Show each commit's per-file change summary
+        #[arg(long, conflicts_with = "graph")]
+        stat: bool,
+        /// Start from this revision instead of HEAD, e.g. origin/main
+        #[arg(short, long)]
+        rev: Option<String>,
+        /// Only show commits whose author name or email contains this text
+        #[arg(long)]
+        author: Option<String>,
+        /// Only show commits whose message contains this text
+        #[arg(long)]
+        grep: Option<String>,
+        /// Only show commits made on or after this date (RFC3339 or YYYY-MM-DD)
+        #[arg(long, visible_alias = "after")]
+        since: Option<String>,
+        /// Only show commits made on or before this date (RFC3339 or YYYY-MM-DD)
+        #[arg(long, visible_alias = "before")]
+        until: Option<String>,
+        /// Only show commits that change this path
+        #[arg(conflicts_with = "graph")]
+        path: Option<String>,
+    },
+    /// Show a commit and its changes
+    Show {
+        /// Commit to show
+        #[arg(default_value = "HEAD")]
+        rev: String,
+        /// Summarize insertions and deletions per file
+        #[arg(long, conflicts_with_all = ["name_only", "name_status"])]
+        stat: bool,
+        /// Only list the changed paths
+        #[arg(long, conflicts_with = "name_status")]
+        name_only: bool,
+        /// List the changed paths with their status (A/M/D)
+        #[arg(long)]
+        name_status: bool,
+        /// Diff merge commits against their first parent
+        #[arg(long)]
+        first_parent: bool,
     },
     /// Create a new branch
     Branch {
         #[arg(default_value = "")]
         name: String,
+        /// New name with -m; NAME is then the branch to rename
+        #[arg(requires = "rename")]
+        new_name: Option<String>,
+        /// Track <remote>/<branch> for NAME (default: the current branch)
+        #[arg(short = 'u', long, value_name = "UPSTREAM", conflicts_with = "unset_upstream")]
+        set_upstream_to: Option<String>,
+        /// Stop tracking an upstream for NAME (default: the current branch)
+        #[arg(long)]
+        unset_upstream: bool,
+        /// Delete the branch NAME if it is merged into the current branch
+        #[arg(short, long, conflicts_with = "force_delete")]
+        delete: bool,
+        /// Delete the branch NAME even if it is not merged
+        #[arg(short = 'D')]
+        force_delete: bool,
+        /// Rename a branch: `-m NEW` renames the current branch, `-m OLD NEW` any other
+        #[arg(short = 'm', long = "move", conflicts_with_all = ["delete", "force_delete"])]
+        rename: bool,
+        /// List remote-tracking branches
+        #[arg(short, long, conflicts_with = "all")]
+        remotes: bool,
+        /// List local and remote-tracking branches
+        #[arg(short, long)]
+        all: bool,
     },
     /// Switch between branches
     Checkout {
-        branch: String,
+        #[arg(required_unless_present_any = ["ours", "theirs"])]
+        branch: Option<String>,
+        /// Commit to start the new branch at with -b (default: HEAD)
+        #[arg(requires = "create")]
+        start_point: Option<String>,
+        /// Resolve the given conflicted paths with our version
+        #[arg(long, value_name = "PATH", num_args = 1.., conflicts_with_all = ["theirs", "branch"])]
+        ours: Vec<PathBuf>,
+        /// Resolve the given conflicted paths with their version
+        #[arg(long, value_name = "PATH", num_args = 1.., conflicts_with = "branch")]
+        theirs: Vec<PathBuf>,
+        /// Switch even if local changes would be overwritten
+        #[arg(short, long)]
+        force: bool,
+        /// Create BRANCH and switch to it
+        #[arg(short = 'b', conflicts_with_all = ["ours", "theirs"])]
+        create: bool,
     },
     /// Merge branches
     Merge {
         branch: String,
         #[arg(long, value_parser = ["ours", "theirs", "manual"], default_value = "manual")]
         strategy: String,
+        /// Report what the merge would do without touching any files
+        #[arg(long)]
+        preview: bool,
+        /// Merge commit message
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Use the default merge message without opening an editor
+        #[arg(long, conflicts_with = "message")]
+        no_edit: bool,
+        /// Allow merging a branch that shares no history with this one
+        #[arg(long)]
+        allow_unrelated_histories: bool,
     },
     /// Clone a repository
     Clone {
         url: String,
         #[arg(default_value = ".")]
         path: PathBuf,
+        /// Clone a git, hg, svn or bzr repository with that tool instead
+        #[arg(long, value_parser = ["git", "hg", "svn", "bzr"])]
+        vcs: Option<String>,
     },
     /// Push changes to remote
     Push {
         #[arg(long)]
         force: bool,
-        #[arg(long)]
+        /// Remote to push to (default: origin)
         remote: Option<String>,
+        /// Refs to push as [+]<src>[:<dst>], e.g. main:release; a leading
+        /// + forces that update (default: the current branch)
+        refspecs: Vec<String>,
+        /// Update all refs on the remote or none of them
+        #[arg(long)]
+        atomic: bool,
+        /// Push all local branches
+        #[arg(long, conflicts_with = "refspecs")]
+        all: bool,
+        /// Verify at most this many generations of outgoing commits
         #[arg(long)]
-        refspec: Option<String>,
+        verify_depth: Option<usize>,
+    },
+    /// Download objects and update remote-tracking refs without merging
+    Fetch {
+        /// Remote to fetch from (default: origin)
+        remote: Option<String>,
+        /// Fetch from every configured remote
+        #[arg(long, conflicts_with = "remote")]
+        all: bool,
+        /// Only fetch history newer than DATE (YYYY-MM-DD, RFC3339 or N.days.ago)
+        #[arg(long, value_name = "DATE")]
+        shallow_since: Option<String>,
     },
     /// Pull changes from remote
     Pull {
@@ -82,11 +279,43 @@ enum Commands {
         branch: Option<String>,
         #[arg(long)]
         rebase: bool,
+        /// Verify at most this many generations of incoming commits
+        #[arg(long)]
+        verify_depth: Option<usize>,
     },
     /// Show differences
     Diff {
-        #[arg(default_value = "")]
-        path: Option<PathBuf>,
+        /// A path to diff against HEAD, a commit to diff against the working
+        /// tree, two commits to compare, or a range (A..B compares the tips,
+        /// A...B compares B with the merge base)
+        args: Vec<String>,
+        /// Show staged changes against HEAD
+        #[arg(long, visible_alias = "cached")]
+        staged: bool,
+        #[arg(long)]
+        find_copies: bool,
+        /// Pair deleted and added files at least N% alike as renames (default 50)
+        #[arg(
+            short = 'M',
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "50",
+            value_parser = clap::value_parser!(u8).range(0..=100)
+        )]
+        find_renames: Option<u8>,
+        /// Color blocks of moved lines differently from added/removed lines
+        #[arg(long)]
+        color_moved: bool,
+        /// Show N lines of context around each change (default 3)
+        #[arg(short = 'U', long, value_name = "N")]
+        unified: Option<usize>,
+        /// Exit with status 1 if there are differences
+        #[arg(long)]
+        exit_code: bool,
+        /// Print nothing; implies --exit-code
+        #[arg(long)]
+        quiet: bool,
     },
     /// Reset repository state
     Reset {
@@ -94,13 +323,23 @@ enum Commands {
         target: String,
         #[arg(long, default_value = "mixed")]
         mode: Option<String>,
+        /// Discard local changes that a hard reset would overwrite
+        #[arg(short, long)]
+        force: bool,
+        /// Only reset these index entries, leaving HEAD and the working tree alone
+        #[arg(last = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Remove files from the staging area (same as `reset -- <paths>`)
+    Unstage {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
     },
     /// Add a remote repository
     Remote {
-        #[arg(short, long)]
-        add: Option<String>,
-        #[arg(short, long)]
-        url: Option<String>,
+        /// Lists the remotes when omitted
+        #[command(subcommand)]
+        subcommand: Option<RemoteSubcommand>,
     },
     /// Manage authentication
     Auth {
@@ -109,8 +348,18 @@ enum Commands {
     },
     /// Restore files from the last commit
     Restore {
+        /// Files, directories or glob patterns (e.g. 'src/*.rs')
         #[arg(default_value = ".")]
         paths: Vec<PathBuf>,
+        /// Commit to restore from (default: HEAD)
+        #[arg(short, long)]
+        source: Option<String>,
+        /// Restore the staged entries
+        #[arg(short = 'S', long)]
+        staged: bool,
+        /// Restore the working tree files (default unless --staged is given)
+        #[arg(short = 'W', long)]
+        worktree: bool,
     },
     /// Key management
     Keygen,
@@ -123,10 +372,142 @@ enum Commands {
     },
     /// Visualize the commit DAG
     Dag,
-    /// Global configuration
+    /// Prune unreachable objects
+    Gc {
+        /// Expiry date for reflog entries and unreachable objects (default: gc.pruneExpire or 2.weeks.ago; "now" prunes everything)
+        #[arg(long)]
+        prune: Option<String>,
+    },
+    /// Maintain the commit graph cache used for ancestry walks
+    CommitGraph {
+        #[command(subcommand)]
+        subcommand: CommitGraphSubcommand,
+    },
+    /// Remove untracked files from the working tree
+    Clean {
+        /// Actually remove the files
+        #[arg(short, long)]
+        force: bool,
+        /// Only list what would be removed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Also remove directories left empty
+        #[arg(short = 'd')]
+        directories: bool,
+    },
+    /// Delete unreachable objects
+    Prune {
+        /// Only list what would be deleted, grouped by type
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    /// Check the object store for corruption
+    Fsck {
+        /// Also list objects not reachable from any ref
+        #[arg(long)]
+        unreachable: bool,
+    },
+    /// Show which commit last changed each line of a file
+    Blame {
+        file: String,
+        /// Commit to blame from (default: HEAD)
+        #[arg(short, long)]
+        rev: Option<String>,
+        /// Stream machine-readable attributions as lines are resolved
+        #[arg(long)]
+        incremental: bool,
+    },
+    /// Create a commit undoing the changes of an earlier one
+    Revert {
+        commit: String,
+    },
+    /// Apply the changes of an existing commit on top of the current branch
+    CherryPick {
+        commit: String,
+    },
+    /// Replay the current branch's commits on top of another commit
+    Rebase {
+        upstream: String,
+        /// Move "fixup!"/"squash!" commits after the commits they name and
+        /// fold them in
+        #[arg(long)]
+        autosquash: bool,
+    },
+    /// Restore working tree files overwritten by the last reset --hard,
+    /// stash apply/pop or checkout --ours/--theirs
+    Undo {
+        /// List the snapshots that can be restored instead
+        #[arg(long)]
+        list: bool,
+    },
+    /// Show the history of HEAD movements
+    Reflog {
+        #[arg(short, long, default_value = "20")]
+        limit: usize,
+    },
+    /// Resolve a revision to its full commit id
+    RevParse {
+        /// HEAD, a branch, a commit hash prefix, optionally with ~<n>/^<n>
+        rev: Option<String>,
+        /// Print the branch name instead of the commit id
+        #[arg(long)]
+        abbrev_ref: bool,
+        /// Print the repository root
+        #[arg(long)]
+        show_toplevel: bool,
+    },
+    /// Create, delete or list tags
+    Tag {
+        /// Tag to create, or a glob filter with -l
+        name: Option<String>,
+        /// Commit to tag (default: HEAD)
+        commit: Option<String>,
+        /// List tags, optionally filtered by the glob in NAME
+        #[arg(short, long)]
+        list: bool,
+        /// Create an annotated tag object
+        #[arg(short, long, conflicts_with_all = ["list", "delete"])]
+        annotate: bool,
+        /// Annotated tag message (implies -a)
+        #[arg(short, long, conflicts_with_all = ["list", "delete"])]
+        message: Option<String>,
+        /// Delete the tag NAME
+        #[arg(short, long, requires = "name", conflicts_with = "list")]
+        delete: bool,
+        /// Order listed tags by refname, version or creatordate (prefix - to reverse)
+        #[arg(long)]
+        sort: Option<String>,
+    },
+    /// Manage nested repositories pinned to a commit
+    Submodule {
+        #[command(subcommand)]
+        subcommand: SubmoduleSubcommand,
+    },
+    /// Validate a pack file and list its objects
+    VerifyPack {
+        file: PathBuf,
+        /// List every object with its id, type and size
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Stash working tree changes
+    Stash {
+        #[command(subcommand)]
+        subcommand: Option<StashSubcommand>,
+    },
+    /// Read and write configuration; user.* and core.autocrlf are stored in
+    /// the repository when run inside one, unless --global is given
     Config {
+        /// Key to read, or to set when VALUE is given
+        key: Option<String>,
+        #[arg(value_name = "VALUE")]
+        key_value: Option<String>,
+        /// Use the global config (~/.helixconfig) even inside a repository
         #[arg(long)]
         global: bool,
+        /// List every key that is set, global first, then the repository's
+        #[arg(short, long, conflicts_with_all = ["key", "get", "set"])]
+        list: bool,
         #[arg(long)]
         get: Option<String>,
         #[arg(long)]
@@ -162,9 +543,116 @@ enum AuthSubcommand {
     },
 }
 
+#[derive(Subcommand)]
+enum RemoteSubcommand {
+    /// Add a remote
+    Add {
+        name: String,
+        url: String,
+    },
+    /// Remove a remote and its remote-tracking refs
+    Remove {
+        name: String,
+    },
+    /// Rename a remote
+    Rename {
+        old: String,
+        new: String,
+    },
+    /// List remotes with their URLs
+    List,
+}
+
+#[derive(Subcommand)]
+enum SubmoduleSubcommand {
+    /// Clone a repository into PATH and record it as a submodule
+    Add {
+        url: String,
+        path: PathBuf,
+    },
+    /// Clone missing submodules and check out their pinned commits
+    Update,
+}
+
+#[derive(Subcommand)]
+enum CommitGraphSubcommand {
+    /// Rebuild .helix/commit-graph from all branches and tags
+    Write,
+}
+
+#[derive(Subcommand)]
+enum StashSubcommand {
+    /// Save local changes to a new stash entry
+    Push {
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// List stash entries
+    List,
+    /// Apply a stash entry and drop it
+    Pop {
+        /// Stash to use, e.g. stash@{1} (default: newest)
+        stash: Option<String>,
+    },
+    /// Apply a stash entry, keeping it in the list
+    Apply {
+        stash: Option<String>,
+    },
+    /// Remove a stash entry without applying it
+    Drop {
+        stash: Option<String>,
+    },
+    /// Show the changes recorded in a stash entry
+    Show {
+        stash: Option<String>,
+    },
+}
+
+/// Apply `alias.*` from the global config to the command line. `!` aliases
+/// run through the shell and exit with its status.
+fn expand_aliases() -> anyhow::Result<Vec<String>> {
+    let args: Vec<String> = std::env::args().collect();
+    let aliases = GlobalConfig::load().map(|c| c.alias).unwrap_or_default();
+    if aliases.is_empty() {
+        return Ok(args);
+    }
+    let builtins: Vec<String> = Cli::command()
+        .get_subcommands()
+        .flat_map(|c| std::iter::once(c.get_name()).chain(c.get_all_aliases()))
+        .chain(std::iter::once("help"))
+        .map(|name| name.to_string())
+        .collect();
+
+    match utils::alias::expand(args, &builtins, &aliases)? {
+        AliasExpansion::Args(args) => Ok(args),
+        AliasExpansion::Shell(command, rest) => {
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("{} \"$@\"", command))
+                .arg(&command)
+                .args(rest)
+                .status()?;
+            std::process::exit(status.code().unwrap_or(1));
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+async fn main() -> anyhow::Result<ExitCode> {
+    let cli = Cli::parse_from(expand_aliases()?);
+
+    if !cli.no_pager && matches!(
+        cli.command,
+        Commands::Log { .. } | Commands::Diff { .. } | Commands::Show { .. }
+    ) {
+        let config = GlobalConfig::load().ok();
+        if let Some(pager) = utils::pager::Pager::for_stdout(config.as_ref()) {
+            if let Some(code) = pager.run()? {
+                return Ok(ExitCode::from(u8::try_from(code).unwrap_or(1)));
+            }
+        }
+        utils::pager::Pager::quiet_broken_pipe();
+    }
 
     // Print beautiful header
     if let Commands::Init { .. } = &cli.command {
@@ -172,50 +660,165 @@ async fn main() -> anyhow::Result<()> {
         println!("{}", "=".repeat(40).blue());
     }
 
+    let mut status = ExitCode::SUCCESS;
     match &cli.command {
         Commands::Init { path } => {
             init::init_repository(path).await?;
         }
-        Commands::Add { paths } => {
+        Commands::Add { paths, update, all } => {
             let mut repo = Repository::open(".")?;
-            add::add_files(&mut repo, paths).await?;
+            let options = add::AddOptions { update: *update, all: *all };
+            add::add_files(&mut repo, paths, &options).await?;
         }
-        Commands::Commit { message } => {
+        Commands::Rm { paths, cached, force, recursive } => {
             let mut repo = Repository::open(".")?;
-            let keypair =
-                utils::key_utils::load_keypair().expect("No keypair found. Run 'hx keygen' first.");
-            commit::commit_changes(&mut repo, message, &keypair).await?;
+            let options = rm::RmOptions {
+                cached: *cached,
+                force: *force,
+                recursive: *recursive,
+            };
+            rm::remove_files(&mut repo, paths, &options).await?;
         }
-        Commands::Status => {
+        Commands::Commit {
+            message,
+            fixup,
+            squash,
+            date,
+            author,
+            amend,
+            no_edit,
+            reset_author,
+            patch,
+            allow_empty,
+        } => {
+            let mut repo = Repository::open(".")?;
+            let keypair = utils::key_utils::load_signing_key()?;
+            let message = match (fixup, squash) {
+                (Some(rev), _) => commit::autosquash_message(&repo, "fixup", rev, message.as_deref())?,
+                (_, Some(rev)) => commit::autosquash_message(&repo, "squash", rev, message.as_deref())?,
+                _ if *no_edit || *amend && message.is_none() => commit::head_message(&repo)?,
+                _ => message.clone().unwrap_or_default(),
+            };
+            let options = commit::CommitOptions {
+                date: date.clone(),
+                amend: *amend,
+                reset_author: *reset_author,
+                author: author.clone(),
+                allow_empty: *allow_empty,
+            };
+            if *patch {
+                commit::commit_patch(&mut repo, &message, &options, &keypair).await?;
+            } else {
+                commit::commit_changes(&mut repo, &message, &options, &keypair).await?;
+            }
+        }
+        Commands::Status { jobs, find_renames } => {
             let repo = Repository::open(".")?;
-            status::show_status(&repo).await?;
+            let options = status::StatusOptions {
+                jobs: *jobs,
+                rename_threshold: *find_renames,
+            };
+            status::show_status(&repo, &options).await?;
         }
-        Commands::Log { limit } => {
+        Commands::Log {
+            limit,
+            graph,
+            merges,
+            no_merges,
+            follow,
+            patch,
+            stat,
+            rev,
+            author,
+            grep,
+            since,
+            until,
+            path,
+        } => {
             let repo = Repository::open(".")?;
-            log::show_log(&repo, *limit).await?;
+            let options = log::LogOptions {
+                limit: *limit,
+                graph: *graph,
+                merges: *merges,
+                no_merges: *no_merges,
+                path: path.clone(),
+                follow: *follow,
+                patch: *patch,
+                stat: *stat,
+                rev: rev.clone(),
+                author: author.clone(),
+                grep: grep.clone(),
+                since: since.as_deref().map(utils::date_utils::parse_date).transpose()?,
+                until: until.as_deref().map(utils::date_utils::parse_date).transpose()?,
+            };
+            log::show_log(&repo, &options).await?;
+        }
+        Commands::Show { rev, stat, name_only, name_status, first_parent } => {
+            let repo = Repository::open(".")?;
+            let options = show::ShowOptions {
+                stat: *stat,
+                name_only: *name_only,
+                name_status: *name_status,
+                first_parent: *first_parent,
+            };
+            show::show_commit(&repo, rev, &options).await?;
         }
-        Commands::Branch { name } => {
+        Commands::Branch { name, new_name, set_upstream_to, unset_upstream, delete, force_delete, rename, remotes, all } => {
             let mut repo = Repository::open(".")?;
-            if name.is_empty() {
-                branch::list_branches(&repo).await?;
+            let target = Some(name.as_str()).filter(|n| !n.is_empty());
+            if *rename {
+                let name = target.ok_or_else(|| anyhow::anyhow!("branch name required"))?;
+                match new_name {
+                    Some(new_name) => branch::rename_branch(&mut repo, name, new_name).await?,
+                    None => {
+                        let current = repo.current_branch.clone();
+                        branch::rename_branch(&mut repo, &current, name).await?
+                    }
+                }
+            } else if *delete || *force_delete {
+                let name = target.ok_or_else(|| anyhow::anyhow!("branch name required"))?;
+                branch::delete_branch(&mut repo, name, *force_delete).await?;
+            } else if let Some(upstream) = set_upstream_to {
+                branch::set_upstream(&mut repo, target, upstream).await?;
+            } else if *unset_upstream {
+                branch::unset_upstream(&mut repo, target).await?;
+            } else if name.is_empty() {
+                if !*remotes {
+                    branch::list_branches(&repo).await?;
+                }
+                if *remotes || *all {
+                    branch::list_remote_branches(&repo).await?;
+                }
             } else {
                 branch::create_branch(&mut repo, name).await?;
             }
         }
-        Commands::Checkout { branch } => {
+        Commands::Checkout { branch, start_point, ours, theirs, force, create } => {
             let mut repo = Repository::open(".")?;
-            checkout::checkout_branch(&mut repo, branch).await?;
+            match branch {
+                Some(branch) if *create => {
+                    checkout::create_and_checkout(&mut repo, branch, start_point.as_deref(), *force).await?;
+                }
+                Some(branch) => checkout::checkout_branch(&mut repo, branch, *force).await?,
+                None if !ours.is_empty() => checkout::checkout_conflict_side(&mut repo, ours, true).await?,
+                None => checkout::checkout_conflict_side(&mut repo, theirs, false).await?,
+            }
         }
-        Commands::Merge { branch, strategy } => {
+        Commands::Merge { branch, strategy, preview, message, no_edit, allow_unrelated_histories } => {
             let mut repo = Repository::open(".")?;
             let strat = match strategy.as_str() {
                 "ours" => merge::MergeStrategy::Ours,
                 "theirs" => merge::MergeStrategy::Theirs,
                 _ => merge::MergeStrategy::Manual,
             };
-            merge::merge_branch(&mut repo, branch, Some(strat)).await?;
+            let options = merge::MergeOptions {
+                message: message.clone(),
+                no_edit: *no_edit,
+                allow_unrelated_histories: *allow_unrelated_histories,
+            };
+            merge::merge_branch(&mut repo, branch, Some(strat), *preview, &options).await?;
         }
-        Commands::Clone { url, path } => {
+        Commands::Clone { url, path, vcs } => {
             let target_path = if path.to_string_lossy() == "." {
                 // Extract repo name from URL
                 let url_str = url.trim_end_matches('/');
@@ -228,37 +831,121 @@ async fn main() -> anyhow::Result<()> {
             } else {
                 path.clone()
             };
-            clone::clone_repository(url, &target_path).await?;
+            clone::clone_repository(url, &target_path, &clone::CloneOptions { vcs: vcs.clone() }).await?;
         }
-        Commands::Push { force, remote, refspec } => {
+        Commands::Push { force, remote, refspecs, atomic, all, verify_depth } => {
             let repo = Repository::open(".")?;
-            push::push_with_options(&repo, *force, remote.as_deref(), refspec.as_deref()).await?;
+            let options = push::PushOptions {
+                force: *force,
+                atomic: *atomic,
+                all: *all,
+                verify_depth: *verify_depth,
+                refspecs: refspecs
+                    .iter()
+                    .map(|spec| push::Refspec::parse(spec))
+                    .collect::<anyhow::Result<_>>()?,
+            };
+            push::push_with_options(&repo, remote.as_deref(), &options).await?;
         }
-        Commands::Pull { remote, branch, rebase } => {
-            let repo = Repository::open(".")?;
-            pull::pull_with_options(&repo, remote.as_deref(), branch.as_deref(), *rebase).await?;
+        Commands::Fetch { remote, all, shallow_since } => {
+            let mut repo = Repository::open(".")?;
+            let options = fetch::FetchOptions {
+                shallow_since: shallow_since
+                    .as_deref()
+                    .map(utils::date_utils::parse_date)
+                    .transpose()?,
+            };
+            if !fetch::fetch(&mut repo, remote.as_deref(), *all, &options).await? {
+                status = ExitCode::FAILURE;
+            }
+        }
+        Commands::Pull { remote, branch, rebase, verify_depth } => {
+            let mut repo = Repository::open(".")?;
+            pull::pull_with_options(&mut repo, remote.as_deref(), branch.as_deref(), *rebase, *verify_depth).await?;
         }
-        Commands::Diff { path } => {
+        Commands::Diff {
+            args,
+            staged,
+            find_copies,
+            find_renames,
+            color_moved,
+            unified,
+            exit_code,
+            quiet,
+        } => {
             let repo = Repository::open(".")?;
-            diff::show_diff(&repo, path.as_ref().map(|v| &**v)).await?;
+            let options = diff::DiffOptions {
+                find_copies: *find_copies,
+                rename_threshold: *find_renames,
+                quiet: *quiet,
+                color_moved: *color_moved,
+                context: *unified,
+            };
+            let has_diff = match args.as_slice() {
+                _ if *staged => {
+                    diff::show_staged_diff(&repo, args.first().map(|s| s.as_str()), &options).await?
+                }
+                [range] if range.contains("..") => diff::show_range_diff(&repo, range, &options).await?,
+                [from, to] => diff::show_commit_diff(&repo, from, to, &options).await?,
+                // A single argument is a commit unless it names a file
+                [rev] if !std::path::Path::new(rev).exists() && repo.resolve_rev(rev).is_ok() => {
+                    diff::show_worktree_diff(&repo, rev, &options).await?
+                }
+                [path] => diff::show_diff(&repo, Some(std::path::Path::new(path)), &options).await?,
+                _ => diff::show_diff(&repo, None, &options).await?,
+            };
+            if has_diff && (*exit_code || *quiet) {
+                status = ExitCode::FAILURE;
+            }
         }
-        Commands::Reset { target, mode } => {
+        Commands::Reset { target, mode, force, paths } => {
             let mut repo = Repository::open(".")?;
             let mode = mode.clone().unwrap_or("mixed".to_string());
-            reset::reset_repository(&mut repo, target, &mode).await?;
+            if paths.is_empty() {
+                reset::reset_repository(&mut repo, target, &mode, *force).await?;
+            } else if mode != "mixed" {
+                anyhow::bail!("Cannot do a {} reset with paths", mode);
+            } else {
+                reset::reset_paths(&mut repo, target, paths.clone()).await?;
+            }
         }
-        Commands::Remote { add, url } => {
+        Commands::Unstage { paths } => {
             let mut repo = Repository::open(".")?;
-            if let (Some(name), Some(remote_url)) = (add, url) {
-                repo.add_remote(&name, &remote_url)?;
-                println!(
-                    "{}",
-                    format!("Added remote '{}' -> {}", name, remote_url)
-                        .green()
-                        .bold()
-                );
-            } else {
-                println!("{}", "Usage: hx remote --add <name> --url <url>".yellow());
+            reset::reset_paths(&mut repo, "HEAD", paths.clone()).await?;
+        }
+        Commands::Remote { subcommand } => {
+            let mut repo = Repository::open(".")?;
+            match subcommand.as_ref().unwrap_or(&RemoteSubcommand::List) {
+                RemoteSubcommand::Add { name, url } => {
+                    repo.add_remote(name, url)?;
+                    println!(
+                        "{}",
+                        format!("Added remote '{}' -> {}", name, url)
+                            .green()
+                            .bold()
+                    );
+                }
+                RemoteSubcommand::Remove { name } => {
+                    repo.remove_remote(name)?;
+                    println!("{}", format!("Removed remote '{}'", name).green());
+                }
+                RemoteSubcommand::Rename { old, new } => {
+                    repo.rename_remote(old, new)?;
+                    println!("{}", format!("Renamed remote '{}' to '{}'", old, new).green());
+                }
+                RemoteSubcommand::List => {
+                    let mut remotes: Vec<_> = repo.remotes.values().collect();
+                    remotes.sort_by(|a, b| a.name.cmp(&b.name));
+                    for remote in remotes {
+                        println!("{}\t{}", remote.name.yellow(), remote.url);
+                        if let Some(fetch_url) = &remote.fetch_url {
+                            println!("  fetch: {}", fetch_url);
+                        }
+                        if let Some(push_url) = &remote.push_url {
+                            println!("  push:  {}", push_url);
+                        }
+                    }
+                }
             }
         }
         Commands::Auth { subcommand } => {
@@ -285,20 +972,56 @@ async fn main() -> anyhow::Result<()> {
                     println!("{}", format!("Removed authentication for {}", host).green().bold());
                 }
                 AuthSubcommand::List => {
-                    // TODO: Implement list functionality
                     println!("{}", "Authentication configurations:".bold());
-                    println!("(List functionality not yet implemented)");
+                    let hosts = auth_manager.list_hosts();
+                    if hosts.is_empty() {
+                        println!("  (none)");
+                    }
+                    for (host, method) in hosts {
+                        let details = method.redacted();
+                        if details.is_empty() {
+                            println!("  {} {}", host.cyan(), method.name().yellow());
+                        } else {
+                            println!("  {} {} ({})", host.cyan(), method.name().yellow(), details);
+                        }
+                    }
                 }
                 AuthSubcommand::Test { host } => {
-                    // TODO: Implement test functionality
                     println!("{}", format!("Testing authentication for {}", host).bold());
-                    println!("(Test functionality not yet implemented)");
+                    let url = if host.contains("://") { host.clone() } else { format!("https://{}", host) };
+                    let configured = utils::auth::extract_host_from_url(&url)?;
+                    if auth_manager.get_config(&configured).is_none() {
+                        println!("{}", format!("No authentication configured for {}", configured).yellow());
+                    }
+                    let client = utils::remote_client::RemoteClient::configured(&url)
+                        .with_auth_manager(auth_manager);
+                    let reachable = client.check_connectivity().await?;
+                    println!(
+                        "  /health: {}",
+                        if reachable { "reachable".green() } else { "unreachable".red() }
+                    );
+                    match client.probe("/info/refs").await {
+                        Ok(status) if status.is_success() => {
+                            println!("  /info/refs: {}", format!("HTTP {}", status).green());
+                            println!("{}", "Authentication succeeded".green().bold());
+                        }
+                        Ok(status) => {
+                            println!("  /info/refs: {}", format!("HTTP {}", status).red());
+                            anyhow::bail!("Authentication failed for {}", host);
+                        }
+                        Err(e) => anyhow::bail!("Could not reach {}: {}", host, e),
+                    }
                 }
             }
         }
-        Commands::Restore { paths } => {
-            let repo = Repository::open(".")?;
-            restore::restore_files(&repo, paths.clone()).await?;
+        Commands::Restore { paths, source, staged, worktree } => {
+            let mut repo = Repository::open(".")?;
+            let options = restore::RestoreOptions {
+                source: source.clone(),
+                staged: *staged,
+                worktree: *worktree,
+            };
+            restore::restore_files(&mut repo, paths.clone(), &options).await?;
         }
         Commands::Keygen => {
             let _key = utils::key_utils::generate_and_save_keypair()?;
@@ -324,41 +1047,176 @@ async fn main() -> anyhow::Result<()> {
             let repo = Repository::open(".")?;
             log::show_dag(&repo).await?;
         }
-        Commands::Config { global, get, set, value } => {
-            if *global {
-                let mut config = GlobalConfig::load()?;
-                if let Some(key) = set {
-                    if let Some(val) = value {
-                        match key.as_str() {
-                            "user.name" => {
-                                config.set_user_name(val.clone());
-                                config.save()?;
-                                println!("Set user.name = {}", val);
-                            }
-                            "user.email" => {
-                                config.set_user_email(val.clone());
-                                config.save()?;
-                                println!("Set user.email = {}", val);
-                            }
-                            _ => println!("Unknown config key: {}", key),
-                        }
-                    } else {
-                        println!("--value is required with --set");
+        Commands::Gc { prune } => {
+            let repo = Repository::open(".")?;
+            gc::run_gc(&repo, prune.as_deref()).await?;
+        }
+        Commands::CommitGraph { subcommand } => {
+            let repo = Repository::open(".")?;
+            match subcommand {
+                CommitGraphSubcommand::Write => commit_graph::write_commit_graph(&repo).await?,
+            }
+        }
+        Commands::Clean { force, dry_run, directories } => {
+            let repo = Repository::open(".")?;
+            let options = clean::CleanOptions {
+                force: *force,
+                dry_run: *dry_run,
+                directories: *directories,
+            };
+            clean::clean(&repo, &options).await?;
+        }
+        Commands::Prune { dry_run } => {
+            let repo = Repository::open(".")?;
+            prune::run_prune(&repo, *dry_run).await?;
+        }
+        Commands::Fsck { unreachable } => {
+            let repo = Repository::open(".")?;
+            if !fsck::run_fsck(&repo, *unreachable).await? {
+                status = ExitCode::FAILURE;
+            }
+        }
+        Commands::Blame { file, rev, incremental } => {
+            let repo = Repository::open(".")?;
+            let options = blame::BlameOptions {
+                rev: rev.clone(),
+                incremental: *incremental,
+            };
+            blame::blame(&repo, file, &options).await?;
+        }
+        Commands::Revert { commit } => {
+            let mut repo = Repository::open(".")?;
+            let keypair = utils::key_utils::load_signing_key()?;
+            revert::revert_commit(&mut repo, commit, &keypair).await?;
+        }
+        Commands::CherryPick { commit } => {
+            let mut repo = Repository::open(".")?;
+            let keypair = utils::key_utils::load_signing_key()?;
+            cherry_pick::cherry_pick(&mut repo, commit, &keypair).await?;
+        }
+        Commands::Rebase { upstream, autosquash } => {
+            let mut repo = Repository::open(".")?;
+            let keypair = utils::key_utils::load_signing_key()?;
+            let upstream = repo.resolve_rev(upstream)?;
+            let options = rebase::RebaseOptions { autosquash: *autosquash };
+            rebase::rebase_onto(&mut repo, &upstream, &options, &keypair).await?;
+        }
+        Commands::Undo { list } => {
+            let repo = Repository::open(".")?;
+            undo::undo(&repo, *list).await?;
+        }
+        Commands::Reflog { limit } => {
+            let repo = Repository::open(".")?;
+            reflog::show_reflog(&repo, *limit).await?;
+        }
+        Commands::RevParse { rev, abbrev_ref, show_toplevel } => {
+            let repo = Repository::open(".")?;
+            if *show_toplevel {
+                rev_parse::show_toplevel(&repo).await?;
+            }
+            if let Some(rev) = rev {
+                rev_parse::rev_parse(&repo, rev, *abbrev_ref).await?;
+            } else if !*show_toplevel {
+                anyhow::bail!("Usage: hx rev-parse [--abbrev-ref] <rev> | --show-toplevel");
+            }
+        }
+        Commands::Tag { name, commit, list, annotate, message, delete, sort } => {
+            let repo = Repository::open(".")?;
+            match name {
+                Some(name) if *delete => tag::delete_tag(&repo, name).await?,
+                Some(name) if !*list => {
+                    let options = tag::TagOptions {
+                        annotate: *annotate,
+                        message: message.clone(),
+                    };
+                    tag::create_tag(&repo, name, commit.as_deref(), &options).await?
+                }
+                _ => tag::list_tags(&repo, name.as_deref(), sort.as_deref()).await?,
+            }
+        }
+        Commands::Submodule { subcommand } => {
+            let mut repo = Repository::open(".")?;
+            match subcommand {
+                SubmoduleSubcommand::Add { url, path } => {
+                    submodule::add_submodule(&mut repo, url, path).await?
+                }
+                SubmoduleSubcommand::Update => submodule::update_submodules(&repo).await?,
+            }
+        }
+        Commands::VerifyPack { file, verbose } => {
+            if !verify_pack::verify_pack(file, *verbose).await? {
+                status = ExitCode::FAILURE;
+            }
+        }
+        Commands::Stash { subcommand } => {
+            let mut repo = Repository::open(".")?;
+            match subcommand {
+                None => stash::stash_push(&mut repo, None).await?,
+                Some(StashSubcommand::Push { message }) => {
+                    stash::stash_push(&mut repo, message.as_deref()).await?
+                }
+                Some(StashSubcommand::List) => stash::stash_list(&repo).await?,
+                Some(StashSubcommand::Pop { stash }) => {
+                    stash::stash_apply(&mut repo, stash.as_deref(), true).await?
+                }
+                Some(StashSubcommand::Apply { stash }) => {
+                    stash::stash_apply(&mut repo, stash.as_deref(), false).await?
+                }
+                Some(StashSubcommand::Drop { stash }) => {
+                    stash::stash_drop(&repo, stash.as_deref()).await?
+                }
+                Some(StashSubcommand::Show { stash }) => {
+                    stash::stash_show(&repo, stash.as_deref()).await?
+                }
+            }
+        }
+        Commands::Config { key, key_value, global, list, get, set, value } => {
+            // `hx config <key> [<value>]` is shorthand for the --get/--set form
+            let (get, set, value) = match (key, key_value) {
+                (Some(key), Some(val)) => (None, Some(key), Some(val)),
+                (Some(key), None) => (Some(key), None, None),
+                _ => (get.as_ref(), set.as_ref(), value.as_ref()),
+            };
+            // Inside a repository, its own keys are read and written there
+            let mut repo = if *global { None } else { Repository::open(".").ok() };
+            let mut config = GlobalConfig::load()?;
+            if *list {
+                let local = repo.as_ref().map(|r| r.config.entries()).unwrap_or_default();
+                for (key, val) in config.entries().into_iter().chain(local) {
+                    println!("{} = {}", key, val);
+                }
+            } else if let Some(key) = set {
+                let Some(val) = value else {
+                    anyhow::bail!("--value is required with --set");
+                };
+                let scope = match repo.as_mut() {
+                    Some(repo) if RepositoryConfig::KEYS.contains(&key.as_str()) => {
+                        repo.config.set(key, val)?;
+                        repo.save()?;
+                        "repository"
                     }
-                } else if let Some(key) = get {
-                    match key.as_str() {
-                        "user.name" => println!("user.name = {}", config.get_user_name().unwrap_or("")),
-                        "user.email" => println!("user.email = {}", config.get_user_email().unwrap_or("")),
-                        _ => println!("Unknown config key: {}", key),
+                    _ => {
+                        config.set(key, val)?;
+                        config.save()?;
+                        "global"
                     }
-                } else {
-                    println!("Use --set <key> --value <val> or --get <key>");
+                };
+                println!("Set {} = {} ({} config)", key, val, scope);
+            } else if let Some(key) = get {
+                let val = match &repo {
+                    Some(repo) => repo.config.get_or_global(&config, key)?,
+                    None => config.get(key)?,
+                };
+                // Like `git config --get`, an unset key prints nothing and fails
+                match val {
+                    Some(val) => println!("{} = {}", key, val),
+                    None => status = ExitCode::FAILURE,
                 }
             } else {
-                println!("Only --global config is supported");
+                println!("Use --set <key> --value <val>, --get <key> or --list");
             }
         }
     }
 
-    Ok(())
+    Ok(status)
 }
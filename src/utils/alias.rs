@@ -0,0 +1,96 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+
+pub enum AliasExpansion {
+    /// Arguments to parse as a regular command line
+    Args(Vec<String>),
+    /// A `!` alias: shell command to run, followed by the remaining arguments
+    Shell(String, Vec<String>),
+}
+
+/// Expand the subcommand in `args` (program name first) when it names an
+/// alias. Built-in commands always win over aliases, and an alias may
+/// expand to another alias.
+pub fn expand(
+    mut args: Vec<String>,
+    builtins: &[String],
+    aliases: &BTreeMap<String, String>,
+) -> Result<AliasExpansion> {
+    let mut seen = HashSet::new();
+    while let Some(name) = args.get(1).cloned() {
+        if builtins.contains(&name) {
+            break;
+        }
+        let expansion = match aliases.get(&name) {
+            Some(expansion) => expansion,
+            None => break,
+        };
+        if !seen.insert(name.clone()) {
+            anyhow::bail!("Alias loop detected while expanding '{}'", name);
+        }
+
+        let rest = args.split_off(2);
+        if let Some(command) = expansion.strip_prefix('!') {
+            return Ok(AliasExpansion::Shell(command.to_string(), rest));
+        }
+        let tokens: Vec<String> = expansion.split_whitespace().map(|t| t.to_string()).collect();
+        if tokens.is_empty() {
+            anyhow::bail!("Alias '{}' is empty", name);
+        }
+        args.truncate(1);
+        args.extend(tokens);
+        args.extend(rest);
+    }
+    Ok(AliasExpansion::Args(args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    fn aliases(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn expanded(line: &str, aliases: &BTreeMap<String, String>) -> Vec<String> {
+        let builtins = args("checkout status log");
+        match expand(args(line), &builtins, aliases).unwrap() {
+            AliasExpansion::Args(args) => args,
+            AliasExpansion::Shell(command, _) => panic!("unexpected shell alias {}", command),
+        }
+    }
+
+    #[test]
+    fn co_expands_to_checkout_and_keeps_its_arguments() {
+        let aliases = aliases(&[("co", "checkout"), ("lg", "log --graph --limit 5"), ("l", "lg")]);
+        assert_eq!(expanded("hx co main --force", &aliases), args("hx checkout main --force"));
+        assert_eq!(expanded("hx lg --stat", &aliases), args("hx log --graph --limit 5 --stat"));
+        assert_eq!(expanded("hx l", &aliases), args("hx log --graph --limit 5"));
+        assert_eq!(expanded("hx status", &aliases), args("hx status"));
+    }
+
+    #[test]
+    fn builtins_win_and_loops_are_refused() {
+        let shadow = aliases(&[("status", "log")]);
+        assert_eq!(expanded("hx status", &shadow), args("hx status"));
+
+        let looping = aliases(&[("a", "b"), ("b", "a")]);
+        assert!(expand(args("hx a"), &[], &looping).is_err());
+    }
+
+    #[test]
+    fn bang_aliases_run_in_the_shell() {
+        let aliases = aliases(&[("hi", "!echo hi")]);
+        match expand(args("hx hi there"), &[], &aliases).unwrap() {
+            AliasExpansion::Shell(command, rest) => {
+                assert_eq!(command, "echo hi");
+                assert_eq!(rest, args("there"));
+            }
+            AliasExpansion::Args(args) => panic!("expected a shell alias, got {:?}", args),
+        }
+    }
+}
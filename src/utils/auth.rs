@@ -16,6 +16,36 @@ pub enum AuthMethod {
     OAuth2 { token: String, refresh_token: Option<String> },
 }
 
+impl AuthMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthMethod::None => "None",
+            AuthMethod::Token(_) => "Token",
+            AuthMethod::Basic { .. } => "Basic",
+            AuthMethod::SSH { .. } => "SSH",
+            AuthMethod::OAuth2 { .. } => "OAuth2",
+        }
+    }
+
+    /// Safe-to-print details: the last 4 characters of a token, the
+    /// username but never the password, the SSH key path.
+    pub fn redacted(&self) -> String {
+        fn tail(secret: &str) -> String {
+            let chars: Vec<char> = secret.chars().collect();
+            let shown: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+            format!("****{}", shown)
+        }
+        match self {
+            AuthMethod::None => String::new(),
+            AuthMethod::Token(token) => tail(token),
+            AuthMethod::Basic { username, .. } => format!("user {}, password ****", username),
+            AuthMethod::SSH { key_path: Some(path) } => format!("key {}", path.display()),
+            AuthMethod::SSH { key_path: None } => "default key".to_string(),
+            AuthMethod::OAuth2 { token, .. } => tail(token),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthConfig {
     pub method: AuthMethod,
@@ -99,6 +129,17 @@ impl AuthManager {
         self.configs.get(host)
     }
 
+    /// Configured hosts and their methods, sorted by host.
+    pub fn list_hosts(&self) -> Vec<(String, &AuthMethod)> {
+        let mut hosts: Vec<(String, &AuthMethod)> = self
+            .configs
+            .iter()
+            .map(|(host, config)| (host.clone(), &config.method))
+            .collect();
+        hosts.sort_by(|a, b| a.0.cmp(&b.0));
+        hosts
+    }
+
     pub fn remove_config(&mut self, host: &str) -> Result<()> {
         self.configs.remove(host);
         self.save_configs()?;
@@ -194,7 +235,7 @@ impl AuthManager {
     }
 }
 
-fn extract_host_from_url(url: &str) -> Result<String> {
+pub fn extract_host_from_url(url: &str) -> Result<String> {
     if url.starts_with("http://") || url.starts_with("https://") {
         let url = Url::parse(url)
             .with_context(|| format!("Invalid URL: {}", url))?;
@@ -241,4 +282,62 @@ pub fn get_default_ssh_key_path() -> Option<PathBuf> {
             None
         }
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::remote_client::RemoteClient;
+    use crate::utils::test_utils::{MockResponse, MockServer};
+
+    /// A manager over `configs` that saves next to the test, not in the
+    /// user's config directory.
+    fn manager(dir: &tempfile::TempDir, configs: Vec<AuthConfig>) -> AuthManager {
+        AuthManager {
+            configs: configs.into_iter().map(|c| (c.host.clone(), c)).collect(),
+            config_file: dir.path().join("auth.json"),
+        }
+    }
+
+    #[test]
+    fn listed_hosts_are_sorted_and_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = manager(
+            &dir,
+            vec![
+                AuthConfig::new("z.example.com").with_basic_auth("alice", "hunter2"),
+                AuthConfig::new("a.example.com").with_token("ghp_secrettoken1234"),
+                AuthConfig::new("m.example.com").with_ssh(None),
+            ],
+        );
+
+        let hosts = auth.list_hosts();
+        let names: Vec<_> = hosts.iter().map(|(host, method)| (host.as_str(), method.name())).collect();
+        assert_eq!(
+            names,
+            vec![("a.example.com", "Token"), ("m.example.com", "SSH"), ("z.example.com", "Basic")]
+        );
+        assert_eq!(hosts[0].1.redacted(), "****1234");
+        assert_eq!(hosts[1].1.redacted(), "default key");
+        let basic = hosts[2].1.redacted();
+        assert!(basic.contains("alice"));
+        assert!(!basic.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn probe_sends_the_configured_credentials() {
+        let server = MockServer::start(|request| match request.header("authorization") {
+            Some("Bearer letmein") => MockResponse::new(200, ""),
+            _ => MockResponse::new(401, ""),
+        });
+        let dir = tempfile::tempdir().unwrap();
+
+        let anonymous = RemoteClient::new(&server.url).with_auth_manager(manager(&dir, vec![]));
+        assert_eq!(anonymous.probe("/info/refs").await.unwrap().as_u16(), 401);
+
+        let host = extract_host_from_url(&server.url).unwrap();
+        let configured = manager(&dir, vec![AuthConfig::new(&host).with_token("letmein")]);
+        let client = RemoteClient::new(&server.url).with_auth_manager(configured);
+        assert_eq!(client.probe("/info/refs").await.unwrap().as_u16(), 200);
+        assert!(server.requests().iter().all(|r| r.path == "/info/refs"));
+    }
+}
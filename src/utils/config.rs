@@ -1,12 +1,21 @@
 use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GlobalConfig {
     pub user: Option<UserConfig>,
+    pub core: Option<CoreConfig>,
+    pub gc: Option<GcConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpConfig>,
+    /// Command aliases: name -> expansion (`!` prefix runs a shell command)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub alias: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -15,6 +24,34 @@ pub struct UserConfig {
     pub email: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CoreConfig {
+    /// Command used to page `log` and `diff` output
+    pub pager: Option<String>,
+    /// Default line-ending policy: `true`, `input` or `false`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autocrlf: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct GcConfig {
+    #[serde(rename = "pruneExpire")]
+    pub prune_expire: Option<String>,
+    /// How long `hx undo` snapshots are kept
+    #[serde(rename = "undoExpire", default, skip_serializing_if = "Option::is_none")]
+    pub undo_expire: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HttpConfig {
+    /// Times a request is retried after a transient failure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<String>,
+    /// Delay before the first retry, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<String>,
+}
+
 impl GlobalConfig {
     pub fn config_path() -> PathBuf {
         dirs::home_dir().unwrap().join(".helixconfig")
@@ -58,4 +95,174 @@ impl GlobalConfig {
     pub fn get_user_email(&self) -> Option<&str> {
         self.user.as_ref()?.email.as_deref()
     }
-} 
\ No newline at end of file
+
+    pub fn set_core_pager(&mut self, pager: String) {
+        self.core.get_or_insert_with(CoreConfig::default).pager = Some(pager);
+    }
+
+    pub fn get_core_pager(&self) -> Option<&str> {
+        self.core.as_ref()?.pager.as_deref()
+    }
+
+    pub fn set_core_autocrlf(&mut self, autocrlf: String) {
+        self.core.get_or_insert_with(CoreConfig::default).autocrlf = Some(autocrlf);
+    }
+
+    pub fn get_core_autocrlf(&self) -> Option<&str> {
+        self.core.as_ref()?.autocrlf.as_deref()
+    }
+
+    pub fn set_gc_prune_expire(&mut self, expire: String) {
+        self.gc.get_or_insert_with(GcConfig::default).prune_expire = Some(expire);
+    }
+
+    pub fn get_gc_prune_expire(&self) -> Option<&str> {
+        self.gc.as_ref()?.prune_expire.as_deref()
+    }
+
+    pub fn set_gc_undo_expire(&mut self, expire: String) {
+        self.gc.get_or_insert_with(GcConfig::default).undo_expire = Some(expire);
+    }
+
+    pub fn get_gc_undo_expire(&self) -> Option<&str> {
+        self.gc.as_ref()?.undo_expire.as_deref()
+    }
+
+    pub fn set_http_retries(&mut self, retries: String) {
+        self.http.get_or_insert_with(HttpConfig::default).retries = Some(retries);
+    }
+
+    pub fn get_http_retries(&self) -> Option<usize> {
+        self.http.as_ref()?.retries.as_deref()?.parse().ok()
+    }
+
+    pub fn set_http_backoff(&mut self, backoff: String) {
+        self.http.get_or_insert_with(HttpConfig::default).backoff = Some(backoff);
+    }
+
+    pub fn get_http_backoff(&self) -> Option<Duration> {
+        self.http.as_ref()?.backoff.as_deref()?.parse().ok().map(Duration::from_millis)
+    }
+
+    pub fn set_alias(&mut self, name: String, expansion: String) {
+        self.alias.insert(name, expansion);
+    }
+
+    pub fn get_alias(&self, name: &str) -> Option<&str> {
+        self.alias.get(name).map(|s| s.as_str())
+    }
+
+    /// Set `key` (`user.name`, `core.pager`, `alias.<name>`, ...), checking
+    /// the value where the key has a format.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = value.to_string();
+        match key {
+            "user.name" => self.set_user_name(value),
+            "user.email" => self.set_user_email(value),
+            "core.pager" => self.set_core_pager(value),
+            "core.autocrlf" => {
+                crate::utils::eol::AutoCrlf::parse(&value)?;
+                self.set_core_autocrlf(value);
+            }
+            "gc.pruneExpire" => {
+                crate::utils::date_utils::parse_date(&value)?;
+                self.set_gc_prune_expire(value);
+            }
+            "gc.undoExpire" => {
+                crate::utils::date_utils::parse_date(&value)?;
+                self.set_gc_undo_expire(value);
+            }
+            "http.retries" => {
+                value.parse::<usize>().map_err(|_| anyhow::anyhow!("http.retries must be a number, not '{}'", value))?;
+                self.set_http_retries(value);
+            }
+            "http.backoff" => {
+                value.parse::<u64>().map_err(|_| anyhow::anyhow!("http.backoff must be milliseconds, not '{}'", value))?;
+                self.set_http_backoff(value);
+            }
+            k if k.starts_with("alias.") && k.len() > "alias.".len() => {
+                self.set_alias(k["alias.".len()..].to_string(), value);
+            }
+            _ => anyhow::bail!("Unknown config key: {}", key),
+        }
+        Ok(())
+    }
+
+    /// The value of `key`, `None` when it is a known key that isn't set.
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let value = match key {
+            "user.name" => self.get_user_name(),
+            "user.email" => self.get_user_email(),
+            "core.pager" => self.get_core_pager(),
+            "core.autocrlf" => self.get_core_autocrlf(),
+            "gc.pruneExpire" => self.get_gc_prune_expire(),
+            "gc.undoExpire" => self.get_gc_undo_expire(),
+            "http.retries" => self.http.as_ref().and_then(|http| http.retries.as_deref()),
+            "http.backoff" => self.http.as_ref().and_then(|http| http.backoff.as_deref()),
+            k if k.starts_with("alias.") => self.get_alias(&k["alias.".len()..]),
+            _ => anyhow::bail!("Unknown config key: {}", key),
+        };
+        Ok(value.map(str::to_string))
+    }
+
+    /// Every key that is set, in `hx config --list` order.
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let keys = [
+            "user.name",
+            "user.email",
+            "core.pager",
+            "core.autocrlf",
+            "gc.pruneExpire",
+            "gc.undoExpire",
+            "http.retries",
+            "http.backoff",
+        ];
+        let mut entries: Vec<(String, String)> = keys
+            .iter()
+            .filter_map(|key| Some((key.to_string(), self.get(key).ok()??)))
+            .collect();
+        entries.extend(
+            self.alias
+                .iter()
+                .map(|(name, expansion)| (format!("alias.{}", name), expansion.clone())),
+        );
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_get_and_list_round_trip() {
+        let mut config = GlobalConfig::default();
+        config.set("user.email", "me@example.com").unwrap();
+        config.set("user.name", "Me").unwrap();
+        config.set("gc.undoExpire", "2.weeks.ago").unwrap();
+        config.set("alias.co", "checkout").unwrap();
+
+        assert_eq!(config.get("user.name").unwrap().as_deref(), Some("Me"));
+        assert_eq!(config.get("core.pager").unwrap(), None);
+        assert_eq!(config.get("alias.co").unwrap().as_deref(), Some("checkout"));
+        let keys: Vec<String> = config.entries().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["user.name", "user.email", "gc.undoExpire", "alias.co"]);
+
+        // What `save` writes, `load` reads back
+        let reloaded: GlobalConfig = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(reloaded.entries(), config.entries());
+    }
+
+    #[test]
+    fn unknown_keys_and_bad_values_are_rejected() {
+        let mut config = GlobalConfig::default();
+        assert!(config.set("user.nickname", "x").is_err());
+        assert!(config.get("user.nickname").is_err());
+        assert!(config.set("alias.", "x").is_err());
+        assert!(config.set("core.autocrlf", "maybe").is_err());
+        assert!(config.set("gc.pruneExpire", "whenever").is_err());
+        assert!(config.set("http.retries", "-1").is_err());
+        assert!(config.set("http.backoff", "1s").is_err());
+        assert!(config.entries().is_empty());
+    }
+}
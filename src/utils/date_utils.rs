@@ -0,0 +1,68 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+/// Environment variables consulted (in order) for an explicit commit date.
+pub const COMMIT_DATE_VARS: [&str; 2] = ["HX_COMMIT_DATE", "HX_AUTHOR_DATE"];
+
+pub fn parse_rfc3339(value: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid date '{}': {} (expected RFC3339, e.g. 2024-01-31T12:00:00Z)",
+                value,
+                e
+            )
+        })
+}
+
+/// The commit timestamp: an explicit `--date`, then `HX_COMMIT_DATE` /
+/// `HX_AUTHOR_DATE`, then the current time.
+pub fn resolve_commit_date(date: Option<&str>) -> Result<DateTime<Utc>> {
+    if let Some(date) = date {
+        return parse_rfc3339(date);
+    }
+    for var in COMMIT_DATE_VARS {
+        if let Ok(value) = std::env::var(var) {
+            if !value.trim().is_empty() {
+                return parse_rfc3339(&value).map_err(|e| anyhow::anyhow!("{}: {}", var, e));
+            }
+        }
+    }
+    Ok(Utc::now())
+}
+
+/// Parse a user-supplied date: `now`, RFC3339, `YYYY-MM-DD`, or a relative
+/// `<n>.<unit>.ago` (units: minutes, hours, days, weeks).
+pub fn parse_date(value: &str) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if value == "now" {
+        return Ok(Utc::now());
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    if let Some(relative) = value.strip_suffix(".ago") {
+        if let Some((amount, unit)) = relative.split_once('.') {
+            if let Ok(amount) = amount.parse::<i64>() {
+                let duration = match unit.trim_end_matches('s') {
+                    "minute" => Some(Duration::minutes(amount)),
+                    "hour" => Some(Duration::hours(amount)),
+                    "day" => Some(Duration::days(amount)),
+                    "week" => Some(Duration::weeks(amount)),
+                    _ => None,
+                };
+                if let Some(duration) = duration {
+                    return Ok(Utc::now() - duration);
+                }
+            }
+        }
+    }
+    anyhow::bail!(
+        "Invalid date '{}' (expected 'now', RFC3339, YYYY-MM-DD or e.g. 2.weeks.ago)",
+        value
+    )
+}
@@ -0,0 +1,285 @@
+use similar::{ChangeTag, TextDiff};
+use std::collections::{BTreeMap, HashMap};
+
+/// Default minimum similarity (percent) for two blobs to be paired as a rename.
+pub const DEFAULT_RENAME_THRESHOLD: u8 = 50;
+
+/// Fewest consecutive lines that count as a moved block.
+pub const MIN_MOVED_LINES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PairKind {
+    Rename,
+    Copy,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilePair {
+    pub kind: PairKind,
+    pub old_path: String,
+    pub new_path: String,
+    pub similarity: u8,
+}
+
+/// Line-based similarity between two blobs, as a percentage.
+pub fn similarity(old: &str, new: &str) -> u8 {
+    if old == new {
+        return 100;
+    }
+    if old.is_empty() || new.is_empty() {
+        return 0;
+    }
+    (TextDiff::from_lines(old, new).ratio() * 100.0).round() as u8
+}
+
+/// Pair added paths with deleted paths (renames) and, optionally, with any
+/// path of the old side (copies).
+///
+/// `old_files` and `new_files` map paths to their blob hashes; `load` returns
+/// the blob content for a hash. Exact hash matches are paired first, then the
+/// best content match at or above `threshold` percent.
+pub fn detect_renames<F>(
+    old_files: &BTreeMap<String, String>,
+    new_files: &BTreeMap<String, String>,
+    threshold: u8,
+    find_copies: bool,
+    mut load: F,
+) -> Vec<FilePair>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let added: Vec<&String> = new_files
+        .keys()
+        .filter(|p| !old_files.contains_key(*p))
+        .collect();
+    let mut deleted: Vec<&String> = old_files
+        .keys()
+        .filter(|p| !new_files.contains_key(*p))
+        .collect();
+
+    let mut pairs = Vec::new();
+    let mut unmatched = Vec::new();
+
+    // Exact renames: identical blob on both sides
+    for new_path in added {
+        let new_hash = &new_files[new_path];
+        if let Some(pos) = deleted.iter().position(|p| &old_files[*p] == new_hash) {
+            let old_path = deleted.remove(pos);
+            pairs.push(FilePair {
+                kind: PairKind::Rename,
+                old_path: old_path.clone(),
+                new_path: new_path.clone(),
+                similarity: 100,
+            });
+        } else {
+            unmatched.push(new_path);
+        }
+    }
+
+    // Inexact renames, then copies from any old path
+    let mut cache: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut content = |hash: &str| -> Option<String> {
+        cache
+            .entry(hash.to_string())
+            .or_insert_with(|| load(hash))
+            .clone()
+    };
+
+    for new_path in unmatched {
+        let new_content = match content(&new_files[new_path]) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        let mut best: Option<(usize, u8)> = None;
+        for (i, old_path) in deleted.iter().enumerate() {
+            if let Some(old_content) = content(&old_files[*old_path]) {
+                let score = similarity(&old_content, &new_content);
+                if score >= threshold && best.is_none_or(|(_, s)| score > s) {
+                    best = Some((i, score));
+                }
+            }
+        }
+        if let Some((i, score)) = best {
+            let old_path = deleted.remove(i);
+            pairs.push(FilePair {
+                kind: PairKind::Rename,
+                old_path: old_path.clone(),
+                new_path: new_path.clone(),
+                similarity: score,
+            });
+            continue;
+        }
+
+        if find_copies {
+            let mut best: Option<(&String, u8)> = None;
+            for (old_path, old_hash) in old_files {
+                if let Some(old_content) = content(old_hash) {
+                    let score = similarity(&old_content, &new_content);
+                    if score >= threshold && best.is_none_or(|(_, s)| score > s) {
+                        best = Some((old_path, score));
+                    }
+                }
+            }
+            if let Some((old_path, score)) = best {
+                pairs.push(FilePair {
+                    kind: PairKind::Copy,
+                    old_path: old_path.clone(),
+                    new_path: new_path.clone(),
+                    similarity: score,
+                });
+            }
+        }
+    }
+
+    pairs
+}
+
+/// Mark deleted and inserted lines that belong to a block moved within the
+/// same diff: at least `min_lines` consecutive deletions reappearing as
+/// consecutive insertions. Lines compare with surrounding whitespace
+/// trimmed, so re-indented blocks still count as moved.
+pub fn detect_moved_lines(changes: &[(ChangeTag, &str)], min_lines: usize) -> Vec<bool> {
+    let mut moved = vec![false; changes.len()];
+    let key = |i: usize| changes[i].1.trim();
+
+    // Deleted line text -> positions, for candidate block starts
+    let mut deleted: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, (tag, _)) in changes.iter().enumerate() {
+        if *tag == ChangeTag::Delete && !key(i).is_empty() {
+            deleted.entry(key(i)).or_default().push(i);
+        }
+    }
+
+    let mut i = 0;
+    while i < changes.len() {
+        if changes[i].0 != ChangeTag::Insert || key(i).is_empty() {
+            i += 1;
+            continue;
+        }
+        // Longest run of unclaimed deletions matching the insertions from here
+        let mut best = (0, 0);
+        for &start in deleted.get(key(i)).map(|v| v.as_slice()).unwrap_or(&[]) {
+            let mut len = 0;
+            while i + len < changes.len()
+                && start + len < changes.len()
+                && changes[i + len].0 == ChangeTag::Insert
+                && changes[start + len].0 == ChangeTag::Delete
+                && !moved[start + len]
+                && key(i + len) == key(start + len)
+            {
+                len += 1;
+            }
+            if len > best.1 {
+                best = (start, len);
+            }
+        }
+        if best.1 >= min_lines {
+            for k in 0..best.1 {
+                moved[i + k] = true;
+                moved[best.0 + k] = true;
+            }
+            i += best.1;
+        } else {
+            i += 1;
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Changes of a line diff as (tag, line) pairs, with the moved flags.
+    fn moved_lines(old: &str, new: &str) -> Vec<(ChangeTag, String, bool)> {
+        let diff = TextDiff::from_lines(old, new);
+        let changes: Vec<(ChangeTag, String)> = diff
+            .iter_all_changes()
+            .map(|change| (change.tag(), change.to_string().trim_end().to_string()))
+            .collect();
+        let borrowed: Vec<(ChangeTag, &str)> =
+            changes.iter().map(|(tag, line)| (*tag, line.as_str())).collect();
+        let moved = detect_moved_lines(&borrowed, MIN_MOVED_LINES);
+        changes.into_iter().zip(moved).map(|((tag, line), moved)| (tag, line, moved)).collect()
+    }
+
+    #[test]
+    fn a_moved_block_is_a_move_not_an_add_and_delete() {
+        let old = "one();\ntwo();\nthree();\nkeep1\nkeep2\nkeep3\nkeep4\n";
+        let new = "keep1\nkeep2\nkeep3\nkeep4\none();\ntwo();\nthree();\nadded();\n";
+        let changed: Vec<(ChangeTag, String, bool)> =
+            moved_lines(old, new).into_iter().filter(|c| c.0 != ChangeTag::Equal).collect();
+        let expected = [
+            (ChangeTag::Delete, "one();", true),
+            (ChangeTag::Delete, "two();", true),
+            (ChangeTag::Delete, "three();", true),
+            (ChangeTag::Insert, "one();", true),
+            (ChangeTag::Insert, "two();", true),
+            (ChangeTag::Insert, "three();", true),
+            (ChangeTag::Insert, "added();", false),
+        ];
+        let changed: Vec<(ChangeTag, &str, bool)> =
+            changed.iter().map(|(tag, line, moved)| (*tag, line.as_str(), *moved)).collect();
+        assert_eq!(changed, expected);
+    }
+
+    #[test]
+    fn short_blocks_are_not_moves_but_reindented_ones_are() {
+        use ChangeTag::{Delete, Insert};
+        let short = [(Delete, "a"), (Delete, "b"), (Insert, "a"), (Insert, "b")];
+        assert_eq!(detect_moved_lines(&short, MIN_MOVED_LINES), vec![false; 4]);
+
+        let reindented = [
+            (Delete, "one"),
+            (Delete, "two"),
+            (Delete, "three"),
+            (Insert, "    one"),
+            (Insert, "    two"),
+            (Insert, "    three"),
+        ];
+        assert_eq!(detect_moved_lines(&reindented, MIN_MOVED_LINES), vec![true; 6]);
+    }
+
+    fn paths(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|(path, hash)| (path.to_string(), hash.to_string())).collect()
+    }
+
+    #[test]
+    fn identical_blobs_pair_without_reading_content() {
+        let old = paths(&[("a.rs", "h1"), ("keep.rs", "h2")]);
+        let new = paths(&[("b.rs", "h1"), ("keep.rs", "h2")]);
+        let pairs = detect_renames(&old, &new, 100, false, |_| panic!("content was loaded"));
+
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].old_path.as_str(), pairs[0].new_path.as_str()), ("a.rs", "b.rs"));
+        assert_eq!((pairs[0].kind.clone(), pairs[0].similarity), (PairKind::Rename, 100));
+    }
+
+    #[test]
+    fn near_identical_blobs_pair_only_at_or_above_the_threshold() {
+        // Seven of eight lines shared: 2 * 7 / 16 = 87.5%
+        let old_content = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        let new_content = "1\n2\n3\n4\n5\n6\n7\nEIGHT\n";
+        assert_eq!(similarity(old_content, new_content), 88);
+
+        let old = paths(&[("a.rs", old_content)]);
+        let new = paths(&[("b.rs", new_content)]);
+        let load = |hash: &str| Some(hash.to_string());
+
+        let at = detect_renames(&old, &new, 88, false, load);
+        assert_eq!(at.len(), 1);
+        assert_eq!(at[0].similarity, 88);
+        assert!(detect_renames(&old, &new, 89, false, load).is_empty());
+        assert_eq!(detect_renames(&old, &new, DEFAULT_RENAME_THRESHOLD, false, load).len(), 1);
+    }
+
+    #[test]
+    fn empty_and_unrelated_blobs_never_pair() {
+        assert_eq!(similarity("", "text\n"), 0);
+        assert_eq!(similarity("same\n", "same\n"), 100);
+        let old = paths(&[("a.rs", "alpha\nbeta\n")]);
+        let new = paths(&[("b.rs", "gamma\ndelta\n")]);
+        assert!(detect_renames(&old, &new, 1, true, |hash| Some(hash.to_string())).is_empty());
+    }
+}
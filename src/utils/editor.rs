@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::Command;
+
+/// The editor for interactive messages: `HX_EDITOR`, then `$VISUAL`, then
+/// `$EDITOR`. `None` when none is set or stdin isn't a terminal.
+pub fn editor() -> Option<String> {
+    if !std::io::stdin().is_terminal() {
+        return None;
+    }
+    ["HX_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(|editor| editor.trim().to_string())
+        .find(|editor| !editor.is_empty())
+}
+
+/// Write `initial` to `path`, open it in `editor` and read the result back.
+/// Lines starting with `#` are dropped and surrounding blank lines trimmed.
+pub fn edit_message(editor: &str, path: &Path, initial: &str) -> Result<String> {
+    fs::write(path, initial).with_context(|| format!("Failed to write {}", path.display()))?;
+    // Let the shell split the editor command, so `code --wait` works
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg(editor)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to start editor '{}'", editor))?;
+    if !status.success() {
+        anyhow::bail!("Editor '{}' exited with {}", editor, status);
+    }
+    let edited = fs::read_to_string(path)?;
+    let _ = fs::remove_file(path);
+    Ok(edited
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string())
+}
@@ -0,0 +1,136 @@
+use crate::core::repository::Repository;
+
+/// `core.autocrlf`: how line endings are normalized between the working
+/// tree and the object store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutoCrlf {
+    /// CRLF -> LF when adding, LF -> CRLF when writing files out
+    True,
+    /// CRLF -> LF when adding only
+    Input,
+    /// Store and write bytes verbatim
+    #[default]
+    False,
+}
+
+impl AutoCrlf {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "true" => Ok(Self::True),
+            "input" => Ok(Self::Input),
+            "false" => Ok(Self::False),
+            other => anyhow::bail!(
+                "Invalid core.autocrlf value '{}' (expected true, input or false)",
+                other
+            ),
+        }
+    }
+}
+
+/// Whether `relative_path` may have its line endings rewritten: never for
+/// content with NUL bytes, or paths marked `binary` or `-text`.
+fn is_text(repo: &Repository, relative_path: &str, data: &[u8]) -> bool {
+    let attributes = repo.attributes();
+    !data.contains(&0) && !attributes.has(relative_path, "binary") && !attributes.has(relative_path, "-text")
+}
+
+/// Working tree bytes as they should be stored.
+pub fn to_repo(repo: &Repository, relative_path: &str, data: Vec<u8>) -> Vec<u8> {
+    match repo.autocrlf() {
+        AutoCrlf::True | AutoCrlf::Input if is_text(repo, relative_path, &data) => {
+            crlf_to_lf(&data)
+        }
+        _ => data,
+    }
+}
+
+/// Stored bytes as they should be written to the working tree.
+pub fn to_worktree(repo: &Repository, relative_path: &str, data: &[u8]) -> Vec<u8> {
+    match repo.autocrlf() {
+        AutoCrlf::True if is_text(repo, relative_path, data) => lf_to_crlf(data),
+        _ => data.to_vec(),
+    }
+}
+
+fn crlf_to_lf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        out.push(byte);
+    }
+    out
+}
+
+/// Bare LFs become CRLF; existing CRLFs are left alone.
+fn lf_to_crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 32);
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(byte);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::restore::{self, RestoreOptions};
+    use crate::core::object::Object;
+    use crate::utils::test_utils::TestRepo;
+
+    /// Commit a CRLF text file and a binary one under `autocrlf`, then
+    /// restore both; returns the stored text blob and the restored copies.
+    async fn round_trip(autocrlf: &str) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let mut t = TestRepo::new();
+        t.repo.config.autocrlf = Some(autocrlf.to_string());
+        t.write("text.txt", "one\r\ntwo\r\n");
+        t.write_bytes("data.bin", b"\r\n\0\n");
+        t.commit("files").await;
+
+        let stored_id = &t.repo.head_files()["text.txt"];
+        let stored = Object::load(&t.repo.get_objects_dir(), stored_id).unwrap().data;
+        t.remove("text.txt");
+        t.remove("data.bin");
+        let paths = vec![t.repo.path.join("text.txt"), t.repo.path.join("data.bin")];
+        restore::restore_files(&mut t.repo, paths, &RestoreOptions::default()).await.unwrap();
+        let text = std::fs::read(t.repo.path.join("text.txt")).unwrap();
+        let binary = std::fs::read(t.repo.path.join("data.bin")).unwrap();
+        (stored, text, binary)
+    }
+
+    #[tokio::test]
+    async fn true_normalizes_both_ways() {
+        let (stored, text, binary) = round_trip("true").await;
+        assert_eq!(stored, b"one\ntwo\n");
+        assert_eq!(text, b"one\r\ntwo\r\n");
+        assert_eq!(binary, b"\r\n\0\n");
+    }
+
+    #[tokio::test]
+    async fn input_normalizes_only_when_adding() {
+        let (stored, text, binary) = round_trip("input").await;
+        assert_eq!(stored, b"one\ntwo\n");
+        assert_eq!(text, b"one\ntwo\n");
+        assert_eq!(binary, b"\r\n\0\n");
+    }
+
+    #[tokio::test]
+    async fn false_keeps_bytes_verbatim() {
+        let (stored, text, binary) = round_trip("false").await;
+        assert_eq!(stored, b"one\r\ntwo\r\n");
+        assert_eq!(text, b"one\r\ntwo\r\n");
+        assert_eq!(binary, b"\r\n\0\n");
+    }
+
+    #[test]
+    fn mixed_endings_and_bad_values() {
+        assert_eq!(crlf_to_lf(b"a\r\nb\rc\n"), b"a\nb\rc\n");
+        assert_eq!(lf_to_crlf(b"a\r\nb\n"), b"a\r\nb\r\n");
+        assert_eq!(AutoCrlf::parse(" Input ").unwrap(), AutoCrlf::Input);
+        assert!(AutoCrlf::parse("sometimes").is_err());
+    }
+}
@@ -12,6 +12,21 @@ pub fn write_file_content(path: &Path, content: &[u8]) -> Result<()> {
     Ok(fs::write(path, content)?)
 }
 
+/// Write `content` and fsync both the file and its directory, so the data
+/// is on disk before anything that depends on it (such as deleting the
+/// loose objects a new pack replaces) happens.
+pub fn write_file_durable(path: &Path, content: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let mut file = fs::File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
 pub fn get_file_mode(path: &Path) -> Result<u32> {
     let metadata = fs::metadata(path)?;
     let permissions = metadata.permissions();
@@ -29,3 +44,23 @@ pub fn is_executable(path: &Path) -> Result<bool> {
     let permissions = metadata.permissions();
     Ok(permissions.mode() & 0o111 != 0)
 }
+
+/// Heuristic binary detection: a NUL byte in the first 8000 bytes.
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|&b| b == 0)
+}
+
+/// Recursively copy a directory tree.
+pub fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
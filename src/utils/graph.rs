@@ -0,0 +1,223 @@
+use colored::*;
+
+const LANE_COLORS: [Color; 6] = [
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+];
+
+/// Column-tracking ASCII renderer for `hx log --graph`.
+///
+/// Each lane holds the id of the commit it is waiting for. A lane keeps the
+/// color it was opened with until it closes, and closed lanes hand their
+/// color back so it can be reused by the next lane that opens.
+pub struct GraphRenderer {
+    lanes: Vec<Option<String>>,
+    colors: Vec<usize>,
+    use_color: bool,
+}
+
+pub struct GraphRows {
+    /// Graph prefix for the commit's own line
+    pub commit_row: String,
+    /// Connector lines to print after the commit line
+    pub connectors: Vec<String>,
+}
+
+impl GraphRenderer {
+    pub fn new(use_color: bool) -> Self {
+        Self {
+            lanes: Vec::new(),
+            colors: Vec::new(),
+            use_color,
+        }
+    }
+
+    pub fn next(&mut self, commit_id: &str, parents: &[String]) -> GraphRows {
+        let col = match self.find_lane(commit_id) {
+            Some(col) => col,
+            None => self.open_lane(commit_id),
+        };
+        let merging: Vec<usize> = (0..self.lanes.len())
+            .filter(|&i| i != col && self.lanes[i].as_deref() == Some(commit_id))
+            .collect();
+
+        let commit_row = self.render_row(|i| if i == col { Some('*') } else { None });
+
+        let mut connectors = Vec::new();
+        if !merging.is_empty() {
+            connectors.push(self.render_row(|i| {
+                if merging.contains(&i) {
+                    Some(if i > col { '/' } else { '\\' })
+                } else {
+                    None
+                }
+            }));
+            for &i in &merging {
+                self.close_lane(i);
+            }
+        }
+
+        match parents.first() {
+            Some(first) => match self.find_lane(first) {
+                // First parent is already tracked further right: that lane
+                // folds into this one, keeping the history leftmost
+                Some(other) if other > col => {
+                    connectors.push(self.render_row(|i| if i == other { Some('/') } else { None }));
+                    self.close_lane(other);
+                    self.lanes[col] = Some(first.clone());
+                }
+                // Tracked further left: this lane joins it
+                Some(other) if other != col => {
+                    connectors.push(self.render_row(|i| {
+                        if i == col {
+                            Some('/')
+                        } else {
+                            None
+                        }
+                    }));
+                    self.close_lane(col);
+                }
+                _ => self.lanes[col] = Some(first.clone()),
+            },
+            None => self.close_lane(col),
+        }
+
+        let mut opened = Vec::new();
+        for parent in parents.iter().skip(1) {
+            if self.find_lane(parent).is_none() {
+                opened.push(self.open_lane(parent));
+            }
+        }
+        if !opened.is_empty() {
+            connectors.push(self.render_row(|i| {
+                if opened.contains(&i) {
+                    Some(if i > col { '\\' } else { '/' })
+                } else {
+                    None
+                }
+            }));
+        }
+
+        while let Some(None) = self.lanes.last() {
+            self.lanes.pop();
+            self.colors.pop();
+        }
+
+        GraphRows {
+            commit_row,
+            connectors,
+        }
+    }
+
+    fn find_lane(&self, commit_id: &str) -> Option<usize> {
+        self.lanes
+            .iter()
+            .position(|lane| lane.as_deref() == Some(commit_id))
+    }
+
+    fn open_lane(&mut self, commit_id: &str) -> usize {
+        let used: Vec<usize> = (0..self.lanes.len())
+            .filter(|&i| self.lanes[i].is_some())
+            .map(|i| self.colors[i])
+            .collect();
+        let color = (0..).find(|c| !used.contains(c)).unwrap() % LANE_COLORS.len();
+
+        match self.lanes.iter().position(|lane| lane.is_none()) {
+            Some(i) => {
+                self.lanes[i] = Some(commit_id.to_string());
+                self.colors[i] = color;
+                i
+            }
+            None => {
+                self.lanes.push(Some(commit_id.to_string()));
+                self.colors.push(color);
+                self.lanes.len() - 1
+            }
+        }
+    }
+
+    fn close_lane(&mut self, i: usize) {
+        self.lanes[i] = None;
+    }
+
+    /// One row of lane cells; `special` overrides the glyph of a lane.
+    fn render_row<F>(&self, special: F) -> String
+    where
+        F: Fn(usize) -> Option<char>,
+    {
+        let mut row = String::new();
+        for i in 0..self.lanes.len() {
+            let glyph = match special(i) {
+                Some(glyph) => glyph,
+                None if self.lanes[i].is_some() => '|',
+                None => ' ',
+            };
+            let cell = format!("{} ", glyph);
+            if self.use_color && glyph != ' ' {
+                row.push_str(&cell.color(LANE_COLORS[self.colors[i]]).to_string());
+            } else {
+                row.push_str(&cell);
+            }
+        }
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::strip_ansi;
+
+    /// Lines `hx log --graph` would draw for `history` (newest first), with
+    /// each commit row ending in the commit's name.
+    fn render(renderer: &mut GraphRenderer, history: &[(&str, &[&str])]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (commit, parents) in history {
+            let parents: Vec<String> = parents.iter().map(|p| p.to_string()).collect();
+            let rows = renderer.next(commit, &parents);
+            lines.push(format!("{}{}", rows.commit_row, commit));
+            lines.extend(rows.connectors.iter().map(|row| row.trim_end().to_string()));
+        }
+        lines
+    }
+
+    const MERGE: &[(&str, &[&str])] = &[
+        ("merge", &["main", "topic"]),
+        ("topic", &["base"]),
+        ("main", &["base"]),
+        ("base", &[]),
+    ];
+
+    #[test]
+    fn merge_opens_and_closes_a_lane() {
+        let lines = render(&mut GraphRenderer::new(false), MERGE);
+        assert_eq!(lines, vec!["* merge", "| \\", "| * topic", "* | main", "| /", "* base"]);
+    }
+
+    #[test]
+    fn color_only_wraps_the_same_structure() {
+        colored::control::set_override(true);
+        let colored_lines = render(&mut GraphRenderer::new(true), MERGE);
+        colored::control::unset_override();
+        let stripped: Vec<String> = colored_lines
+            .iter()
+            .map(|line| strip_ansi(line).trim_end().to_string())
+            .collect();
+
+        assert_ne!(colored_lines, stripped);
+        assert_eq!(stripped, render(&mut GraphRenderer::new(false), MERGE));
+    }
+
+    #[test]
+    fn closed_lanes_hand_back_their_color() {
+        let mut renderer = GraphRenderer::new(false);
+        render(&mut renderer, &MERGE[..3]);
+        // Only the first lane is open again; a new branch reuses the freed color
+        renderer.next("other", &["elsewhere".to_string()]);
+        assert_eq!(renderer.colors, vec![0, 1]);
+    }
+}
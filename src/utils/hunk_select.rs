@@ -0,0 +1,156 @@
+use anyhow::Result;
+use colored::*;
+use similar::{ChangeTag, DiffOp, DiffTag, TextDiff};
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+const CONTEXT: usize = 3;
+
+const HELP: &str = "y - include this hunk
+n - do not include this hunk
+q - quit; do not include this hunk or any of the remaining ones
+a - include this hunk and all later hunks in the file
+d - do not include this hunk or any of the later hunks in the file";
+
+/// Interactive hunk selection between two versions of a file, answered
+/// line by line from `input` (`y`/`n`/`q`/`a`/`d`). `verb` names what is
+/// being done with the selected hunks in the prompt, e.g. "Commit".
+pub struct HunkSelector<R: BufRead> {
+    input: R,
+    verb: String,
+    quit: bool,
+}
+
+impl<R: BufRead> HunkSelector<R> {
+    pub fn new(input: R, verb: &str) -> Self {
+        Self {
+            input,
+            verb: verb.to_string(),
+            quit: false,
+        }
+    }
+
+    /// Whether `q` was answered (or input ran out); no further hunks are
+    /// offered once it has been.
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Offer each hunk of the change from `old` to `new` and return `old`
+    /// with only the accepted hunks applied, or `None` if none were.
+    pub fn select(&mut self, path: &str, old: &str, new: &str) -> Result<Option<String>> {
+        if self.quit {
+            return Ok(None);
+        }
+        let diff = TextDiff::from_lines(old, new);
+        let mut unified = diff.unified_diff();
+        unified.context_radius(CONTEXT);
+        let hunks: Vec<_> = unified.iter_hunks().collect();
+
+        let mut selected: HashSet<DiffOp> = HashSet::new();
+        let mut rest: Option<bool> = None;
+        println!("{}", format!("diff --hx a/{} b/{}", path, path).bold());
+        for (i, hunk) in hunks.iter().enumerate() {
+            let take = match rest {
+                Some(take) => take,
+                None => {
+                    println!("{}", hunk.header().to_string().cyan());
+                    for change in hunk.iter_changes() {
+                        let line = format!("{}{}", change.tag(), change);
+                        match change.tag() {
+                            ChangeTag::Delete => print!("{}", line.red()),
+                            ChangeTag::Insert => print!("{}", line.green()),
+                            ChangeTag::Equal => print!("{}", line),
+                        }
+                        if change.missing_newline() {
+                            println!("\n\\ No newline at end of file");
+                        }
+                    }
+                    match self.ask(i + 1, hunks.len())? {
+                        'y' => true,
+                        'a' => {
+                            rest = Some(true);
+                            true
+                        }
+                        'd' => {
+                            rest = Some(false);
+                            false
+                        }
+                        'q' => {
+                            self.quit = true;
+                            break;
+                        }
+                        _ => false,
+                    }
+                }
+            };
+            if take {
+                selected.extend(hunk.ops().iter().filter(|op| op.tag() != DiffTag::Equal));
+            }
+        }
+        if selected.is_empty() {
+            return Ok(None);
+        }
+
+        // Unselected changes keep the old side, so those lines stay as they
+        // were and the rest of the change remains in the working tree
+        let (old_lines, new_lines) = (diff.old_slices(), diff.new_slices());
+        let mut result = String::with_capacity(new.len());
+        for op in diff.ops() {
+            let lines = if selected.contains(op) {
+                &new_lines[op.new_range()]
+            } else {
+                &old_lines[op.old_range()]
+            };
+            lines.iter().for_each(|line| result.push_str(line));
+        }
+        Ok(Some(result))
+    }
+
+    /// Ask a yes/no question about a whole file, such as its deletion.
+    pub fn confirm(&mut self, question: &str) -> Result<bool> {
+        if self.quit {
+            return Ok(false);
+        }
+        loop {
+            print!("{} [y,n,q]? ", question.blue().bold());
+            std::io::stdout().flush()?;
+            match self.read_answer()? {
+                Some('y') => return Ok(true),
+                Some('n') => return Ok(false),
+                Some('q') | None => {
+                    self.quit = true;
+                    return Ok(false);
+                }
+                _ => println!("y - yes\nn - no\nq - quit"),
+            }
+        }
+    }
+
+    fn ask(&mut self, index: usize, total: usize) -> Result<char> {
+        loop {
+            print!(
+                "{} ",
+                format!("({}/{}) {} this hunk [y,n,q,a,d,?]?", index, total, self.verb)
+                    .blue()
+                    .bold()
+            );
+            std::io::stdout().flush()?;
+            match self.read_answer()? {
+                Some(answer @ ('y' | 'n' | 'q' | 'a' | 'd')) => return Ok(answer),
+                None => return Ok('q'),
+                _ => println!("{}", HELP),
+            }
+        }
+    }
+
+    /// First character of the next answer, or `None` at end of input.
+    fn read_answer(&mut self) -> Result<Option<char>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            println!();
+            return Ok(None);
+        }
+        Ok(Some(line.trim().chars().next().unwrap_or('?').to_ascii_lowercase()))
+    }
+}
@@ -1,11 +1,24 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use dirs;
-use ed25519_dalek::{SigningKey, SECRET_KEY_LENGTH};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey, SECRET_KEY_LENGTH};
 use rand::rngs::OsRng;
 use rand::RngCore;
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{Error, ErrorKind, IsTerminal};
 use std::path::PathBuf;
 
+/// Passphrase for the signing key when there is no terminal to ask on.
+pub const PASSPHRASE_ENV: &str = "HX_KEY_PASSPHRASE";
+
+/// Encrypted key files: this header, the Argon2 salt, the nonce, then the
+/// ChaCha20-Poly1305 sealed secret. Files that are just the 32 secret bytes
+/// are the older plaintext format.
+const ENCRYPTED_MAGIC: &[u8] = b"HXK1";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
 pub fn get_key_dir() -> PathBuf {
     if let Some(home) = dirs::home_dir() {
         home.join(".helix/keys/")
@@ -23,25 +36,115 @@ pub fn generate_and_save_keypair() -> std::io::Result<SigningKey> {
     let mut secret_bytes = [0u8; SECRET_KEY_LENGTH];
     csprng.fill_bytes(&mut secret_bytes);
     let keypair = SigningKey::from_bytes(&secret_bytes);
+    // An empty passphrase keeps the key in plaintext, as before
+    let data = match new_passphrase()? {
+        Some(passphrase) => encrypt_secret(&keypair.to_bytes(), &passphrase)?,
+        None => keypair.to_bytes().to_vec(),
+    };
     let key_dir = get_key_dir();
     fs::create_dir_all(&key_dir)?;
-    let mut file = fs::File::create(keypair_path())?;
-    file.write_all(&keypair.to_bytes())?;
+    fs::write(keypair_path(), data)?;
     Ok(keypair)
 }
 
 pub fn load_keypair() -> std::io::Result<SigningKey> {
-    let mut file = fs::File::open(keypair_path())?;
-    let mut buf = [0u8; SECRET_KEY_LENGTH];
-    file.read_exact(&mut buf)?;
-    let keypair = SigningKey::from_bytes(&buf);
-    Ok(keypair)
+    let data = fs::read(keypair_path())?;
+    let secret: [u8; SECRET_KEY_LENGTH] = match data.strip_prefix(ENCRYPTED_MAGIC) {
+        Some(sealed) => {
+            let passphrase = read_passphrase("Passphrase for signing key: ")?.ok_or_else(|| {
+                Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!("signing key is encrypted; set {} or run from a terminal", PASSPHRASE_ENV),
+                )
+            })?;
+            decrypt_secret(sealed, &passphrase)?
+        }
+        None => data.as_slice().try_into().map_err(|_| {
+            Error::new(ErrorKind::InvalidData, "signing key file is not a valid key")
+        })?,
+    };
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+/// Passphrase from `HX_KEY_PASSPHRASE`, otherwise asked for on the
+/// terminal. `None` when neither is available.
+fn read_passphrase(prompt: &str) -> std::io::Result<Option<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Some(passphrase));
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    rpassword::prompt_password(prompt).map(Some)
+}
+
+/// Passphrase to protect a new key with, asked twice on a terminal.
+/// `None` (or an empty answer) stores the key unencrypted.
+fn new_passphrase() -> std::io::Result<Option<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Some(passphrase).filter(|p| !p.is_empty()));
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let passphrase = rpassword::prompt_password("Passphrase for signing key (empty for none): ")?;
+    if passphrase.is_empty() {
+        return Ok(None);
+    }
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(Error::new(ErrorKind::InvalidInput, "passphrases do not match"));
+    }
+    Ok(Some(passphrase))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> std::io::Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::other(format!("key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+fn encrypt_secret(secret: &[u8; SECRET_KEY_LENGTH], passphrase: &str) -> std::io::Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), secret.as_slice())
+        .map_err(|_| Error::other("failed to encrypt signing key"))?;
+    Ok([ENCRYPTED_MAGIC, &salt, &nonce, &sealed].concat())
+}
+
+fn decrypt_secret(sealed: &[u8], passphrase: &str) -> std::io::Result<[u8; SECRET_KEY_LENGTH]> {
+    if sealed.len() < SALT_LENGTH + NONCE_LENGTH {
+        return Err(Error::new(ErrorKind::InvalidData, "signing key file is truncated"));
+    }
+    let (salt, rest) = sealed.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    let secret = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::new(ErrorKind::PermissionDenied, "wrong passphrase for signing key"))?;
+    secret
+        .try_into()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "signing key file is not a valid key"))
 }
 
 pub fn keypair_exists() -> bool {
     keypair_path().exists()
 }
 
+/// The signing key for a command that must sign. Only a missing key gets
+/// the `hx keygen` hint; a key that fails to decrypt reports why.
+pub fn load_signing_key() -> anyhow::Result<SigningKey> {
+    if !keypair_exists() {
+        anyhow::bail!("No keypair found. Run 'hx keygen' first.");
+    }
+    Ok(load_keypair()?)
+}
+
 pub fn export_keypair(path: &str) -> std::io::Result<()> {
     fs::copy(keypair_path(), path)?;
     Ok(())
@@ -53,3 +156,54 @@ pub fn import_keypair(path: &str) -> std::io::Result<()> {
     fs::copy(path, keypair_path())?;
     Ok(())
 }
+
+/// Sign `payload`, returning the public key and signature bytes as commits
+/// and tags store them.
+pub fn sign_payload(keypair: &SigningKey, payload: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let sig = keypair.sign(payload);
+    (
+        keypair.verifying_key().to_bytes().to_vec(),
+        sig.to_bytes().to_vec(),
+    )
+}
+
+/// Whether `signature` over `payload` checks out against `public_key`.
+pub fn verify_payload(public_key: &[u8], signature: &[u8], payload: &[u8]) -> bool {
+    if let (Ok(pk_array), Ok(sig_array)) = (public_key.try_into(), signature.try_into()) {
+        if let Ok(pk) = VerifyingKey::from_bytes(pk_array) {
+            let sig = Signature::from_bytes(sig_array);
+            return pk.verify(payload, &sig).is_ok();
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_round_trips_with_the_right_passphrase() {
+        let secret = [7u8; SECRET_KEY_LENGTH];
+        let sealed = encrypt_secret(&secret, "correct horse").unwrap();
+        assert!(sealed.starts_with(ENCRYPTED_MAGIC));
+        assert!(!sealed.windows(SECRET_KEY_LENGTH).any(|w| w == secret));
+
+        let body = sealed.strip_prefix(ENCRYPTED_MAGIC).unwrap();
+        assert_eq!(decrypt_secret(body, "correct horse").unwrap(), secret);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let sealed = encrypt_secret(&[7u8; SECRET_KEY_LENGTH], "correct horse").unwrap();
+        let body = sealed.strip_prefix(ENCRYPTED_MAGIC).unwrap();
+        let err = decrypt_secret(body, "battery staple").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn truncated_file_is_refused() {
+        let err = decrypt_secret(&[0u8; SALT_LENGTH], "correct horse").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -1,8 +1,19 @@
+pub mod alias;
 pub mod auth;
+pub mod date_utils;
+pub mod editor;
+pub mod eol;
 pub mod file_utils;
+pub mod graph;
 pub mod hash_utils;
+pub mod hunk_select;
 pub mod key_utils;
 pub mod pack;
+pub mod pager;
 pub mod path_utils;
 pub mod remote_client;
+#[cfg(test)]
+pub mod test_utils;
+pub mod transport;
 pub mod config;
+pub mod diff_utils;
@@ -1,6 +1,53 @@
-use anyhow::{Result};
+use crate::core::object;
+use crate::utils::{file_utils, hash_utils};
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Version 2 packs hold each object's data as is. From version 3 it is
+/// deflated, with the compressed length after the entry header so readers
+/// can find where the next entry begins. Version 4 also records each
+/// object's id (32 raw bytes) before that length.
+const RAW_DATA_VERSION: u32 = 2;
+const IDS_VERSION: u32 = 4;
+const PACK_VERSION: u32 = IDS_VERSION;
+const ID_LENGTH: usize = 32;
+
+/// Packs written by `gc` live in `.helix/objects/pack/` as
+/// `pack-<hash>.pack`, each with a `pack-<hash>.idx` mapping object ids to
+/// the offset of their entry.
+pub fn pack_dir(objects_dir: &Path) -> PathBuf {
+    objects_dir.join("pack")
+}
+
+pub fn index_path(pack_path: &Path) -> PathBuf {
+    pack_path.with_extension("idx")
+}
+
+/// Pack entry type for a Helix object type.
+pub fn pack_type(object_type: &str) -> u8 {
+    match object_type {
+        "commit" => 1,
+        "tree" => 2,
+        "blob" => 3,
+        "tag" => 4,
+        _ => 0,
+    }
+}
+
+/// `pack-<name>.keep` next to `pack-<name>.pack`: while it exists the pack
+/// must not be deleted or repacked (e.g. it is being served).
+pub fn keep_marker(pack_path: &Path) -> PathBuf {
+    pack_path.with_extension("keep")
+}
+
+pub fn is_kept(pack_path: &Path) -> bool {
+    keep_marker(pack_path).exists()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackHeader {
@@ -29,7 +76,7 @@ impl Pack {
         Self {
             header: PackHeader {
                 signature: *b"PACK",
-                version: 2,
+                version: PACK_VERSION,
                 object_count: 0,
             },
             objects: Vec::new(),
@@ -64,20 +111,37 @@ impl Pack {
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(self.to_bytes_with_offsets()?.0)
+    }
+
+    /// Serialize the pack, also returning where each object's entry starts.
+    pub fn to_bytes_with_offsets(&self) -> Result<(Vec<u8>, Vec<u64>)> {
         let mut buffer = Vec::new();
+        let mut offsets = Vec::with_capacity(self.objects.len());
         
         // Write header
         buffer.extend_from_slice(&self.header.signature);
         buffer.extend_from_slice(&self.header.version.to_be_bytes());
         buffer.extend_from_slice(&self.header.object_count.to_be_bytes());
         
+        let mut ids: Vec<Option<&String>> = vec![None; self.objects.len()];
+        for (hash, &i) in &self.index {
+            if let Some(id) = ids.get_mut(i) {
+                *id = Some(hash);
+            }
+        }
+        
         // Write objects
-        for object in &self.objects {
+        for (object, id) in self.objects.iter().zip(ids) {
+            offsets.push(buffer.len() as u64);
             // Write object header
-            let header_byte = (object.object_type << 4) | (object.size & 0x0F) as u8;
+            let mut header_byte = (object.object_type << 4) | (object.size & 0x0F) as u8;
+            if object.size > 0x0F {
+                header_byte |= 0x80;
+            }
             buffer.push(header_byte);
             
-            if object.size >= 0x0F {
+            if object.size > 0x0F {
                 let mut size = object.size >> 4;
                 while size > 0 {
                     let byte = (size & 0x7F) as u8;
@@ -90,11 +154,22 @@ impl Pack {
                 }
             }
             
+            // Entries not keyed by an object id get an all-zero one
+            if self.header.version >= IDS_VERSION {
+                buffer.extend_from_slice(&id.and_then(|id| decode_id(id)).unwrap_or([0; ID_LENGTH]));
+            }
+            
             // Write object data
-            buffer.extend_from_slice(&object.data);
+            if self.header.version > RAW_DATA_VERSION {
+                let compressed = object::deflate(&object.data)?;
+                buffer.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+                buffer.extend_from_slice(&compressed);
+            } else {
+                buffer.extend_from_slice(&object.data);
+            }
         }
         
-        Ok(buffer)
+        Ok((buffer, offsets))
     }
 
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
@@ -122,8 +197,11 @@ impl Pack {
         
         let mut offset = 12;
         for i in 0..object_count {
-            let (object, new_offset) = Self::parse_object(&data[offset..])?;
-            pack.index.insert(format!("object_{}", i), pack.objects.len());
+            let (object, id, new_offset) = Self::parse_object(&data[offset..], version)
+                .map_err(|e| anyhow::anyhow!("Object {}: {}", i, e))?;
+            // Older packs don't say which object is which
+            let key = id.unwrap_or_else(|| format!("object_{}", i));
+            pack.index.insert(key, pack.objects.len());
             pack.objects.push(object);
             offset += new_offset;
         }
@@ -131,10 +209,53 @@ impl Pack {
         Ok(pack)
     }
 
-    fn parse_object(data: &[u8]) -> Result<(PackObject, usize)> {
+    /// The entry starting `data`, its object id if the pack records one,
+    /// and the entry's length.
+    fn parse_object(data: &[u8], version: u32) -> Result<(PackObject, Option<String>, usize)> {
+        let header = Self::parse_header(data, version)?;
+        let mut offset = header.len;
+        
+        if data.len() - offset < header.stored_len {
+            return Err(anyhow::anyhow!(
+                "Truncated pack: object needs {} bytes, {} left",
+                header.stored_len,
+                data.len() - offset
+            ));
+        }
+        let object_data =
+            Self::decode_data(&data[offset..offset + header.stored_len], header.size, version)?;
+        offset += header.stored_len;
+        
+        let object = PackObject {
+            object_type: header.object_type,
+            size: header.size,
+            data: object_data,
+            delta_base: None,
+        };
+        
+        Ok((object, header.id, offset))
+    }
+
+    /// Inflate an entry's stored data (for packs that compress it) and check
+    /// it has the size the header promised.
+    fn decode_data(stored: &[u8], size: u64, version: u32) -> Result<Vec<u8>> {
+        if version <= RAW_DATA_VERSION {
+            return Ok(stored.to_vec());
+        }
+        let data = object::inflate(stored).context("Corrupt compressed object data")?;
+        if data.len() as u64 != size {
+            anyhow::bail!("Object inflates to {} bytes, header says {}", data.len(), size);
+        }
+        Ok(data)
+    }
+
+    fn parse_header(data: &[u8], version: u32) -> Result<EntryHeader> {
         let mut offset = 0;
         
         // Parse object header
+        if data.is_empty() {
+            return Err(anyhow::anyhow!("Truncated pack: missing object header"));
+        }
         let header_byte = data[offset];
         offset += 1;
         
@@ -157,20 +278,203 @@ impl Pack {
             }
         }
         
-        // For now, we'll assume the rest is object data
-        // In a real implementation, you'd need to handle compression
-        let object_data = data[offset..offset + size as usize].to_vec();
-        offset += size as usize;
+        let mut id = None;
+        if version >= IDS_VERSION {
+            let raw = data
+                .get(offset..offset + ID_LENGTH)
+                .ok_or_else(|| anyhow::anyhow!("Truncated pack: missing object id"))?;
+            if raw.iter().any(|&b| b != 0) {
+                id = Some(raw.iter().map(|b| format!("{:02x}", b)).collect());
+            }
+            offset += ID_LENGTH;
+        }
         
-        let object = PackObject {
+        if version <= RAW_DATA_VERSION {
+            return Ok(EntryHeader {
+                object_type,
+                size,
+                id,
+                stored_len: size as usize,
+                len: offset,
+            });
+        }
+        let stored_len = data
+            .get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| anyhow::anyhow!("Truncated pack: missing compressed length"))?;
+        Ok(EntryHeader {
             object_type,
             size,
-            data: object_data,
+            id,
+            stored_len,
+            len: offset + 4,
+        })
+    }
+
+    /// Read the single entry at `offset` of a pack file without loading the
+    /// rest of it.
+    pub fn read_entry(file: &mut fs::File, offset: u64) -> Result<PackObject> {
+        let mut pack_header = [0u8; 12];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut pack_header)
+            .map_err(|_| anyhow::anyhow!("Invalid pack data: too short"))?;
+        if pack_header[..4] != *b"PACK" {
+            return Err(anyhow::anyhow!("Invalid pack signature"));
+        }
+        let version = u32::from_be_bytes([pack_header[4], pack_header[5], pack_header[6], pack_header[7]]);
+
+        // A 64-bit size never needs more than 11 header bytes, plus the id
+        // and 4 for the compressed length
+        let mut header = Vec::new();
+        file.seek(SeekFrom::Start(offset))?;
+        file.by_ref().take((11 + ID_LENGTH + 4) as u64).read_to_end(&mut header)?;
+        let header = Self::parse_header(&header, version)?;
+
+        let mut stored = vec![0u8; header.stored_len];
+        file.seek(SeekFrom::Start(offset + header.len as u64))?;
+        file.read_exact(&mut stored).map_err(|_| {
+            anyhow::anyhow!("Truncated pack: object needs {} bytes", header.stored_len)
+        })?;
+        Ok(PackObject {
+            object_type: header.object_type,
+            size: header.size,
+            data: Self::decode_data(&stored, header.size, version)?,
             delta_base: None,
+        })
+    }
+}
+
+/// Write `pack` (keyed by object id) and its index into the pack
+/// directory, returning the pack's path. The index is written last, so a
+/// pack is only looked at once it is complete, and both are synced to disk
+/// before this returns, so the objects they replace can safely be deleted.
+pub fn write_pack(objects_dir: &Path, pack: &Pack) -> Result<PathBuf> {
+    let (bytes, offsets) = pack.to_bytes_with_offsets()?;
+    let index: HashMap<&String, u64> = pack
+        .index
+        .iter()
+        .map(|(hash, &i)| (hash, offsets[i]))
+        .collect();
+
+    let dir = pack_dir(objects_dir);
+    fs::create_dir_all(&dir)?;
+    let pack_path = dir.join(format!("pack-{}.pack", hash_utils::calculate_hash(&bytes)));
+    file_utils::write_file_durable(&pack_path, &bytes)
+        .with_context(|| format!("Failed to write {}", pack_path.display()))?;
+    file_utils::write_file_durable(&index_path(&pack_path), &serde_json::to_vec(&index)?)
+        .with_context(|| format!("Failed to write index for {}", pack_path.display()))?;
+    Ok(pack_path)
+}
+
+/// Packs that may be repacked (those without a `.keep` marker), each with
+/// the ids it holds.
+pub fn repackable_packs(objects_dir: &Path) -> Vec<(PathBuf, Vec<String>)> {
+    pack_indexes(objects_dir)
+        .into_iter()
+        .filter(|(pack_path, _)| !is_kept(pack_path))
+        .map(|(pack_path, index)| (pack_path, index.keys().cloned().collect()))
+        .collect()
+}
+
+/// Delete a pack and its index, index first so readers stop using the pack
+/// before it goes. Kept packs are refused.
+pub fn remove_pack(pack_path: &Path) -> Result<()> {
+    if is_kept(pack_path) {
+        anyhow::bail!("{} is marked .keep", pack_path.display());
+    }
+    fs::remove_file(index_path(pack_path))?;
+    fs::remove_file(pack_path)?;
+    Ok(())
+}
+
+type PackIndex = Arc<HashMap<String, u64>>;
+
+/// Indexes of the packs in `objects_dir`, parsed once per process.
+fn pack_indexes(objects_dir: &Path) -> Vec<(PathBuf, PackIndex)> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, PackIndex>>> = OnceLock::new();
+
+    let Ok(entries) = fs::read_dir(pack_dir(objects_dir)) else {
+        return Vec::new();
+    };
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+    let mut indexes = Vec::new();
+    for entry in entries.flatten() {
+        let idx_path = entry.path();
+        if idx_path.extension().is_none_or(|ext| ext != "idx") {
+            continue;
+        }
+        let index = match cache.get(&idx_path) {
+            Some(index) => index.clone(),
+            None => {
+                // An unreadable index just means that pack isn't consulted
+                let Some(index) = fs::read(&idx_path)
+                    .ok()
+                    .and_then(|data| serde_json::from_slice::<HashMap<String, u64>>(&data).ok())
+                else {
+                    continue;
+                };
+                let index = Arc::new(index);
+                cache.insert(idx_path.clone(), index.clone());
+                index
+            }
         };
-        
-        Ok((object, offset))
+        indexes.push((idx_path.with_extension("pack"), index));
+    }
+    indexes
+}
+
+/// Compressed object data for `id` from whichever pack holds it.
+pub fn read_packed(objects_dir: &Path, id: &str) -> Result<Option<Vec<u8>>> {
+    for (pack_path, index) in pack_indexes(objects_dir) {
+        if let Some(&offset) = index.get(id) {
+            let mut file = fs::File::open(&pack_path)
+                .with_context(|| format!("Failed to open {}", pack_path.display()))?;
+            let entry = Pack::read_entry(&mut file, offset)
+                .with_context(|| format!("Failed to read {} from {}", id, pack_path.display()))?;
+            return Ok(Some(entry.data));
+        }
+    }
+    Ok(None)
+}
+
+pub fn is_packed(objects_dir: &Path, id: &str) -> bool {
+    pack_indexes(objects_dir)
+        .iter()
+        .any(|(_, index)| index.contains_key(id))
+}
+
+/// Ids of every packed object.
+pub fn packed_ids(objects_dir: &Path) -> Vec<String> {
+    let mut ids: Vec<String> = pack_indexes(objects_dir)
+        .iter()
+        .flat_map(|(_, index)| index.keys().cloned())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+/// What precedes an entry's data: its type, data size, object id (when
+/// recorded), how many bytes the data takes in the pack and the header's
+/// own length.
+struct EntryHeader {
+    object_type: u8,
+    size: u64,
+    id: Option<String>,
+    stored_len: usize,
+    len: usize,
+}
+
+/// Raw bytes of a 64-digit hex object id.
+fn decode_id(id: &str) -> Option<[u8; ID_LENGTH]> {
+    if id.len() != ID_LENGTH * 2 || !id.is_ascii() {
+        return None;
+    }
+    let mut raw = [0u8; ID_LENGTH];
+    for (i, byte) in raw.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&id[i * 2..i * 2 + 2], 16).ok()?;
     }
+    Some(raw)
 }
 
 pub struct PackBuilder {
@@ -308,14 +612,189 @@ pub fn create_thin_pack(
     pack
 }
 
+/// Longest chain of deltas-on-deltas followed before giving up, which also
+/// stops a delta that (indirectly) names itself as its base.
+const MAX_DELTA_DEPTH: usize = 50;
+
+/// Rebuild the target of a delta made by `PackBuilder::compute_delta`
+/// from its base.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let read_u32 = |pos: usize| -> Result<usize> {
+        delta
+            .get(pos..pos + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| anyhow::anyhow!("Truncated delta at byte {}", pos))
+    };
+
+    let base_size = read_u32(0)?;
+    let target_size = read_u32(4)?;
+    if base_size != base.len() {
+        anyhow::bail!("Delta expects a {}-byte base, got {} bytes", base_size, base.len());
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut pos = 8;
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        match op {
+            0x00 => {
+                let (offset, len) = (read_u32(pos)?, read_u32(pos + 4)?);
+                pos += 8;
+                let copied = offset
+                    .checked_add(len)
+                    .and_then(|end| base.get(offset..end))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Delta copies {}..{} past the end of its base", offset, offset + len)
+                    })?;
+                target.extend_from_slice(copied);
+            }
+            0x01 => {
+                let len = read_u32(pos)?;
+                pos += 4;
+                let inserted = delta
+                    .get(pos..pos + len)
+                    .ok_or_else(|| anyhow::anyhow!("Truncated delta insert of {} bytes", len))?;
+                target.extend_from_slice(inserted);
+                pos += len;
+            }
+            other => anyhow::bail!("Unknown delta instruction 0x{:02x}", other),
+        }
+    }
+
+    if target.len() != target_size {
+        anyhow::bail!(
+            "Delta produced {} bytes, expected {}",
+            target.len(),
+            target_size
+        );
+    }
+    Ok(target)
+}
+
+/// Object data keyed by hash, with delta objects rebuilt from their bases.
+/// Deltas whose base isn't in the pack can't be rebuilt and are left out.
 pub fn extract_objects_from_pack(pack: &Pack) -> HashMap<String, Vec<u8>> {
     let mut objects = HashMap::new();
     
-    for (hash, &index) in &pack.index {
-        if let Some(object) = pack.objects.get(index) {
-            objects.insert(hash.clone(), object.data.clone());
+    for hash in pack.index.keys() {
+        if let Some(data) = resolve_object(pack, hash, 0) {
+            objects.insert(hash.clone(), data);
         }
     }
     
     objects
-} 
\ No newline at end of file
+}
+
+fn resolve_object(pack: &Pack, hash: &str, depth: usize) -> Option<Vec<u8>> {
+    let object = pack.objects.get(*pack.index.get(hash)?)?;
+    match &object.delta_base {
+        None => Some(object.data.clone()),
+        Some(base_hash) if depth < MAX_DELTA_DEPTH => {
+            let base = resolve_object(pack, base_hash, depth + 1)?;
+            apply_delta(&base, &object.data).ok()
+        }
+        Some(_) => None,
+    }
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &[u8] = b"The quick brown fox jumps over the lazy dog, again and again.\n";
+    const TARGET: &[u8] = b"The quick brown fox leaps over the lazy dog, again and again!\nThe end.\n";
+
+    #[test]
+    fn a_delta_rebuilds_its_target() {
+        let builder = PackBuilder::new();
+        let delta = builder.compute_delta(BASE, TARGET).unwrap();
+        assert!(delta.contains(&0x00), "no copy instructions in {:?}", delta);
+        assert_eq!(apply_delta(BASE, &delta).unwrap(), TARGET);
+        assert_eq!(apply_delta(BASE, &builder.compute_delta(BASE, b"").unwrap()).unwrap(), b"");
+
+        // The wrong base, or a target size that doesn't add up, is refused
+        assert!(apply_delta(b"short", &delta).is_err());
+        let mut wrong_size = delta.clone();
+        wrong_size[4..8].copy_from_slice(&(TARGET.len() as u32 + 1).to_be_bytes());
+        assert!(apply_delta(BASE, &wrong_size).is_err());
+    }
+
+    #[test]
+    fn extracting_a_pack_resolves_deltas_against_their_base() {
+        let base_id = "aa".repeat(32);
+        let target_id = "bb".repeat(32);
+        let mut builder = PackBuilder::new();
+        builder.add_object(&base_id, BASE.to_vec());
+        builder.create_delta(&target_id, &base_id, TARGET).unwrap();
+        let mut pack = builder.build_pack();
+
+        let objects = extract_objects_from_pack(&pack);
+        assert_eq!(objects[&base_id], BASE);
+        assert_eq!(objects[&target_id], TARGET);
+
+        // Without its base a delta can't be rebuilt
+        pack.index.remove(&base_id);
+        assert!(!extract_objects_from_pack(&pack).contains_key(&target_id));
+    }
+
+    #[test]
+    fn packed_objects_are_compressed_and_read_back() {
+        let objects: Vec<(String, Vec<u8>)> = vec![
+            ("01".repeat(32), Vec::new()),
+            ("02".repeat(32), b"tiny".to_vec()),
+            ("03".repeat(32), b"repetitive ".repeat(500)),
+            ("04".repeat(32), (0..=255u8).cycle().take(3000).collect()),
+        ];
+        let mut pack = Pack::new();
+        for (id, data) in &objects {
+            pack.add_object(id, pack_type("blob"), data.clone());
+        }
+        let (bytes, offsets) = pack.to_bytes_with_offsets().unwrap();
+        let raw: usize = objects.iter().map(|(_, data)| data.len()).sum();
+        assert!(bytes.len() < raw, "{} bytes packed from {}", bytes.len(), raw);
+
+        let read = Pack::from_bytes(&bytes).unwrap();
+        assert_eq!(read.objects.len(), objects.len());
+        for (id, data) in &objects {
+            assert_eq!(&read.objects[read.index[id]].data, data);
+        }
+
+        // Each entry can also be read on its own from its offset
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.pack");
+        fs::write(&path, &bytes).unwrap();
+        let mut file = fs::File::open(&path).unwrap();
+        for ((_, data), offset) in objects.iter().zip(offsets) {
+            assert_eq!(&Pack::read_entry(&mut file, offset).unwrap().data, data);
+        }
+
+        // A truncated entry is reported, not misread
+        assert!(Pack::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn object_ids_survive_a_round_trip() {
+        let blobs: Vec<object::Object> = ["one", "two", "three"]
+            .iter()
+            .map(|text| object::Object::new("blob".to_string(), text.as_bytes()))
+            .collect();
+        let mut pack = Pack::new();
+        for blob in &blobs {
+            pack.add_object(&blob.id, pack_type("blob"), blob.data.clone());
+        }
+        // A key that isn't an object id can't be recorded
+        pack.add_object("not-an-id", pack_type("blob"), b"anonymous".to_vec());
+
+        let read = Pack::from_bytes(&pack.to_bytes().unwrap()).unwrap();
+        let mut keys: Vec<&String> = read.index.keys().collect();
+        keys.sort();
+        let mut expected: Vec<&String> = blobs.iter().map(|blob| &blob.id).collect();
+        let fallback = "object_3".to_string();
+        expected.push(&fallback);
+        expected.sort();
+        assert_eq!(keys, expected);
+        for blob in &blobs {
+            assert_eq!(read.objects[read.index[&blob.id]].data, blob.data);
+        }
+    }
+}
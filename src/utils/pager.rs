@@ -0,0 +1,84 @@
+use crate::utils::config::GlobalConfig;
+use anyhow::{Context, Result};
+use std::io::IsTerminal;
+use std::process::{Command, Stdio};
+
+/// Set in the re-run command so it writes straight to the pager.
+const PAGER_ACTIVE_ENV: &str = "HX_PAGER_ACTIVE";
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Pages a command's output. The command is run again as a child process
+/// whose stdout is the pager's stdin, so every exit path (including a
+/// panic) still lets the pager finish before we return.
+pub struct Pager {
+    command: String,
+}
+
+impl Pager {
+    /// The pager for this invocation: `HX_PAGER`, then `core.pager`, then
+    /// `$PAGER`, then `less -R`. `None` when stdout isn't a terminal, the
+    /// pager is empty or `cat`, or we are already the paged child.
+    pub fn for_stdout(config: Option<&GlobalConfig>) -> Option<Self> {
+        if std::env::var_os(PAGER_ACTIVE_ENV).is_some() || !std::io::stdout().is_terminal() {
+            return None;
+        }
+        let command = std::env::var("HX_PAGER")
+            .ok()
+            .or_else(|| config.and_then(|c| c.get_core_pager()).map(|p| p.to_string()))
+            .or_else(|| std::env::var("PAGER").ok())
+            .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+        let command = command.trim().to_string();
+        if command.is_empty() || command == "cat" {
+            return None;
+        }
+        Some(Self { command })
+    }
+
+    /// In the paged child, quitting the pager early closes our stdout;
+    /// exit quietly then instead of reporting a panic.
+    pub fn quiet_broken_pipe() {
+        if std::env::var_os(PAGER_ACTIVE_ENV).is_none() {
+            return;
+        }
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let message = info
+                .payload()
+                .downcast_ref::<String>()
+                .map(|s| s.as_str())
+                .unwrap_or("");
+            if message.contains("failed printing to stdout") {
+                std::process::exit(0);
+            }
+            default_hook(info);
+        }));
+    }
+
+    /// Re-run the current command with its output piped into the pager and
+    /// return its exit code, or `None` if the pager couldn't be started.
+    pub fn run(&self) -> Result<Option<i32>> {
+        let mut pager = match Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            // Quit on short output, keep colors, don't clear the screen
+            .env("LESS", std::env::var("LESS").unwrap_or_else(|_| "FRX".to_string()))
+            .spawn()
+        {
+            Ok(pager) => pager,
+            Err(_) => return Ok(None),
+        };
+        let pager_stdin = pager.stdin.take().context("Pager has no stdin")?;
+
+        let status = Command::new(std::env::current_exe()?)
+            .args(std::env::args_os().skip(1))
+            .env(PAGER_ACTIVE_ENV, "1")
+            // Output still ends up on a terminal, so keep it colored
+            .env("CLICOLOR_FORCE", "1")
+            .stdout(Stdio::from(pager_stdin))
+            .status()
+            .context("Failed to run command under the pager")?;
+        pager.wait()?;
+        Ok(Some(status.code().unwrap_or(1)))
+    }
+}
@@ -1,12 +1,16 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::{Arc, Mutex};
 
 pub fn normalize_path(path: &Path) -> PathBuf {
     path.to_path_buf()
 }
 
-pub fn load_helixignore(repo_path: &Path) -> Vec<String> {
-    let ignore_file = repo_path.join(".helixignore");
+/// Lines of the `.helixignore` in `dir` (the repository root or any
+/// directory below it).
+pub fn load_helixignore(dir: &Path) -> Vec<String> {
+    let ignore_file = dir.join(".helixignore");
     if let Ok(content) = fs::read_to_string(&ignore_file) {
         content
             .lines()
@@ -19,101 +23,396 @@ pub fn load_helixignore(repo_path: &Path) -> Vec<String> {
     }
 }
 
-pub fn is_ignored(path: &Path, repo_path: &Path) -> bool {
-    let relative_path = get_relative_path(repo_path, path).unwrap_or_default();
-
-    // Load .helixignore patterns
-    let ignore_patterns = load_helixignore(repo_path);
-
-    // Common ignore patterns (built-in)
-    let built_in_patterns = [
-        ".helix",
-        ".git",
-        "target",
-        "node_modules",
-        ".DS_Store",
-        "*.tmp",
-        "*.log",
-        "*.swp",
-        "*.swo",
-        "*~",
-        ".vscode",
-        ".idea",
-        "*.o",
-        "*.so",
-        "*.dylib",
-        "*.dll",
-        "*.exe",
-        "*.pyc",
-        "__pycache__",
-        ".pytest_cache",
-        "*.class",
-        "*.jar",
-        "*.war",
-        "*.ear",
-        "*.min.js",
-        "*.min.css",
-        "dist",
-        "build",
-        "out",
-        "coverage",
-        ".nyc_output",
-        "*.lcov",
-        ".env",
-        ".env.local",
-        ".env.*.local",
-    ];
-
-    // Check built-in patterns
-    for pattern in &built_in_patterns {
-        if matches_pattern(&relative_path, pattern) {
-            return true;
+/// Load `.helixattributes` as (pattern, attributes) pairs.
+pub fn load_helixattributes(repo_path: &Path) -> Vec<(String, Vec<String>)> {
+    let attributes_file = repo_path.join(".helixattributes");
+    if let Ok(content) = fs::read_to_string(&attributes_file) {
+        content
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next()?.to_string();
+                Some((pattern, parts.map(|a| a.to_string()).collect()))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Parsed `.helixattributes` rules, in file order.
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    rules: Vec<(IgnorePattern, Vec<String>)>,
+}
+
+impl Attributes {
+    pub fn load(repo_path: &Path) -> Self {
+        let rules = load_helixattributes(repo_path)
+            .into_iter()
+            .filter_map(|(pattern, attributes)| Some((IgnorePattern::parse(&pattern)?, attributes)))
+            .collect();
+        Self { rules }
+    }
+
+    /// Whether `attribute` is set for a repo-relative path. Later lines
+    /// win, and `-attribute` unsets it.
+    pub fn has(&self, relative_path: &str, attribute: &str) -> bool {
+        let unset = format!("-{}", attribute);
+        let mut set = false;
+        for (pattern, attributes) in &self.rules {
+            if pattern.matches(relative_path, false) {
+                for attr in attributes {
+                    if attr == attribute {
+                        set = true;
+                    } else if attr == &unset {
+                        set = false;
+                    }
+                }
+            }
         }
+        set
+    }
+}
+
+/// Whether `path` is selected by a pathspec: `.` selects everything, a
+/// pattern with `*` or `?` is a glob over the whole path, anything else
+/// names a file or a directory prefix.
+pub fn pathspec_matches(spec: &str, path: &str) -> bool {
+    if spec == "." || spec.is_empty() {
+        return true;
+    }
+    if spec.contains(['*', '?']) {
+        return glob_match(spec, path);
     }
+    let spec = spec.trim_end_matches('/');
+    path == spec || path.starts_with(&format!("{}/", spec))
+}
 
-    // Check .helixignore patterns
-    for pattern in &ignore_patterns {
-        if matches_pattern(&relative_path, pattern) {
-            return true;
+/// Always ignored; `.helixignore` patterns are applied after these, so a
+/// `!pattern` there can re-include something they exclude.
+const BUILT_IN_IGNORES: &[&str] = &[
+    ".helix",
+    ".git",
+    "target",
+    "node_modules",
+    ".DS_Store",
+    "*.tmp",
+    "*.log",
+    "*.swp",
+    "*.swo",
+    "*~",
+    ".vscode",
+    ".idea",
+    "*.o",
+    "*.so",
+    "*.dylib",
+    "*.dll",
+    "*.exe",
+    "*.pyc",
+    "__pycache__",
+    ".pytest_cache",
+    "*.class",
+    "*.jar",
+    "*.war",
+    "*.ear",
+    "*.min.js",
+    "*.min.css",
+    "dist",
+    "build",
+    "out",
+    "coverage",
+    ".nyc_output",
+    "*.lcov",
+    ".env",
+    ".env.local",
+    ".env.*.local",
+];
+
+/// One `.helixignore` line in gitignore syntax: `*`, `?` and `[...]` match
+/// within a path component, `**` spans components, a leading or inner `/`
+/// anchors the pattern to the repository root, a trailing `/` matches only
+/// directories and `!` re-includes what an earlier pattern excluded.
+#[derive(Debug, Clone)]
+pub struct IgnorePattern {
+    /// Per-component globs; unanchored patterns start with `**`
+    segments: Vec<String>,
+    negated: bool,
+    dir_only: bool,
+    /// Directory of the `.helixignore` the pattern came from; it only
+    /// applies below it, with anchors relative to it
+    base: String,
+}
+
+impl IgnorePattern {
+    /// `None` for blank lines and comments.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            // `\!` and `\#` start patterns that are literally `!...`/`#...`
+            None => (
+                false,
+                line.strip_prefix('\\')
+                    .filter(|rest| rest.starts_with(['!', '#']))
+                    .unwrap_or(line),
+            ),
+        };
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let mut segments: Vec<String> = pattern
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.to_string())
+            .collect();
+        if segments.is_empty() {
+            return None;
         }
+        if !anchored {
+            segments.insert(0, "**".to_string());
+        }
+        Some(Self {
+            segments,
+            negated,
+            dir_only,
+            base: String::new(),
+        })
+    }
+
+    /// Scope the pattern to `base`, the repo-relative directory holding the
+    /// ignore file it was read from.
+    pub fn scoped(mut self, base: &str) -> Self {
+        self.base = base.trim_matches('/').to_string();
+        self
     }
 
-    false
+    /// Whether the pattern matches a repo-relative path.
+    pub fn matches(&self, relative_path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative_path = if self.base.is_empty() {
+            relative_path
+        } else {
+            match relative_path
+                .strip_prefix(self.base.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => return false,
+            }
+        };
+        let parts: Vec<&str> = relative_path.split('/').filter(|p| !p.is_empty()).collect();
+        match_segments(&self.segments, &parts)
+    }
 }
 
-fn matches_pattern(path: &str, pattern: &str) -> bool {
-    // Handle simple patterns
-    if pattern.starts_with("*.") {
-        let ext = &pattern[2..];
-        return path.ends_with(ext);
+fn match_segments(pattern: &[String], parts: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=parts.len()).any(|skip| match_segments(rest, &parts[skip..]))
+        }
+        Some((first, rest)) => {
+            !parts.is_empty()
+                && match_component(
+                    &first.chars().collect::<Vec<_>>(),
+                    &parts[0].chars().collect::<Vec<_>>(),
+                )
+                && match_segments(rest, &parts[1..])
+        }
     }
+}
 
-    if pattern.ends_with("/") {
-        // Directory pattern
-        let dir_pattern = &pattern[..pattern.len() - 1];
-        return path.contains(dir_pattern) && path.split('/').any(|part| part == dir_pattern);
+/// Glob match of a single path component.
+fn match_component(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = &pattern[1..];
+            (0..=text.len()).any(|skip| match_component(rest, &text[skip..]))
+        }
+        Some('?') => !text.is_empty() && match_component(&pattern[1..], &text[1..]),
+        Some('[') => match (text.first(), match_class(&pattern[1..], text.first().copied())) {
+            (Some(_), Some((matched, len))) => {
+                matched && match_component(&pattern[1 + len..], &text[1..])
+            }
+            (None, Some(_)) => false,
+            // No closing `]`: the `[` is literal
+            (_, None) => text.first() == Some(&'[') && match_component(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && match_component(&pattern[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && match_component(&pattern[1..], &text[1..]),
     }
+}
 
-    if pattern.starts_with("/") {
-        // Pattern from root
-        let root_pattern = &pattern[1..];
-        return path.starts_with(root_pattern);
+/// Match `c` against a character class whose `[` has been consumed.
+/// Returns whether it matched and how many pattern characters the class
+/// used, or `None` if the class is never closed.
+fn match_class(class: &[char], c: Option<char>) -> Option<(bool, usize)> {
+    let negated = matches!(class.first(), Some('!' | '^'));
+    let mut i = usize::from(negated);
+    let start = i;
+    let mut matched = false;
+    while i < class.len() {
+        // A `]` right after the opening bracket is a literal member
+        if class[i] == ']' && i > start {
+            return Some((matched != negated, i + 1));
+        }
+        if i + 2 < class.len() && class[i + 1] == '-' && class[i + 2] != ']' {
+            matched |= c.is_some_and(|c| (class[i]..=class[i + 2]).contains(&c));
+            i += 3;
+        } else {
+            matched |= c == Some(class[i]);
+            i += 1;
+        }
     }
+    None
+}
 
-    if pattern.ends_with("*") {
-        // Prefix pattern
-        let prefix = &pattern[..pattern.len() - 1];
-        return path.starts_with(prefix);
+/// Apply `patterns` in order to a repo-relative path: the last one that
+/// matches decides. As in Git, nothing inside an ignored directory can be
+/// re-included, so each parent directory is checked first.
+pub fn ignored_by(patterns: &[&IgnorePattern], relative_path: &str, is_dir: bool) -> bool {
+    let decide = |path: &str, is_dir: bool| {
+        patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map(|pattern| !pattern.negated)
+    };
+    let parts: Vec<&str> = relative_path.split('/').filter(|p| !p.is_empty()).collect();
+    for end in 1..parts.len() {
+        if decide(&parts[..end].join("/"), true) == Some(true) {
+            return true;
+        }
     }
+    decide(relative_path, is_dir) == Some(true)
+}
 
-    if pattern.starts_with("*") && pattern.ends_with("*") {
-        // Contains pattern
-        let contains = &pattern[1..pattern.len() - 1];
-        return path.contains(contains);
+/// Ignore rules for one repository: the built-in patterns, then every
+/// `.helixignore` from the root down to a path's directory, deeper files
+/// overriding shallower ones. Ignore files are read once per directory, so
+/// keep one of these for the length of a walk.
+pub struct IgnoreRules {
+    repo_path: PathBuf,
+    built_in: Vec<IgnorePattern>,
+    /// Parsed `.helixignore` per repo-relative directory ("" is the root)
+    by_dir: Mutex<HashMap<String, Arc<Vec<IgnorePattern>>>>,
+}
+
+impl IgnoreRules {
+    pub fn new(repo_path: &Path) -> Self {
+        Self {
+            repo_path: repo_path.to_path_buf(),
+            built_in: BUILT_IN_IGNORES
+                .iter()
+                .filter_map(|pattern| IgnorePattern::parse(pattern))
+                .collect(),
+            by_dir: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn dir_patterns(&self, dir: &str) -> Arc<Vec<IgnorePattern>> {
+        if let Some(patterns) = self.by_dir.lock().unwrap().get(dir) {
+            return patterns.clone();
+        }
+        let patterns: Arc<Vec<IgnorePattern>> = Arc::new(
+            load_helixignore(&self.repo_path.join(dir))
+                .iter()
+                .filter_map(|line| IgnorePattern::parse(line))
+                .map(|pattern| pattern.scoped(dir))
+                .collect(),
+        );
+        self.by_dir
+            .lock()
+            .unwrap()
+            .insert(dir.to_string(), patterns.clone());
+        patterns
+    }
+
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let relative_path = get_relative_path(&self.repo_path, path)
+            .or_else(|| path.is_relative().then(|| path.to_string_lossy().to_string()))
+            .unwrap_or_default();
+        let relative_path = relative_path.trim_start_matches("./");
+
+        let parts: Vec<&str> = relative_path.split('/').filter(|p| !p.is_empty()).collect();
+        let dirs = (0..parts.len()).map(|depth| parts[..depth].join("/"));
+        let files: Vec<Arc<Vec<IgnorePattern>>> = dirs.map(|dir| self.dir_patterns(&dir)).collect();
+        let patterns: Vec<&IgnorePattern> = self
+            .built_in
+            .iter()
+            .chain(files.iter().flat_map(|patterns| patterns.iter()))
+            .collect();
+        ignored_by(&patterns, relative_path, path.is_dir())
+    }
+}
+
+/// One-off check; walks over many paths should share an [`IgnoreRules`].
+pub fn is_ignored(path: &Path, repo_path: &Path) -> bool {
+    IgnoreRules::new(repo_path).is_ignored(path)
+}
+
+/// Shell-style glob match of a whole string: `*` matches any run of
+/// characters, `?` a single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
     }
+    pattern[p..].iter().all(|&c| c == '*')
+}
 
-    // Exact match
-    path == pattern || path.contains(pattern)
+/// A subdirectory with its own `.helix` or `.git` is a separate repository;
+/// working tree walks treat it as a boundary instead of descending into it.
+pub fn is_nested_repo(dir: &Path, repo_path: &Path) -> bool {
+    dir != repo_path && (dir.join(".helix").is_dir() || dir.join(".git").exists())
+}
+
+/// A path as given on the command line, as the repository-relative,
+/// `/`-separated form trees record: absolute paths inside `repo_root` lose
+/// that prefix, and `.` and `..` components are resolved.
+pub fn repo_relative_path(repo_root: &Path, path: &str) -> String {
+    let path = Path::new(path);
+    let root = repo_root.canonicalize().unwrap_or_else(|_| repo_root.to_path_buf());
+    let path = path.strip_prefix(&root).unwrap_or(path);
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            std::path::Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    parts.join("/")
 }
 
 pub fn get_relative_path(base: &Path, path: &Path) -> Option<String> {
@@ -163,3 +462,113 @@ pub fn collect_trackable_files(repo_path: &Path) -> Vec<PathBuf> {
     
     files
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `path` is ignored by the `.helixignore` lines alone.
+    fn ignored(lines: &[&str], path: &str, is_dir: bool) -> bool {
+        let patterns: Vec<IgnorePattern> = lines.iter().filter_map(|line| IgnorePattern::parse(line)).collect();
+        ignored_by(&patterns.iter().collect::<Vec<_>>(), path, is_dir)
+    }
+
+    #[test]
+    fn star_matches_within_a_component_at_any_depth() {
+        assert!(ignored(&["*.log"], "debug.log", false));
+        assert!(ignored(&["*.log"], "logs/deep/debug.log", false));
+        assert!(!ignored(&["*.log"], "debug.log.txt", false));
+        assert!(!ignored(&["a*b"], "a/b", false));
+    }
+
+    #[test]
+    fn double_star_spans_directories() {
+        assert!(ignored(&["docs/**/*.pdf"], "docs/a.pdf", false));
+        assert!(ignored(&["docs/**/*.pdf"], "docs/x/y/a.pdf", false));
+        assert!(!ignored(&["docs/**/*.pdf"], "other/docs/a.pdf", false));
+        assert!(ignored(&["**/cache"], "a/b/cache", true));
+    }
+
+    #[test]
+    fn question_mark_and_classes_match_one_character() {
+        assert!(ignored(&["file?.txt"], "file1.txt", false));
+        assert!(!ignored(&["file?.txt"], "file10.txt", false));
+        assert!(ignored(&["*.[oa]"], "lib.a", false));
+        assert!(!ignored(&["*.[oa]"], "lib.c", false));
+        assert!(ignored(&["v[0-9]"], "v7", false));
+        assert!(!ignored(&["v[!0-9]"], "v7", false));
+        assert!(ignored(&["v[!0-9]"], "vx", false));
+    }
+
+    #[test]
+    fn a_slash_anchors_to_the_root() {
+        assert!(ignored(&["/build"], "build", true));
+        assert!(!ignored(&["/build"], "src/build", true));
+        assert!(ignored(&["src/gen"], "src/gen/a.rs", false));
+        assert!(!ignored(&["src/gen"], "lib/src/gen", true));
+    }
+
+    #[test]
+    fn a_trailing_slash_matches_only_directories() {
+        assert!(ignored(&["tmp/"], "tmp", true));
+        assert!(!ignored(&["tmp/"], "tmp", false));
+        // Files inside an ignored directory are ignored with it
+        assert!(ignored(&["tmp/"], "a/tmp/file", false));
+    }
+
+    #[test]
+    fn negation_re_includes_in_order() {
+        assert!(!ignored(&["*.log", "!keep.log"], "keep.log", false));
+        assert!(ignored(&["*.log", "!keep.log"], "other.log", false));
+        // A later pattern wins back
+        assert!(ignored(&["!keep.log", "*.log"], "keep.log", false));
+        // Nothing inside an ignored directory can be re-included
+        assert!(ignored(&["out/", "!out/keep"], "out/keep", false));
+    }
+
+    #[test]
+    fn user_patterns_apply_after_the_built_in_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".helixignore"), "!important.log\n").unwrap();
+        let rules = IgnoreRules::new(dir.path());
+        assert!(rules.is_ignored(&dir.path().join("debug.log")));
+        assert!(!rules.is_ignored(&dir.path().join("important.log")));
+        assert!(rules.is_ignored(&dir.path().join(".helix")));
+    }
+
+    #[test]
+    fn nested_ignore_files_apply_below_their_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join(".helixignore"), "*.bak\n/generated.rs\n").unwrap();
+        fs::write(root.join("src/.helixignore"), "!keep.bak\n/generated.rs\n").unwrap();
+
+        let rules = IgnoreRules::new(root);
+        assert!(rules.is_ignored(&root.join("notes.bak")));
+        assert!(rules.is_ignored(&root.join("src/old.bak")));
+        // The deeper file overrides the root one below `src/` only
+        assert!(!rules.is_ignored(&root.join("src/keep.bak")));
+        assert!(rules.is_ignored(&root.join("docs/keep.bak")));
+        // Anchors are relative to the directory of the ignore file
+        assert!(rules.is_ignored(&root.join("generated.rs")));
+        assert!(rules.is_ignored(&root.join("src/generated.rs")));
+        assert!(!rules.is_ignored(&root.join("src/gen/generated.rs")));
+        assert!(!rules.is_ignored(&root.join("docs/generated.rs")));
+        // Each directory's file is read once per walk
+        assert_eq!(rules.by_dir.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn later_attribute_lines_win() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".helixattributes"), "*.dat binary\n# comment\nkeep.dat -binary\n").unwrap();
+        let attributes = Attributes::load(dir.path());
+
+        assert!(attributes.has("data/blob.dat", "binary"));
+        assert!(!attributes.has("keep.dat", "binary"));
+        assert!(!attributes.has("notes.txt", "binary"));
+        assert!(!Attributes::load(&dir.path().join("missing")).has("blob.dat", "binary"));
+    }
+}
@@ -1,12 +1,36 @@
 use anyhow::{Context, Result};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
-    Client, Response,
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER},
+    Client, RequestBuilder, Response, StatusCode,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Mutex;
 use std::time::Duration;
 use crate::utils::auth::AuthManager;
+use crate::utils::config::GlobalConfig;
+use rand::Rng;
+
+/// Number of hashes requested per `/objects` page.
+pub const OBJECT_PAGE_SIZE: usize = 1000;
+
+/// Default number of objects fetched per `/objects/batch` request.
+pub const OBJECT_BATCH_SIZE: usize = 256;
+
+/// Bytes sent per request by `upload_pack_resumable`.
+pub const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Consecutive failed chunk uploads tolerated before giving up.
+const UPLOAD_MAX_RETRIES: usize = 5;
+
+/// Default number of times a request is retried after a transient failure.
+pub const DEFAULT_RETRIES: usize = 3;
+
+/// Default delay before the first retry; it doubles with each attempt.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest `Retry-After` honoured; a server asking for more fails the request.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NegotiationRequest {
@@ -32,6 +56,9 @@ pub struct PushRequest {
     pub refs: HashMap<String, String>,
     pub objects: Vec<String>,
     pub force: bool,
+    /// Ask the server to apply every ref update or none of them
+    #[serde(default)]
+    pub atomic: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,9 +67,13 @@ pub struct PushResponse {
     pub updated_refs: Vec<String>,
     pub rejected_refs: Vec<String>,
     pub error: Option<String>,
+    /// Per-ref outcome from servers advertising `report-status`:
+    /// "ok" or the reason the update was refused
+    #[serde(default)]
+    pub ref_status: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Capabilities {
     pub multi_ack: bool,
     pub multi_ack_detailed: bool,
@@ -58,6 +89,19 @@ pub struct Capabilities {
     pub quiet: bool,
     pub atomic: bool,
     pub push_options: bool,
+    /// Server accepts `POST /objects/batch`
+    #[serde(default)]
+    pub object_batch: bool,
+    /// Server accepts chunked pack uploads through `/upload-pack/session`
+    #[serde(default)]
+    pub resumable_upload: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub session_id: String,
+    /// Bytes the server has stored so far
+    pub received: u64,
 }
 
 pub struct RemoteClient {
@@ -67,6 +111,15 @@ pub struct RemoteClient {
     pub auth_token: Option<String>,
     pub timeout: Duration,
     pub auth_manager: Option<AuthManager>,
+    /// Objects requested per batch download
+    pub batch_size: usize,
+    /// Retries after connection errors and 5xx/429 responses
+    pub max_retries: usize,
+    /// Delay before the first retry
+    pub backoff: Duration,
+    /// Last ref listing and the ETag it was served with, reused when the
+    /// server answers a conditional request with 304 Not Modified
+    refs_cache: Mutex<Option<(String, HashMap<String, String>)>>,
 }
 
 impl RemoteClient {
@@ -94,6 +147,10 @@ impl RemoteClient {
             auth_token: None,
             timeout: Duration::from_secs(30),
             auth_manager: None,
+            batch_size: OBJECT_BATCH_SIZE,
+            max_retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            refs_cache: Mutex::new(None),
         }
     }
 
@@ -112,31 +169,121 @@ impl RemoteClient {
         self
     }
 
-    async fn make_request(&self, method: &str, endpoint: &str, body: Option<&[u8]>) -> Result<Response> {
-        let url = format!("{}/{}", self.base_url, endpoint.trim_start_matches('/'));
-        let mut request = self.client.request(
-            method.parse().unwrap(),
-            &url,
-        );
+    #[allow(dead_code)]
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
 
-        // Add authentication headers
-        if let Some(token) = &self.auth_token {
-            request = request.header("Authorization", format!("Bearer {}", token));
-        } else if let Some(auth_manager) = &self.auth_manager {
-            let auth_headers = auth_manager.get_auth_headers(&url)?;
-            for (key, value) in auth_headers {
-                request = request.header(key, value);
-            }
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// `with_retries` and `with_backoff` from `http.retries` and
+    /// `http.backoff` in the global config, where set.
+    pub fn with_config(mut self, config: &GlobalConfig) -> Self {
+        if let Some(retries) = config.get_http_retries() {
+            self = self.with_retries(retries);
         }
+        if let Some(backoff) = config.get_http_backoff() {
+            self = self.with_backoff(backoff);
+        }
+        self
+    }
+
+    /// A client for `base_url` with the retry settings of the global config.
+    pub fn configured(base_url: &str) -> Self {
+        Self::new(base_url).with_config(&GlobalConfig::load().unwrap_or_default())
+    }
 
-        if let Some(body_data) = body {
-            request = request.body(body_data.to_vec());
+    /// How long to wait before retry number `attempt` (from 0): the backoff
+    /// doubled per attempt plus up to half again of jitter, or what the
+    /// server asked for in `Retry-After` up to `MAX_RETRY_AFTER`.
+    fn retry_delay(&self, attempt: usize, response: Option<&Response>) -> Result<Duration> {
+        let retry_after = response
+            .and_then(|r| r.headers().get(RETRY_AFTER))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok());
+        if let Some(seconds) = retry_after {
+            let delay = Duration::from_secs(seconds);
+            if delay > MAX_RETRY_AFTER {
+                anyhow::bail!(
+                    "Server asked to retry after {}s, longer than the {}s allowed",
+                    seconds,
+                    MAX_RETRY_AFTER.as_secs()
+                );
+            }
+            return Ok(delay);
         }
+        let delay = self.backoff.saturating_mul(1 << attempt.min(16));
+        let jitter = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 2);
+        Ok(delay + Duration::from_millis(jitter))
+    }
 
-        let response = request.send().await
-            .with_context(|| format!("Failed to connect to {}", url))?;
+    async fn make_request(&self, method: &str, endpoint: &str, body: Option<&[u8]>) -> Result<Response> {
+        self.make_conditional_request(method, endpoint, body, None).await
+    }
+
+    /// Like `make_request`, but sends `If-None-Match` when an ETag is given
+    /// and then accepts a 304 response.
+    async fn make_conditional_request(
+        &self,
+        method: &str,
+        endpoint: &str,
+        body: Option<&[u8]>,
+        etag: Option<&str>,
+    ) -> Result<Response> {
+        let url = format!("{}/{}", self.base_url, endpoint.trim_start_matches('/'));
+        // A POST or PATCH that failed after reaching the server may still
+        // have taken effect, so those are only retried when the server
+        // can't have acted on them
+        let idempotent = matches!(method, "GET" | "HEAD" | "PUT" | "DELETE");
+
+        let mut attempt = 0;
+        let response = loop {
+            let mut request = self.authorized_request(method, &url)?;
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(body_data) = body {
+                request = request.body(body_data.to_vec());
+            }
+
+            let can_retry = attempt < self.max_retries;
+            match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    let transient = status == StatusCode::TOO_MANY_REQUESTS
+                        || status == StatusCode::SERVICE_UNAVAILABLE
+                        || (idempotent && status.is_server_error());
+                    if !(can_retry && transient) {
+                        break response;
+                    }
+                    let delay = self
+                        .retry_delay(attempt, Some(&response))
+                        .with_context(|| format!("HTTP {} from {}", status, url))?;
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) if can_retry && (idempotent || e.is_connect()) => {
+                    tokio::time::sleep(self.retry_delay(attempt, None)?).await;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to connect to {}", url));
+                }
+            }
+            attempt += 1;
+        };
 
         let status = response.status();
+        if etag.is_some() && status == StatusCode::NOT_MODIFIED {
+            return Ok(response);
+        }
         if !status.is_success() {
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
             return Err(anyhow::anyhow!(
@@ -149,7 +296,44 @@ impl RemoteClient {
         Ok(response)
     }
 
+    /// A request for `url` carrying the client's token or the auth
+    /// manager's headers for its host.
+    fn authorized_request(&self, method: &str, url: &str) -> Result<RequestBuilder> {
+        let mut request = self.client.request(
+            method.parse().unwrap(),
+            url,
+        );
+
+        // Add authentication headers
+        if let Some(token) = &self.auth_token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        } else if let Some(auth_manager) = &self.auth_manager {
+            let auth_headers = auth_manager.get_auth_headers(url)?;
+            for (key, value) in auth_headers {
+                request = request.header(key, value);
+            }
+        }
+        Ok(request)
+    }
+
+    /// Status of an authenticated `GET endpoint`, without treating error
+    /// statuses as failures; only connection errors are.
+    pub async fn probe(&self, endpoint: &str) -> Result<StatusCode> {
+        let url = format!("{}/{}", self.base_url, endpoint.trim_start_matches('/'));
+        let response = self
+            .authorized_request("GET", &url)?
+            .send()
+            .await
+            .with_context(|| format!("Failed to connect to {}", url))?;
+        Ok(response.status())
+    }
+
     pub async fn discover_capabilities(&mut self) -> Result<Capabilities> {
+        // Capabilities don't change within one invocation
+        if let Some(capabilities) = &self.capabilities {
+            return Ok(capabilities.clone());
+        }
+
         let response = self.make_request("GET", "/info/refs", None).await?;
         let text = response.text().await?;
         
@@ -169,6 +353,8 @@ impl RemoteClient {
             quiet: text.contains("quiet"),
             atomic: text.contains("atomic"),
             push_options: text.contains("push-options"),
+            object_batch: text.contains("object-batch"),
+            resumable_upload: text.contains("resumable-upload"),
         };
 
         self.capabilities = Some(capabilities.clone());
@@ -201,6 +387,64 @@ impl RemoteClient {
         }
     }
 
+    /// Upload a pack in `UPLOAD_CHUNK_SIZE` pieces within one upload session.
+    /// After a failed chunk the server is asked how much it has stored and
+    /// the upload continues from there. `progress` gets (bytes sent, total).
+    pub async fn upload_pack_resumable<F>(&self, pack_data: &[u8], mut progress: F) -> Result<()>
+    where
+        F: FnMut(u64, u64),
+    {
+        let total = pack_data.len() as u64;
+        let body = serde_json::to_vec(&serde_json::json!({ "size": total }))?;
+        let response = self.make_request("POST", "/upload-pack/session", Some(&body)).await?;
+        let session: UploadSession = response.json().await?;
+        let endpoint = format!("/upload-pack/session/{}", session.session_id);
+
+        let mut offset = session.received.min(total);
+        let mut failures = 0;
+        progress(offset, total);
+        while offset < total {
+            let end = (offset as usize + UPLOAD_CHUNK_SIZE).min(pack_data.len());
+            let chunk = &pack_data[offset as usize..end];
+            let result = self
+                .make_request("PATCH", &format!("{}?offset={}", endpoint, offset), Some(chunk))
+                .await;
+            let received = match result {
+                Ok(response) => response
+                    .json::<UploadSession>()
+                    .await
+                    .map(|s| s.received)
+                    .map_err(anyhow::Error::from),
+                Err(e) => Err(e),
+            };
+            match received {
+                Ok(received) => {
+                    failures = 0;
+                    offset = received.min(total);
+                }
+                Err(e) => {
+                    failures += 1;
+                    if failures > UPLOAD_MAX_RETRIES {
+                        return Err(e.context(format!(
+                            "Pack upload failed at byte {} of {}",
+                            offset, total
+                        )));
+                    }
+                    // Resume from whatever the server actually stored
+                    if let Ok(response) = self.make_request("GET", &endpoint, None).await {
+                        if let Ok(state) = response.json::<UploadSession>().await {
+                            offset = state.received.min(total);
+                        }
+                    }
+                }
+            }
+            progress(offset, total);
+        }
+
+        self.make_request("POST", &format!("{}/finish", endpoint), None).await?;
+        Ok(())
+    }
+
     pub async fn download_pack(&self, pack_id: &str) -> Result<Vec<u8>> {
         let response = self.make_request("GET", &format!("/pack/{}", pack_id), None).await?;
         Ok(response.bytes().await?.to_vec())
@@ -221,6 +465,28 @@ impl RemoteClient {
         Ok(response.bytes().await?.to_vec())
     }
 
+    /// Fetch several objects, batching requests when the server advertises
+    /// `object-batch` (discovered via `discover_capabilities`) and falling
+    /// back to one request per object otherwise.
+    pub async fn download_objects(&self, hashes: &[String]) -> Result<HashMap<String, Vec<u8>>> {
+        let mut objects = HashMap::new();
+        let batched = self.capabilities.as_ref().is_some_and(|c| c.object_batch);
+        if !batched {
+            for hash in hashes {
+                objects.insert(hash.clone(), self.download_object(hash).await?);
+            }
+            return Ok(objects);
+        }
+
+        for chunk in hashes.chunks(self.batch_size) {
+            let body = serde_json::to_vec(&serde_json::json!({ "hashes": chunk }))?;
+            let response = self.make_request("POST", "/objects/batch", Some(&body)).await?;
+            let data = response.bytes().await?;
+            objects.extend(parse_object_frames(&data)?);
+        }
+        Ok(objects)
+    }
+
     pub async fn get_ref(&self, branch: &str) -> Result<String> {
         let response = self.make_request("GET", &format!("/refs/{}", branch), None).await?;
         Ok(response.text().await?)
@@ -235,19 +501,48 @@ impl RemoteClient {
         }
     }
 
-    pub async fn get_all_object_hashes(&self) -> Result<Vec<String>> {
-        let response = self.make_request("GET", "/objects", None).await?;
+    /// Fetch one page of remote object hashes (sorted ascending), starting
+    /// after `after`.
+    pub async fn get_object_hash_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let endpoint = match after {
+            Some(after) => format!("/objects?after={}&limit={}", after, limit),
+            None => format!("/objects?limit={}", limit),
+        };
+        let response = self.make_request("GET", &endpoint, None).await?;
         let text = response.text().await?;
-            Ok(text
-                .lines()
-                .map(|l| l.trim().to_string())
-                .filter(|l| !l.is_empty())
-                .collect())
+        Ok(text
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect())
     }
 
     pub async fn get_refs(&self) -> Result<HashMap<String, String>> {
-        let response = self.make_request("GET", "/refs", None).await?;
+        let cached_etag = self
+            .refs_cache
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(etag, _)| etag.clone());
+        let response = self
+            .make_conditional_request("GET", "/refs", None, cached_etag.as_deref())
+            .await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some((_, refs)) = self.refs_cache.lock().unwrap().as_ref() {
+                return Ok(refs.clone());
+            }
+        }
+
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
         let refs: HashMap<String, String> = response.json().await?;
+        if let Some(etag) = etag {
+            *self.refs_cache.lock().unwrap() = Some((etag, refs.clone()));
+        }
         Ok(refs)
     }
 
@@ -258,3 +553,249 @@ impl RemoteClient {
         }
     }
 }
+
+/// Split a batch response into objects. Each frame is the 64-character hex
+/// id, a big-endian u32 length and that many bytes of object data.
+fn parse_object_frames(mut data: &[u8]) -> Result<HashMap<String, Vec<u8>>> {
+    let mut objects = HashMap::new();
+    while !data.is_empty() {
+        if data.len() < 68 {
+            anyhow::bail!("Truncated object batch frame header");
+        }
+        let hash = std::str::from_utf8(&data[..64])
+            .context("Invalid object id in batch response")?
+            .to_string();
+        let len = u32::from_be_bytes([data[64], data[65], data[66], data[67]]) as usize;
+        data = &data[68..];
+        if data.len() < len {
+            anyhow::bail!("Truncated object {} in batch response", hash);
+        }
+        objects.insert(hash, data[..len].to_vec());
+        data = &data[len..];
+    }
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::{MockResponse, MockServer};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn not_modified_reuses_the_cached_refs() {
+        let server = MockServer::start(|request| match request.header("if-none-match") {
+            Some("\"v1\"") => MockResponse::new(304, ""),
+            _ => MockResponse::new(200, r#"{"refs/heads/main":"abc"}"#).header("ETag", "\"v1\""),
+        });
+        let client = RemoteClient::new(&server.url);
+
+        let first = client.get_refs().await.unwrap();
+        let second = client.get_refs().await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(second["refs/heads/main"], "abc");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.method == "GET" && r.path == "/refs" && r.body.is_empty()));
+        assert_eq!(requests[0].header("if-none-match"), None);
+        assert_eq!(requests[1].header("if-none-match"), Some("\"v1\""));
+    }
+
+    /// Serves `object-<id>` for every object, per object or framed in batches.
+    fn object_server(capabilities: &'static str) -> MockServer {
+        MockServer::start(move |request| match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/info/refs") => MockResponse::new(200, capabilities),
+            ("POST", "/objects/batch") => {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let mut frames = Vec::new();
+                for hash in body["hashes"].as_array().unwrap() {
+                    let hash = hash.as_str().unwrap();
+                    let data = format!("object-{}", hash);
+                    frames.extend_from_slice(hash.as_bytes());
+                    frames.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                    frames.extend_from_slice(data.as_bytes());
+                }
+                MockResponse::new(200, frames)
+            }
+            ("GET", path) => MockResponse::new(200, format!("object-{}", &path["/objects/".len()..])),
+            _ => MockResponse::new(404, ""),
+        })
+    }
+
+    fn hashes(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("{:064x}", i)).collect()
+    }
+
+    #[tokio::test]
+    async fn objects_are_fetched_in_batches_when_advertised() {
+        let server = object_server("side-band-64k object-batch");
+        let mut client = RemoteClient::new(&server.url).with_batch_size(2);
+        client.discover_capabilities().await.unwrap();
+
+        let wanted = hashes(5);
+        let objects = client.download_objects(&wanted).await.unwrap();
+        assert_eq!(objects.len(), 5);
+        for hash in &wanted {
+            assert_eq!(objects[hash], format!("object-{}", hash).into_bytes());
+        }
+
+        let batches: Vec<usize> = server
+            .requests()
+            .iter()
+            .filter(|r| r.path == "/objects/batch")
+            .map(|r| {
+                let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+                body["hashes"].as_array().unwrap().len()
+            })
+            .collect();
+        assert_eq!(batches, vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn objects_are_fetched_one_by_one_otherwise() {
+        let server = object_server("side-band-64k");
+        let mut client = RemoteClient::new(&server.url).with_batch_size(2);
+        client.discover_capabilities().await.unwrap();
+
+        let objects = client.download_objects(&hashes(3)).await.unwrap();
+        assert_eq!(objects.len(), 3);
+        let paths: Vec<String> = server.requests().into_iter().skip(1).map(|r| r.path).collect();
+        assert_eq!(paths, hashes(3).iter().map(|h| format!("/objects/{}", h)).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_upload_resumes_where_the_server_stopped() {
+        let pack: Vec<u8> = (0..UPLOAD_CHUNK_SIZE * 5 / 2).map(|i| (i % 251) as u8).collect();
+        let stored = Arc::new(Mutex::new(Vec::new()));
+        let server_stored = Arc::clone(&stored);
+        let patches = Arc::new(Mutex::new(0));
+        let server = MockServer::start(move |request| {
+            let mut stored = server_stored.lock().unwrap();
+            let state = |received: usize| {
+                MockResponse::new(200, format!(r#"{{"session_id":"s1","received":{}}}"#, received))
+            };
+            match (request.method.as_str(), request.path.as_str()) {
+                ("PATCH", path) => {
+                    let offset: usize = path.rsplit_once("offset=").unwrap().1.parse().unwrap();
+                    assert_eq!(offset, stored.len(), "chunk sent from the wrong offset");
+                    let mut patches = patches.lock().unwrap();
+                    *patches += 1;
+                    if *patches == 2 {
+                        // Keep half the chunk, then lose the connection
+                        stored.extend_from_slice(&request.body[..request.body.len() / 2]);
+                        return MockResponse::dropped();
+                    }
+                    stored.extend_from_slice(&request.body);
+                    state(stored.len())
+                }
+                _ => state(stored.len()),
+            }
+        });
+        let client = RemoteClient::new(&server.url).with_retries(0);
+
+        let mut reported = Vec::new();
+        client
+            .upload_pack_resumable(&pack, |done, _| reported.push(done as usize))
+            .await
+            .unwrap();
+
+        assert_eq!(*stored.lock().unwrap(), pack);
+        let half = UPLOAD_CHUNK_SIZE / 2;
+        let offsets: Vec<usize> = server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "PATCH")
+            .map(|r| r.path.rsplit_once("offset=").unwrap().1.parse().unwrap())
+            .collect();
+        // The resumed chunk starts at what the server kept and runs to the end
+        assert_eq!(offsets, vec![0, UPLOAD_CHUNK_SIZE, UPLOAD_CHUNK_SIZE + half]);
+        assert_eq!(reported.last(), Some(&pack.len()));
+        let last = server.requests().pop().unwrap();
+        assert_eq!((last.method.as_str(), last.path.as_str()), ("POST", "/upload-pack/session/s1/finish"));
+    }
+
+    /// Answers `failure` to the first `failures` requests, then `{}`.
+    fn flaky_server(failures: usize, failure: fn() -> MockResponse) -> MockServer {
+        let seen = Mutex::new(0);
+        MockServer::start(move |_| {
+            let mut seen = seen.lock().unwrap();
+            *seen += 1;
+            if *seen <= failures {
+                failure()
+            } else {
+                MockResponse::new(200, "{}")
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let server = flaky_server(2, || MockResponse::new(503, "busy"));
+        let client = RemoteClient::new(&server.url).with_backoff(Duration::from_millis(1));
+        assert!(client.get_refs().await.unwrap().is_empty());
+        assert_eq!(server.requests().len(), 3);
+
+        let server = flaky_server(1, || MockResponse::new(429, "").header("Retry-After", "0"));
+        let client = RemoteClient::new(&server.url).with_backoff(Duration::from_secs(60));
+        assert!(client.get_refs().await.is_ok());
+        assert_eq!(server.requests().len(), 2);
+
+        let server = flaky_server(usize::MAX, || MockResponse::new(502, "down"));
+        let client = RemoteClient::new(&server.url)
+            .with_retries(2)
+            .with_backoff(Duration::from_millis(1));
+        let err = client.get_refs().await.unwrap_err();
+        assert!(err.to_string().contains("502"), "{}", err);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_retry_after_beyond_the_limit_fails_instead_of_waiting() {
+        let server = flaky_server(1, || MockResponse::new(503, "").header("Retry-After", "86400"));
+        let client = RemoteClient::new(&server.url).with_backoff(Duration::from_millis(1));
+        let err = client.get_refs().await.unwrap_err();
+        assert!(format!("{:#}", err).contains("retry after 86400s"), "{:#}", err);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn retry_settings_come_from_the_global_config() {
+        let mut config = GlobalConfig::default();
+        config.set("http.retries", "7").unwrap();
+        config.set("http.backoff", "250").unwrap();
+        let client = RemoteClient::new("http://localhost").with_config(&config);
+        assert_eq!(client.max_retries, 7);
+        assert_eq!(client.backoff, Duration::from_millis(250));
+
+        let client = RemoteClient::new("http://localhost").with_config(&GlobalConfig::default());
+        assert_eq!(client.max_retries, DEFAULT_RETRIES);
+        assert_eq!(client.backoff, DEFAULT_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn a_post_the_server_failed_is_not_repeated() {
+        let server = flaky_server(1, || MockResponse::new(500, "oops"));
+        let client = RemoteClient::new(&server.url).with_backoff(Duration::from_millis(1));
+        let request = NegotiationRequest {
+            wants: vec!["a".repeat(64)],
+            haves: Vec::new(),
+            shallow: Vec::new(),
+            deepen_since: None,
+            deepen_not: None,
+            filter: None,
+        };
+        assert!(client.negotiate_fetch(&request).await.is_err());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn backoff_doubles_with_up_to_half_again_of_jitter() {
+        let client = RemoteClient::new("http://localhost").with_backoff(Duration::from_millis(100));
+        for attempt in 0..4 {
+            let base = Duration::from_millis(100 << attempt);
+            let delay = client.retry_delay(attempt, None).unwrap();
+            assert!(delay >= base && delay <= base + base / 2, "attempt {}: {:?}", attempt, delay);
+        }
+    }
+}
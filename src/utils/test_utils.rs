@@ -0,0 +1,220 @@
+//! Scratch repositories for tests.
+
+use crate::commands::{add, checkout, commit, merge};
+use crate::core::branch::Branch;
+use crate::core::repository::Repository;
+use crate::utils::file_utils;
+use ed25519_dalek::SigningKey;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// A repository in a temporary directory, removed when dropped.
+pub struct TestRepo {
+    _dir: TempDir,
+    pub repo: Repository,
+}
+
+impl TestRepo {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let mut repo = Repository::new(dir.path()).unwrap();
+        repo.branches.insert("main".to_string(), Branch::new("main"));
+        std::fs::create_dir_all(repo.get_objects_dir()).unwrap();
+        std::fs::create_dir_all(repo.get_refs_dir()).unwrap();
+        repo.config.author = "Test".to_string();
+        repo.config.email = "test@example.com".to_string();
+        repo.save().unwrap();
+        Self { _dir: dir, repo }
+    }
+
+    pub fn write(&self, path: &str, content: &str) {
+        self.write_bytes(path, content.as_bytes());
+    }
+
+    pub fn write_bytes(&self, path: &str, content: &[u8]) {
+        file_utils::write_file_content(&self.repo.path.join(path), content).unwrap();
+    }
+
+    pub fn read(&self, path: &str) -> String {
+        std::fs::read_to_string(self.repo.path.join(path)).unwrap()
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.repo.path.join(path).exists()
+    }
+
+    pub fn remove(&self, path: &str) {
+        std::fs::remove_file(self.repo.path.join(path)).unwrap();
+    }
+
+    /// Stage everything, deletions included.
+    pub async fn add_all(&mut self) {
+        let options = add::AddOptions { all: true, ..Default::default() };
+        let root = self.repo.path.clone();
+        add::add_files(&mut self.repo, &[root], &options).await.unwrap();
+    }
+
+    /// Stage everything and commit it; returns the new HEAD.
+    pub async fn commit(&mut self, message: &str) -> String {
+        self.commit_with(message, &commit::CommitOptions::default()).await.unwrap()
+    }
+
+    /// `commit` with options, passing on a refusal.
+    pub async fn commit_with(&mut self, message: &str, options: &commit::CommitOptions) -> anyhow::Result<String> {
+        self.add_all().await;
+        commit::commit_changes(&mut self.repo, message, options, &keypair()).await?;
+        Ok(self.head())
+    }
+
+    pub fn head(&self) -> String {
+        self.repo.head_commit().cloned().expect("no commits")
+    }
+
+    pub async fn checkout(&mut self, branch: &str) {
+        checkout::checkout_branch(&mut self.repo, branch, false).await.unwrap();
+    }
+
+    pub async fn checkout_new(&mut self, branch: &str) {
+        checkout::create_and_checkout(&mut self.repo, branch, None, false).await.unwrap();
+    }
+
+    /// Merge `branch` into HEAD with the default message; returns the new HEAD.
+    pub async fn merge(&mut self, branch: &str) -> String {
+        let options = merge::MergeOptions { no_edit: true, ..Default::default() };
+        merge::merge_branch(&mut self.repo, branch, None, false, &options).await.unwrap();
+        self.head()
+    }
+}
+
+/// A fixed signing key, so tests never touch `~/.helix/keys`.
+pub fn keypair() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+/// `text` without ANSI color sequences.
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// A request as seen by a `MockServer`; header names are lowercase.
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// What a `MockServer` handler answers with.
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Close the connection without answering, like a dropped link
+    pub dropped: bool,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self { status, headers: Vec::new(), body: body.into(), dropped: false }
+    }
+
+    pub fn dropped() -> Self {
+        Self { dropped: true, ..Self::new(0, "") }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A scripted HTTP/1.1 server on a local port, one request per connection,
+/// recording every request it answers.
+pub struct MockServer {
+    pub url: String,
+    pub requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let Some(request) = read_request(&mut BufReader::new(&stream)) else {
+                    continue;
+                };
+                let response = handler(&request);
+                seen.lock().unwrap().push(request);
+                if response.dropped {
+                    continue;
+                }
+                let mut head = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                );
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                head.push_str("\r\n");
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&response.body);
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Option<MockRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let len = headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).ok()?;
+    Some(MockRequest { method, path, headers, body })
+}
@@ -0,0 +1,608 @@
+use crate::utils::pack;
+use crate::utils::remote_client::{
+    Capabilities, NegotiationRequest, NegotiationResponse, PushRequest, PushResponse, RemoteClient,
+};
+use anyhow::{Context, Result};
+use futures_util::stream::{self, Stream, TryStreamExt};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// What push, pull and clone need from a remote, so they can run against
+/// something other than a live server.
+pub trait RemoteTransport {
+    async fn check_connectivity(&self) -> Result<bool>;
+
+    /// Remotes that don't advertise anything support only the basics.
+    async fn discover_capabilities(&mut self) -> Result<Capabilities> {
+        Ok(Capabilities::default())
+    }
+
+    /// Ref name (`refs/heads/<branch>`) -> object id.
+    async fn get_refs(&self) -> Result<HashMap<String, String>>;
+
+    /// Object id at the head of `branch`.
+    async fn get_ref(&self, branch: &str) -> Result<String> {
+        self.get_refs()
+            .await?
+            .remove(&format!("refs/heads/{}", branch))
+            .ok_or_else(|| anyhow::anyhow!("Remote has no branch '{}'", branch))
+    }
+
+    /// Up to `limit` object hashes, sorted ascending, after `after`.
+    async fn get_object_hash_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>>;
+
+    /// Raw (compressed) object data.
+    async fn download_object(&self, hash: &str) -> Result<Vec<u8>>;
+
+    async fn download_objects(&self, hashes: &[String]) -> Result<HashMap<String, Vec<u8>>> {
+        let mut objects = HashMap::new();
+        for hash in hashes {
+            objects.insert(hash.clone(), self.download_object(hash).await?);
+        }
+        Ok(objects)
+    }
+
+    async fn download_pack(&self, pack_id: &str) -> Result<Vec<u8>>;
+
+    async fn negotiate_fetch(&self, request: &NegotiationRequest) -> Result<NegotiationResponse>;
+
+    async fn negotiate_push(&self, request: &PushRequest) -> Result<PushResponse>;
+
+    async fn upload_pack(&self, pack_data: &[u8]) -> Result<()>;
+
+    /// Upload in pieces, reporting (sent, total) bytes; remotes without
+    /// resumable uploads take the pack in one go.
+    async fn upload_pack_resumable<F>(&self, pack_data: &[u8], mut progress: F) -> Result<()>
+    where
+        F: FnMut(u64, u64),
+    {
+        self.upload_pack(pack_data).await?;
+        progress(pack_data.len() as u64, pack_data.len() as u64);
+        Ok(())
+    }
+}
+
+/// Stream every object hash on `remote`, requesting pages of `page_size`
+/// until a short page comes back. Remotes that ignore pagination and return
+/// the full list are handled by dropping hashes already seen.
+pub fn object_hashes<T: RemoteTransport + ?Sized>(
+    remote: &T,
+    page_size: usize,
+) -> impl Stream<Item = Result<String>> + '_ {
+    stream::try_unfold(Some(None::<String>), move |cursor| async move {
+        let after = match cursor {
+            Some(after) => after,
+            None => return Ok::<_, anyhow::Error>(None),
+        };
+        let mut page = remote.get_object_hash_page(after.as_deref(), page_size).await?;
+        if let Some(after) = &after {
+            page.retain(|hash| hash > after);
+        }
+        let next = if page.len() != page_size {
+            None
+        } else {
+            Some(page.last().cloned())
+        };
+        if page.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((stream::iter(page.into_iter().map(Ok)), next)))
+    })
+    .try_flatten()
+}
+
+impl RemoteTransport for RemoteClient {
+    async fn check_connectivity(&self) -> Result<bool> {
+        RemoteClient::check_connectivity(self).await
+    }
+
+    async fn discover_capabilities(&mut self) -> Result<Capabilities> {
+        RemoteClient::discover_capabilities(self).await
+    }
+
+    async fn get_refs(&self) -> Result<HashMap<String, String>> {
+        RemoteClient::get_refs(self).await
+    }
+
+    async fn get_ref(&self, branch: &str) -> Result<String> {
+        RemoteClient::get_ref(self, branch).await
+    }
+
+    async fn get_object_hash_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        RemoteClient::get_object_hash_page(self, after, limit).await
+    }
+
+    async fn download_object(&self, hash: &str) -> Result<Vec<u8>> {
+        RemoteClient::download_object(self, hash).await
+    }
+
+    async fn download_objects(&self, hashes: &[String]) -> Result<HashMap<String, Vec<u8>>> {
+        RemoteClient::download_objects(self, hashes).await
+    }
+
+    async fn download_pack(&self, pack_id: &str) -> Result<Vec<u8>> {
+        RemoteClient::download_pack(self, pack_id).await
+    }
+
+    async fn negotiate_fetch(&self, request: &NegotiationRequest) -> Result<NegotiationResponse> {
+        RemoteClient::negotiate_fetch(self, request).await
+    }
+
+    async fn negotiate_push(&self, request: &PushRequest) -> Result<PushResponse> {
+        RemoteClient::negotiate_push(self, request).await
+    }
+
+    async fn upload_pack(&self, pack_data: &[u8]) -> Result<()> {
+        RemoteClient::upload_pack(self, pack_data).await
+    }
+
+    async fn upload_pack_resumable<F>(&self, pack_data: &[u8], progress: F) -> Result<()>
+    where
+        F: FnMut(u64, u64),
+    {
+        RemoteClient::upload_pack_resumable(self, pack_data, progress).await
+    }
+}
+
+/// How a Helix remote is reached, chosen from the shape of its URL.
+pub enum Transport {
+    /// `http://` or `https://`: the Helix HTTP protocol
+    Http(Box<RemoteClient>),
+    /// `ssh://[user@]host/path` or `[user@]host:path`: the remote
+    /// repository's files are read over `ssh`
+    Ssh { host: String, path: String },
+    /// `file://path` or a local directory holding a `.helix` repository
+    File(PathBuf),
+}
+
+impl Transport {
+    pub fn from_url(url: &str) -> Result<Self> {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Ok(Self::Http(Box::new(RemoteClient::configured(url))));
+        }
+        if let Some(path) = url.strip_prefix("file://") {
+            return Ok(Self::File(PathBuf::from(path)));
+        }
+        if let Some(rest) = url.strip_prefix("ssh://") {
+            let (host, path) = rest
+                .split_once('/')
+                .ok_or_else(|| anyhow::anyhow!("Missing repository path in '{}'", url))?;
+            return Ok(Self::Ssh {
+                host: host.to_string(),
+                path: format!("/{}", path),
+            });
+        }
+        if std::path::Path::new(url).exists() {
+            return Ok(Self::File(PathBuf::from(url)));
+        }
+        // scp-like `host:path`, as long as the host part isn't a path itself
+        if let Some((host, path)) = url.split_once(':') {
+            if !host.is_empty() && !host.contains('/') && !path.is_empty() {
+                return Ok(Self::Ssh {
+                    host: host.to_string(),
+                    path: path.to_string(),
+                });
+            }
+        }
+        anyhow::bail!("Unsupported remote URL '{}' (expected http(s)://, ssh://, host:path or file://)", url)
+    }
+
+    /// What a file or SSH remote can't do, as an error.
+    fn unsupported(&self, operation: &str) -> anyhow::Error {
+        let kind = match self {
+            Self::Http(_) => "HTTP",
+            Self::Ssh { .. } => "SSH",
+            Self::File(_) => "local",
+        };
+        anyhow::anyhow!("{} is not supported for {} remotes", operation, kind)
+    }
+
+    /// Read a file relative to the root of a file or SSH remote.
+    fn read_file(&self, relative: &str) -> Result<Vec<u8>> {
+        match self {
+            Self::File(root) => {
+                let path = root.join(relative);
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
+            }
+            Self::Ssh { host, path } => {
+                let remote_path = format!("{}/{}", path.trim_end_matches('/'), relative);
+                let output = Command::new("ssh")
+                    .arg(host)
+                    .arg("--")
+                    .arg(format!("cat {}", shell_quote(&remote_path)))
+                    .output()
+                    .with_context(|| format!("Failed to run ssh to {}", host))?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "Failed to read {} on {}: {}",
+                        remote_path,
+                        host,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                Ok(output.stdout)
+            }
+            Self::Http(_) => unreachable!("HTTP remotes are not read as files"),
+        }
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+impl RemoteTransport for Transport {
+    async fn check_connectivity(&self) -> Result<bool> {
+        match self {
+            Self::Http(client) => client.check_connectivity().await,
+            // Problems reaching the host show up on the first read
+            Self::Ssh { .. } => Ok(true),
+            Self::File(path) => Ok(path.join(".helix").is_dir()),
+        }
+    }
+
+    async fn discover_capabilities(&mut self) -> Result<Capabilities> {
+        match self {
+            Self::Http(client) => client.discover_capabilities().await,
+            Self::Ssh { .. } | Self::File(_) => Ok(Capabilities::default()),
+        }
+    }
+
+    async fn get_refs(&self) -> Result<HashMap<String, String>> {
+        match self {
+            Self::Http(client) => client.get_refs().await,
+            Self::Ssh { .. } | Self::File(_) => {
+                let branches: HashMap<String, serde_json::Value> =
+                    serde_json::from_slice(&self.read_file(".helix/branches.json")?)?;
+                Ok(branches
+                    .into_iter()
+                    .filter_map(|(name, branch)| {
+                        let head = branch.get("head_commit")?.as_str()?.to_string();
+                        Some((format!("refs/heads/{}", name), head))
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    async fn get_ref(&self, branch: &str) -> Result<String> {
+        match self {
+            Self::Http(client) => client.get_ref(branch).await,
+            Self::Ssh { .. } | Self::File(_) => self
+                .get_refs()
+                .await?
+                .remove(&format!("refs/heads/{}", branch))
+                .ok_or_else(|| anyhow::anyhow!("Remote has no branch '{}'", branch)),
+        }
+    }
+
+    async fn get_object_hash_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let root = match self {
+            Self::Http(client) => return client.get_object_hash_page(after, limit).await,
+            Self::Ssh { .. } => return Err(self.unsupported("Listing objects")),
+            Self::File(root) => root,
+        };
+        let objects_dir = root.join(".helix/objects");
+        let mut hashes = pack::packed_ids(&objects_dir);
+        for shard in fs::read_dir(&objects_dir)?.flatten() {
+            let shard_name = shard.file_name().to_string_lossy().to_string();
+            if shard_name.len() != 2 || !shard.path().is_dir() {
+                continue;
+            }
+            for object in fs::read_dir(shard.path())?.flatten() {
+                hashes.push(format!("{}{}", shard_name, object.file_name().to_string_lossy()));
+            }
+        }
+        hashes.sort();
+        hashes.dedup();
+        Ok(hashes
+            .into_iter()
+            .filter(|hash| after.is_none_or(|after| hash.as_str() > after))
+            .take(limit)
+            .collect())
+    }
+
+    async fn download_object(&self, hash: &str) -> Result<Vec<u8>> {
+        if let Self::Http(client) = self {
+            return client.download_object(hash).await;
+        }
+        let (dir, file) = hash.split_at(2.min(hash.len()));
+        let loose = self.read_file(&format!(".helix/objects/{}/{}", dir, file));
+        // A local remote may have packed it with `gc`
+        match (loose, self) {
+            (Ok(data), _) => Ok(data),
+            (Err(e), Self::File(root)) => pack::read_packed(&root.join(".helix/objects"), hash)?.ok_or(e),
+            (Err(e), _) => Err(e),
+        }
+    }
+
+    async fn download_objects(&self, hashes: &[String]) -> Result<HashMap<String, Vec<u8>>> {
+        if let Self::Http(client) = self {
+            return client.download_objects(hashes).await;
+        }
+        let mut objects = HashMap::new();
+        for hash in hashes {
+            objects.insert(hash.clone(), self.download_object(hash).await?);
+        }
+        Ok(objects)
+    }
+
+    async fn download_pack(&self, pack_id: &str) -> Result<Vec<u8>> {
+        match self {
+            Self::Http(client) => client.download_pack(pack_id).await,
+            _ => Err(self.unsupported("Downloading packs")),
+        }
+    }
+
+    async fn negotiate_fetch(&self, request: &NegotiationRequest) -> Result<NegotiationResponse> {
+        match self {
+            Self::Http(client) => client.negotiate_fetch(request).await,
+            _ => Err(self.unsupported("Fetch negotiation")),
+        }
+    }
+
+    async fn negotiate_push(&self, request: &PushRequest) -> Result<PushResponse> {
+        match self {
+            Self::Http(client) => client.negotiate_push(request).await,
+            _ => Err(self.unsupported("Pushing")),
+        }
+    }
+
+    async fn upload_pack(&self, pack_data: &[u8]) -> Result<()> {
+        match self {
+            Self::Http(client) => client.upload_pack(pack_data).await,
+            _ => Err(self.unsupported("Pushing")),
+        }
+    }
+}
+
+/// An in-memory remote for tests: refs and objects live in maps, and ref
+/// updates listed in `reject` are refused the way a server hook would.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockRemote {
+    pub capabilities: Capabilities,
+    pub refs: std::sync::Mutex<HashMap<String, String>>,
+    /// Object id -> raw (compressed) data
+    pub objects: std::sync::Mutex<std::collections::BTreeMap<String, Vec<u8>>>,
+    pub reject: std::collections::HashSet<String>,
+    /// Answer every hash page with the full list, like old servers
+    pub unpaginated: bool,
+    /// `after` cursor of each hash page asked for
+    pub page_requests: std::sync::Mutex<Vec<Option<String>>>,
+    /// Every object id downloaded, in order
+    pub downloads: std::sync::Mutex<Vec<String>>,
+    /// Fail every download after this many, like a dropped connection
+    pub fail_after: Option<usize>,
+    pub fetch_requests: std::sync::Mutex<Vec<NegotiationRequest>>,
+    pub push_requests: std::sync::Mutex<Vec<PushRequest>>,
+}
+
+#[cfg(test)]
+impl MockRemote {
+    /// A remote holding every object and branch of `repo`.
+    pub fn from_repo(repo: &crate::core::repository::Repository) -> Self {
+        let objects_dir = repo.get_objects_dir();
+        let mut objects = std::collections::BTreeMap::new();
+        for id in crate::commands::gc::list_loose_objects(repo).unwrap() {
+            objects.insert(id.clone(), fs::read(objects_dir.join(&id[..2]).join(&id[2..])).unwrap());
+        }
+        let refs = repo
+            .branches
+            .values()
+            .filter_map(|b| Some((format!("refs/heads/{}", b.name), b.get_head_commit()?.clone())))
+            .collect();
+        Self {
+            refs: std::sync::Mutex::new(refs),
+            objects: std::sync::Mutex::new(objects),
+            ..Default::default()
+        }
+    }
+
+    pub fn get(&self, ref_name: &str) -> Option<String> {
+        self.refs.lock().unwrap().get(ref_name).cloned()
+    }
+}
+
+#[cfg(test)]
+impl RemoteTransport for MockRemote {
+    async fn check_connectivity(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn discover_capabilities(&mut self) -> Result<Capabilities> {
+        Ok(self.capabilities.clone())
+    }
+
+    async fn get_refs(&self) -> Result<HashMap<String, String>> {
+        Ok(self.refs.lock().unwrap().clone())
+    }
+
+    async fn get_object_hash_page(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        self.page_requests.lock().unwrap().push(after.map(str::to_string));
+        let objects = self.objects.lock().unwrap();
+        if self.unpaginated {
+            return Ok(objects.keys().cloned().collect());
+        }
+        Ok(objects
+            .keys()
+            .filter(|hash| after.is_none_or(|after| hash.as_str() > after))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn download_object(&self, hash: &str) -> Result<Vec<u8>> {
+        let mut downloads = self.downloads.lock().unwrap();
+        if self.fail_after.is_some_and(|limit| downloads.len() >= limit) {
+            anyhow::bail!("Connection reset while fetching {}", hash);
+        }
+        downloads.push(hash.to_string());
+        self.objects
+            .lock()
+            .unwrap()
+            .get(hash)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Object {} not found", hash))
+    }
+
+    async fn download_pack(&self, pack_id: &str) -> Result<Vec<u8>> {
+        anyhow::bail!("No pack '{}'", pack_id)
+    }
+
+    /// Acknowledges the haves it has; objects are then downloaded one by one.
+    async fn negotiate_fetch(&self, request: &NegotiationRequest) -> Result<NegotiationResponse> {
+        self.fetch_requests.lock().unwrap().push(request.clone());
+        let objects = self.objects.lock().unwrap();
+        Ok(NegotiationResponse {
+            acks: request.haves.iter().filter(|h| objects.contains_key(*h)).cloned().collect(),
+            nak: Vec::new(),
+            shallow: Vec::new(),
+            unshallow: Vec::new(),
+            packfile: None,
+        })
+    }
+
+    async fn negotiate_push(&self, request: &PushRequest) -> Result<PushResponse> {
+        self.push_requests.lock().unwrap().push(request.clone());
+        let mut rejected: Vec<String> = request
+            .refs
+            .keys()
+            .filter(|ref_name| self.reject.contains(*ref_name))
+            .cloned()
+            .collect();
+        rejected.sort();
+        let mut ref_status: HashMap<String, String> = rejected
+            .iter()
+            .map(|ref_name| (ref_name.clone(), "hook declined".to_string()))
+            .collect();
+        let mut updated = Vec::new();
+        // An atomic push with any refusal changes nothing
+        if !request.atomic || rejected.is_empty() {
+            let mut refs = self.refs.lock().unwrap();
+            for (ref_name, id) in &request.refs {
+                if !rejected.contains(ref_name) {
+                    refs.insert(ref_name.clone(), id.clone());
+                    ref_status.insert(ref_name.clone(), "ok".to_string());
+                    updated.push(ref_name.clone());
+                }
+            }
+        }
+        updated.sort();
+        Ok(PushResponse {
+            success: rejected.is_empty(),
+            updated_refs: updated,
+            rejected_refs: rejected,
+            error: None,
+            ref_status,
+        })
+    }
+
+    async fn upload_pack(&self, pack_data: &[u8]) -> Result<()> {
+        let pack = pack::Pack::from_bytes(pack_data)?;
+        self.objects.lock().unwrap().extend(pack::extract_objects_from_pack(&pack));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{pull, push};
+    use crate::utils::test_utils::TestRepo;
+    use futures_util::TryStreamExt;
+
+    fn remote_with_objects(count: usize) -> MockRemote {
+        let objects = (0..count).map(|i| (format!("{:064x}", i), Vec::new())).collect();
+        MockRemote { objects: std::sync::Mutex::new(objects), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn object_hashes_follow_the_pages() {
+        let remote = remote_with_objects(5);
+        let hashes: Vec<String> = object_hashes(&remote, 3).try_collect().await.unwrap();
+
+        let expected: Vec<String> = remote.objects.lock().unwrap().keys().cloned().collect();
+        assert_eq!(hashes, expected);
+        let pages = remote.page_requests.lock().unwrap();
+        assert_eq!(*pages, vec![None, Some(expected[2].clone())]);
+    }
+
+    #[tokio::test]
+    async fn object_hashes_from_a_remote_ignoring_pagination_are_not_repeated() {
+        let remote = MockRemote { unpaginated: true, ..remote_with_objects(5) };
+        let hashes: Vec<String> = object_hashes(&remote, 2).try_collect().await.unwrap();
+
+        assert_eq!(hashes.len(), 5);
+        assert_eq!(hashes.iter().collect::<std::collections::HashSet<_>>().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn pull_negotiates_with_the_objects_we_have() {
+        let mut remote = TestRepo::new();
+        remote.write("a", "1\n");
+        let first = remote.commit("first").await;
+        let mut local = TestRepo::new();
+        local.repo.add_remote("origin", "mock://remote").unwrap();
+
+        let mut mock = MockRemote::from_repo(&remote.repo);
+        pull::pull_changes(&mut local.repo, &mut mock, "origin", None).await.unwrap();
+        assert_eq!(local.head(), first);
+        assert_eq!(local.read("a"), "1\n");
+
+        remote.write("a", "2\n");
+        let second = remote.commit("second").await;
+        let mut mock = MockRemote::from_repo(&remote.repo);
+        let pulled = pull::pull_changes(&mut local.repo, &mut mock, "origin", None).await.unwrap();
+
+        assert_eq!(pulled.as_deref(), Some(second.as_str()));
+        let requests = mock.fetch_requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].wants, std::slice::from_ref(&second));
+        assert!(requests[0].haves.contains(&first));
+        assert!(!requests[0].haves.contains(&second));
+        assert_eq!(local.head(), second);
+        assert_eq!(local.read("a"), "2\n");
+        assert_eq!(local.repo.get_remote_ref("origin", "main"), Some(second));
+    }
+
+    #[tokio::test]
+    async fn push_uploads_objects_and_moves_the_remote_ref() {
+        let mut local = TestRepo::new();
+        local.write("a", "1\n");
+        let head = local.commit("first").await;
+        let mut mock = MockRemote::default();
+
+        push::push_changes(&local.repo, &mut mock, "origin", &push::PushOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(mock.get("refs/heads/main"), Some(head.clone()));
+        let objects = mock.objects.lock().unwrap();
+        for id in crate::commands::gc::list_loose_objects(&local.repo).unwrap() {
+            assert!(objects.contains_key(&id), "{} was not uploaded", id);
+        }
+        let pushes = mock.push_requests.lock().unwrap();
+        assert_eq!(pushes.len(), 1);
+        assert!(!pushes[0].force);
+    }
+
+    #[tokio::test]
+    async fn push_refuses_a_non_fast_forward() {
+        let mut local = TestRepo::new();
+        local.write("a", "1\n");
+        local.commit("first").await;
+        let mut mock = MockRemote::default();
+        mock.refs
+            .lock()
+            .unwrap()
+            .insert("refs/heads/main".to_string(), "f".repeat(64));
+
+        let result = push::push_changes(&local.repo, &mut mock, "origin", &push::PushOptions::default()).await;
+        assert!(result.is_err());
+        assert_eq!(mock.get("refs/heads/main"), Some("f".repeat(64)));
+        assert!(mock.push_requests.lock().unwrap().is_empty());
+    }
+}